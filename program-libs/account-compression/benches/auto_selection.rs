@@ -0,0 +1,103 @@
+#![feature(test)]
+extern crate test;
+
+use {
+    compression_lib::{CompressedAccount, CompressionType},
+    test::Bencher,
+};
+
+const FIXTURE_SIZE: usize = 64 * 1024;
+
+/// xorshift64, so the fixtures are reproducible without a rand dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn text_like() -> Vec<u8> {
+    const WORDS: [&str; 8] = ["proof", "flow", "root", "the", "verifier", "nullifier", "of", "merkle"];
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let mut out = Vec::with_capacity(FIXTURE_SIZE + 16);
+    while out.len() < FIXTURE_SIZE {
+        out.extend_from_slice(WORDS[(rng.next() % 8) as usize].as_bytes());
+        out.push(b' ');
+    }
+    out.truncate(FIXTURE_SIZE);
+    out
+}
+
+fn binary_structured() -> Vec<u8> {
+    let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+    let mut out = Vec::with_capacity(FIXTURE_SIZE + 40);
+    let mut id = 1_000u64;
+    while out.len() < FIXTURE_SIZE {
+        id += 1 + rng.next() % 3;
+        out.extend_from_slice(&id.to_le_bytes());
+        out.extend_from_slice(&((rng.next() % 10_000) as u32).to_le_bytes());
+        out.extend_from_slice(&[(rng.next() % 4) as u8; 20]);
+        out.extend_from_slice(&[0u8; 8]);
+    }
+    out.truncate(FIXTURE_SIZE);
+    out
+}
+
+fn random() -> Vec<u8> {
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    (0..FIXTURE_SIZE).map(|_| rng.next() as u8).collect()
+}
+
+// Throughput is reported per input byte; compare each Auto bench with the
+// fastest manual bench on the same fixture, and the printed sizes for ratio
+fn bench(b: &mut Bencher, data: &[u8], compression_type: CompressionType) {
+    let compressed = CompressedAccount::new(data, compression_type).unwrap();
+    eprintln!(
+        "{:?} -> {:?}: {} bytes",
+        compression_type,
+        compressed.compression_type,
+        compressed.data.len()
+    );
+
+    b.bytes = data.len() as u64;
+    b.iter(|| CompressedAccount::new(data, compression_type).unwrap());
+}
+
+macro_rules! fixture_benches {
+    ($fixture:ident: $($name:ident => $compression_type:expr),* $(,)?) => {
+        $(
+            #[bench]
+            fn $name(b: &mut Bencher) {
+                bench(b, &$fixture(), $compression_type);
+            }
+        )*
+    };
+}
+
+fixture_benches!(text_like:
+    bench_text_none => CompressionType::None,
+    bench_text_lz4 => CompressionType::Lz4,
+    bench_text_snappy => CompressionType::Snappy,
+    bench_text_zstd => CompressionType::Zstd,
+    bench_text_auto => CompressionType::Auto,
+);
+
+fixture_benches!(binary_structured:
+    bench_binary_none => CompressionType::None,
+    bench_binary_lz4 => CompressionType::Lz4,
+    bench_binary_snappy => CompressionType::Snappy,
+    bench_binary_zstd => CompressionType::Zstd,
+    bench_binary_auto => CompressionType::Auto,
+);
+
+fixture_benches!(random:
+    bench_random_none => CompressionType::None,
+    bench_random_lz4 => CompressionType::Lz4,
+    bench_random_snappy => CompressionType::Snappy,
+    bench_random_zstd => CompressionType::Zstd,
+    bench_random_auto => CompressionType::Auto,
+);
\ No newline at end of file
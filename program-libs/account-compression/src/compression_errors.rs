@@ -9,6 +9,8 @@ pub enum CompressionError {
     DecompressionFailed,
     #[error("Invalid Compression Type")]
     InvalidCompressionType,
+    #[error("Insufficient Buffer Size")]
+    InsufficientBufferSize,
 }
 
 impl From<CompressionError> for ProgramError {
@@ -7,15 +7,40 @@ use {
         clock::UnixTimestamp,
     },
     std::{
-        io::{self, Write},
+        io::{self, Read, Write},
         collections::VecDeque,
     },
 };
 
+pub mod compression_errors;
+
+use compression_errors::CompressionError;
+
 pub const COMPRESSION_HEADER_SIZE: usize = 8;
 pub const MAX_UNCOMPRESSED_SIZE: usize = 10 * 1024 * 1024; // 10MB
 pub const MAX_QUEUE_SIZE: usize = 1000;
 
+/// `CompressedAccount::version` for data stored as a chunk table followed
+/// by independently compressed chunks
+pub const CHUNKED_VERSION: u8 = 2;
+/// Chunk table entry: original and compressed length, both u32 LE
+pub const CHUNK_TABLE_ENTRY_SIZE: usize = 8;
+/// Plaintext `StreamCompressor` buffers before compressing it as one chunk
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes `CompressionType::Auto` samples from the front of the payload. A
+/// 256-bin histogram settles well within 4 KB, and the sample bounds the
+/// cost of selection regardless of payload size.
+pub const AUTO_SAMPLE_SIZE: usize = 4 * 1024;
+/// Sample entropy, in bits per byte, at or below which Auto picks Zstd.
+/// Data this redundant compresses markedly better under Zstd, which pays
+/// for its slower encoder.
+pub const AUTO_ZSTD_MAX_ENTROPY: f64 = 5.0;
+/// Sample entropy at or below which Auto picks Lz4, and above which it
+/// stores the data uncompressed. Past this, payloads are near-random: every
+/// algorithm saves a few percent at best and may expand them.
+pub const AUTO_LZ4_MAX_ENTROPY: f64 = 7.5;
+
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct QueueMetadata {
     pub creation_time: UnixTimestamp,
@@ -58,16 +83,111 @@ pub struct AccountMetadata {
     pub saved_space: u32,
 }
 
+/// Original and compressed length of one chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSize {
+    pub original_size: u32,
+    pub compressed_size: u32,
+}
+
+/// Layout of a compressed account, read from its chunk table without
+/// decompressing anything
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionManifest {
+    pub algorithm: CompressionType,
+    pub num_chunks: u32,
+    pub chunk_sizes: Vec<ChunkSize>,
+    pub original_size: u32,
+    pub compressed_size: u32,
+    /// Compressed over original size, as `get_compression_ratio`
+    pub ratio: f64,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, BorshSerialize, BorshDeserialize)]
 pub enum CompressionType {
     None = 0,
     Lz4 = 1,
     Snappy = 2,
     Zstd = 3,
+    /// Picks one of the above from the payload's sampled entropy. Only
+    /// valid as a request: compressed data always records the algorithm
+    /// that was chosen.
+    Auto = 4,
+}
+
+impl CompressionType {
+    /// The concrete algorithm to compress `data` with: `self`, unless it is
+    /// `Auto`.
+    pub fn resolve(self, data: &[u8], config: &AutoSelectConfig) -> Self {
+        match self {
+            Self::Auto => config.select(data),
+            concrete => concrete,
+        }
+    }
+}
+
+/// Thresholds `CompressionType::Auto` selects with. The defaults are the
+/// `AUTO_*` constants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoSelectConfig {
+    pub sample_size: usize,
+    pub zstd_max_entropy: f64,
+    pub lz4_max_entropy: f64,
+}
+
+impl Default for AutoSelectConfig {
+    fn default() -> Self {
+        Self {
+            sample_size: AUTO_SAMPLE_SIZE,
+            zstd_max_entropy: AUTO_ZSTD_MAX_ENTROPY,
+            lz4_max_entropy: AUTO_LZ4_MAX_ENTROPY,
+        }
+    }
+}
+
+impl AutoSelectConfig {
+    pub fn select(&self, data: &[u8]) -> CompressionType {
+        let entropy = sample_entropy(&data[..data.len().min(self.sample_size)]);
+        if entropy <= self.zstd_max_entropy {
+            CompressionType::Zstd
+        } else if entropy <= self.lz4_max_entropy {
+            CompressionType::Lz4
+        } else {
+            CompressionType::None
+        }
+    }
+}
+
+/// Shannon entropy of `sample`'s byte histogram, in bits per byte (0 to 8)
+pub fn sample_entropy(sample: &[u8]) -> f64 {
+    if sample.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0u32; 256];
+    for byte in sample {
+        counts[*byte as usize] += 1;
+    }
+
+    let total = sample.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
 }
 
 impl CompressionQueue {
     pub fn new(authority: Pubkey) -> Self {
+        Self::with_capacity(authority, 0)
+    }
+
+    /// Like `new`, with room for `capacity` items (at most
+    /// `MAX_QUEUE_SIZE`) allocated up front
+    pub fn with_capacity(authority: Pubkey, capacity: usize) -> Self {
         Self {
             metadata: QueueMetadata {
                 creation_time: 0,
@@ -77,7 +197,7 @@ impl CompressionQueue {
                 total_items_processed: 0,
                 compression_ratio: 1.0,
             },
-            pending_items: VecDeque::new(),
+            pending_items: VecDeque::with_capacity(capacity.min(MAX_QUEUE_SIZE)),
             processed_count: 0,
         }
     }
@@ -103,6 +223,13 @@ impl CompressionQueue {
             timestamp: 0, // Should be set from blockchain
         };
 
+        // Grow by doubling as usual, but never past MAX_QUEUE_SIZE
+        let len = self.pending_items.len();
+        if len == self.pending_items.capacity() {
+            let target = (len * 2).clamp(1, MAX_QUEUE_SIZE);
+            self.pending_items.reserve_exact(target - len);
+        }
+
         match priority {
             0 => self.pending_items.push_back(item),
             _ => self.pending_items.push_front(item),
@@ -119,11 +246,13 @@ impl CompressionQueue {
         let item = self.pending_items.pop_front().unwrap();
         let original_size = item.data.len() as u32;
 
-        let compressed_data = match item.compression_type {
+        let compression_type = item.compression_type.resolve(&item.data, &AutoSelectConfig::default());
+        let compressed_data = match compression_type {
             CompressionType::None => item.data,
             CompressionType::Lz4 => compress_lz4(&item.data)?,
             CompressionType::Snappy => compress_snappy(&item.data)?,
             CompressionType::Zstd => compress_zstd(&item.data)?,
+            CompressionType::Auto => unreachable!("resolved above"),
         };
 
         let saved_space = if compressed_data.len() > item.data.len() {
@@ -135,7 +264,7 @@ impl CompressionQueue {
         let account = CompressedAccount {
             version: 1,
             original_size,
-            compression_type: item.compression_type,
+            compression_type,
             data: compressed_data,
             metadata: AccountMetadata {
                 last_compressed: 0, // Should be set from blockchain
@@ -162,45 +291,231 @@ impl CompressionQueue {
 
 impl CompressedAccount {
     pub fn new(data: &[u8], compression_type: CompressionType) -> Result<Self, ProgramError> {
+        Self::new_with_config(data, compression_type, &AutoSelectConfig::default())
+    }
+
+    /// Same as [`new`](Self::new), with `config` in place of the default
+    /// thresholds when `compression_type` is `Auto`
+    pub fn new_with_config(
+        data: &[u8],
+        compression_type: CompressionType,
+        config: &AutoSelectConfig,
+    ) -> Result<Self, ProgramError> {
         if data.len() > MAX_UNCOMPRESSED_SIZE {
             return Err(ProgramError::InvalidArgument);
         }
 
-        let original_size = data.len() as u32;
-        let compressed_data = match compression_type {
-            CompressionType::None => data.to_vec(),
-            CompressionType::Lz4 => compress_lz4(data)?,
-            CompressionType::Snappy => compress_snappy(data)?,
-            CompressionType::Zstd => compress_zstd(data)?,
-        };
+        let compression_type = compression_type.resolve(data, config);
+        let compressed_data = compress_with(data, compression_type)?;
+        Ok(Self::from_parts(1, data.len(), compression_type, compressed_data))
+    }
 
-        let saved_space = if compressed_data.len() > data.len() {
-            0
-        } else {
-            (data.len() - compressed_data.len()) as u32
-        };
+    /// Compresses `data` as independent chunks of at most `chunk_size`
+    /// bytes. The stored data is a chunk table (u32 LE chunk count, then one
+    /// `CHUNK_TABLE_ENTRY_SIZE` entry per chunk) followed by the chunks.
+    /// `Auto` picks one algorithm for all chunks from the front of `data`.
+    pub fn new_chunked(
+        data: &[u8],
+        compression_type: CompressionType,
+        chunk_size: usize,
+    ) -> Result<Self, ProgramError> {
+        if data.len() > MAX_UNCOMPRESSED_SIZE || chunk_size == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
 
-        Ok(Self {
-            version: 1,
+        let compression_type = compression_type.resolve(data, &AutoSelectConfig::default());
+
+        let chunks = data
+            .chunks(chunk_size)
+            .map(|chunk| compress_with(chunk, compression_type))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::from_chunks(data, compression_type, chunk_size, chunks))
+    }
+
+    /// Same as [`new_chunked`](Self::new_chunked), but with `parallel_chunks`
+    /// set the chunks are compressed concurrently on the rayon thread pool.
+    /// Chunk order, and so the output, is identical either way. Only built
+    /// off-chain; programs always compress on a single thread.
+    #[cfg(all(feature = "parallel", not(target_os = "solana")))]
+    pub fn new_chunked_with(
+        data: &[u8],
+        compression_type: CompressionType,
+        chunk_size: usize,
+        parallel_chunks: bool,
+    ) -> Result<Self, ProgramError> {
+        use rayon::prelude::*;
+
+        if !parallel_chunks {
+            return Self::new_chunked(data, compression_type, chunk_size);
+        }
+        if data.len() > MAX_UNCOMPRESSED_SIZE || chunk_size == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let compression_type = compression_type.resolve(data, &AutoSelectConfig::default());
+        let chunks = data
+            .par_chunks(chunk_size)
+            .map(|chunk| compress_with(chunk, compression_type))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::from_chunks(data, compression_type, chunk_size, chunks))
+    }
+
+    /// Lays out the chunk table and the compressed `chunks` of `data`, which
+    /// must be in chunk order.
+    fn from_chunks(
+        data: &[u8],
+        compression_type: CompressionType,
+        chunk_size: usize,
+        chunks: Vec<Vec<u8>>,
+    ) -> Self {
+        let payload_size: usize = chunks.iter().map(Vec::len).sum();
+        let mut stored = Vec::with_capacity(4 + chunks.len() * CHUNK_TABLE_ENTRY_SIZE + payload_size);
+        stored.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+        for (raw, compressed) in data.chunks(chunk_size).zip(&chunks) {
+            stored.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+            stored.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        }
+        for compressed in &chunks {
+            stored.extend_from_slice(compressed);
+        }
+
+        Self::from_parts(CHUNKED_VERSION, data.len(), compression_type, stored)
+    }
+
+    fn from_parts(
+        version: u8,
+        original_len: usize,
+        compression_type: CompressionType,
+        data: Vec<u8>,
+    ) -> Self {
+        let original_size = original_len as u32;
+        let saved_space = original_len.saturating_sub(data.len()) as u32;
+
+        Self {
+            version,
             original_size,
             compression_type,
-            data: compressed_data,
+            data,
             metadata: AccountMetadata {
                 last_compressed: 0,
                 compression_count: 1,
                 original_space: original_size,
                 saved_space,
             },
-        })
+        }
     }
 
     pub fn decompress(&self) -> Result<Vec<u8>, ProgramError> {
-        match self.compression_type {
-            CompressionType::None => Ok(self.data.clone()),
-            CompressionType::Lz4 => decompress_lz4(&self.data, self.original_size as usize),
-            CompressionType::Snappy => decompress_snappy(&self.data, self.original_size as usize),
-            CompressionType::Zstd => decompress_zstd(&self.data, self.original_size as usize),
+        if self.version != CHUNKED_VERSION {
+            return decompress_with(&self.data, self.compression_type, self.original_size as usize);
         }
+
+        let table = self.chunk_table()?;
+        let mut offset = 4 + table.len() * CHUNK_TABLE_ENTRY_SIZE;
+        let mut decompressed = Vec::with_capacity(self.original_size as usize);
+        for entry in table {
+            let end = offset + entry.compressed_size as usize;
+            let chunk = decompress_with(
+                &self.data[offset..end],
+                self.compression_type,
+                entry.original_size as usize,
+            )?;
+            if chunk.len() != entry.original_size as usize {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            decompressed.extend_from_slice(&chunk);
+            offset = end;
+        }
+        Ok(decompressed)
+    }
+
+    /// Like `decompress`, but writes into `out` instead of allocating and
+    /// returns the number of bytes written. Fails with
+    /// `InsufficientBufferSize` unless `out` holds `original_size` bytes.
+    pub fn decompress_into(&self, out: &mut [u8]) -> Result<usize, ProgramError> {
+        let out = out
+            .get_mut(..self.original_size as usize)
+            .ok_or(CompressionError::InsufficientBufferSize)?;
+        if self.version != CHUNKED_VERSION {
+            return decompress_into_with(&self.data, self.compression_type, out);
+        }
+
+        let table = self.chunk_table()?;
+        let mut offset = 4 + table.len() * CHUNK_TABLE_ENTRY_SIZE;
+        let mut written = 0;
+        for entry in table {
+            let end = offset + entry.compressed_size as usize;
+            let chunk_out = out
+                .get_mut(written..written + entry.original_size as usize)
+                .ok_or(ProgramError::InvalidAccountData)?;
+            let chunk_len = decompress_into_with(&self.data[offset..end], self.compression_type, chunk_out)?;
+            if chunk_len != entry.original_size as usize {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            written += chunk_len;
+            offset = end;
+        }
+        Ok(written)
+    }
+
+    /// Reads the chunk table. Unchunked data is reported as a single chunk.
+    pub fn chunk_table(&self) -> Result<Vec<ChunkSize>, ProgramError> {
+        if self.version != CHUNKED_VERSION {
+            return Ok(vec![ChunkSize {
+                original_size: self.original_size,
+                compressed_size: self.data.len() as u32,
+            }]);
+        }
+
+        let read_u32 = |offset: usize| -> Result<u32, ProgramError> {
+            self.data
+                .get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or(ProgramError::InvalidAccountData)
+        };
+
+        let num_chunks = read_u32(0)? as usize;
+        let header_size = num_chunks
+            .checked_mul(CHUNK_TABLE_ENTRY_SIZE)
+            .and_then(|size| size.checked_add(4))
+            .ok_or(ProgramError::InvalidAccountData)?;
+        if header_size > self.data.len() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let table = (0..num_chunks)
+            .map(|i| {
+                let entry = 4 + i * CHUNK_TABLE_ENTRY_SIZE;
+                Ok(ChunkSize {
+                    original_size: read_u32(entry)?,
+                    compressed_size: read_u32(entry + 4)?,
+                })
+            })
+            .collect::<Result<Vec<_>, ProgramError>>()?;
+
+        // The table must account for exactly the stored chunks and the
+        // original length
+        let payload: u64 = table.iter().map(|e| e.compressed_size as u64).sum();
+        let original: u64 = table.iter().map(|e| e.original_size as u64).sum();
+        if payload != (self.data.len() - header_size) as u64 || original != self.original_size as u64 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(table)
+    }
+
+    pub fn manifest(&self) -> Result<CompressionManifest, ProgramError> {
+        let chunk_sizes = self.chunk_table()?;
+        Ok(CompressionManifest {
+            algorithm: self.compression_type,
+            num_chunks: chunk_sizes.len() as u32,
+            chunk_sizes,
+            original_size: self.original_size,
+            compressed_size: self.data.len() as u32,
+            ratio: self.get_compression_ratio(),
+        })
     }
 
     pub fn get_compression_ratio(&self) -> f64 {
@@ -220,6 +535,173 @@ impl CompressedAccount {
     }
 }
 
+/// Builds a chunked `CompressedAccount` from data fed in pieces, holding at
+/// most one `STREAM_CHUNK_SIZE` chunk of plaintext at a time. At level 0 the
+/// result is the same as [`CompressedAccount::new_chunked`] with
+/// `STREAM_CHUNK_SIZE` chunks over the concatenated writes. Only built
+/// off-chain.
+#[cfg(not(target_os = "solana"))]
+pub struct StreamCompressor {
+    /// Resolved from the first chunk when created as `Auto`
+    compression_type: CompressionType,
+    level: i32,
+    pending: Vec<u8>,
+    table: Vec<ChunkSize>,
+    payload: Vec<u8>,
+    original_len: usize,
+}
+
+#[cfg(not(target_os = "solana"))]
+impl StreamCompressor {
+    /// `level` only applies to Zstd; the other algorithms have none.
+    pub fn new(compression_type: CompressionType, level: i32) -> Self {
+        Self {
+            compression_type,
+            level,
+            pending: Vec::with_capacity(STREAM_CHUNK_SIZE),
+            table: Vec::new(),
+            payload: Vec::new(),
+            original_len: 0,
+        }
+    }
+
+    /// Appends `chunk`, compressing every chunk it completes. Fails once
+    /// the total written exceeds `MAX_UNCOMPRESSED_SIZE`.
+    pub fn write(&mut self, mut chunk: &[u8]) -> Result<(), ProgramError> {
+        if self.original_len + chunk.len() > MAX_UNCOMPRESSED_SIZE {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.original_len += chunk.len();
+
+        while !chunk.is_empty() {
+            let take = (STREAM_CHUNK_SIZE - self.pending.len()).min(chunk.len());
+            self.pending.extend_from_slice(&chunk[..take]);
+            chunk = &chunk[take..];
+            if self.pending.len() == STREAM_CHUNK_SIZE {
+                self.compress_pending()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn compress_pending(&mut self) -> Result<(), ProgramError> {
+        self.compression_type = self.compression_type.resolve(&self.pending, &AutoSelectConfig::default());
+        let compressed = match self.compression_type {
+            CompressionType::Zstd => compress_zstd_level(&self.pending, self.level)?,
+            compression_type => compress_with(&self.pending, compression_type)?,
+        };
+
+        self.table.push(ChunkSize {
+            original_size: self.pending.len() as u32,
+            compressed_size: compressed.len() as u32,
+        });
+        self.payload.extend_from_slice(&compressed);
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Compresses whatever is left and lays out the chunk table.
+    pub fn finish(mut self) -> Result<CompressedAccount, ProgramError> {
+        if !self.pending.is_empty() {
+            self.compress_pending()?;
+        } else if self.table.is_empty() {
+            // Nothing written: resolve Auto as `new_chunked` does for empty data
+            self.compression_type = self.compression_type.resolve(&[], &AutoSelectConfig::default());
+        }
+
+        let mut stored = Vec::with_capacity(4 + self.table.len() * CHUNK_TABLE_ENTRY_SIZE + self.payload.len());
+        stored.extend_from_slice(&(self.table.len() as u32).to_le_bytes());
+        for entry in &self.table {
+            stored.extend_from_slice(&entry.original_size.to_le_bytes());
+            stored.extend_from_slice(&entry.compressed_size.to_le_bytes());
+        }
+        stored.extend_from_slice(&self.payload);
+
+        Ok(CompressedAccount::from_parts(
+            CHUNKED_VERSION,
+            self.original_len,
+            self.compression_type,
+            stored,
+        ))
+    }
+}
+
+fn compress_with(data: &[u8], compression_type: CompressionType) -> Result<Vec<u8>, ProgramError> {
+    match compression_type {
+        CompressionType::None => Ok(data.to_vec()),
+        CompressionType::Lz4 => compress_lz4(data),
+        CompressionType::Snappy => compress_snappy(data),
+        CompressionType::Zstd => compress_zstd(data),
+        // Callers resolve Auto before compressing
+        CompressionType::Auto => Err(ProgramError::InvalidArgument),
+    }
+}
+
+fn decompress_with(
+    data: &[u8],
+    compression_type: CompressionType,
+    original_size: usize,
+) -> Result<Vec<u8>, ProgramError> {
+    match compression_type {
+        CompressionType::None => Ok(data.to_vec()),
+        CompressionType::Lz4 => decompress_lz4(data, original_size),
+        CompressionType::Snappy => decompress_snappy(data, original_size),
+        CompressionType::Zstd => decompress_zstd(data, original_size),
+        // Never recorded, so stored data claiming it is corrupt
+        CompressionType::Auto => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Decompresses into `out`, which is sized from the recorded original size,
+/// so output that doesn't fit means the data is corrupt
+fn decompress_into_with(
+    data: &[u8],
+    compression_type: CompressionType,
+    out: &mut [u8],
+) -> Result<usize, ProgramError> {
+    match compression_type {
+        CompressionType::None => {
+            out.get_mut(..data.len())
+                .ok_or(ProgramError::InvalidAccountData)?
+                .copy_from_slice(data);
+            Ok(data.len())
+        }
+        CompressionType::Lz4 => read_into(lz4_flex::frame::FrameDecoder::new(data), out),
+        CompressionType::Snappy => {
+            let len = snap::raw::decompress_len(data).map_err(|_| ProgramError::InvalidArgument)?;
+            if len > out.len() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            snap::raw::Decoder::new()
+                .decompress(data, out)
+                .map_err(|_| ProgramError::InvalidArgument)
+        }
+        CompressionType::Zstd => {
+            // A byte slice is already buffered, so no BufReader is allocated
+            let decoder = zstd::stream::read::Decoder::with_buffer(data)
+                .map_err(|_| ProgramError::InvalidArgument)?;
+            read_into(decoder, out)
+        }
+        CompressionType::Auto => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Reads `reader` to its end into `out`
+fn read_into(mut reader: impl Read, out: &mut [u8]) -> Result<usize, ProgramError> {
+    let mut written = 0;
+    while written < out.len() {
+        match reader.read(&mut out[written..]).map_err(|_| ProgramError::InvalidArgument)? {
+            0 => return Ok(written),
+            n => written += n,
+        }
+    }
+    let mut extra = [0u8; 1];
+    if reader.read(&mut extra).map_err(|_| ProgramError::InvalidArgument)? != 0 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(written)
+}
+
 fn compress_lz4(data: &[u8]) -> Result<Vec<u8>, ProgramError> {
     let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
     encoder.write_all(data).map_err(|_| ProgramError::InvalidArgument)?;
@@ -247,7 +729,11 @@ fn decompress_snappy(compressed: &[u8], original_size: usize) -> Result<Vec<u8>,
 }
 
 fn compress_zstd(data: &[u8]) -> Result<Vec<u8>, ProgramError> {
-    zstd::encode_all(data, 0)
+    compress_zstd_level(data, 0)
+}
+
+fn compress_zstd_level(data: &[u8], level: i32) -> Result<Vec<u8>, ProgramError> {
+    zstd::encode_all(data, level)
         .map_err(|_| ProgramError::InvalidArgument)
 }
 
@@ -307,6 +793,91 @@ mod tests {
         assert_eq!(compressed_zstd.decompress().unwrap(), data);
     }
 
+    #[test]
+    fn test_chunked_manifest() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let account = CompressedAccount::new_chunked(&data, CompressionType::Lz4, 4096).unwrap();
+
+        let manifest = account.manifest().unwrap();
+        assert_eq!(manifest.algorithm, CompressionType::Lz4);
+        assert_eq!(manifest.num_chunks, 3);
+        assert_eq!(
+            manifest.chunk_sizes.iter().map(|c| c.original_size).collect::<Vec<_>>(),
+            vec![4096, 4096, 1808]
+        );
+        assert_eq!(manifest.original_size, data.len() as u32);
+        assert_eq!(manifest.compressed_size, account.data.len() as u32);
+
+        let payload: u32 = manifest.chunk_sizes.iter().map(|c| c.compressed_size).sum();
+        let table = 4 + manifest.num_chunks * CHUNK_TABLE_ENTRY_SIZE as u32;
+        assert_eq!(payload + table, manifest.compressed_size);
+
+        assert_eq!(account.decompress().unwrap(), data);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_chunks_match_sequential() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 253) as u8).collect();
+
+        for compression_type in [CompressionType::Lz4, CompressionType::Snappy, CompressionType::Zstd] {
+            let sequential =
+                CompressedAccount::new_chunked_with(&data, compression_type, 4096, false).unwrap();
+            let parallel =
+                CompressedAccount::new_chunked_with(&data, compression_type, 4096, true).unwrap();
+
+            assert_eq!(parallel.data, sequential.data);
+            assert_eq!(parallel.original_size, sequential.original_size);
+            assert_eq!(parallel.decompress().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_stream_compressor_matches_whole_buffer() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i * 13 % 241) as u8).collect();
+
+        for compression_type in [CompressionType::Lz4, CompressionType::Snappy, CompressionType::Zstd, CompressionType::Auto] {
+            let mut stream = StreamCompressor::new(compression_type, 0);
+            for piece in data.chunks(4096) {
+                stream.write(piece).unwrap();
+            }
+            let streamed = stream.finish().unwrap();
+
+            let whole = CompressedAccount::new_chunked(&data, compression_type, STREAM_CHUNK_SIZE).unwrap();
+            assert_eq!(streamed.data, whole.data);
+            assert_eq!(streamed.compression_type, whole.compression_type);
+            assert_eq!(streamed.original_size, data.len() as u32);
+            assert_eq!(streamed.decompress().unwrap(), data);
+        }
+
+        // Levels only change the Zstd output, which still round-trips
+        let mut stream = StreamCompressor::new(CompressionType::Zstd, 19);
+        stream.write(&data).unwrap();
+        assert_eq!(stream.finish().unwrap().decompress().unwrap(), data);
+
+        let empty = StreamCompressor::new(CompressionType::Lz4, 0).finish().unwrap();
+        assert_eq!(empty.manifest().unwrap().num_chunks, 0);
+        assert!(empty.decompress().unwrap().is_empty());
+
+        let mut oversized = StreamCompressor::new(CompressionType::Lz4, 0);
+        oversized.write(&vec![0u8; MAX_UNCOMPRESSED_SIZE]).unwrap();
+        assert_eq!(oversized.write(&[0u8]), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn test_manifest_rejects_corrupt_table() {
+        let data = vec![7u8; 5000];
+        let mut account = CompressedAccount::new_chunked(&data, CompressionType::Snappy, 1024).unwrap();
+
+        // Unchunked accounts report a single chunk
+        let whole = CompressedAccount::new(&data, CompressionType::Snappy).unwrap();
+        assert_eq!(whole.manifest().unwrap().num_chunks, 1);
+
+        account.data[4] ^= 0xff;
+        assert_eq!(account.manifest(), Err(ProgramError::InvalidAccountData));
+        assert!(account.decompress().is_err());
+    }
+
     #[test]
     fn test_queue_priority() {
         let mut queue = CompressionQueue::new(Pubkey::new_unique());
@@ -339,4 +910,159 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_queue_capacity_bounds() {
+        let mut preallocated = CompressionQueue::with_capacity(Pubkey::new_unique(), MAX_QUEUE_SIZE);
+        let capacity = preallocated.pending_items.capacity();
+        let mut grown = CompressionQueue::new(Pubkey::new_unique());
+
+        for i in 0..MAX_QUEUE_SIZE {
+            let data = (i as u32).to_le_bytes().to_vec();
+            preallocated.enqueue(data.clone(), CompressionType::None, 0).unwrap();
+            grown.enqueue(data, CompressionType::None, 0).unwrap();
+        }
+
+        // Filling a preallocated queue never reallocates, and growth stops
+        // at the limit
+        assert_eq!(preallocated.pending_items.capacity(), capacity);
+        assert_eq!(grown.pending_items.capacity(), MAX_QUEUE_SIZE);
+
+        for queue in [&mut preallocated, &mut grown] {
+            assert_eq!(
+                queue.enqueue(vec![0u8], CompressionType::None, 0),
+                Err(ProgramError::InvalidArgument)
+            );
+            for i in 0..MAX_QUEUE_SIZE {
+                let account = queue.process_next().unwrap().unwrap();
+                assert_eq!(account.decompress().unwrap(), (i as u32).to_le_bytes());
+            }
+        }
+
+        // Oversized hints are clamped
+        let clamped = CompressionQueue::with_capacity(Pubkey::new_unique(), usize::MAX);
+        assert!(clamped.pending_items.capacity() < 2 * MAX_QUEUE_SIZE);
+    }
+
+    /// xorshift64, so the fixtures are reproducible without a rand dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn text_like(len: usize) -> Vec<u8> {
+        const WORDS: [&str; 8] = ["proof", "flow", "root", "the", "verifier", "nullifier", "of", "merkle"];
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        let mut out = Vec::with_capacity(len + 16);
+        while out.len() < len {
+            out.extend_from_slice(WORDS[(rng.next() % 8) as usize].as_bytes());
+            out.push(b' ');
+        }
+        out.truncate(len);
+        out
+    }
+
+    /// Fixed-size records: increasing ids, small amounts, a few keys
+    fn binary_structured(len: usize) -> Vec<u8> {
+        let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+        let mut out = Vec::with_capacity(len + 40);
+        let mut id = 1_000u64;
+        while out.len() < len {
+            id += 1 + rng.next() % 3;
+            out.extend_from_slice(&id.to_le_bytes());
+            out.extend_from_slice(&((rng.next() % 10_000) as u32).to_le_bytes());
+            out.extend_from_slice(&[(rng.next() % 4) as u8; 20]);
+            out.extend_from_slice(&[0u8; 8]);
+        }
+        out.truncate(len);
+        out
+    }
+
+    fn random(len: usize) -> Vec<u8> {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        (0..len).map(|_| rng.next() as u8).collect()
+    }
+
+    #[test]
+    fn test_sample_entropy_bounds() {
+        assert_eq!(sample_entropy(&[]), 0.0);
+        assert_eq!(sample_entropy(&[7u8; 100]), 0.0);
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert!((sample_entropy(&all_bytes) - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_auto_matches_best_manual_ratio() {
+        let manual = [CompressionType::None, CompressionType::Lz4, CompressionType::Snappy, CompressionType::Zstd];
+        for data in [text_like(64 * 1024), binary_structured(64 * 1024), random(64 * 1024)] {
+            let best = manual
+                .iter()
+                .map(|t| CompressedAccount::new(&data, *t).unwrap().data.len())
+                .min()
+                .unwrap();
+
+            let auto = CompressedAccount::new(&data, CompressionType::Auto).unwrap();
+            assert_ne!(auto.compression_type, CompressionType::Auto);
+            assert!(auto.data.len() as f64 <= best as f64 * 1.1);
+            assert_eq!(auto.decompress().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_auto_thresholds() {
+        assert_eq!(CompressedAccount::new(&text_like(8192), CompressionType::Auto).unwrap().compression_type, CompressionType::Zstd);
+        assert_eq!(CompressedAccount::new(&random(8192), CompressionType::Auto).unwrap().compression_type, CompressionType::None);
+
+        // Overridden thresholds move both fixtures to Lz4
+        let config = AutoSelectConfig {
+            zstd_max_entropy: 1.0,
+            lz4_max_entropy: 8.0,
+            ..AutoSelectConfig::default()
+        };
+        for data in [text_like(8192), random(8192)] {
+            let account = CompressedAccount::new_with_config(&data, CompressionType::Auto, &config).unwrap();
+            assert_eq!(account.compression_type, CompressionType::Lz4);
+            assert_eq!(account.decompress().unwrap(), data);
+        }
+
+        // Only the sample decides: a random tail behind a constant prefix
+        let mut data = vec![0u8; AUTO_SAMPLE_SIZE];
+        data.extend(random(4096));
+        assert_eq!(AutoSelectConfig::default().select(&data), CompressionType::Zstd);
+
+        // Chunked accounts record the resolved algorithm for every chunk
+        let chunked = CompressedAccount::new_chunked(&text_like(8192), CompressionType::Auto, 1024).unwrap();
+        assert_eq!(chunked.compression_type, CompressionType::Zstd);
+        assert_eq!(chunked.decompress().unwrap(), text_like(8192));
+    }
+
+    #[test]
+    fn test_decompress_into_buffer() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let accounts = [
+            CompressedAccount::new(&data, CompressionType::None).unwrap(),
+            CompressedAccount::new(&data, CompressionType::Lz4).unwrap(),
+            CompressedAccount::new(&data, CompressionType::Snappy).unwrap(),
+            CompressedAccount::new(&data, CompressionType::Zstd).unwrap(),
+            CompressedAccount::new_chunked(&data, CompressionType::Lz4, 4096).unwrap(),
+        ];
+
+        for account in &accounts {
+            let mut exact = vec![0u8; data.len()];
+            assert_eq!(account.decompress_into(&mut exact).unwrap(), data.len());
+            assert_eq!(exact, data);
+
+            let mut too_small = vec![0u8; data.len() - 1];
+            assert_eq!(
+                account.decompress_into(&mut too_small),
+                Err(CompressionError::InsufficientBufferSize.into())
+            );
+        }
+    }
 } 
\ No newline at end of file
@@ -1,19 +1,23 @@
 use {
     borsh::{BorshDeserialize, BorshSerialize},
-    solana_program::{
-        program_error::ProgramError,
-        pubkey::Pubkey,
-        clock::UnixTimestamp,
-    },
+    crate::types::{ProgramError, Pubkey, UnixTimestamp},
     std::{
-        collections::{hash_map::DefaultHasher, HashMap},
+        collections::{hash_map::DefaultHasher, HashSet},
         hash::{Hash, Hasher},
     },
 };
 
+pub mod set_errors;
+pub mod types;
+
+use set_errors::HashSetError;
+
 const BUCKET_SIZE: usize = 32;
 const DEFAULT_CAPACITY: usize = 1024;
 const MAX_ROLLOVER_ITEMS: usize = 100;
+/// Default bucket fill, as a percentage of `BUCKET_SIZE`, at which inserts
+/// start staging a rollover
+const DEFAULT_ROLLOVER_HIGH_WATER_PERCENT: u8 = 75;
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct StateMetadata {
@@ -33,6 +37,8 @@ pub struct OnChainHashSet {
     metadata: StateMetadata,
     rollover_buffer: RolloverBuffer,
     operation_log: OperationLog,
+    /// Bucket fill, in percent of `BUCKET_SIZE`, that stages a rollover
+    rollover_high_water_percent: u8,
 }
 
 #[derive(Debug, Default, BorshSerialize, BorshDeserialize)]
@@ -42,6 +48,9 @@ struct Bucket {
     operation_count: u32,
 }
 
+/// Items moved out of a crowded bucket and waiting for `process_rollover` to
+/// place them in their other candidate bucket. They are still members of
+/// the set while parked here.
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 struct RolloverBuffer {
     items: Vec<[u8; 32]>,
@@ -56,11 +65,20 @@ struct OperationLog {
 }
 
 #[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
-struct Operation {
+pub struct Operation {
     op_type: OperationType,
     item: [u8; 32],
     timestamp: UnixTimestamp,
     bucket_index: usize,
+    /// `total_operations` when this was logged. Unlike the log itself it
+    /// survives checkpoints, so it orders operations across them.
+    global_sequence: u64,
+}
+
+impl Operation {
+    pub fn global_sequence(&self) -> u64 {
+        self.global_sequence
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, BorshSerialize, BorshDeserialize)]
@@ -97,9 +115,29 @@ impl OnChainHashSet {
                 operations: Vec::new(),
                 last_checkpoint: 0,
             },
+            rollover_high_water_percent: DEFAULT_ROLLOVER_HIGH_WATER_PERCENT,
         }
     }
 
+    /// Sets the bucket fill, in percent of `BUCKET_SIZE`, at which an insert
+    /// stages part of the bucket for rollover.
+    pub fn set_rollover_high_water(&mut self, percent: u8) -> Result<(), ProgramError> {
+        if percent == 0 || percent > 100 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.rollover_high_water_percent = percent;
+        Ok(())
+    }
+
+    fn high_water_mark(&self) -> usize {
+        (BUCKET_SIZE * self.rollover_high_water_percent as usize / 100).max(1)
+    }
+
+    /// Whether items are staged and waiting for a `process_rollover` crank
+    pub fn needs_rollover(&self) -> bool {
+        self.rollover_buffer.is_active
+    }
+
     pub fn insert(&mut self, item: &[u8; 32], timestamp: UnixTimestamp) -> Result<bool, ProgramError> {
         if self.metadata.is_frozen {
             return Err(ProgramError::InvalidAccountData);
@@ -109,33 +147,55 @@ impl OnChainHashSet {
             return Err(ProgramError::InvalidArgument);
         }
 
-        let bucket_idx = self.get_bucket_index(item);
-        let bucket = &mut self.buckets[bucket_idx];
-
         // Check if item already exists
-        if bucket.items.contains(item) {
+        if self.contains(item) {
             return Ok(false);
         }
 
+        // The home bucket, then the alternate; with both full the item waits
+        // in the rollover buffer, and past that the insert fails
+        let (home, alternate) = self.candidate_buckets(item);
+        let bucket_idx = if self.buckets[home].items.len() < BUCKET_SIZE {
+            Some(home)
+        } else if self.buckets[alternate].items.len() < BUCKET_SIZE {
+            Some(alternate)
+        } else if self.rollover_buffer.items.len() < MAX_ROLLOVER_ITEMS {
+            None
+        } else {
+            return Err(HashSetError::RolloverBufferFull.into());
+        };
+
         // Insert new item
-        bucket.items.push(*item);
-        bucket.last_modified = timestamp;
-        bucket.operation_count += 1;
+        match bucket_idx {
+            Some(bucket_idx) => {
+                let bucket = &mut self.buckets[bucket_idx];
+                bucket.items.push(*item);
+                bucket.last_modified = timestamp;
+                bucket.operation_count += 1;
+            }
+            None => {
+                self.rollover_buffer.items.push(*item);
+                self.rollover_buffer.is_active = true;
+            }
+        }
         self.item_count += 1;
         
         // Log operation
-        self.log_operation(Operation {
-            op_type: OperationType::Insert,
-            item: *item,
+        self.log_operation(
+            OperationType::Insert,
+            *item,
             timestamp,
-            bucket_index: bucket_idx,
-        });
+            bucket_idx.unwrap_or(home),
+        );
 
         // Check if bucket needs rollover
-        if bucket.items.len() >= BUCKET_SIZE {
-            self.prepare_rollover(bucket_idx)?;
+        if let Some(bucket_idx) = bucket_idx {
+            if self.buckets[bucket_idx].items.len() >= self.high_water_mark() {
+                self.prepare_rollover(bucket_idx)?;
+            }
         }
 
+        self.debug_assert_invariants();
         Ok(true)
     }
 
@@ -144,71 +204,122 @@ impl OnChainHashSet {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let bucket_idx = self.get_bucket_index(item);
-        let bucket = &mut self.buckets[bucket_idx];
+        let (home, alternate) = self.candidate_buckets(item);
+        let found = [home, alternate].into_iter().find_map(|bucket_idx| {
+            let pos = self.buckets[bucket_idx].items.iter().position(|x| x == item)?;
+            Some((bucket_idx, pos))
+        });
 
-        if let Some(pos) = bucket.items.iter().position(|x| x == item) {
+        if let Some((bucket_idx, pos)) = found {
+            let bucket = &mut self.buckets[bucket_idx];
             bucket.items.swap_remove(pos);
             bucket.last_modified = timestamp;
             bucket.operation_count += 1;
-            self.item_count -= 1;
-
-            // Log operation
-            self.log_operation(Operation {
-                op_type: OperationType::Remove,
-                item: *item,
-                timestamp,
-                bucket_index: bucket_idx,
-            });
-
-            Ok(true)
+        } else if let Some(pos) = self.rollover_buffer.items.iter().position(|x| x == item) {
+            self.rollover_buffer.items.swap_remove(pos);
+            self.rollover_buffer.is_active = !self.rollover_buffer.items.is_empty();
         } else {
-            Ok(false)
+            return Ok(false);
         }
+        self.item_count -= 1;
+
+        // Log operation
+        self.log_operation(
+            OperationType::Remove,
+            *item,
+            timestamp,
+            found.map_or(home, |(bucket_idx, _)| bucket_idx),
+        );
+
+        self.debug_assert_invariants();
+        Ok(true)
     }
 
     pub fn contains(&self, item: &[u8; 32]) -> bool {
-        let bucket_idx = self.get_bucket_index(item);
-        self.buckets[bucket_idx].items.contains(item)
+        let (home, alternate) = self.candidate_buckets(item);
+        self.buckets[home].items.contains(item)
+            || self.buckets[alternate].items.contains(item)
+            || self.rollover_buffer.items.contains(item)
     }
 
-    pub fn process_rollover(&mut self, timestamp: UnixTimestamp) -> Result<(), ProgramError> {
-        if !self.rollover_buffer.is_active {
-            return Ok(());
+    /// Inserts every item of `other` not already present and returns how
+    /// many were added. Fails without inserting anything if this set is
+    /// frozen or the union would exceed its capacity.
+    pub fn union_with(&mut self, other: &OnChainHashSet, timestamp: UnixTimestamp) -> Result<u32, ProgramError> {
+        if self.metadata.is_frozen {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let missing: Vec<[u8; 32]> = other.members().filter(|item| !self.contains(item)).copied().collect();
+        if self.item_count as usize + missing.len() > self.capacity {
+            return Err(ProgramError::InvalidArgument);
         }
 
-        // Create a temporary map for rehashing
-        let mut new_locations: HashMap<[u8; 32], usize> = HashMap::new();
+        for item in &missing {
+            self.insert(item, timestamp)?;
+        }
+        Ok(missing.len() as u32)
+    }
+
+    /// Items present in both sets, in this set's bucket order, then any
+    /// staged for rollover.
+    pub fn intersection(&self, other: &OnChainHashSet) -> Vec<[u8; 32]> {
+        self.members().filter(|item| other.contains(item)).copied().collect()
+    }
+
+    /// Drains the rollover buffer as far as it can. Items whose candidate
+    /// buckets are both at the high-water mark stay staged; see
+    /// `process_rollover_step`.
+    pub fn process_rollover(&mut self, timestamp: UnixTimestamp) -> Result<(), ProgramError> {
+        while self.process_rollover_step(timestamp, MAX_ROLLOVER_ITEMS)? > 0 {}
+        Ok(())
+    }
 
-        // Recalculate bucket indices for all items in rollover buffer
-        for item in &self.rollover_buffer.items {
-            let new_bucket_idx = self.get_bucket_index(item);
-            new_locations.insert(*item, new_bucket_idx);
+    /// One crank of staged rebalancing: moves up to `max_items` staged items
+    /// into whichever of their candidate buckets is emptier, as long as that
+    /// bucket is below the high-water mark. Returns how many were placed.
+    pub fn process_rollover_step(&mut self, timestamp: UnixTimestamp, max_items: usize) -> Result<usize, ProgramError> {
+        if !self.rollover_buffer.is_active {
+            return Ok(0);
         }
 
-        // Move items to their new buckets
-        for (item, new_bucket_idx) in new_locations {
-            let bucket = &mut self.buckets[new_bucket_idx];
+        let high_water = self.high_water_mark();
+        let mut placed = 0;
+        let mut pos = 0;
+        while placed < max_items && pos < self.rollover_buffer.items.len() {
+            let item = self.rollover_buffer.items[pos];
+            let (home, alternate) = self.candidate_buckets(&item);
+            let target = if self.buckets[alternate].items.len() < self.buckets[home].items.len() {
+                alternate
+            } else {
+                home
+            };
+            if self.buckets[target].items.len() >= high_water {
+                pos += 1;
+                continue;
+            }
+
+            self.rollover_buffer.items.swap_remove(pos);
+            let bucket = &mut self.buckets[target];
             bucket.items.push(item);
             bucket.last_modified = timestamp;
             bucket.operation_count += 1;
+            placed += 1;
         }
 
-        // Log rollover operation
-        self.log_operation(Operation {
-            op_type: OperationType::Rollover,
-            item: [0u8; 32],
-            timestamp,
-            bucket_index: 0,
-        });
+        if placed > 0 {
+            // Log rollover operation
+            self.log_operation(OperationType::Rollover, [0u8; 32], timestamp, 0);
+        }
 
-        // Clear rollover buffer
-        self.rollover_buffer.items.clear();
-        self.rollover_buffer.source_buckets.clear();
-        self.rollover_buffer.is_active = false;
-        self.metadata.rollover_count += 1;
+        if self.rollover_buffer.items.is_empty() {
+            self.rollover_buffer.source_buckets.clear();
+            self.rollover_buffer.is_active = false;
+            self.metadata.rollover_count += 1;
+        }
 
-        Ok(())
+        self.debug_assert_invariants();
+        Ok(placed)
     }
 
     pub fn checkpoint(&mut self, timestamp: UnixTimestamp) -> Result<(), ProgramError> {
@@ -218,12 +329,7 @@ impl OnChainHashSet {
         }
 
         // Log checkpoint operation
-        self.log_operation(Operation {
-            op_type: OperationType::Checkpoint,
-            item: [0u8; 32],
-            timestamp,
-            bucket_index: 0,
-        });
+        self.log_operation(OperationType::Checkpoint, [0u8; 32], timestamp, 0);
 
         // Update checkpoint
         self.operation_log.last_checkpoint = self.metadata.total_operations;
@@ -234,35 +340,114 @@ impl OnChainHashSet {
         Ok(())
     }
 
+    /// Stages up to half of a crowded bucket for rollover, skipping items
+    /// whose other candidate is this same bucket and stopping when the
+    /// buffer is full. Whatever doesn't fit stays put until a later insert.
     fn prepare_rollover(&mut self, bucket_idx: usize) -> Result<(), ProgramError> {
-        if self.rollover_buffer.is_active {
-            return Ok(());
+        let room = MAX_ROLLOVER_ITEMS - self.rollover_buffer.items.len();
+        let items_to_move = (self.buckets[bucket_idx].items.len() / 2).min(room);
+
+        let mut moved = 0;
+        let mut pos = 0;
+        while moved < items_to_move && pos < self.buckets[bucket_idx].items.len() {
+            let item = self.buckets[bucket_idx].items[pos];
+            let (home, alternate) = self.candidate_buckets(&item);
+            if home == alternate {
+                pos += 1;
+                continue;
+            }
+            self.buckets[bucket_idx].items.swap_remove(pos);
+            self.rollover_buffer.items.push(item);
+            moved += 1;
         }
 
-        let bucket = &mut self.buckets[bucket_idx];
-        
-        // Move half of the items to rollover buffer
-        let items_to_move = bucket.items.len() / 2;
-        let mut items: Vec<[u8; 32]> = bucket.items.drain(..items_to_move).collect();
-        
-        self.rollover_buffer.items.append(&mut items);
-        self.rollover_buffer.source_buckets.push(bucket_idx);
-        self.rollover_buffer.is_active = true;
+        if moved > 0 {
+            if !self.rollover_buffer.source_buckets.contains(&bucket_idx) {
+                self.rollover_buffer.source_buckets.push(bucket_idx);
+            }
+            self.rollover_buffer.is_active = true;
+        }
 
         Ok(())
     }
 
-    fn log_operation(&mut self, operation: Operation) {
-        self.operation_log.operations.push(operation);
+    /// Panics if the bookkeeping has drifted: `item_count` must equal the
+    /// bucketed items plus those parked in the rollover buffer, no item may
+    /// be stored twice, and the count must stay within capacity. Compiled
+    /// out of release builds.
+    #[cfg(debug_assertions)]
+    fn debug_assert_invariants(&self) {
+        let stored: usize = self.buckets.iter().map(|bucket| bucket.items.len()).sum();
+        let in_flight = self.rollover_buffer.items.len();
+        assert_eq!(
+            stored + in_flight,
+            self.item_count as usize,
+            "item_count out of sync: {} bucketed, {} in rollover",
+            stored,
+            in_flight,
+        );
+        assert!(
+            self.item_count as usize <= self.capacity,
+            "item_count {} exceeds capacity {}",
+            self.item_count,
+            self.capacity,
+        );
+        assert!(
+            self.buckets.iter().all(|bucket| bucket.items.len() <= BUCKET_SIZE),
+            "bucket overflowed BUCKET_SIZE",
+        );
+        assert!(in_flight <= MAX_ROLLOVER_ITEMS, "rollover buffer overflowed");
+
+        let mut seen = HashSet::with_capacity(stored + in_flight);
+        for item in self.members() {
+            assert!(seen.insert(item), "item {:?} stored twice", item);
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn debug_assert_invariants(&self) {}
+
+    fn log_operation(
+        &mut self,
+        op_type: OperationType,
+        item: [u8; 32],
+        timestamp: UnixTimestamp,
+        bucket_index: usize,
+    ) {
+        self.operation_log.operations.push(Operation {
+            op_type,
+            item,
+            timestamp,
+            bucket_index,
+            global_sequence: self.metadata.total_operations,
+        });
         self.metadata.total_operations += 1;
     }
 
+    fn items(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.buckets.iter().flat_map(|bucket| bucket.items.iter())
+    }
+
+    /// Bucketed items followed by those staged for rollover
+    fn members(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.items().chain(&self.rollover_buffer.items)
+    }
+
     fn get_bucket_index(&self, item: &[u8; 32]) -> usize {
         let mut hasher = DefaultHasher::new();
         item.hash(&mut hasher);
         (hasher.finish() as usize) % self.buckets.len()
     }
 
+    /// The item's home bucket and the alternate a rollover may move it to.
+    /// The two coincide for some items, which then never move.
+    fn candidate_buckets(&self, item: &[u8; 32]) -> (usize, usize) {
+        let mut hasher = DefaultHasher::new();
+        1u8.hash(&mut hasher);
+        item.hash(&mut hasher);
+        (self.get_bucket_index(item), (hasher.finish() as usize) % self.buckets.len())
+    }
+
     pub fn get_bucket_stats(&self) -> Vec<BucketStats> {
         self.buckets
             .iter()
@@ -276,6 +461,42 @@ impl OnChainHashSet {
             .collect()
     }
 
+    /// Number of buckets holding exactly N items, indexed by N. Buckets
+    /// grown past `BUCKET_SIZE` by a rollover count in the last slot.
+    pub fn occupancy_histogram(&self) -> [u32; BUCKET_SIZE + 1] {
+        let mut histogram = [0u32; BUCKET_SIZE + 1];
+        for bucket in &self.buckets {
+            histogram[bucket.items.len().min(BUCKET_SIZE)] += 1;
+        }
+        histogram
+    }
+
+    /// The `n` fullest buckets, fullest first; ties go to the lower index.
+    pub fn hottest_buckets(&self, n: usize) -> Vec<BucketStats> {
+        let mut stats = self.get_bucket_stats();
+        stats.sort_by(|a, b| {
+            b.item_count
+                .cmp(&a.item_count)
+                .then(a.bucket_index.cmp(&b.bucket_index))
+        });
+        stats.truncate(n);
+        stats
+    }
+
+    /// Largest bucket size over the mean bucket size: 1.0 for a perfectly
+    /// even spread (and for an empty set), growing as items cluster.
+    pub fn skew_metric(&self) -> f64 {
+        let stored: usize = self.buckets.iter().map(|b| b.items.len()).sum();
+        if stored == 0 {
+            return 1.0;
+        }
+        let max = self.buckets.iter().map(|b| b.items.len()).max().unwrap_or(0);
+        let mean = stored as f64 / self.buckets.len() as f64;
+        max as f64 / mean
+    }
+
+    /// Operations since the last checkpoint, oldest first. Each carries a
+    /// `global_sequence` that keeps counting across checkpoints.
     pub fn get_operation_history(&self) -> &[Operation] {
         &self.operation_log.operations
     }
@@ -380,6 +601,248 @@ mod tests {
         assert_eq!(set.operation_log.last_checkpoint, 2);
     }
 
+    #[test]
+    fn test_global_sequence_spans_checkpoints() {
+        let mut set = create_test_set();
+        let timestamp = 1000;
+        let sequences = |set: &OnChainHashSet| {
+            set.get_operation_history()
+                .iter()
+                .map(Operation::global_sequence)
+                .collect::<Vec<_>>()
+        };
+
+        set.insert(&[1u8; 32], timestamp).unwrap();
+        set.insert(&[2u8; 32], timestamp).unwrap();
+        set.remove(&[1u8; 32], timestamp).unwrap();
+        let mut seen = sequences(&set);
+
+        // The checkpoint's own entry is cleared with the rest of the log;
+        // it took the sequence just below `last_checkpoint`
+        set.checkpoint(timestamp).unwrap();
+        seen.push(set.operation_log.last_checkpoint - 1);
+
+        set.insert(&[3u8; 32], timestamp).unwrap();
+        set.remove(&[2u8; 32], timestamp).unwrap();
+        seen.extend(sequences(&set));
+
+        assert_eq!(seen, (0..set.metadata.total_operations).collect::<Vec<_>>());
+    }
+
+    /// Items whose bucket index is `bucket`, found by probing the set's hasher
+    fn items_in_bucket(set: &OnChainHashSet, bucket: usize, count: usize) -> Vec<[u8; 32]> {
+        (0u32..)
+            .map(|i| {
+                let mut item = [0u8; 32];
+                item[..4].copy_from_slice(&i.to_le_bytes());
+                item
+            })
+            .filter(|item| set.get_bucket_index(item) == bucket)
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn test_skewed_distribution_metrics() {
+        let mut set = create_test_set();
+        let timestamp = 1000;
+        assert_eq!(set.buckets.len(), 4);
+        assert_eq!(set.skew_metric(), 1.0);
+
+        // Cluster ten items into bucket 2, below the rollover threshold
+        for item in items_in_bucket(&set, 2, 10) {
+            set.insert(&item, timestamp).unwrap();
+        }
+
+        let histogram = set.occupancy_histogram();
+        assert_eq!(histogram[0], 3);
+        assert_eq!(histogram[10], 1);
+        assert_eq!(histogram.iter().sum::<u32>(), 4);
+
+        let hottest = set.hottest_buckets(2);
+        assert_eq!(hottest.len(), 2);
+        assert_eq!(hottest[0].bucket_index, 2);
+        assert_eq!(hottest[0].item_count, 10);
+        assert_eq!(hottest[1].item_count, 0);
+
+        // max 10 over a mean of 2.5
+        assert_eq!(set.skew_metric(), 4.0);
+    }
+
+    #[test]
+    fn test_even_distribution_metrics() {
+        let mut set = create_test_set();
+        let timestamp = 1000;
+
+        for bucket in 0..set.buckets.len() {
+            for item in items_in_bucket(&set, bucket, 3) {
+                set.insert(&item, timestamp).unwrap();
+            }
+        }
+
+        assert_eq!(set.occupancy_histogram()[3], 4);
+        assert_eq!(set.skew_metric(), 1.0);
+        assert_eq!(set.hottest_buckets(10).len(), 4);
+    }
+
+    #[test]
+    fn test_union_and_intersection() {
+        let timestamp = 1000;
+        let mut a = create_test_set();
+        let mut b = create_test_set();
+        for i in 0..40u8 {
+            a.insert(&[i; 32], timestamp).unwrap();
+        }
+        for i in 30..60u8 {
+            b.insert(&[i; 32], timestamp).unwrap();
+        }
+
+        let mut common = a.intersection(&b);
+        common.sort();
+        assert_eq!(common, (30..40u8).map(|i| [i; 32]).collect::<Vec<_>>());
+        assert_eq!(b.intersection(&a).len(), 10);
+
+        assert_eq!(a.union_with(&b, timestamp).unwrap(), 20);
+        assert_eq!(a.item_count, 60);
+        assert!((0..60u8).all(|i| a.contains(&[i; 32])));
+        assert_eq!(a.union_with(&b, timestamp).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_union_respects_capacity_and_frozen() {
+        let timestamp = 1000;
+        let mut small = OnChainHashSet::new(Some(32), Pubkey::new_unique());
+        let mut other = create_test_set();
+        for i in 0..20u8 {
+            small.insert(&[i; 32], timestamp).unwrap();
+        }
+        for i in 10..40u8 {
+            other.insert(&[i; 32], timestamp).unwrap();
+        }
+
+        // 20 + 20 new items would exceed 32, so nothing is inserted
+        assert!(small.union_with(&other, timestamp).is_err());
+        assert_eq!(small.item_count, 20);
+        assert!(!small.contains(&[39u8; 32]));
+
+        let mut frozen = create_test_set();
+        frozen.metadata.is_frozen = true;
+        assert!(frozen.union_with(&other, timestamp).is_err());
+        // Reading is still allowed
+        assert_eq!(frozen.intersection(&other).len(), 0);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "item_count out of sync")]
+    fn test_invariants_catch_count_desync() {
+        let mut set = create_test_set();
+        let timestamp = 1000;
+        set.insert(&[1u8; 32], timestamp).unwrap();
+
+        // A count bump with no matching bucket entry, as a lost item would leave
+        set.item_count += 1;
+        set.insert(&[2u8; 32], timestamp).unwrap();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "stored twice")]
+    fn test_invariants_catch_duplicate_items() {
+        let mut set = create_test_set();
+        let timestamp = 1000;
+        let item = [1u8; 32];
+        set.insert(&item, timestamp).unwrap();
+
+        // The same item parked in the rollover buffer and still bucketed
+        set.rollover_buffer.items.push(item);
+        set.rollover_buffer.is_active = true;
+        set.item_count += 1;
+        set.process_rollover(timestamp).unwrap();
+    }
+
+    fn assert_bounded(set: &OnChainHashSet) {
+        assert!(set.buckets.iter().all(|bucket| bucket.items.len() <= BUCKET_SIZE));
+        assert!(set.rollover_buffer.items.len() <= MAX_ROLLOVER_ITEMS);
+    }
+
+    #[test]
+    fn test_clustered_inserts_stay_bounded() {
+        let mut set = OnChainHashSet::new(Some(256), Pubkey::new_unique());
+        let timestamp = 1000;
+        let high_water = set.high_water_mark();
+        assert_eq!(high_water, BUCKET_SIZE * 3 / 4);
+
+        // Every key hashes home to bucket 2; a crank runs every ten inserts
+        let keys = items_in_bucket(&set, 2, 150);
+        for (i, key) in keys.iter().enumerate() {
+            assert!(set.insert(key, timestamp).unwrap());
+            assert_bounded(&set);
+            if i % 10 == 9 && set.needs_rollover() {
+                assert!(set.process_rollover_step(timestamp, 8).unwrap() <= 8);
+                assert_bounded(&set);
+            }
+        }
+
+        set.process_rollover(timestamp).unwrap();
+        assert_bounded(&set);
+        assert_eq!(set.item_count, 150);
+        assert!(keys.iter().all(|key| set.contains(key)));
+        // Rebalanced items landed in other buckets, below the mark
+        assert!(set.buckets[2].items.len() <= BUCKET_SIZE);
+        assert!(set
+            .buckets
+            .iter()
+            .enumerate()
+            .all(|(idx, bucket)| idx == 2 || bucket.items.len() <= high_water));
+        assert!(set.metadata.rollover_count > 0);
+
+        // Staged and bucketed items remove alike
+        for key in &keys {
+            assert!(set.remove(key, timestamp).unwrap());
+        }
+        assert_eq!(set.item_count, 0);
+        assert!(!set.needs_rollover());
+    }
+
+    #[test]
+    fn test_rollover_buffer_full_is_reported() {
+        let mut set = OnChainHashSet::new(Some(512), Pubkey::new_unique());
+        let timestamp = 1000;
+
+        // Keys whose home and alternate are both bucket 3 can never move
+        let stuck: Vec<[u8; 32]> = (0u32..)
+            .map(|i| {
+                let mut item = [0u8; 32];
+                item[..4].copy_from_slice(&i.to_le_bytes());
+                item
+            })
+            .filter(|item| set.candidate_buckets(item) == (3, 3))
+            .take(BUCKET_SIZE + MAX_ROLLOVER_ITEMS + 1)
+            .collect();
+
+        for key in &stuck[..BUCKET_SIZE + MAX_ROLLOVER_ITEMS] {
+            assert!(set.insert(key, timestamp).unwrap());
+        }
+        assert!(set.needs_rollover());
+        assert_eq!(
+            set.insert(stuck.last().unwrap(), timestamp),
+            Err(HashSetError::RolloverBufferFull.into())
+        );
+        assert_eq!(set.item_count as usize, BUCKET_SIZE + MAX_ROLLOVER_ITEMS);
+        assert!(!set.contains(stuck.last().unwrap()));
+        assert_bounded(&set);
+
+        // Nowhere to drain to; the buffer keeps its items
+        assert_eq!(set.process_rollover_step(timestamp, MAX_ROLLOVER_ITEMS).unwrap(), 0);
+        assert!(stuck[..BUCKET_SIZE + MAX_ROLLOVER_ITEMS].iter().all(|key| set.contains(key)));
+
+        assert!(set.set_rollover_high_water(0).is_err());
+        assert!(set.set_rollover_high_water(101).is_err());
+        set.set_rollover_high_water(50).unwrap();
+        assert_eq!(set.high_water_mark(), BUCKET_SIZE / 2);
+    }
+
     #[test]
     fn test_frozen_state() {
         let mut set = create_test_set();
@@ -396,4 +859,12 @@ mod tests {
         // Contains should still work
         assert!(!set.contains(&item));
     }
+
+    #[test]
+    fn test_send_sync_bounds() {
+        // Off-chain indexers share a set across threads behind a lock
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<OnChainHashSet>();
+        assert_send_sync::<BucketStats>();
+    }
 } 
\ No newline at end of file
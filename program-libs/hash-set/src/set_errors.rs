@@ -1,5 +1,5 @@
 use thiserror::Error;
-use solana_program::program_error::ProgramError;
+use crate::types::ProgramError;
 
 #[derive(Error, Debug, Copy, Clone)]
 pub enum HashSetError {
@@ -9,6 +9,8 @@ pub enum HashSetError {
     SetFull,
     #[error("Operation Not Allowed")]
     OperationNotAllowed,
+    #[error("Rollover Buffer is Full")]
+    RolloverBufferFull,
 }
 
 impl From<HashSetError> for ProgramError {
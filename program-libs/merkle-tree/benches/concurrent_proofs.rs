@@ -0,0 +1,55 @@
+#![feature(test)]
+#![cfg(feature = "offchain")]
+extern crate test;
+
+use {
+    merkle_tree::{types::Pubkey, ConcurrentTreeReader, MerkleTree, TreeWriter},
+    std::thread,
+    test::Bencher,
+};
+
+const DEPTH: usize = 16;
+const LEAVES: u64 = 4096;
+const PROOFS: u64 = 8192;
+const THREADS: u64 = 8;
+
+fn setup_writer() -> TreeWriter {
+    let mut writer = TreeWriter::new(MerkleTree::new(DEPTH, Pubkey::new_unique(), 1000, true));
+    for i in 0..LEAVES {
+        let mut leaf = [0u8; 32];
+        leaf[..8].copy_from_slice(&i.to_le_bytes());
+        writer.insert(&leaf).unwrap();
+    }
+    writer
+}
+
+fn prove_range(reader: &ConcurrentTreeReader, start: u64, count: u64) {
+    for i in start..start + count {
+        test::black_box(reader.get_proof(i % LEAVES).unwrap());
+    }
+}
+
+// Baseline: every proof generated on one thread
+#[bench]
+fn bench_proofs_single_thread(b: &mut Bencher) {
+    let writer = setup_writer();
+    let reader = writer.reader();
+
+    b.iter(|| prove_range(&reader, 0, PROOFS));
+}
+
+// The same proofs split across THREADS readers sharing the tree
+#[bench]
+fn bench_proofs_eight_threads(b: &mut Bencher) {
+    let writer = setup_writer();
+    let per_thread = PROOFS / THREADS;
+
+    b.iter(|| {
+        thread::scope(|scope| {
+            for t in 0..THREADS {
+                let reader = writer.reader();
+                scope.spawn(move || prove_range(&reader, t * per_thread, per_thread));
+            }
+        });
+    });
+}
\ No newline at end of file
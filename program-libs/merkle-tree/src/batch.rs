@@ -1,10 +1,6 @@
 use {
     borsh::{BorshDeserialize, BorshSerialize},
-    solana_program::{
-        program_error::ProgramError,
-        pubkey::Pubkey,
-        clock::UnixTimestamp,
-    },
+    crate::types::{ProgramError, Pubkey, UnixTimestamp},
 };
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
@@ -0,0 +1,191 @@
+//! Sharing one `MerkleTree` across threads, for off-chain provers that
+//! generate witnesses in parallel.
+//!
+//! `MerkleTree` owns all of its data, so it is `Send + Sync` and can sit
+//! behind an `RwLock`. A single [`TreeWriter`] holds the only mutable
+//! handle; any number of cloned [`ConcurrentTreeReader`]s read alongside
+//! it. After each write the writer publishes a small [`TreeHead`] (root and
+//! leaf count), so reading the root never waits on the tree lock. Proofs
+//! take a read lock only for the O(depth) sibling walk, and come back with
+//! the head they are valid against.
+
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    types::{ProgramError, Pubkey},
+    verify_merkle_proof, BatchType, HashKind, MerkleTree,
+};
+
+/// The root and leaf count of one state of the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeHead {
+    pub root: [u8; 32],
+    pub leaf_count: u64,
+}
+
+impl TreeHead {
+    fn of(tree: &MerkleTree) -> Self {
+        Self {
+            root: tree.root,
+            leaf_count: tree.leaf_count,
+        }
+    }
+}
+
+struct Shared {
+    tree: RwLock<MerkleTree>,
+    head: RwLock<TreeHead>,
+    depth: usize,
+}
+
+/// The one handle that can modify a shared tree. It is deliberately not
+/// `Clone`: appends stay serialized without readers contending for a
+/// writer slot.
+pub struct TreeWriter {
+    shared: Arc<Shared>,
+}
+
+/// A cheap, cloneable read handle on a tree owned by a [`TreeWriter`]
+#[derive(Clone)]
+pub struct ConcurrentTreeReader {
+    shared: Arc<Shared>,
+}
+
+impl TreeWriter {
+    pub fn new(tree: MerkleTree) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                head: RwLock::new(TreeHead::of(&tree)),
+                depth: tree.depth,
+                tree: RwLock::new(tree),
+            }),
+        }
+    }
+
+    pub fn reader(&self) -> ConcurrentTreeReader {
+        ConcurrentTreeReader {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    pub fn insert(&mut self, leaf: &[u8; 32]) -> Result<u64, ProgramError> {
+        self.write(|tree| tree.insert(leaf))
+    }
+
+    pub fn create_batch(
+        &mut self,
+        leaves: Vec<[u8; 32]>,
+        processor: Pubkey,
+        batch_type: BatchType,
+    ) -> Result<u64, ProgramError> {
+        self.write(|tree| tree.create_batch(leaves, processor, batch_type))
+    }
+
+    pub fn process_next_batch(&mut self) -> Result<Option<u64>, ProgramError> {
+        self.write(|tree| tree.process_next_batch())
+    }
+
+    /// Runs `f` under the tree's write lock, then publishes the new head.
+    /// Readers never see a head whose nodes are not yet written.
+    fn write<T>(&mut self, f: impl FnOnce(&mut MerkleTree) -> T) -> T {
+        let mut tree = self.shared.tree.write().expect("merkle tree lock poisoned");
+        let result = f(&mut tree);
+        *self.shared.head.write().expect("tree head lock poisoned") = TreeHead::of(&tree);
+        result
+    }
+}
+
+impl ConcurrentTreeReader {
+    /// The latest published head, without touching the tree lock
+    pub fn head(&self) -> TreeHead {
+        *self.shared.head.read().expect("tree head lock poisoned")
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.head().root
+    }
+
+    /// The sibling path of leaf `index`, with the head it proves against.
+    /// A write may land right after, so check the proof against the
+    /// returned head rather than a later `root()`.
+    pub fn get_proof(&self, index: u64) -> Result<(TreeHead, Vec<[u8; 32]>), ProgramError> {
+        let tree = self.shared.tree.read().expect("merkle tree lock poisoned");
+        Ok((TreeHead::of(&tree), tree.get_proof(index)?))
+    }
+
+    /// Checks `proof` against the latest published root
+    pub fn verify(&self, leaf: &[u8; 32], proof: &[[u8; 32]], index: u64) -> bool {
+        proof.len() == self.shared.depth
+            && verify_merkle_proof(&self.root(), leaf, index, proof, HashKind::Sha256)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync_bounds() {
+        assert_send_sync::<MerkleTree>();
+        assert_send_sync::<TreeWriter>();
+        assert_send_sync::<ConcurrentTreeReader>();
+    }
+
+    #[test]
+    fn test_readers_follow_writer() {
+        let mut writer = TreeWriter::new(MerkleTree::new(4, Pubkey::new_unique(), 1000, true));
+        let reader = writer.reader();
+        assert_eq!(reader.head(), TreeHead { root: [0u8; 32], leaf_count: 0 });
+
+        writer.insert(&[1u8; 32]).unwrap();
+        writer.create_batch(vec![[2u8; 32], [3u8; 32]], Pubkey::new_unique(), BatchType::Standard).unwrap();
+        // A pending batch does not move the head
+        assert_eq!(reader.head().leaf_count, 1);
+        writer.process_next_batch().unwrap();
+
+        let (head, proof) = reader.get_proof(2).unwrap();
+        assert_eq!(head, reader.head());
+        assert_eq!(head.leaf_count, 3);
+        assert!(reader.verify(&[3u8; 32], &proof, 2));
+        assert!(!reader.verify(&[3u8; 32], &proof, 1));
+        assert!(reader.get_proof(3).is_err());
+    }
+
+    #[test]
+    fn test_parallel_proofs_during_appends() {
+        let mut writer = TreeWriter::new(MerkleTree::new(8, Pubkey::new_unique(), 1000, true));
+        for i in 0..64u8 {
+            writer.insert(&[i; 32]).unwrap();
+        }
+
+        let readers: Vec<_> = (0..8)
+            .map(|t| {
+                let reader = writer.reader();
+                thread::spawn(move || {
+                    for round in 0..200u64 {
+                        let index = (t * 31 + round * 7) % 64;
+                        let (head, proof) = reader.get_proof(index).unwrap();
+                        assert!(verify_merkle_proof(
+                            &head.root,
+                            &[index as u8; 32],
+                            index,
+                            &proof,
+                            HashKind::Sha256,
+                        ));
+                    }
+                })
+            })
+            .collect();
+
+        for i in 64..128u8 {
+            writer.insert(&[i; 32]).unwrap();
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(writer.reader().head().leaf_count, 128);
+    }
+}
\ No newline at end of file
@@ -1,7 +1,7 @@
 use thiserror::Error;
-use solana_program::program_error::ProgramError;
+use crate::types::ProgramError;
 
-#[derive(Error, Debug, Copy, Clone)]
+#[derive(Error, Debug, Copy, Clone, PartialEq)]
 pub enum MerkleTreeError {
     #[error("Invalid Merkle Tree Depth")]
     InvalidDepth,
@@ -11,6 +11,10 @@ pub enum MerkleTreeError {
     InvalidProof,
     #[error("Batch Processing Error")]
     BatchProcessingError,
+    #[error("Node Index Out Of Bounds")]
+    NodeIndexOutOfBounds,
+    #[error("Leaf Already In Tree")]
+    DuplicateLeaf,
 }
 
 impl From<MerkleTreeError> for ProgramError {
@@ -1,22 +1,29 @@
 use {
     borsh::{BorshDeserialize, BorshSerialize},
     sha2::{Digest, Sha256},
-    solana_program::{
-        program_error::ProgramError,
-        pubkey::Pubkey,
-        clock::UnixTimestamp,
-    },
+    crate::types::{ProgramError, Pubkey, UnixTimestamp},
     std::{
+        cmp::Reverse,
         collections::{VecDeque, HashMap},
-        sync::Arc,
     },
 };
 
+#[cfg(feature = "offchain")]
+mod concurrent;
+pub mod errors;
+mod proof;
+pub mod types;
+
+#[cfg(feature = "offchain")]
+pub use concurrent::{ConcurrentTreeReader, TreeHead, TreeWriter};
+pub use errors::MerkleTreeError;
+pub use proof::{verify_merkle_proof, verify_merkle_proof_bounded, HashKind};
+
 pub const MAX_TREE_DEPTH: usize = 32;
 pub const EMPTY_SLICE: [u8; 32] = [0u8; 32];
 pub const MAX_BATCH_SIZE: usize = 1024;
 
-#[derive(Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct TreeMetadata {
     pub creation_time: UnixTimestamp,
     pub last_modified: UnixTimestamp,
@@ -25,6 +32,38 @@ pub struct TreeMetadata {
     pub max_leaf_size: u32,
     pub compression_enabled: bool,
     pub version: u8,
+    /// Value of an unset node; `EMPTY_SLICE` unless a circuit needs a
+    /// different sentinel
+    pub empty_leaf: [u8; 32],
+    pub fill_strategy: FillStrategy,
+    /// Makes `insert` and batch processing fail with
+    /// `MerkleTreeError::DuplicateLeaf` for a leaf already in the tree
+    pub reject_duplicates: bool,
+}
+
+/// Order in which `insert` hands out leaf slots. Either way the n-th
+/// insertion lands at `slot_for(n)` and slot `i` holds the
+/// `slot_for(i)`-th insertion, since both orders are their own inverse.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum FillStrategy {
+    /// Leaf 0, 1, 2, ... from left to right
+    #[default]
+    Sequential,
+    /// Alternates between subtrees at every level: the insertion count with
+    /// its `depth` bits reversed, so the first `2^k` leaves put one leaf
+    /// under each of the `2^k` subtrees at level `k`
+    SubtreeFirst,
+}
+
+impl FillStrategy {
+    /// Leaf slot of the `position`-th insertion into a tree of `depth`
+    pub fn slot_for(self, position: u64, depth: usize) -> u64 {
+        match self {
+            FillStrategy::Sequential => position,
+            FillStrategy::SubtreeFirst if depth == 0 => position,
+            FillStrategy::SubtreeFirst => position.reverse_bits() >> (64 - depth),
+        }
+    }
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
@@ -33,6 +72,19 @@ pub struct BatchOperation {
     pub leaves: Vec<[u8; 32]>,
     pub metadata: BatchMetadata,
     pub status: BatchStatus,
+    /// What the batch did to the tree; set once it completes
+    pub summary: Option<BatchSummary>,
+}
+
+/// Where a completed batch's leaves landed and the root it left behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct BatchSummary {
+    pub sequence_number: u64,
+    /// Insertion position of the batch's first leaf; its slot is
+    /// `FillStrategy::slot_for` of this
+    pub start_leaf_index: u64,
+    pub leaf_count: u32,
+    pub root_after: [u8; 32],
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
@@ -58,6 +110,76 @@ pub enum BatchStatus {
     Failed,
 }
 
+/// Portable copy of a tree's leaves and metadata. Pending batches are not
+/// included; process or drop them before exporting.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct TreeSnapshot {
+    pub root: [u8; 32],
+    pub depth: u32,
+    pub leaf_count: u64,
+    /// In insertion order, which is slot order only for `Sequential` trees
+    pub leaves: Vec<[u8; 32]>,
+    pub metadata: TreeMetadata,
+}
+
+impl TreeSnapshot {
+    /// SHA-256 of the snapshot's Borsh encoding, for committing to a
+    /// snapshot on-chain before it is needed
+    pub fn snapshot_hash(&self) -> [u8; 32] {
+        let bytes = self.try_to_vec().expect("serializing to a Vec cannot fail");
+        Sha256::digest(bytes).into()
+    }
+}
+
+#[cfg(feature = "offchain")]
+impl TreeSnapshot {
+    /// Writes the snapshot's Borsh encoding to `path`
+    pub fn write_to_file(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.try_to_vec()?)
+    }
+
+    /// Reads a snapshot written by `write_to_file`. The contents are not
+    /// checked; `MerkleTree::from_snapshot` does that.
+    pub fn read_from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Self::try_from_slice(&std::fs::read(path)?)
+    }
+}
+
+/// Where a leaf entered the tree: the batch that inserted it and its
+/// position within that batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct LeafOrigin {
+    pub sequence_number: u64,
+    pub position_in_batch: u32,
+}
+
+/// Leaf-to-batch index kept off-chain; it is never part of the tree's
+/// account data but can be serialized on its own
+#[derive(Debug, Default, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct LeafProvenance {
+    origins: HashMap<[u8; 32], LeafOrigin>,
+}
+
+impl LeafProvenance {
+    /// Records `origin` for `leaf`, keeping the first origin seen for a
+    /// repeated leaf value
+    pub fn record(&mut self, leaf: [u8; 32], origin: LeafOrigin) {
+        self.origins.entry(leaf).or_insert(origin);
+    }
+
+    pub fn get(&self, leaf: &[u8; 32]) -> Option<LeafOrigin> {
+        self.origins.get(leaf).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.origins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+}
+
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct MerkleTree {
     pub root: [u8; 32],
@@ -67,6 +189,9 @@ pub struct MerkleTree {
     metadata: TreeMetadata,
     pending_batches: VecDeque<BatchOperation>,
     processed_batches: HashMap<u64, BatchOperation>,
+    #[cfg(feature = "offchain")]
+    #[borsh_skip]
+    leaf_provenance: Option<LeafProvenance>,
 }
 
 impl MerkleTree {
@@ -75,6 +200,18 @@ impl MerkleTree {
         authority: Pubkey,
         max_leaf_size: u32,
         compression_enabled: bool,
+    ) -> Self {
+        Self::new_with_empty_leaf(depth, authority, max_leaf_size, compression_enabled, EMPTY_SLICE)
+    }
+
+    /// Like `new`, but every node of the empty tree (and so its root) starts
+    /// as `empty_leaf` instead of all zeros.
+    pub fn new_with_empty_leaf(
+        depth: usize,
+        authority: Pubkey,
+        max_leaf_size: u32,
+        compression_enabled: bool,
+        empty_leaf: [u8; 32],
     ) -> Self {
         assert!(depth <= MAX_TREE_DEPTH, "Tree depth exceeds maximum");
         let capacity = (1 << (depth + 1)) - 1;
@@ -87,19 +224,111 @@ impl MerkleTree {
             max_leaf_size,
             compression_enabled,
             version: 1,
+            empty_leaf,
+            fill_strategy: FillStrategy::Sequential,
+            reject_duplicates: false,
         };
         
         Self {
-            root: EMPTY_SLICE,
+            root: empty_leaf,
             leaf_count: 0,
-            nodes: vec![EMPTY_SLICE; capacity],
+            nodes: vec![empty_leaf; capacity],
             depth,
             metadata,
             pending_batches: VecDeque::new(),
             processed_batches: HashMap::new(),
+            #[cfg(feature = "offchain")]
+            leaf_provenance: None,
         }
     }
 
+    /// Chooses where later insertions land. Only allowed on an empty tree,
+    /// since existing leaves were placed under the old order.
+    pub fn set_fill_strategy(&mut self, fill_strategy: FillStrategy) -> Result<(), ProgramError> {
+        if self.leaf_count > 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.metadata.fill_strategy = fill_strategy;
+        Ok(())
+    }
+
+    pub fn fill_strategy(&self) -> FillStrategy {
+        self.metadata.fill_strategy
+    }
+
+    /// Leaves the tree holds when full
+    pub fn capacity(&self) -> u64 {
+        1 << self.depth
+    }
+
+    /// Leaves that can still be inserted. Pending batches count against it
+    /// only once processed.
+    pub fn remaining_capacity(&self) -> u64 {
+        self.capacity() - self.leaf_count
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.remaining_capacity() == 0
+    }
+
+    /// Turns duplicate-leaf rejection on or off. Leaves already in the tree
+    /// are kept either way.
+    pub fn set_reject_duplicates(&mut self, reject_duplicates: bool) {
+        self.metadata.reject_duplicates = reject_duplicates;
+    }
+
+    pub fn reject_duplicates(&self) -> bool {
+        self.metadata.reject_duplicates
+    }
+
+    /// Whether `leaf` occupies one of the inserted slots. There is no
+    /// on-chain value index, so this reads every leaf.
+    pub fn contains_leaf(&self, leaf: &[u8; 32]) -> bool {
+        (0..self.leaf_count).any(|position| {
+            let slot = self.metadata.fill_strategy.slot_for(position, self.depth);
+            self.nodes.get(self.get_leaf_node_index(slot as usize)) == Some(leaf)
+        })
+    }
+
+    /// Fails if any of `leaves` is already in the tree or repeats earlier
+    /// in `leaves`
+    fn check_no_duplicates(&self, leaves: &[[u8; 32]]) -> Result<(), MerkleTreeError> {
+        for (i, leaf) in leaves.iter().enumerate() {
+            if leaves[..i].contains(leaf) || self.contains_leaf(leaf) {
+                return Err(MerkleTreeError::DuplicateLeaf);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts recording which batch inserted each leaf. Off by default;
+    /// leaves inserted before enabling, or outside a batch, have no origin.
+    #[cfg(feature = "offchain")]
+    pub fn enable_leaf_provenance(&mut self) {
+        self.leaf_provenance.get_or_insert_with(LeafProvenance::default);
+    }
+
+    #[cfg(feature = "offchain")]
+    pub fn leaf_provenance_enabled(&self) -> bool {
+        self.leaf_provenance.is_some()
+    }
+
+    #[cfg(feature = "offchain")]
+    pub fn leaf_provenance(&self) -> Option<&LeafProvenance> {
+        self.leaf_provenance.as_ref()
+    }
+
+    /// Reattaches a provenance index saved alongside a deserialized tree
+    #[cfg(feature = "offchain")]
+    pub fn restore_leaf_provenance(&mut self, provenance: LeafProvenance) {
+        self.leaf_provenance = Some(provenance);
+    }
+
+    #[cfg(feature = "offchain")]
+    pub fn find_leaf_origin(&self, leaf: &[u8; 32]) -> Option<LeafOrigin> {
+        self.leaf_provenance.as_ref()?.get(leaf)
+    }
+
     pub fn create_batch(
         &mut self,
         leaves: Vec<[u8; 32]>,
@@ -109,6 +338,11 @@ impl MerkleTree {
         if leaves.len() > MAX_BATCH_SIZE {
             return Err(ProgramError::InvalidArgument);
         }
+        // Batches already queued will take their share of the space first
+        let pending_leaves: u64 = self.pending_batches.iter().map(|batch| batch.leaves.len() as u64).sum();
+        if leaves.len() as u64 + pending_leaves > self.remaining_capacity() {
+            return Err(MerkleTreeError::TreeFull.into());
+        }
 
         let sequence_number = self.get_next_sequence_number();
         let batch = BatchOperation {
@@ -125,20 +359,60 @@ impl MerkleTree {
                 batch_type,
             },
             status: BatchStatus::Pending,
+            summary: None,
         };
 
         self.pending_batches.push_back(batch);
         Ok(sequence_number)
     }
 
+    /// Position in `pending_batches` of the batch to process next: highest
+    /// priority first, then lowest sequence number. Sequence numbers are
+    /// unique, so the choice never depends on queue order.
+    fn next_batch_index(&self) -> Option<usize> {
+        self.pending_batches
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, batch)| (Reverse(batch.metadata.priority), batch.sequence_number))
+            .map(|(index, _)| index)
+    }
+
     pub fn process_next_batch(&mut self) -> Result<Option<u64>, ProgramError> {
-        if let Some(mut batch) = self.pending_batches.pop_front() {
+        let next = self.next_batch_index().and_then(|index| self.pending_batches.remove(index));
+        if let Some(mut batch) = next {
             batch.status = BatchStatus::Processing;
+            let start_leaf_index = self.leaf_count;
+
+            // Checked up front so a rejected batch inserts nothing
+            if self.metadata.reject_duplicates {
+                if let Err(e) = self.check_no_duplicates(&batch.leaves) {
+                    batch.status = BatchStatus::Failed;
+                    self.processed_batches.insert(batch.sequence_number, batch);
+                    return Err(e.into());
+                }
+            }
             
             for leaf in &batch.leaves {
                 self.insert(leaf)?;
             }
 
+            batch.summary = Some(BatchSummary {
+                sequence_number: batch.sequence_number,
+                start_leaf_index,
+                leaf_count: batch.leaves.len() as u32,
+                root_after: self.root,
+            });
+
+            #[cfg(feature = "offchain")]
+            if let Some(provenance) = self.leaf_provenance.as_mut() {
+                for (position, leaf) in batch.leaves.iter().enumerate() {
+                    provenance.record(*leaf, LeafOrigin {
+                        sequence_number: batch.sequence_number,
+                        position_in_batch: position as u32,
+                    });
+                }
+            }
+
             batch.status = BatchStatus::Completed;
             let sequence_number = batch.sequence_number;
             self.processed_batches.insert(sequence_number, batch);
@@ -150,20 +424,23 @@ impl MerkleTree {
     }
 
     pub fn insert(&mut self, leaf: &[u8; 32]) -> Result<u64, ProgramError> {
-        if self.leaf_count as usize >= 1 << self.depth {
+        if self.is_full() {
             return Err(ProgramError::InvalidArgument);
         }
+        if self.metadata.reject_duplicates && self.contains_leaf(leaf) {
+            return Err(MerkleTreeError::DuplicateLeaf.into());
+        }
 
-        let leaf_index = self.leaf_count as usize;
-        let node_index = self.get_leaf_node_index(leaf_index);
+        let leaf_index = self.metadata.fill_strategy.slot_for(self.leaf_count, self.depth);
+        let node_index = self.get_leaf_node_index(leaf_index as usize);
         
-        self.nodes[node_index] = *leaf;
-        self.update_path_to_root(node_index);
+        *self.nodes.get_mut(node_index).ok_or(MerkleTreeError::NodeIndexOutOfBounds)? = *leaf;
+        self.update_path_to_root(node_index)?;
         
         self.leaf_count += 1;
         self.metadata.last_modified = 0; // Should be set from blockchain
         
-        Ok(self.leaf_count - 1)
+        Ok(leaf_index)
     }
 
     pub fn verify(&self, leaf: &[u8; 32], proof: &[[u8; 32]], index: u64) -> bool {
@@ -171,19 +448,7 @@ impl MerkleTree {
             return false;
         }
 
-        let mut current_hash = *leaf;
-        let mut current_index = self.get_leaf_node_index(index as usize);
-
-        for sibling in proof {
-            current_hash = if current_index % 2 == 0 {
-                hash_pair(&current_hash, sibling)
-            } else {
-                hash_pair(sibling, &current_hash)
-            };
-            current_index = (current_index - 1) / 2;
-        }
-
-        current_hash == self.root
+        verify_merkle_proof(&self.root, leaf, index, proof, HashKind::Sha256)
     }
 
     pub fn get_batch_status(&self, sequence_number: u64) -> Option<BatchStatus> {
@@ -197,6 +462,68 @@ impl MerkleTree {
         }
     }
 
+    pub fn export_snapshot(&self) -> TreeSnapshot {
+        let leaves = (0..self.leaf_count)
+            .map(|position| {
+                let slot = self.metadata.fill_strategy.slot_for(position, self.depth);
+                self.nodes[self.get_leaf_node_index(slot as usize)]
+            })
+            .collect();
+        TreeSnapshot {
+            root: self.root,
+            depth: self.depth as u32,
+            leaf_count: self.leaf_count,
+            leaves,
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    /// Rebuilds the tree `snapshot` was exported from, authority included.
+    /// See `import_snapshot`.
+    pub fn from_snapshot(snapshot: TreeSnapshot) -> Result<Self, ProgramError> {
+        let authority = snapshot.metadata.authority;
+        Self::import_snapshot(snapshot, authority)
+    }
+
+    /// Rebuilds a tree from `snapshot` under a new `authority`, recomputing
+    /// every internal node. Fails if the result doesn't reproduce the
+    /// snapshot's root, so a corrupted snapshot is never accepted.
+    pub fn import_snapshot(snapshot: TreeSnapshot, authority: Pubkey) -> Result<Self, ProgramError> {
+        let depth = snapshot.depth as usize;
+        if depth > MAX_TREE_DEPTH
+            || snapshot.leaves.len() as u64 != snapshot.leaf_count
+            || snapshot.leaf_count > 1u64 << depth
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut tree = Self::new_with_empty_leaf(
+            depth,
+            authority,
+            snapshot.metadata.max_leaf_size,
+            snapshot.metadata.compression_enabled,
+            snapshot.metadata.empty_leaf,
+        );
+        tree.metadata.fill_strategy = snapshot.metadata.fill_strategy;
+        for leaf in &snapshot.leaves {
+            tree.insert(leaf)?;
+        }
+
+        if tree.root != snapshot.root {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        tree.metadata = TreeMetadata {
+            authority,
+            ..snapshot.metadata
+        };
+        Ok(tree)
+    }
+
+    pub fn get_batch_summary(&self, sequence_number: u64) -> Option<BatchSummary> {
+        self.processed_batches.get(&sequence_number)?.summary
+    }
+
     pub fn finalize(&mut self) -> Result<(), ProgramError> {
         if !self.pending_batches.is_empty() {
             return Err(ProgramError::InvalidArgument);
@@ -209,7 +536,15 @@ impl MerkleTree {
         (1 << self.depth) - 1 + leaf_index
     }
 
-    fn update_path_to_root(&mut self, mut node_index: usize) {
+    /// The node at `index`, or an error if `nodes` is shorter than the
+    /// depth implies, as after loading corrupted or undersized state
+    fn node(&self, index: usize) -> Result<&[u8; 32], MerkleTreeError> {
+        self.nodes.get(index).ok_or(MerkleTreeError::NodeIndexOutOfBounds)
+    }
+
+    /// Rehashes from `node_index` up to the root. On error the path is left
+    /// partly updated; the caller's instruction fails and discards it.
+    fn update_path_to_root(&mut self, mut node_index: usize) -> Result<(), MerkleTreeError> {
         while node_index > 0 {
             let parent_index = (node_index - 1) / 2;
             let sibling_index = if node_index % 2 == 0 {
@@ -218,14 +553,16 @@ impl MerkleTree {
                 node_index + 1
             };
 
-            self.nodes[parent_index] = hash_pair(
-                &self.nodes[if node_index % 2 == 0 { sibling_index } else { node_index }],
-                &self.nodes[if node_index % 2 == 0 { node_index } else { sibling_index }],
+            let parent = hash_pair(
+                self.node(if node_index % 2 == 0 { sibling_index } else { node_index })?,
+                self.node(if node_index % 2 == 0 { node_index } else { sibling_index })?,
             );
+            *self.nodes.get_mut(parent_index).ok_or(MerkleTreeError::NodeIndexOutOfBounds)? = parent;
 
             node_index = parent_index;
         }
-        self.root = self.nodes[0];
+        self.root = *self.node(0)?;
+        Ok(())
     }
 
     fn get_next_sequence_number(&self) -> u64 {
@@ -239,7 +576,11 @@ impl MerkleTree {
     }
 
     pub fn get_proof(&self, index: u64) -> Result<Vec<[u8; 32]>, ProgramError> {
-        if index >= self.leaf_count {
+        // Slots are filled in `fill_strategy` order, so `index` is occupied
+        // iff it was among the first `leaf_count` slots handed out
+        if index >= 1 << self.depth
+            || self.metadata.fill_strategy.slot_for(index, self.depth) >= self.leaf_count
+        {
             return Err(ProgramError::InvalidArgument);
         }
 
@@ -252,7 +593,7 @@ impl MerkleTree {
             } else {
                 current_index + 1
             };
-            proof.push(self.nodes[sibling_index]);
+            proof.push(*self.node(sibling_index)?);
             current_index = (current_index - 1) / 2;
         }
 
@@ -324,6 +665,178 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_batch_summaries_tile_leaf_space() {
+        let mut tree = create_test_tree();
+        let processor = Pubkey::new_unique();
+
+        let mut sequence_numbers = Vec::new();
+        for size in [2u8, 3, 1] {
+            let leaves = (0..size).map(|i| [size * 10 + i; 32]).collect();
+            sequence_numbers.push(tree.create_batch(leaves, processor, BatchType::Standard).unwrap());
+        }
+        assert_eq!(tree.get_batch_summary(sequence_numbers[0]), None);
+
+        let mut roots = Vec::new();
+        while tree.process_next_batch().unwrap().is_some() {
+            roots.push(tree.root);
+        }
+
+        let mut next_leaf = 0;
+        for (seq, root) in sequence_numbers.iter().zip(&roots) {
+            let summary = tree.get_batch_summary(*seq).unwrap();
+            assert_eq!(summary.sequence_number, *seq);
+            assert_eq!(summary.start_leaf_index, next_leaf);
+            assert_eq!(summary.root_after, *root);
+            next_leaf += summary.leaf_count as u64;
+        }
+        assert_eq!(next_leaf, tree.leaf_count);
+        assert_eq!(next_leaf, 6);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut tree = create_test_tree();
+        for i in 0..5u8 {
+            tree.insert(&[i + 1; 32]).unwrap();
+        }
+
+        let snapshot = tree.export_snapshot();
+        assert_eq!(snapshot.leaves.len(), 5);
+
+        let bytes = snapshot.try_to_vec().unwrap();
+        let new_authority = Pubkey::new_unique();
+        let imported = MerkleTree::import_snapshot(
+            TreeSnapshot::try_from_slice(&bytes).unwrap(),
+            new_authority,
+        ).unwrap();
+
+        assert_eq!(imported.root, tree.root);
+        assert_eq!(imported.leaf_count, tree.leaf_count);
+        assert_eq!(imported.metadata.authority, new_authority);
+        assert_eq!(imported.get_proof(3).unwrap(), tree.get_proof(3).unwrap());
+    }
+
+    #[test]
+    fn test_tampered_snapshot_rejected() {
+        let mut tree = create_test_tree();
+        for i in 0..5u8 {
+            tree.insert(&[i + 1; 32]).unwrap();
+        }
+
+        let mut snapshot = tree.export_snapshot();
+        snapshot.leaves[2][0] ^= 1;
+        assert_eq!(
+            MerkleTree::import_snapshot(snapshot, Pubkey::new_unique()).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        let mut snapshot = tree.export_snapshot();
+        snapshot.leaf_count += 1;
+        assert!(MerkleTree::import_snapshot(snapshot, Pubkey::new_unique()).is_err());
+    }
+
+    #[test]
+    fn test_truncated_nodes_error_instead_of_panicking() {
+        let mut tree = create_test_tree();
+        tree.insert(&[1u8; 32]).unwrap();
+        tree.insert(&[2u8; 32]).unwrap();
+
+        // A depth-3 tree has 15 nodes; keep the first leaf but drop its sibling
+        tree.nodes.truncate(8);
+        let out_of_bounds: ProgramError = MerkleTreeError::NodeIndexOutOfBounds.into();
+        assert_eq!(tree.get_proof(0), Err(out_of_bounds.clone()));
+        assert_eq!(tree.insert(&[3u8; 32]), Err(out_of_bounds));
+
+        // Even the root can go missing
+        tree.nodes.clear();
+        assert_eq!(tree.update_path_to_root(0), Err(MerkleTreeError::NodeIndexOutOfBounds));
+    }
+
+    #[test]
+    fn test_randomized_snapshot_round_trip() {
+        // xorshift64, so the contents are reproducible
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for depth in [1usize, 4, 7] {
+            let mut tree = MerkleTree::new(depth, Pubkey::new_unique(), 1000, true);
+            let leaf_count = next() % (1u64 << depth) + 1;
+            for _ in 0..leaf_count {
+                let mut leaf = [0u8; 32];
+                for chunk in leaf.chunks_mut(8) {
+                    chunk.copy_from_slice(&next().to_le_bytes());
+                }
+                tree.insert(&leaf).unwrap();
+            }
+
+            let snapshot = tree.export_snapshot();
+            let restored = MerkleTree::from_snapshot(snapshot.clone()).unwrap();
+            assert_eq!(restored.root, tree.root);
+            assert_eq!(restored.leaf_count, tree.leaf_count);
+            assert_eq!(restored.metadata.authority, tree.metadata.authority);
+            assert_eq!(restored.nodes, tree.nodes);
+            assert_eq!(restored.export_snapshot().snapshot_hash(), snapshot.snapshot_hash());
+
+            // Corrupting any one leaf changes the hash and fails the import
+            let mut corrupted = snapshot.clone();
+            let index = (next() % leaf_count) as usize;
+            corrupted.leaves[index][(next() % 32) as usize] ^= 1;
+            assert_ne!(corrupted.snapshot_hash(), snapshot.snapshot_hash());
+            assert_eq!(
+                MerkleTree::from_snapshot(corrupted).unwrap_err(),
+                ProgramError::InvalidAccountData
+            );
+        }
+    }
+
+    #[cfg(feature = "offchain")]
+    #[test]
+    fn test_snapshot_file_round_trip() {
+        let mut tree = create_test_tree();
+        for i in 0..5u8 {
+            tree.insert(&[i + 1; 32]).unwrap();
+        }
+        let snapshot = tree.export_snapshot();
+
+        let path = std::env::temp_dir().join(format!("tree-snapshot-{}.bin", std::process::id()));
+        snapshot.write_to_file(&path).unwrap();
+        let read = TreeSnapshot::read_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.snapshot_hash(), snapshot.snapshot_hash());
+        assert_eq!(MerkleTree::from_snapshot(read).unwrap().root, tree.root);
+    }
+
+    #[test]
+    fn test_custom_empty_leaf() {
+        let authority = Pubkey::new_unique();
+        let sentinel = hash_pair(b"wave-verifier:empty-leaf:v1.....", &EMPTY_SLICE);
+
+        let zeros = MerkleTree::new(3, authority, 1000, true);
+        let custom = MerkleTree::new_with_empty_leaf(3, authority, 1000, true, sentinel);
+        assert_eq!(zeros.root, EMPTY_SLICE);
+        assert_eq!(custom.root, sentinel);
+        assert_ne!(custom.root, zeros.root);
+
+        // Unfilled siblings keep using the sentinel after inserts
+        let mut zeros = zeros;
+        let mut custom = custom;
+        zeros.insert(&[1u8; 32]).unwrap();
+        custom.insert(&[1u8; 32]).unwrap();
+        assert_ne!(custom.root, zeros.root);
+        assert_eq!(custom.get_proof(0).unwrap()[0], sentinel);
+
+        // The sentinel survives a snapshot round trip
+        let imported = MerkleTree::import_snapshot(custom.export_snapshot(), authority).unwrap();
+        assert_eq!(imported.root, custom.root);
+    }
+
     #[test]
     fn test_priority_batches() {
         let mut tree = create_test_tree();
@@ -382,4 +895,188 @@ mod tests {
         );
         assert!(result.is_err());
     }
+
+    #[cfg(feature = "offchain")]
+    #[test]
+    fn test_leaf_provenance() {
+        let mut tree = create_test_tree();
+        let processor = Pubkey::new_unique();
+
+        // Off by default
+        assert!(!tree.leaf_provenance_enabled());
+        tree.create_batch(vec![[1u8; 32]], processor, BatchType::Standard).unwrap();
+        tree.process_next_batch().unwrap();
+        assert_eq!(tree.find_leaf_origin(&[1u8; 32]), None);
+
+        tree.enable_leaf_provenance();
+
+        // Individually inserted leaves have no batch origin
+        tree.insert(&[2u8; 32]).unwrap();
+        assert_eq!(tree.find_leaf_origin(&[2u8; 32]), None);
+
+        let seq = tree.create_batch(
+            vec![[3u8; 32], [4u8; 32], [5u8; 32]],
+            processor,
+            BatchType::Standard,
+        ).unwrap();
+        tree.process_next_batch().unwrap();
+
+        assert_eq!(
+            tree.find_leaf_origin(&[4u8; 32]),
+            Some(LeafOrigin { sequence_number: seq, position_in_batch: 1 })
+        );
+        assert_eq!(
+            tree.find_leaf_origin(&[5u8; 32]),
+            Some(LeafOrigin { sequence_number: seq, position_in_batch: 2 })
+        );
+
+        // The index survives its own round trip but not the tree's
+        let provenance = tree.leaf_provenance().unwrap().clone();
+        let bytes = provenance.try_to_vec().unwrap();
+        assert_eq!(LeafProvenance::try_from_slice(&bytes).unwrap(), provenance);
+
+        let mut restored = MerkleTree::try_from_slice(&tree.try_to_vec().unwrap()).unwrap();
+        assert!(!restored.leaf_provenance_enabled());
+        restored.restore_leaf_provenance(provenance);
+        assert_eq!(restored.find_leaf_origin(&[3u8; 32]), tree.find_leaf_origin(&[3u8; 32]));
+    }
+
+    #[test]
+    fn test_fill_strategies() {
+        for (strategy, expected_slots) in [
+            (FillStrategy::Sequential, [0, 1, 2, 3, 4, 5, 6, 7]),
+            (FillStrategy::SubtreeFirst, [0, 4, 2, 6, 1, 5, 3, 7]),
+        ] {
+            let mut tree = create_test_tree();
+            tree.set_fill_strategy(strategy).unwrap();
+
+            let mut slots = Vec::new();
+            for i in 0..8u8 {
+                slots.push(tree.insert(&[i + 1; 32]).unwrap());
+
+                // Empty slots have no proof, even below `leaf_count`
+                for slot in 0..8u64 {
+                    assert_eq!(tree.get_proof(slot).is_ok(), slots.contains(&slot), "{strategy:?}");
+                }
+            }
+            assert_eq!(slots, expected_slots);
+            assert!(tree.insert(&[9u8; 32]).is_err());
+            assert_eq!(
+                tree.set_fill_strategy(FillStrategy::Sequential),
+                Err(ProgramError::InvalidArgument)
+            );
+
+            for (i, slot) in slots.iter().enumerate() {
+                let proof = tree.get_proof(*slot).unwrap();
+                assert!(tree.verify(&[i as u8 + 1; 32], &proof, *slot), "{strategy:?}");
+            }
+
+            // Snapshots keep insertion order and replay under the same strategy
+            let imported = MerkleTree::from_snapshot(tree.export_snapshot()).unwrap();
+            assert_eq!(imported.fill_strategy(), strategy);
+            assert_eq!(imported.root, tree.root);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_leaf_rejection() {
+        let leaf = [7u8; 32];
+
+        let mut tree = MerkleTree::new(3, Pubkey::new_unique(), 1000, true);
+        assert!(!tree.reject_duplicates());
+        tree.insert(&leaf).unwrap();
+        tree.insert(&leaf).unwrap();
+        assert_eq!(tree.leaf_count, 2);
+
+        let mut tree = MerkleTree::new(3, Pubkey::new_unique(), 1000, true);
+        tree.set_reject_duplicates(true);
+        tree.insert(&leaf).unwrap();
+        let root = tree.root;
+        assert_eq!(tree.insert(&leaf), Err(MerkleTreeError::DuplicateLeaf.into()));
+        assert_eq!(tree.leaf_count, 1);
+        assert_eq!(tree.root, root);
+
+        // A batch repeating a leaf, or holding one already inserted, inserts nothing
+        let processor = Pubkey::new_unique();
+        for leaves in [vec![[1u8; 32], [1u8; 32]], vec![[2u8; 32], leaf]] {
+            let seq = tree.create_batch(leaves, processor, BatchType::Standard).unwrap();
+            assert_eq!(tree.process_next_batch(), Err(MerkleTreeError::DuplicateLeaf.into()));
+            assert_eq!(tree.get_batch_status(seq), Some(BatchStatus::Failed));
+            assert_eq!(tree.leaf_count, 1);
+            assert_eq!(tree.root, root);
+        }
+
+        tree.create_batch(vec![[1u8; 32], [2u8; 32]], processor, BatchType::Standard).unwrap();
+        assert!(tree.process_next_batch().unwrap().is_some());
+        assert_eq!(tree.leaf_count, 3);
+    }
+
+    #[test]
+    fn test_equal_priority_batches_process_in_sequence_order() {
+        let batch_types = [BatchType::Standard, BatchType::Priority, BatchType::Rollover];
+        let queue_orders: [fn(&mut VecDeque<BatchOperation>); 3] = [
+            |_| {},
+            |queue| queue.make_contiguous().reverse(),
+            |queue| queue.rotate_left(7),
+        ];
+
+        for reorder in queue_orders {
+            let mut tree = MerkleTree::new(5, Pubkey::new_unique(), 1000, true);
+            let processor = Pubkey::new_unique();
+            let mut created = Vec::new();
+            for i in 0..24u8 {
+                let batch_type = batch_types[(i % 5 % 3) as usize];
+                let seq = tree.create_batch(vec![[i + 1; 32]], processor, batch_type).unwrap();
+                created.push((batch_type, seq));
+            }
+            reorder(&mut tree.pending_batches);
+
+            let priority = |batch_type: BatchType| match batch_type {
+                BatchType::Standard => 0,
+                BatchType::Priority => 1,
+                BatchType::Rollover => 2,
+            };
+            created.sort_by_key(|&(batch_type, seq)| (Reverse(priority(batch_type)), seq));
+            let expected: Vec<u64> = created.iter().map(|&(_, seq)| seq).collect();
+
+            let mut processed = Vec::new();
+            while let Some(seq) = tree.process_next_batch().unwrap() {
+                processed.push(seq);
+            }
+            assert_eq!(processed, expected);
+        }
+    }
+
+    #[test]
+    fn test_capacity_tracking() {
+        let mut tree = create_test_tree();
+        assert_eq!(tree.capacity(), 8);
+
+        for inserted in 0..8u8 {
+            assert_eq!(tree.remaining_capacity(), 8 - inserted as u64);
+            assert!(!tree.is_full());
+            tree.insert(&[inserted + 1; 32]).unwrap();
+        }
+        assert_eq!(tree.remaining_capacity(), 0);
+        assert!(tree.is_full());
+        assert!(tree.insert(&[9u8; 32]).is_err());
+
+        // Batches are sized against what's left, pending batches included
+        let mut tree = create_test_tree();
+        let processor = Pubkey::new_unique();
+        tree.insert(&[1u8; 32]).unwrap();
+        let leaves = |count: u8| (0..count).map(|i| [i + 2; 32]).collect::<Vec<_>>();
+        assert_eq!(
+            tree.create_batch(leaves(8), processor, BatchType::Standard),
+            Err(MerkleTreeError::TreeFull.into())
+        );
+        tree.create_batch(leaves(4), processor, BatchType::Standard).unwrap();
+        assert_eq!(
+            tree.create_batch(leaves(4), processor, BatchType::Standard),
+            Err(MerkleTreeError::TreeFull.into())
+        );
+        tree.create_batch(leaves(3), processor, BatchType::Standard).unwrap();
+        while tree.process_next_batch().unwrap().is_some() {}
+        assert!(tree.is_full());
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,177 @@
+use crate::MAX_TREE_DEPTH;
+
+/// Hash function a tree combines sibling nodes with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashKind {
+    Sha256,
+    Keccak256,
+}
+
+impl HashKind {
+    /// Hashes `left || right`. On-chain both variants use the runtime's hash
+    /// syscalls; without the `solana` feature they use `sha2` and `sha3`.
+    #[cfg(feature = "solana")]
+    pub fn hash_pair(self, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        use solana_program::{hash, keccak};
+
+        match self {
+            Self::Sha256 => hash::hashv(&[left, right]).to_bytes(),
+            Self::Keccak256 => keccak::hashv(&[left, right]).to_bytes(),
+        }
+    }
+
+    #[cfg(not(feature = "solana"))]
+    pub fn hash_pair(self, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        use sha2::Digest;
+
+        match self {
+            Self::Sha256 => sha2::Sha256::new().chain_update(left).chain_update(right).finalize().into(),
+            Self::Keccak256 => sha3::Keccak256::new().chain_update(left).chain_update(right).finalize().into(),
+        }
+    }
+}
+
+/// Checks that `leaf` sits at `index` in the tree with `root`, given the
+/// sibling path from the leaf up (`siblings[0]` is the leaf's sibling). The
+/// tree depth is `siblings.len()`, at most `MAX_TREE_DEPTH`.
+///
+/// Does not allocate and needs nothing beyond the hash functions, so callback
+/// programs can check proofs without pulling in `MerkleTree`.
+pub fn verify_merkle_proof(
+    root: &[u8; 32],
+    leaf: &[u8; 32],
+    index: u64,
+    siblings: &[[u8; 32]],
+    hasher: HashKind,
+) -> bool {
+    verify_merkle_proof_bounded::<MAX_TREE_DEPTH>(root, leaf, index, siblings, hasher)
+}
+
+/// Same as [`verify_merkle_proof`] with the depth capped at `MAX_DEPTH`
+/// instead of `MAX_TREE_DEPTH`. Longer proofs, and indices past the last
+/// leaf, are rejected before any hashing.
+pub fn verify_merkle_proof_bounded<const MAX_DEPTH: usize>(
+    root: &[u8; 32],
+    leaf: &[u8; 32],
+    index: u64,
+    siblings: &[[u8; 32]],
+    hasher: HashKind,
+) -> bool {
+    let depth = siblings.len();
+    if depth > MAX_DEPTH || depth > 64 || (depth < 64 && index >> depth != 0) {
+        return false;
+    }
+
+    let mut current = *leaf;
+    for (level, sibling) in siblings.iter().enumerate() {
+        current = if (index >> level) & 1 == 0 {
+            hasher.hash_pair(&current, sibling)
+        } else {
+            hasher.hash_pair(sibling, &current)
+        };
+    }
+
+    current == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+    use crate::types::Pubkey;
+
+    /// xorshift64, so the random trees are reproducible without a rand dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn bytes(&mut self) -> [u8; 32] {
+            let mut out = [0u8; 32];
+            for chunk in out.chunks_mut(8) {
+                chunk.copy_from_slice(&self.next().to_le_bytes());
+            }
+            out
+        }
+    }
+
+    #[test]
+    fn test_agrees_with_tree_verify() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+
+        for _ in 0..64 {
+            let depth = 1 + (rng.next() % 6) as usize;
+            let mut tree = MerkleTree::new(depth, Pubkey::new_unique(), 1000, false);
+            let leaf_count = 1 + rng.next() % (1 << depth);
+            let leaves: Vec<_> = (0..leaf_count).map(|_| rng.bytes()).collect();
+            for leaf in &leaves {
+                tree.insert(leaf).unwrap();
+            }
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let index = index as u64;
+                let mut proof = tree.get_proof(index).unwrap();
+                let verify = |leaf: &[u8; 32], proof: &[[u8; 32]], index: u64| {
+                    let standalone =
+                        verify_merkle_proof(&tree.root, leaf, index, proof, HashKind::Sha256);
+                    assert_eq!(standalone, tree.verify(leaf, proof, index));
+                    standalone
+                };
+
+                assert!(verify(leaf, &proof, index));
+                assert!(!verify(&rng.bytes(), &proof, index));
+                assert!(!verify(leaf, &proof, index ^ 1));
+
+                let level = (rng.next() % depth as u64) as usize;
+                proof[level][0] ^= 1;
+                assert!(!verify(leaf, &proof, index));
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_out_of_range_proofs() {
+        let leaf = [1u8; 32];
+        let sibling = [2u8; 32];
+        let root = HashKind::Sha256.hash_pair(&leaf, &sibling);
+
+        assert!(verify_merkle_proof(&root, &leaf, 0, &[sibling], HashKind::Sha256));
+        // Index 2 is past the last leaf of a depth-1 tree even though its
+        // low bit matches
+        assert!(!verify_merkle_proof(&root, &leaf, 2, &[sibling], HashKind::Sha256));
+        assert!(!verify_merkle_proof_bounded::<0>(&root, &leaf, 0, &[sibling], HashKind::Sha256));
+        assert!(!verify_merkle_proof(&root, &leaf, 0, &[sibling], HashKind::Keccak256));
+
+        let keccak_root = HashKind::Keccak256.hash_pair(&sibling, &leaf);
+        assert!(verify_merkle_proof(&keccak_root, &leaf, 1, &[sibling], HashKind::Keccak256));
+        assert!(!verify_merkle_proof(&root, &leaf, 0, &[sibling; MAX_TREE_DEPTH + 1], HashKind::Sha256));
+    }
+
+    /// Known digests of 64 zero bytes, so the syscall and the off-chain
+    /// hashers are held to the same output
+    #[test]
+    fn test_hash_pair_known_digests() {
+        let zero = [0u8; 32];
+        assert_eq!(
+            HashKind::Sha256.hash_pair(&zero, &zero),
+            hex("f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b")
+        );
+        assert_eq!(
+            HashKind::Keccak256.hash_pair(&zero, &zero),
+            hex("ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5")
+        );
+    }
+
+    fn hex(s: &str) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+}
\ No newline at end of file
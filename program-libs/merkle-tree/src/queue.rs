@@ -1,6 +1,6 @@
 use {
     borsh::{BorshDeserialize, BorshSerialize},
-    solana_program::pubkey::Pubkey,
+    crate::types::Pubkey,
     std::collections::VecDeque,
 };
 
@@ -0,0 +1,76 @@
+//! The Solana types this crate's API uses. With the default `solana`
+//! feature they are `solana_program`'s own. Without it they are stand-ins
+//! with the same names, borsh layout and used variants. Off-chain services
+//! can then build the crate without `solana_program`, and code written
+//! against either configuration compiles against both.
+
+#[cfg(feature = "solana")]
+pub use solana_program::{clock::UnixTimestamp, program_error::ProgramError, pubkey::Pubkey};
+
+#[cfg(not(feature = "solana"))]
+pub use offchain::{ProgramError, Pubkey, UnixTimestamp};
+
+#[cfg(not(feature = "solana"))]
+mod offchain {
+    use {
+        borsh::{BorshDeserialize, BorshSerialize},
+        std::sync::atomic::{AtomicU64, Ordering},
+        thiserror::Error,
+    };
+
+    pub type UnixTimestamp = i64;
+
+    /// A 32-byte account address
+    #[derive(
+        BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+    )]
+    pub struct Pubkey(pub [u8; 32]);
+
+    impl Pubkey {
+        pub const fn new_from_array(bytes: [u8; 32]) -> Self {
+            Self(bytes)
+        }
+
+        pub const fn to_bytes(self) -> [u8; 32] {
+            self.0
+        }
+
+        /// A key no other call in this process returns, for tests
+        pub fn new_unique() -> Self {
+            static COUNTER: AtomicU64 = AtomicU64::new(1);
+            let mut bytes = [0u8; 32];
+            bytes[..8].copy_from_slice(&COUNTER.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+            Self(bytes)
+        }
+    }
+
+    impl AsRef<[u8]> for Pubkey {
+        fn as_ref(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    /// The `ProgramError` variants this crate returns
+    #[derive(Error, Debug, Clone, PartialEq, Eq)]
+    pub enum ProgramError {
+        #[error("Custom program error: {0:#x}")]
+        Custom(u32),
+        #[error("The arguments provided to a program instruction were invalid")]
+        InvalidArgument,
+        #[error("An account's data contents was invalid")]
+        InvalidAccountData,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, borsh::BorshSerialize};
+
+    #[test]
+    fn test_pubkey_encodes_as_32_bytes() {
+        let key = Pubkey::new_from_array([7u8; 32]);
+        assert_eq!(key.try_to_vec().unwrap(), vec![7u8; 32]);
+        assert_eq!(key.to_bytes(), [7u8; 32]);
+        assert_ne!(Pubkey::new_unique(), Pubkey::new_unique());
+    }
+}
\ No newline at end of file
@@ -10,9 +10,14 @@ use {
         account_info::AccountInfo,
         pubkey::Pubkey,
     },
-    test::Bencher,
+    std::time::{Duration, Instant},
+    test::{black_box, Bencher},
 };
 
+/// Buffer compared whole and in chunks by `bench_chunked_vs_whole`
+const CHUNKED_VS_WHOLE_SIZE: usize = 100 * 1024;
+const CHUNKED_VS_WHOLE_CHUNK_SIZES: [usize; 5] = [512, 1024, 4096, 16 * 1024, 32 * 1024];
+
 fn setup_test_data(size: usize) -> Vec<u8> {
     // Create test data with some patterns to make compression meaningful
     let mut data = Vec::with_capacity(size);
@@ -34,6 +39,8 @@ fn bench_lz4_compression(b: &mut Bencher) {
             concurrent_compressions_limit: 1,
             verify_all_compressions: false,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         };
         
         let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
@@ -152,6 +159,7 @@ fn bench_compression_algorithms_comparison(b: &mut Bencher) {
     });
 }
 
+#[cfg(feature = "parallel")]
 #[bench]
 fn bench_concurrent_compression(b: &mut Bencher) {
     use rayon::prelude::*;
@@ -190,4 +198,69 @@ fn bench_compression_with_different_chunk_sizes(b: &mut Bencher) {
             (chunk_size, compressed_chunks)
         }).collect::<Vec<_>>()
     });
-} 
\ No newline at end of file
+}
+
+/// Total LZ4 output when each `chunk_size` piece of `data` is compressed on
+/// its own, as CompressAccount does per chunk
+fn compressed_size_chunked(data: &[u8], chunk_size: usize) -> usize {
+    data.chunks(chunk_size).map(|chunk| lz4_flex::compress(chunk).len()).sum()
+}
+
+fn time_chunked(data: &[u8], chunk_size: usize) -> Duration {
+    const RUNS: u32 = 20;
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        black_box(compressed_size_chunked(black_box(data), chunk_size));
+    }
+    start.elapsed() / RUNS
+}
+
+// Prints each chunk size's ratio and time against one whole-buffer pass;
+// the iterations time every chunk size together
+#[bench]
+fn bench_chunked_vs_whole(b: &mut Bencher) {
+    let test_data = setup_test_data(CHUNKED_VS_WHOLE_SIZE);
+    let whole_size = compressed_size_chunked(&test_data, test_data.len());
+    let whole_time = time_chunked(&test_data, test_data.len());
+    let whole_ratio = test_data.len() as f64 / whole_size as f64;
+    eprintln!("whole: ratio {:.2}, {:?}", whole_ratio, whole_time);
+
+    for chunk_size in CHUNKED_VS_WHOLE_CHUNK_SIZES {
+        let size = compressed_size_chunked(&test_data, chunk_size);
+        let time = time_chunked(&test_data, chunk_size);
+        eprintln!(
+            "{:>5}-byte chunks: ratio {:.2} ({:+.1}% size), {:?} ({:.2}x time)",
+            chunk_size,
+            test_data.len() as f64 / size as f64,
+            (size as f64 / whole_size as f64 - 1.0) * 100.0,
+            time,
+            time.as_secs_f64() / whole_time.as_secs_f64(),
+        );
+    }
+
+    b.iter(|| {
+        CHUNKED_VS_WHOLE_CHUNK_SIZES
+            .iter()
+            .map(|&chunk_size| compressed_size_chunked(&test_data, chunk_size))
+            .sum::<usize>()
+    });
+}
+
+#[test]
+fn test_chunking_never_improves_ratio() {
+    let test_data = setup_test_data(CHUNKED_VS_WHOLE_SIZE);
+    let whole_size = compressed_size_chunked(&test_data, test_data.len());
+
+    // Every chunk restarts the match window, so chunked output can only be
+    // larger; 1% allows for block framing differences
+    for chunk_size in CHUNKED_VS_WHOLE_CHUNK_SIZES {
+        let size = compressed_size_chunked(&test_data, chunk_size);
+        assert!(
+            size * 100 >= whole_size * 99,
+            "{}-byte chunks compressed to {} bytes, whole buffer to {}",
+            chunk_size,
+            size,
+            whole_size,
+        );
+    }
+}
\ No newline at end of file
@@ -41,6 +41,27 @@ pub enum CompressionError {
     
     #[error("Unauthorized operation")]
     Unauthorized,
+
+    #[error("Merkle membership check failed")]
+    InvalidMerkleProof,
+
+    #[error("Invalid concurrent compressions limit")]
+    InvalidConcurrencyLimit,
+
+    #[error("Invalid verification sample rate")]
+    InvalidSampleRate,
+
+    #[error("Compressed data does not decompress to the original")]
+    VerificationFailed,
+
+    #[error("Program is paused")]
+    ProgramPaused,
+
+    #[error("Compression succeeded, so there is no failure to record")]
+    FailureNotReproduced,
+
+    #[error("Compressed account could not grow back in one decompression")]
+    UnrestorableCompression,
 }
 
 impl From<CompressionError> for ProgramError {
@@ -0,0 +1,35 @@
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{log::sol_log_data, pubkey::Pubkey},
+};
+
+use crate::state::{AuditEntry, CompressionAlgorithm};
+
+/// Structured events, logged with `sol_log_data` as one borsh-encoded field
+/// so indexers can decode them without scraping `msg!` output
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum CompressionEvent {
+    CompressionFailed {
+        account: Pubkey,
+        algorithm: CompressionAlgorithm,
+        original_size: u64,
+    },
+    /// `algorithm` failed `failures` times within one health window and no
+    /// longer accepts new compressions. Existing accounts still decompress.
+    AlgorithmDisabled {
+        algorithm: CompressionAlgorithm,
+        failures: u32,
+    },
+    /// The entry just written to the `AuditLog`, which only keeps the most
+    /// recent ones
+    AuditRecorded {
+        entry: AuditEntry,
+    },
+}
+
+impl CompressionEvent {
+    pub fn emit(&self) {
+        // Serializing a fixed-layout enum into a Vec cannot fail
+        sol_log_data(&[&self.try_to_vec().unwrap()]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,50 @@
+//! Byte offsets of `state::CompressedAccountMetadata` fields, for memcmp
+//! filters. The struct has no `Option` fields and only unit enums, so every
+//! offset is fixed.
+
+pub mod compressed_account_metadata {
+    pub const ACCOUNT_TYPE: usize = 0;
+    pub const ORIGINAL_SIZE: usize = 1;
+    pub const COMPRESSED_SIZE: usize = 9;
+    pub const COMPRESSION_ALGORITHM: usize = 17;
+    pub const COMPRESSION_LEVEL: usize = 18;
+    pub const LAST_ACCESSED: usize = 19;
+    pub const ACCESS_COUNT: usize = 27;
+    pub const COMPRESSION_TIME_MS: usize = 35;
+    pub const VERIFICATION_HASH: usize = 43;
+    pub const LEN: usize = 75;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compressed_account_metadata::*;
+    use crate::state::{AccountType, CompressedAccountMetadata, CompressionAlgorithm};
+    use borsh::BorshSerialize;
+
+    #[test]
+    fn test_compressed_account_metadata_offsets() {
+        let metadata = CompressedAccountMetadata {
+            account_type: AccountType::NFT,
+            original_size: 0x0101_0101_0101_0101,
+            compressed_size: 0x0202_0202_0202_0202,
+            compression_algorithm: CompressionAlgorithm::Zstd,
+            compression_level: 9,
+            last_accessed: 0x0303_0303_0303_0303,
+            access_count: 0x0404_0404_0404_0404,
+            compression_time_ms: 0x0505_0505_0505_0505,
+            verification_hash: [6u8; 32],
+        };
+        let data = metadata.try_to_vec().unwrap();
+
+        assert_eq!(data.len(), LEN);
+        assert_eq!(data[ACCOUNT_TYPE], AccountType::NFT as u8);
+        assert_eq!(data[ORIGINAL_SIZE..ORIGINAL_SIZE + 8], [1u8; 8]);
+        assert_eq!(data[COMPRESSED_SIZE..COMPRESSED_SIZE + 8], [2u8; 8]);
+        assert_eq!(data[COMPRESSION_ALGORITHM], CompressionAlgorithm::Zstd as u8);
+        assert_eq!(data[COMPRESSION_LEVEL], 9);
+        assert_eq!(data[LAST_ACCESSED..LAST_ACCESSED + 8], [3u8; 8]);
+        assert_eq!(data[ACCESS_COUNT..ACCESS_COUNT + 8], [4u8; 8]);
+        assert_eq!(data[COMPRESSION_TIME_MS..COMPRESSION_TIME_MS + 8], [5u8; 8]);
+        assert_eq!(data[VERIFICATION_HASH..], [6u8; 32]);
+    }
+}
\ No newline at end of file
@@ -4,15 +4,37 @@ use {
         account_info::{next_account_info, AccountInfo},
         entrypoint,
         entrypoint::ProgramResult,
-        msg,
+        entrypoint::MAX_PERMITTED_DATA_INCREASE,
+        program::{invoke, invoke_signed, set_return_data},
         program_error::ProgramError,
+        program_pack::Pack,
         pubkey::Pubkey,
         clock::Clock,
+        rent::Rent,
+        system_instruction,
         sysvar::Sysvar,
     },
     std::collections::HashMap,
 };
 
+#[macro_use]
+mod log;
+
+pub mod error;
+pub mod events;
+pub mod layout;
+pub mod state;
+
+pub use state::CompressionAlgorithm;
+use {
+    error::CompressionError,
+    events::CompressionEvent,
+    state::{
+        AuditEntry, AuditLog, AuditOperation, Capabilities, CompressionState, ALGORITHM_FAILURE_THRESHOLD,
+        AUDIT_LOG_SEED, GLOBAL_STATE_SEED, MAX_MERKLE_PROOF_DEPTH, MAX_UNCOMPRESSED_SIZE,
+    },
+};
+
 // Declare the program's entrypoint
 entrypoint!(process_instruction);
 
@@ -22,10 +44,34 @@ pub enum AccountCompressionInstruction {
         max_depth: u32,
         max_buffer_size: u32,
     },
+    /// An error from the algorithm itself is reported with a
+    /// `CompressionFailed` event and fails the instruction. A failed
+    /// instruction writes nothing, so RecordCompressionFailure counts the
+    /// failure against the algorithm; successes are counted here, in the
+    /// global state's `algorithm_health`.
+    ///
+    /// Accounts: the signer, which must be the account itself or the global
+    /// authority, the account, its header, the global state (writable) and
+    /// the audit log. The program's own global state and audit log can't be
+    /// compressed.
+    ///
+    /// On success the account holds the compressed bytes, shrunk to fit, and
+    /// its header records the sizes and algorithm, and an entry is appended
+    /// to the audit log. With the global config's `refund_excess_rent` set,
+    /// a sixth account chosen by the signer receives the lamports above the
+    /// smaller account's rent-exempt minimum.
+    ///
+    /// A compression that saves more than an account can grow by in one
+    /// instruction fails with `UnrestorableCompression`, since
+    /// DecompressAccount couldn't restore it.
     CompressAccount {
         account_type: AccountType,
         compression_config: CompressionConfig,
     },
+    /// Restores the account's original bytes and size. Accounts: the payer
+    /// (signer, writable), covering the rent of the regrown account, the
+    /// compressed account, its header, the global state, the audit log,
+    /// which gets an entry on success, and the system program.
     DecompressAccount {
         account_id: Pubkey,
     },
@@ -36,6 +82,53 @@ pub enum AccountCompressionInstruction {
         account_id: Pubkey,
         expected_hash: [u8; 32],
     },
+    /// Returns this build's `state::Capabilities` as return data. Takes no
+    /// accounts.
+    GetCapabilities,
+    /// Decompresses the account without modifying it and checks that the
+    /// SHA-256 of the plaintext is the leaf at `index` under `expected_root`.
+    /// Accounts: the compressed account, its header and the global state.
+    DecompressAndVerify {
+        account_id: Pubkey,
+        merkle_proof: Vec<[u8; 32]>,
+        index: u64,
+        expected_root: [u8; 32],
+    },
+    /// Pauses or resumes compression and decompression for the whole
+    /// program. Accounts: the global authority (signer) and the global
+    /// state account (writable).
+    SetPaused {
+        paused: bool,
+    },
+    /// Creates the `AUDIT_LOG_SEED` PDA that CompressAccount and
+    /// DecompressAccount record to. Accounts: the payer (signer, writable),
+    /// the audit log PDA (writable) and the system program.
+    InitializeAuditLog,
+    /// Counts a CompressAccount failure against `algorithm` in the global
+    /// state's `algorithm_health`, disabling it after enough failures.
+    /// Accounts: the account that failed to compress and the global state
+    /// (writable).
+    ///
+    /// The compression is re-run on the account's data, and only an error
+    /// from the algorithm itself is recorded; if it compresses, the report
+    /// fails with `FailureNotReproduced`.
+    RecordCompressionFailure {
+        account_id: Pubkey,
+        algorithm: CompressionAlgorithm,
+        level: u8,
+    },
+    /// Creates the `GLOBAL_STATE_SEED` PDA that every other instruction
+    /// reads as the global state, advertising this build's capabilities.
+    /// The signer becomes its authority. Accounts: the authority, who pays
+    /// (signer, writable), the global state PDA (writable) and the system
+    /// program.
+    ///
+    /// `max_decompress_size` may not exceed `MAX_UNCOMPRESSED_SIZE`.
+    InitializeGlobalState {
+        max_depth: u32,
+        max_buffer_size: u32,
+        max_decompress_size: u64,
+    },
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
@@ -47,13 +140,6 @@ pub struct CompressionConfig {
     pub verify_compression: bool,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
-pub enum CompressionAlgorithm {
-    Lz4,
-    Snappy,
-    Zstd,
-}
-
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub enum AccountType {
     User,
@@ -91,25 +177,56 @@ pub fn process_instruction(
 
     match instruction {
         AccountCompressionInstruction::InitializeCompression { max_depth, max_buffer_size } => {
-            msg!("Instruction: InitializeCompression");
+            log_debug!("Instruction: InitializeCompression");
             process_initialize_compression(program_id, account_info_iter, max_depth, max_buffer_size)
         }
         AccountCompressionInstruction::CompressAccount { account_type, compression_config } => {
-            msg!("Instruction: CompressAccount");
+            log_debug!("Instruction: CompressAccount");
             process_compress_account(program_id, account_info_iter, account_type, compression_config)
         }
         AccountCompressionInstruction::DecompressAccount { account_id } => {
-            msg!("Instruction: DecompressAccount");
+            log_debug!("Instruction: DecompressAccount");
             process_decompress_account(program_id, account_info_iter, account_id)
         }
         AccountCompressionInstruction::UpdateCompressionParams { new_config } => {
-            msg!("Instruction: UpdateCompressionParams");
+            log_debug!("Instruction: UpdateCompressionParams");
             process_update_compression_params(program_id, account_info_iter, new_config)
         }
         AccountCompressionInstruction::ValidateCompression { account_id, expected_hash } => {
-            msg!("Instruction: ValidateCompression");
+            log_debug!("Instruction: ValidateCompression");
             process_validate_compression(program_id, account_info_iter, account_id, expected_hash)
         }
+        AccountCompressionInstruction::GetCapabilities => {
+            log_debug!("Instruction: GetCapabilities");
+            set_return_data(&Capabilities::compiled().try_to_vec()?);
+            Ok(())
+        }
+        AccountCompressionInstruction::DecompressAndVerify { account_id, merkle_proof, index, expected_root } => {
+            log_debug!("Instruction: DecompressAndVerify");
+            process_decompress_and_verify(program_id, account_info_iter, account_id, &merkle_proof, index, expected_root)
+        }
+        AccountCompressionInstruction::SetPaused { paused } => {
+            log_debug!("Instruction: SetPaused");
+            process_set_paused(program_id, account_info_iter, paused)
+        }
+        AccountCompressionInstruction::InitializeAuditLog => {
+            log_debug!("Instruction: InitializeAuditLog");
+            process_initialize_audit_log(program_id, account_info_iter)
+        }
+        AccountCompressionInstruction::RecordCompressionFailure { account_id, algorithm, level } => {
+            log_debug!("Instruction: RecordCompressionFailure");
+            process_record_compression_failure(program_id, account_info_iter, account_id, algorithm, level)
+        }
+        AccountCompressionInstruction::InitializeGlobalState { max_depth, max_buffer_size, max_decompress_size } => {
+            log_debug!("Instruction: InitializeGlobalState");
+            process_initialize_global_state(
+                program_id,
+                account_info_iter,
+                max_depth,
+                max_buffer_size,
+                max_decompress_size,
+            )
+        }
     }
 }
 
@@ -153,29 +270,108 @@ fn process_compress_account(
     account_type: AccountType,
     compression_config: CompressionConfig,
 ) -> ProgramResult {
+    let authority = next_account_info(account_info_iter)?;
     let account_to_compress = next_account_info(account_info_iter)?;
     let state_account = next_account_info(account_info_iter)?;
+    let global_state_account = next_account_info(account_info_iter)?;
+    let audit_log_account = next_account_info(account_info_iter)?;
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
 
     // Verify account ownership
     if account_to_compress.owner != program_id {
         return Err(ProgramError::InvalidAccountData);
     }
 
+    // Only new compressions are held to the deployment's algorithm mask
+    let mut global_state = load_global_state(program_id, global_state_account)?;
+    if global_state.program_paused {
+        return Err(CompressionError::ProgramPaused.into());
+    }
+    if !global_state.capabilities.supports(&compression_config.algorithm) {
+        return Err(CompressionError::InvalidAlgorithm.into());
+    }
+
+    // Compressing overwrites the account, so only its own keypair or the
+    // deployment's authority may, and never on the program's own state
+    if account_to_compress.key == global_state_account.key
+        || account_to_compress.key == &AuditLog::find_pda(program_id).0
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if authority.key != account_to_compress.key && authority.key != &global_state.authority {
+        return Err(CompressionError::Unauthorized.into());
+    }
+
+    let refund_destination = if global_state.config.refund_excess_rent {
+        Some(next_account_info(account_info_iter)?)
+    } else {
+        None
+    };
+
     // Read current state
     let mut compression_state = CompressedAccountState::try_from_slice(&state_account.try_borrow_data()?)?;
+    if compression_state.is_compressed {
+        return Err(CompressionError::AlreadyCompressed.into());
+    }
 
     // Perform compression based on account type and config
     let data = account_to_compress.try_borrow_data()?;
     let original_size = data.len() as u64;
     
-    let compressed_data = match compression_config.algorithm {
-        CompressionAlgorithm::Lz4 => compress_lz4(&data, compression_config.level)?,
-        CompressionAlgorithm::Snappy => compress_snappy(&data)?,
-        CompressionAlgorithm::Zstd => compress_zstd(&data, compression_config.level)?,
+    // Anything over the decompression cap could never be restored, so it
+    // fails here like any other compressor error
+    if original_size > global_state.max_decompress_size {
+        log_info!("{:?} compression failed: {} bytes exceeds the {} byte cap",
+            compression_config.algorithm, original_size, global_state.max_decompress_size);
+        CompressionEvent::CompressionFailed {
+            account: *account_to_compress.key,
+            algorithm: compression_config.algorithm,
+            original_size,
+        }
+        .emit();
+        return Err(CompressionError::CompressionFailed.into());
+    }
+
+    // Algorithm errors are counted by RecordCompressionFailure, since
+    // failing here rolls back any record of them
+    let compressed_data = match compress_with(&compression_config.algorithm, &data, compression_config.level) {
+        Ok(compressed) => compressed,
+        Err(err) => {
+            CompressionEvent::CompressionFailed {
+                account: *account_to_compress.key,
+                algorithm: compression_config.algorithm,
+                original_size,
+            }
+            .emit();
+            return Err(err);
+        }
     };
 
-    // Update compression stats
+    // The compressed bytes replace the account's data in place
     let compressed_size = compressed_data.len() as u64;
+    if compressed_size > original_size {
+        return Err(CompressionError::InsufficientBufferSize.into());
+    }
+    if original_size - compressed_size > MAX_PERMITTED_DATA_INCREASE as u64 {
+        return Err(CompressionError::UnrestorableCompression.into());
+    }
+
+    // A program has no timer to measure itself with, so only the ratios
+    // move
+    let verify_policy = global_state.config.clone();
+    global_state.record_algorithm_result(&compression_config.algorithm, true);
+    global_state.compression_stats.record_compression(original_size, compressed_size, 0);
+    CompressionState::pack(global_state, &mut global_state_account.try_borrow_mut_data()?)?;
+
+    let clock = Clock::get()?;
+    if verify_policy.should_verify(clock.slot, account_to_compress.key) {
+        verify_compression(&compression_config.algorithm, &compressed_data, &data)?;
+    }
+
+    // Update compression stats
     let compression_ratio = original_size as f64 / compressed_size as f64;
     
     compression_state.compression_stats.total_compressions += 1;
@@ -188,10 +384,37 @@ fn process_compress_account(
     }
 
     compression_state.compression_stats.total_bytes_saved += original_size - compressed_size;
-    compression_state.last_modified = Clock::get()?.unix_timestamp;
-    
+    compression_state.last_modified = clock.unix_timestamp;
+    compression_state.is_compressed = true;
+    compression_state.original_size = original_size;
+    compression_state.compressed_size = compressed_size;
+    compression_state.compression_algorithm = compression_config.algorithm;
+
+    let audit_entry = AuditEntry {
+        operation: AuditOperation::Compress,
+        account: *account_to_compress.key,
+        slot: clock.slot,
+        original_hash: solana_program::hash::hash(&data).to_bytes(),
+        result_hash: solana_program::hash::hash(&compressed_data).to_bytes(),
+    };
+
     // Save compressed data and updated state
+    drop(data);
+    account_to_compress.try_borrow_mut_data()?[..compressed_data.len()].copy_from_slice(&compressed_data);
+    account_to_compress.realloc(compressed_data.len(), false)?;
     compression_state.serialize(&mut *state_account.try_borrow_mut_data()?)?;
+    record_audit(program_id, audit_log_account, audit_entry)?;
+
+    if let Some(refund_destination) = refund_destination {
+        // An account that was below the rent-exempt minimum for its new size
+        // has nothing to give back
+        let minimum = Rent::get()?.minimum_balance(compressed_data.len());
+        let excess = account_to_compress.lamports().saturating_sub(minimum);
+        if excess > 0 {
+            **account_to_compress.try_borrow_mut_lamports()? -= excess;
+            **refund_destination.try_borrow_mut_lamports()? += excess;
+        }
+    }
 
     Ok(())
 }
@@ -201,37 +424,311 @@ fn process_decompress_account(
     account_info_iter: &mut std::slice::Iter<AccountInfo>,
     account_id: Pubkey,
 ) -> ProgramResult {
+    let payer = next_account_info(account_info_iter)?;
     let account_to_decompress = next_account_info(account_info_iter)?;
     let state_account = next_account_info(account_info_iter)?;
+    let global_state_account = next_account_info(account_info_iter)?;
+    let audit_log_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // DecompressAndVerify writes nothing, so only this path honors the pause
+    if load_global_state(program_id, global_state_account)?.program_paused {
+        return Err(CompressionError::ProgramPaused.into());
+    }
+
+    let (mut compression_state, decompressed_data) = decompress_account(
+        program_id,
+        account_to_decompress,
+        state_account,
+        global_state_account,
+        &account_id,
+    )?;
+
+    // Update stats
+    let clock = Clock::get()?;
+    compression_state.compression_stats.total_decompressions += 1;
+    compression_state.last_modified = clock.unix_timestamp;
+    compression_state.is_compressed = false;
+
+    let audit_entry = AuditEntry {
+        operation: AuditOperation::Decompress,
+        account: account_id,
+        slot: clock.slot,
+        original_hash: solana_program::hash::hash(&account_to_decompress.try_borrow_data()?).to_bytes(),
+        result_hash: solana_program::hash::hash(&decompressed_data).to_bytes(),
+    };
+
+    // The account grows back to its original size, with the payer covering
+    // any rent the extra bytes need
+    let shortfall = Rent::get()?
+        .minimum_balance(decompressed_data.len())
+        .saturating_sub(account_to_decompress.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account_to_decompress.key, shortfall),
+            &[payer.clone(), account_to_decompress.clone(), system_program.clone()],
+        )?;
+    }
+    account_to_decompress.realloc(decompressed_data.len(), false)?;
+    account_to_decompress.try_borrow_mut_data()?.copy_from_slice(&decompressed_data);
 
+    // Save state
+    compression_state.serialize(&mut *state_account.try_borrow_mut_data()?)?;
+    record_audit(program_id, audit_log_account, audit_entry)
+}
+
+fn process_decompress_and_verify(
+    program_id: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<AccountInfo>,
+    account_id: Pubkey,
+    merkle_proof: &[[u8; 32]],
+    index: u64,
+    expected_root: [u8; 32],
+) -> ProgramResult {
+    let account_to_decompress = next_account_info(account_info_iter)?;
+    let state_account = next_account_info(account_info_iter)?;
+    let global_state_account = next_account_info(account_info_iter)?;
+
+    let (_, plaintext) = decompress_account(
+        program_id,
+        account_to_decompress,
+        state_account,
+        global_state_account,
+        &account_id,
+    )?;
+
+    let leaf = solana_program::hash::hash(&plaintext).to_bytes();
+    if !verify_merkle_proof(&expected_root, &leaf, index, merkle_proof) {
+        return Err(CompressionError::InvalidMerkleProof.into());
+    }
+
+    Ok(())
+}
+
+/// Checks that `leaf` sits at `index` in the SHA-256 tree with `root`, given
+/// the sibling path from the leaf up. Parents hash `left || right`, so
+/// proofs from the merkle-tree lib's `HashKind::Sha256` trees verify here.
+fn verify_merkle_proof(root: &[u8; 32], leaf: &[u8; 32], index: u64, siblings: &[[u8; 32]]) -> bool {
+    let depth = siblings.len();
+    if depth > MAX_MERKLE_PROOF_DEPTH || index >> depth != 0 {
+        return false;
+    }
+
+    let mut current = *leaf;
+    for (level, sibling) in siblings.iter().enumerate() {
+        current = if (index >> level) & 1 == 0 {
+            solana_program::hash::hashv(&[&current, sibling]).to_bytes()
+        } else {
+            solana_program::hash::hashv(&[sibling, &current]).to_bytes()
+        };
+    }
+
+    current == *root
+}
+
+/// Checks the accounts shared by the decompressing instructions and returns
+/// the account's header with its decompressed contents. Writes nothing.
+fn decompress_account(
+    program_id: &Pubkey,
+    account_to_decompress: &AccountInfo,
+    state_account: &AccountInfo,
+    global_state_account: &AccountInfo,
+    account_id: &Pubkey,
+) -> Result<(CompressedAccountState, Vec<u8>), ProgramError> {
     // Verify account
-    if account_to_decompress.key != &account_id {
+    if account_to_decompress.key != account_id {
         return Err(ProgramError::InvalidArgument);
     }
+    let global_state = load_global_state(program_id, global_state_account)?;
 
     // Read compression state
-    let mut compression_state = CompressedAccountState::try_from_slice(&state_account.try_borrow_data()?)?;
+    let compression_state = CompressedAccountState::try_from_slice(&state_account.try_borrow_data()?)?;
 
     if !compression_state.is_compressed {
         return Err(ProgramError::InvalidAccountData);
     }
 
-    // Perform decompression
+    // The header's size claim sizes the output buffer, so refuse oversized
+    // claims before the decompressors allocate for them
+    if compression_state.original_size > global_state.max_decompress_size {
+        return Err(CompressionError::BufferOverflow.into());
+    }
+
+    // Perform decompression. This deliberately skips the algorithm mask:
+    // disabling an algorithm must not strand accounts already using it.
     let compressed_data = account_to_decompress.try_borrow_data()?;
-    let decompressed_data = match compression_state.compression_algorithm {
-        CompressionAlgorithm::Lz4 => decompress_lz4(&compressed_data, compression_state.original_size as usize)?,
-        CompressionAlgorithm::Snappy => decompress_snappy(&compressed_data, compression_state.original_size as usize)?,
-        CompressionAlgorithm::Zstd => decompress_zstd(&compressed_data, compression_state.original_size as usize)?,
-    };
+    let decompressed_data = decompress_with(
+        &compression_state.compression_algorithm,
+        &compressed_data,
+        compression_state.original_size as usize,
+    )?;
 
-    // Update stats
-    compression_state.compression_stats.total_decompressions += 1;
-    compression_state.last_modified = Clock::get()?.unix_timestamp;
-    compression_state.is_compressed = false;
+    Ok((compression_state, decompressed_data))
+}
 
-    // Save state
-    compression_state.serialize(&mut *state_account.try_borrow_mut_data()?)?;
+fn process_set_paused(
+    program_id: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<AccountInfo>,
+    paused: bool,
+) -> ProgramResult {
+    let authority_account = next_account_info(account_info_iter)?;
+    let global_state_account = next_account_info(account_info_iter)?;
+
+    if !authority_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut global_state = load_global_state(program_id, global_state_account)?;
+    if authority_account.key != &global_state.authority {
+        return Err(CompressionError::Unauthorized.into());
+    }
+
+    global_state.program_paused = paused;
+    CompressionState::pack(global_state, &mut global_state_account.try_borrow_mut_data()?)?;
+    log_info!("Program {}", if paused { "paused" } else { "resumed" });
+    Ok(())
+}
 
+fn process_initialize_audit_log(
+    program_id: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<AccountInfo>,
+) -> ProgramResult {
+    let payer = next_account_info(account_info_iter)?;
+    let audit_log_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let (audit_log_key, bump) = AuditLog::find_pda(program_id);
+    if audit_log_account.key != &audit_log_key {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            audit_log_account.key,
+            Rent::get()?.minimum_balance(AuditLog::LEN),
+            AuditLog::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), audit_log_account.clone(), system_program.clone()],
+        &[&[AUDIT_LOG_SEED, &[bump]]],
+    )?;
+    AuditLog::pack(AuditLog::new(), &mut audit_log_account.try_borrow_mut_data()?)
+}
+
+fn process_initialize_global_state(
+    program_id: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<AccountInfo>,
+    max_depth: u32,
+    max_buffer_size: u32,
+    max_decompress_size: u64,
+) -> ProgramResult {
+    let authority = next_account_info(account_info_iter)?;
+    let global_state_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let (global_state_key, bump) = CompressionState::find_pda(program_id);
+    if global_state_account.key != &global_state_key {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if max_decompress_size > MAX_UNCOMPRESSED_SIZE {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            authority.key,
+            global_state_account.key,
+            Rent::get()?.minimum_balance(CompressionState::LEN),
+            CompressionState::LEN as u64,
+            program_id,
+        ),
+        &[authority.clone(), global_state_account.clone(), system_program.clone()],
+        &[&[GLOBAL_STATE_SEED, &[bump]]],
+    )?;
+
+    let mut global_state = CompressionState::new(*authority.key, max_depth, max_buffer_size);
+    global_state.max_decompress_size = max_decompress_size;
+    CompressionState::pack(global_state, &mut global_state_account.try_borrow_mut_data()?)
+}
+
+/// Reads the global state, which must be the program's `GLOBAL_STATE_SEED`
+/// PDA
+fn load_global_state(
+    program_id: &Pubkey,
+    global_state_account: &AccountInfo,
+) -> Result<CompressionState, ProgramError> {
+    if global_state_account.key != &CompressionState::find_pda(program_id).0
+        || global_state_account.owner != program_id
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    CompressionState::unpack(&global_state_account.try_borrow_data()?)
+}
+
+/// Appends `entry` to the audit log, which must be the program's
+/// `AUDIT_LOG_SEED` PDA, and emits it as an event
+fn record_audit(program_id: &Pubkey, audit_log_account: &AccountInfo, entry: AuditEntry) -> ProgramResult {
+    if audit_log_account.key != &AuditLog::find_pda(program_id).0 || audit_log_account.owner != program_id {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut audit_log = AuditLog::unpack(&audit_log_account.try_borrow_data()?)?;
+    audit_log.record(entry.clone());
+    AuditLog::pack(audit_log, &mut audit_log_account.try_borrow_mut_data()?)?;
+    CompressionEvent::AuditRecorded { entry }.emit();
+    Ok(())
+}
+
+fn process_record_compression_failure(
+    program_id: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<AccountInfo>,
+    account_id: Pubkey,
+    algorithm: CompressionAlgorithm,
+    level: u8,
+) -> ProgramResult {
+    let failed_account = next_account_info(account_info_iter)?;
+    let global_state_account = next_account_info(account_info_iter)?;
+
+    if failed_account.key != &account_id {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if failed_account.owner != program_id {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Anyone can report, so the failure has to happen again here; errors
+    // other than the algorithm's own, like a compiled-out algorithm, are
+    // not its fault
+    let result = compress_with(&algorithm, &failed_account.try_borrow_data()?, level);
+    match result {
+        Ok(_) => return Err(CompressionError::FailureNotReproduced.into()),
+        Err(err) if err != CompressionError::CompressionFailed.into() => return Err(err),
+        Err(_) => {}
+    }
+
+    let mut global_state = load_global_state(program_id, global_state_account)?;
+    let disabled = global_state.record_algorithm_result(&algorithm, false);
+    CompressionState::pack(global_state, &mut global_state_account.try_borrow_mut_data()?)?;
+
+    if disabled {
+        log_info!("{:?} disabled after repeated failures", algorithm);
+        CompressionEvent::AlgorithmDisabled {
+            algorithm,
+            failures: ALGORITHM_FAILURE_THRESHOLD,
+        }
+        .emit();
+    }
     Ok(())
 }
 
@@ -288,13 +785,61 @@ fn process_validate_compression(
     Ok(())
 }
 
-// Helper functions for compression algorithms
+// Helper functions for compression algorithms. Algorithms compiled out of
+// this build fail with InvalidAlgorithm; a runtime mask is the caller's
+// concern and only applies to compression.
+#[allow(unreachable_patterns)]
+fn compress_with(algorithm: &CompressionAlgorithm, data: &[u8], level: u8) -> Result<Vec<u8>, ProgramError> {
+    match algorithm {
+        #[cfg(feature = "lz4")]
+        CompressionAlgorithm::Lz4 => compress_lz4(data, level),
+        #[cfg(feature = "snappy")]
+        CompressionAlgorithm::Snappy => compress_snappy(data),
+        #[cfg(feature = "zstd")]
+        CompressionAlgorithm::Zstd => compress_zstd(data, level),
+        _ => Err(CompressionError::InvalidAlgorithm.into()),
+    }
+}
+
+// The error code stays a plain CompressionFailed; which algorithm failed
+// and why goes to the log
+#[cfg(any(feature = "lz4", feature = "snappy", feature = "zstd"))]
+fn compression_failed(algorithm: CompressionAlgorithm, err: impl std::fmt::Display) -> ProgramError {
+    log_info!("{:?} compression failed: {}", algorithm, err);
+    CompressionError::CompressionFailed.into()
+}
+
+/// Checks that `compressed` decompresses back to exactly `original`
+fn verify_compression(algorithm: &CompressionAlgorithm, compressed: &[u8], original: &[u8]) -> ProgramResult {
+    let restored = decompress_with(algorithm, compressed, original.len())
+        .map_err(|_| CompressionError::VerificationFailed)?;
+    if restored != original {
+        return Err(CompressionError::VerificationFailed.into());
+    }
+    Ok(())
+}
+
+#[allow(unreachable_patterns)]
+fn decompress_with(algorithm: &CompressionAlgorithm, compressed: &[u8], original_size: usize) -> Result<Vec<u8>, ProgramError> {
+    match algorithm {
+        #[cfg(feature = "lz4")]
+        CompressionAlgorithm::Lz4 => decompress_lz4(compressed, original_size),
+        #[cfg(feature = "snappy")]
+        CompressionAlgorithm::Snappy => decompress_snappy(compressed, original_size),
+        #[cfg(feature = "zstd")]
+        CompressionAlgorithm::Zstd => decompress_zstd(compressed, original_size),
+        _ => Err(CompressionError::InvalidAlgorithm.into()),
+    }
+}
+
+#[cfg(feature = "lz4")]
 fn compress_lz4(data: &[u8], level: u8) -> Result<Vec<u8>, ProgramError> {
     let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
-    std::io::Write::write_all(&mut encoder, data).map_err(|_| ProgramError::InvalidAccountData)?;
-    encoder.finish().map_err(|_| ProgramError::InvalidAccountData)
+    std::io::Write::write_all(&mut encoder, data).map_err(|e| compression_failed(CompressionAlgorithm::Lz4, e))?;
+    encoder.finish().map_err(|e| compression_failed(CompressionAlgorithm::Lz4, e))
 }
 
+#[cfg(feature = "lz4")]
 fn decompress_lz4(compressed: &[u8], original_size: usize) -> Result<Vec<u8>, ProgramError> {
     let mut decoder = lz4_flex::frame::FrameDecoder::new(compressed);
     let mut decompressed = Vec::with_capacity(original_size);
@@ -302,23 +847,27 @@ fn decompress_lz4(compressed: &[u8], original_size: usize) -> Result<Vec<u8>, Pr
     Ok(decompressed)
 }
 
+#[cfg(feature = "snappy")]
 fn compress_snappy(data: &[u8]) -> Result<Vec<u8>, ProgramError> {
     snap::raw::Encoder::new()
         .compress_vec(data)
-        .map_err(|_| ProgramError::InvalidAccountData)
+        .map_err(|e| compression_failed(CompressionAlgorithm::Snappy, e))
 }
 
+#[cfg(feature = "snappy")]
 fn decompress_snappy(compressed: &[u8], original_size: usize) -> Result<Vec<u8>, ProgramError> {
     snap::raw::Decoder::new()
         .decompress_vec(compressed)
         .map_err(|_| ProgramError::InvalidAccountData)
 }
 
+#[cfg(feature = "zstd")]
 fn compress_zstd(data: &[u8], level: u8) -> Result<Vec<u8>, ProgramError> {
     zstd::encode_all(data, level as i32)
-        .map_err(|_| ProgramError::InvalidAccountData)
+        .map_err(|e| compression_failed(CompressionAlgorithm::Zstd, e))
 }
 
+#[cfg(feature = "zstd")]
 fn decompress_zstd(compressed: &[u8], original_size: usize) -> Result<Vec<u8>, ProgramError> {
     zstd::decode_all(compressed)
         .map_err(|_| ProgramError::InvalidAccountData)
@@ -343,6 +892,65 @@ mod tests {
         )
     }
 
+    /// Header of an account that has never been compressed
+    fn uncompressed_state() -> CompressedAccountState {
+        CompressedAccountState {
+            is_compressed: false,
+            original_size: 0,
+            compressed_size: 0,
+            compression_algorithm: CompressionAlgorithm::Lz4,
+            last_modified: 0,
+            compression_stats: CompressionStats {
+                total_compressions: 0,
+                total_decompressions: 0,
+                average_compression_ratio: 1.0,
+                best_compression_ratio: 1.0,
+                total_bytes_saved: 0,
+            },
+        }
+    }
+
+    /// Header of an Lz4 account compressed once from `original_size` bytes
+    fn compressed_state(original_size: u64, compressed_size: u64) -> CompressedAccountState {
+        let mut header = uncompressed_state();
+        header.is_compressed = true;
+        header.original_size = original_size;
+        header.compressed_size = compressed_size;
+        header.compression_stats.total_compressions = 1;
+        header
+    }
+
+    /// Packed global state account data
+    fn global_state_data(global_state: CompressionState) -> Vec<u8> {
+        let mut data = vec![0u8; CompressionState::LEN];
+        CompressionState::pack(global_state, &mut data).unwrap();
+        data
+    }
+
+    /// Writable account owned by the program
+    fn program_account<'a>(
+        key: &'a Pubkey,
+        lamports: &'a mut u64,
+        data: &'a mut [u8],
+        program_id: &'a Pubkey,
+    ) -> AccountInfo<'a> {
+        AccountInfo::new(key, false, true, lamports, data, program_id, false, Epoch::default())
+    }
+
+    /// Data-less system account, such as a payer or the global authority
+    fn system_account<'a>(key: &'a Pubkey, lamports: &'a mut u64, is_signer: bool) -> AccountInfo<'a> {
+        AccountInfo::new(
+            key,
+            is_signer,
+            true,
+            lamports,
+            &mut [],
+            &solana_program::system_program::ID,
+            false,
+            Epoch::default(),
+        )
+    }
+
     #[test]
     fn test_initialize_compression() {
         let program_id = Pubkey::new_unique();
@@ -370,6 +978,312 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_compiled_out_algorithm_is_rejected() {
+        let capabilities = Capabilities::compiled();
+        for algorithm in [CompressionAlgorithm::Lz4, CompressionAlgorithm::Snappy, CompressionAlgorithm::Zstd] {
+            let result = compress_with(&algorithm, &[1u8; 64], 1);
+            if capabilities.supports(&algorithm) {
+                assert!(result.is_ok());
+            } else {
+                assert_eq!(result, Err(CompressionError::InvalidAlgorithm.into()));
+            }
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_masked_algorithm_still_decompresses() {
+        let program_id = Pubkey::new_unique();
+        let data = vec![7u8; 512];
+
+        // An account compressed with Zstd before the mask changed
+        let existing = compress_with(&CompressionAlgorithm::Zstd, &data, 3).unwrap();
+
+        // Deployment stops new Zstd compressions
+        let authority_key = Pubkey::new_unique();
+        let mut global_state = CompressionState::new(authority_key, 32, 1024);
+        global_state.capabilities.supported_algorithms &= !state::ALGORITHM_ZSTD;
+        let mut global_data = global_state_data(global_state);
+
+        let (account_key, state_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let global_key = CompressionState::find_pda(&program_id).0;
+        let (mut account_lamports, mut state_lamports, mut global_lamports) = (0, 0, 0);
+        let mut account_data = data.clone();
+        let mut state_data = vec![0u8; 1000];
+        let account = program_account(&account_key, &mut account_lamports, &mut account_data, &program_id);
+        let state = program_account(&state_key, &mut state_lamports, &mut state_data, &program_id);
+        let global = program_account(&global_key, &mut global_lamports, &mut global_data, &program_id);
+
+        let config = CompressionConfig {
+            algorithm: CompressionAlgorithm::Zstd,
+            level: 3,
+            chunk_size: 1024,
+            concurrent_compression: false,
+            verify_compression: false,
+        };
+        let audit_key = Pubkey::new_unique();
+        let (mut audit_lamports, mut audit_data) = (0, vec![]);
+        let audit_log = program_account(&audit_key, &mut audit_lamports, &mut audit_data, &program_id);
+        let mut authority_lamports = 0;
+        let authority = system_account(&authority_key, &mut authority_lamports, true);
+        let accounts = vec![authority, account, state, global, audit_log];
+        let result = process_compress_account(&program_id, &mut accounts.iter(), AccountType::User, config);
+        assert_eq!(result, Err(CompressionError::InvalidAlgorithm.into()));
+
+        assert_eq!(decompress_with(&CompressionAlgorithm::Zstd, &existing, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_oversized_decompress_claim_is_rejected() {
+        let program_id = Pubkey::new_unique();
+        let account_key = Pubkey::new_unique();
+
+        // A header claiming 1 GB of output for a few bytes of input
+        let header = compressed_state(1 << 30, 16);
+        let mut state_data = header.try_to_vec().unwrap();
+
+        let global_state = CompressionState::new(Pubkey::new_unique(), 32, 1024);
+        assert!(header.original_size > global_state.max_decompress_size);
+        let mut global_data = global_state_data(global_state);
+
+        let (mut account_lamports, mut state_lamports, mut global_lamports) = (0, 0, 0);
+        let mut account_data = vec![0xFFu8; 16];
+        let state_key = Pubkey::new_unique();
+        let global_key = CompressionState::find_pda(&program_id).0;
+        let account = program_account(&account_key, &mut account_lamports, &mut account_data, &program_id);
+        let state = program_account(&state_key, &mut state_lamports, &mut state_data, &program_id);
+        let global = program_account(&global_key, &mut global_lamports, &mut global_data, &program_id);
+
+        let audit_key = Pubkey::new_unique();
+        let (mut audit_lamports, mut audit_data) = (0, vec![]);
+        let audit_log = program_account(&audit_key, &mut audit_lamports, &mut audit_data, &program_id);
+        let (payer_key, mut payer_lamports, mut system_lamports) = (Pubkey::new_unique(), 1_000_000_000, 0);
+        let payer = system_account(&payer_key, &mut payer_lamports, true);
+        let system_program = system_account(&solana_program::system_program::ID, &mut system_lamports, false);
+        let accounts = vec![payer, account, state.clone(), global, audit_log, system_program];
+        let result = process_decompress_account(&program_id, &mut accounts.iter(), account_key);
+        assert_eq!(result, Err(CompressionError::BufferOverflow.into()));
+
+        // Rejected before decompressing: the header is untouched
+        let after = CompressedAccountState::try_from_slice(&state.try_borrow_data().unwrap()).unwrap();
+        assert!(after.is_compressed);
+        assert_eq!(after.compression_stats.total_decompressions, 0);
+    }
+
+    // Collects sol_log_data output, which the default stubs only print
+    static LOGGED_DATA: std::sync::Mutex<Vec<Vec<u8>>> = std::sync::Mutex::new(Vec::new());
+
+    struct CaptureLogData;
+
+    impl solana_program::program_stubs::SyscallStubs for CaptureLogData {
+        fn sol_log_data(&self, fields: &[&[u8]]) {
+            LOGGED_DATA.lock().unwrap().extend(fields.iter().map(|field| field.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_oversized_compress_emits_failure_event() {
+        solana_program::program_stubs::set_syscall_stubs(Box::new(CaptureLogData));
+        let program_id = Pubkey::new_unique();
+        let account_key = Pubkey::new_unique();
+
+        let authority_key = Pubkey::new_unique();
+        let mut global_state = CompressionState::new(authority_key, 32, 1024);
+        global_state.max_decompress_size = 256;
+        let mut global_data = global_state_data(global_state);
+
+        let (mut account_lamports, mut state_lamports, mut global_lamports) = (0, 0, 0);
+        let mut account_data = vec![1u8; 512];
+        let mut state_data = uncompressed_state().try_to_vec().unwrap();
+        let (state_key, global_key) = (Pubkey::new_unique(), CompressionState::find_pda(&program_id).0);
+        let account = program_account(&account_key, &mut account_lamports, &mut account_data, &program_id);
+        let state = program_account(&state_key, &mut state_lamports, &mut state_data, &program_id);
+        let global = program_account(&global_key, &mut global_lamports, &mut global_data, &program_id);
+
+        let config = CompressionConfig {
+            algorithm: CompressionAlgorithm::Lz4,
+            level: 1,
+            chunk_size: 1024,
+            concurrent_compression: false,
+            verify_compression: false,
+        };
+        let audit_key = Pubkey::new_unique();
+        let (mut audit_lamports, mut audit_data) = (0, vec![]);
+        let audit_log = program_account(&audit_key, &mut audit_lamports, &mut audit_data, &program_id);
+        let mut authority_lamports = 0;
+        let authority = system_account(&authority_key, &mut authority_lamports, true);
+        let accounts = vec![authority, account, state, global, audit_log];
+        let result = process_compress_account(&program_id, &mut accounts.iter(), AccountType::User, config);
+        assert_eq!(result, Err(CompressionError::CompressionFailed.into()));
+
+        let expected = CompressionEvent::CompressionFailed {
+            account: account_key,
+            algorithm: CompressionAlgorithm::Lz4,
+            original_size: 512,
+        };
+        let logged = LOGGED_DATA.lock().unwrap();
+        assert!(logged
+            .iter()
+            .any(|data| CompressionEvent::try_from_slice(data).ok().as_ref() == Some(&expected)));
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_unreproduced_failure_is_not_recorded() {
+        let program_id = Pubkey::new_unique();
+        let account_key = Pubkey::new_unique();
+
+        let mut global_data = global_state_data(CompressionState::new(Pubkey::new_unique(), 32, 1024));
+
+        let (mut account_lamports, mut global_lamports) = (0, 0);
+        let mut account_data = vec![1u8; 512];
+        let global_key = CompressionState::find_pda(&program_id).0;
+        let account = program_account(&account_key, &mut account_lamports, &mut account_data, &program_id);
+        let global = program_account(&global_key, &mut global_lamports, &mut global_data, &program_id);
+
+        // Lz4 compresses this fine, so a report of it failing is refused
+        let accounts = vec![account, global.clone()];
+        let result = process_record_compression_failure(
+            &program_id,
+            &mut accounts.iter(),
+            account_key,
+            CompressionAlgorithm::Lz4,
+            1,
+        );
+        assert_eq!(result, Err(CompressionError::FailureNotReproduced.into()));
+
+        let after = CompressionState::unpack(&global.try_borrow_data().unwrap()).unwrap();
+        assert_eq!(after.algorithm_health[0], state::AlgorithmHealth::default());
+        assert!(after.capabilities.supports(&CompressionAlgorithm::Lz4));
+    }
+
+    /// Runs DecompressAndVerify over `compressed`, an Lz4 account holding
+    /// `original_size` bytes
+    fn decompress_and_verify(
+        compressed: &[u8],
+        original_size: u64,
+        merkle_proof: &[[u8; 32]],
+        index: u64,
+        expected_root: [u8; 32],
+    ) -> ProgramResult {
+        let program_id = Pubkey::new_unique();
+        let account_key = Pubkey::new_unique();
+        let mut state_data = compressed_state(original_size, compressed.len() as u64).try_to_vec().unwrap();
+        let mut global_data = global_state_data(CompressionState::new(Pubkey::new_unique(), 32, 1024));
+
+        let (mut account_lamports, mut state_lamports, mut global_lamports) = (0, 0, 0);
+        let mut account_data = compressed.to_vec();
+        let (state_key, global_key) = (Pubkey::new_unique(), CompressionState::find_pda(&program_id).0);
+        let account = program_account(&account_key, &mut account_lamports, &mut account_data, &program_id);
+        let state = program_account(&state_key, &mut state_lamports, &mut state_data, &program_id);
+        let global = program_account(&global_key, &mut global_lamports, &mut global_data, &program_id);
+
+        let accounts = vec![account, state.clone(), global];
+        let result = process_decompress_and_verify(
+            &program_id,
+            &mut accounts.iter(),
+            account_key,
+            merkle_proof,
+            index,
+            expected_root,
+        );
+
+        // Verification never touches the header
+        let after = CompressedAccountState::try_from_slice(&state.try_borrow_data().unwrap()).unwrap();
+        assert!(after.is_compressed);
+        assert_eq!(after.compression_stats.total_decompressions, 0);
+        result
+    }
+
+    /// Root of the depth-3 SHA-256 tree over `leaves`, zero-padded, and the
+    /// sibling path of the leaf at `index`
+    fn merkle_root_and_proof(leaves: &[[u8; 32]], index: usize) -> ([u8; 32], Vec<[u8; 32]>) {
+        let mut level = leaves.to_vec();
+        level.resize(8, [0u8; 32]);
+        let mut proof = Vec::new();
+        let mut index = index;
+        while level.len() > 1 {
+            proof.push(level[index ^ 1]);
+            level = level
+                .chunks(2)
+                .map(|pair| solana_program::hash::hashv(&[&pair[0], &pair[1]]).to_bytes())
+                .collect();
+            index /= 2;
+        }
+        (level[0], proof)
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_decompress_and_verify_membership() {
+        let leaves: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 300]).collect();
+        let hashes: Vec<[u8; 32]> = leaves.iter().map(|leaf| solana_program::hash::hash(leaf).to_bytes()).collect();
+        let (root, proof) = merkle_root_and_proof(&hashes, 2);
+
+        let compressed = compress_with(&CompressionAlgorithm::Lz4, &leaves[2], 1).unwrap();
+        assert_eq!(decompress_and_verify(&compressed, 300, &proof, 2, root), Ok(()));
+
+        // Wrong index and wrong root both fail membership
+        assert_eq!(
+            decompress_and_verify(&compressed, 300, &proof, 1, root),
+            Err(CompressionError::InvalidMerkleProof.into())
+        );
+        assert_eq!(
+            decompress_and_verify(&compressed, 300, &proof, 2, [7u8; 32]),
+            Err(CompressionError::InvalidMerkleProof.into())
+        );
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_decompress_and_verify_rejects_tampered_data() {
+        let leaf = vec![5u8; 300];
+        let (root, proof) = merkle_root_and_proof(&[solana_program::hash::hash(&leaf).to_bytes()], 0);
+
+        // Valid compressed data whose plaintext differs by one byte
+        let mut tampered = leaf.clone();
+        tampered[150] ^= 1;
+        let compressed = compress_with(&CompressionAlgorithm::Lz4, &tampered, 1).unwrap();
+        assert_eq!(
+            decompress_and_verify(&compressed, 300, &proof, 0, root),
+            Err(CompressionError::InvalidMerkleProof.into())
+        );
+
+        // Corrupted compressed bytes never reach the membership check
+        let mut corrupted = compress_with(&CompressionAlgorithm::Lz4, &leaf, 1).unwrap();
+        corrupted.truncate(corrupted.len() / 2);
+        assert!(decompress_and_verify(&corrupted, 300, &proof, 0, root).is_err());
+    }
+
+    #[test]
+    fn test_sampled_verification() {
+        let config = state::GlobalCompressionConfig::builder().verify_sample_rate(50).build().unwrap();
+        let slot = 42;
+
+        let mut verified = 0;
+        for i in 0..1000u32 {
+            let account = Pubkey::new_unique();
+            if !config.should_verify(slot, &account) {
+                continue;
+            }
+            verified += 1;
+            let data: Vec<u8> = i.to_le_bytes().iter().copied().cycle().take(256).collect();
+            let compressed = compress_with(&CompressionAlgorithm::Lz4, &data, 1).unwrap();
+            assert_eq!(verify_compression(&CompressionAlgorithm::Lz4, &compressed, &data), Ok(()));
+        }
+        assert!((400..=600).contains(&verified), "{} of 1000 verified at 50%", verified);
+
+        let data = vec![7u8; 256];
+        let compressed = compress_with(&CompressionAlgorithm::Lz4, &data, 1).unwrap();
+        let mut other = data.clone();
+        other[0] = 8;
+        assert_eq!(
+            verify_compression(&CompressionAlgorithm::Lz4, &compressed, &other),
+            Err(CompressionError::VerificationFailed.into())
+        );
+    }
+
     #[test]
     fn test_compression_workflow() {
         let program_id = Pubkey::new_unique();
@@ -395,7 +1309,34 @@ mod tests {
             verify_compression: true,
         };
 
-        let accounts = vec![account.clone(), state.clone()];
+        let mut global_data = vec![0u8; CompressionState::LEN];
+        CompressionState::pack(CompressionState::new(Pubkey::new_unique(), 32, 1024), &mut global_data).unwrap();
+        let global = AccountInfo::new(
+            &Pubkey::new_unique(),
+            false,
+            false,
+            &mut 0,
+            &mut global_data,
+            &program_id,
+            false,
+            Epoch::default(),
+        );
+
+        let (audit_key, _) = AuditLog::find_pda(&program_id);
+        let mut audit_data = vec![0u8; AuditLog::LEN];
+        AuditLog::pack(AuditLog::new(), &mut audit_data).unwrap();
+        let audit_log = AccountInfo::new(
+            &audit_key,
+            false,
+            true,
+            &mut 0,
+            &mut audit_data,
+            &program_id,
+            false,
+            Epoch::default(),
+        );
+
+        let accounts = vec![account.clone(), state.clone(), global, audit_log];
         let result = process_compress_account(
             &program_id,
             &mut accounts.iter(),
@@ -1,6 +1,7 @@
 use {
     borsh::{BorshDeserialize, BorshSerialize},
     solana_program::{
+        hash::hashv,
         program_error::ProgramError,
         program_pack::{IsInitialized, Pack, Sealed},
         pubkey::Pubkey,
@@ -9,6 +10,45 @@ use {
 
 use crate::error::CompressionError;
 
+/// Bits of `Capabilities::supported_algorithms`
+pub const ALGORITHM_LZ4: u8 = 1 << 0;
+pub const ALGORITHM_SNAPPY: u8 = 1 << 1;
+pub const ALGORITHM_ZSTD: u8 = 1 << 2;
+
+/// Largest chunk the program will compress in one pass
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024;
+
+/// Default `CompressionState::max_decompress_size`, the same limit the
+/// program-libs compressor puts on its input
+pub const MAX_UNCOMPRESSED_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Weight of the newest sample in `recent_compression_ratio`, the same
+/// weighting the program-libs compression queue uses for its ratio
+pub const RATIO_EWMA_WEIGHT: f64 = 0.1;
+
+/// Slots after which a queue claim that was never completed lapses and the
+/// account can be claimed again, about a minute at 400ms slots
+pub const CLAIM_EXPIRY_SLOTS: u64 = 150;
+
+/// Compressions per algorithm health window; an algorithm's counts start
+/// over once a window fills
+pub const ALGORITHM_HEALTH_WINDOW: u32 = 20;
+
+/// Failures within one window that disable an algorithm, a 25% failure rate
+pub const ALGORITHM_FAILURE_THRESHOLD: u32 = 5;
+
+/// Seed of the program's global `CompressionState` PDA
+pub const GLOBAL_STATE_SEED: &[u8] = b"global_state";
+
+/// Seed of the program's `AuditLog` PDA
+pub const AUDIT_LOG_SEED: &[u8] = b"audit_log";
+
+/// Entries an `AuditLog` keeps before overwriting the oldest
+pub const AUDIT_LOG_CAPACITY: usize = 64;
+
+/// Deepest tree a DecompressAndVerify proof can climb
+pub const MAX_MERKLE_PROOF_DEPTH: usize = 32;
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct CompressionState {
     pub is_initialized: bool,
@@ -19,20 +59,234 @@ pub struct CompressionState {
     pub total_bytes_saved: u64,
     pub compression_stats: GlobalCompressionStats,
     pub config: GlobalCompressionConfig,
+    pub capabilities: Capabilities,
+    /// Largest `original_size` a compressed account header may claim before
+    /// decompression is refused. Headers are account data, so the claim is
+    /// checked before anything is allocated for it.
+    pub max_decompress_size: u64,
+    /// Recent compression outcomes, indexed like `CompressionAlgorithm`
+    pub algorithm_health: [AlgorithmHealth; 3],
+    /// Set by the authority with `SetPaused`. While set, compressing and
+    /// decompressing fail with `ProgramPaused`; reads still work.
+    pub program_paused: bool,
+}
+
+impl CompressionState {
+    pub fn new(authority: Pubkey, max_depth: u32, max_buffer_size: u32) -> Self {
+        Self {
+            is_initialized: true,
+            authority,
+            max_depth,
+            max_buffer_size,
+            total_accounts_compressed: 0,
+            total_bytes_saved: 0,
+            compression_stats: GlobalCompressionStats::new(),
+            config: GlobalCompressionConfig::default(),
+            capabilities: Capabilities::compiled(),
+            max_decompress_size: MAX_UNCOMPRESSED_SIZE,
+            algorithm_health: [AlgorithmHealth::default(); 3],
+            program_paused: false,
+        }
+    }
+
+    pub fn find_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[GLOBAL_STATE_SEED], program_id)
+    }
+
+    /// Records the outcome of one compression with `algorithm`. Returns true
+    /// if this failure reached `ALGORITHM_FAILURE_THRESHOLD` within the
+    /// current window and disabled the algorithm.
+    ///
+    /// Disabling clears the algorithm's bit in `capabilities`, so only new
+    /// compressions are refused, and moves `default_algorithm` to one that
+    /// is still enabled.
+    pub fn record_algorithm_result(&mut self, algorithm: &CompressionAlgorithm, succeeded: bool) -> bool {
+        let health = &mut self.algorithm_health[algorithm.index()];
+        if health.attempts >= ALGORITHM_HEALTH_WINDOW {
+            health.attempts = 0;
+            health.failures = 0;
+        }
+        health.attempts += 1;
+        if succeeded {
+            return false;
+        }
+        health.failures += 1;
+        health.total_failures += 1;
+
+        if health.failures < ALGORITHM_FAILURE_THRESHOLD || !self.capabilities.supports(algorithm) {
+            return false;
+        }
+        self.capabilities.supported_algorithms &= !algorithm.mask();
+        if self.config.default_algorithm == *algorithm {
+            if let Some(healthy) = CompressionAlgorithm::ALL
+                .into_iter()
+                .find(|candidate| self.capabilities.supports(candidate))
+            {
+                self.config.default_algorithm = healthy;
+            }
+        }
+        true
+    }
+
+    /// Brings counters a past bug may have corrupted back into range, so
+    /// the corruption doesn't spread into every later update. Returns true
+    /// if anything changed. `unpack` runs this on every load.
+    pub fn repair(&mut self) -> bool {
+        let mut repaired = self.compression_stats.repair();
+        for health in &mut self.algorithm_health {
+            if health.attempts > ALGORITHM_HEALTH_WINDOW || health.failures > health.attempts {
+                // Start the window over rather than guess at its counts
+                health.attempts = 0;
+                health.failures = 0;
+                repaired = true;
+            }
+        }
+        if self.config.verify_sample_rate > 100 {
+            self.config.verify_sample_rate = 100;
+            repaired = true;
+        }
+        repaired
+    }
+}
+
+/// One algorithm's compression outcomes in the current health window
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct AlgorithmHealth {
+    pub attempts: u32,
+    pub failures: u32,
+    /// Failures across every window, for operators
+    pub total_failures: u64,
+}
+
+/// What this build of the program can do, fixed by its Cargo features
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    /// `ALGORITHM_*` bits for the algorithms compiled in. In
+    /// `CompressionState` a deployment may clear bits to stop new
+    /// compressions with an algorithm; decompression ignores the mask so
+    /// existing accounts stay readable.
+    pub supported_algorithms: u8,
+    pub max_chunk_size: u32,
+    /// Reserved; no build supports dictionaries yet
+    pub supports_dictionaries: bool,
+    /// Reserved; no build supports multi-transaction sessions yet
+    pub supports_sessions: bool,
+}
+
+impl Capabilities {
+    pub fn compiled() -> Self {
+        let mut supported_algorithms = 0;
+        if cfg!(feature = "lz4") {
+            supported_algorithms |= ALGORITHM_LZ4;
+        }
+        if cfg!(feature = "snappy") {
+            supported_algorithms |= ALGORITHM_SNAPPY;
+        }
+        if cfg!(feature = "zstd") {
+            supported_algorithms |= ALGORITHM_ZSTD;
+        }
+
+        Self {
+            supported_algorithms,
+            max_chunk_size: MAX_CHUNK_SIZE,
+            supports_dictionaries: false,
+            supports_sessions: false,
+        }
+    }
+
+    pub fn supports(&self, algorithm: &CompressionAlgorithm) -> bool {
+        self.supported_algorithms & algorithm.mask() != 0
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct GlobalCompressionStats {
     pub total_compressions: u64,
     pub total_decompressions: u64,
+    /// Cumulative average over every compression ever recorded
     pub average_compression_ratio: f64,
+    /// Exponentially-weighted moving average of the ratio, so drift in
+    /// recent compressibility shows up despite a long history
+    pub recent_compression_ratio: f64,
     pub best_compression_ratio: f64,
     pub worst_compression_ratio: f64,
     pub total_compression_time_ms: u64,
     pub average_compression_time_ms: u64,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+impl GlobalCompressionStats {
+    pub fn new() -> Self {
+        Self {
+            total_compressions: 0,
+            total_decompressions: 0,
+            average_compression_ratio: 1.0,
+            recent_compression_ratio: 1.0,
+            best_compression_ratio: 1.0,
+            worst_compression_ratio: 1.0,
+            total_compression_time_ms: 0,
+            average_compression_time_ms: 0,
+        }
+    }
+
+    /// Records one compression in both the cumulative and the moving
+    /// averages. The first sample seeds the moving average directly.
+    pub fn record_compression(&mut self, original_size: u64, compressed_size: u64, elapsed_ms: u64) {
+        let ratio = if compressed_size == 0 {
+            1.0
+        } else {
+            original_size as f64 / compressed_size as f64
+        };
+
+        self.total_compressions += 1;
+        let count = self.total_compressions as f64;
+        self.average_compression_ratio += (ratio - self.average_compression_ratio) / count;
+
+        if self.total_compressions == 1 {
+            self.recent_compression_ratio = ratio;
+            self.best_compression_ratio = ratio;
+            self.worst_compression_ratio = ratio;
+        } else {
+            self.recent_compression_ratio = (1.0 - RATIO_EWMA_WEIGHT) * self.recent_compression_ratio
+                + RATIO_EWMA_WEIGHT * ratio;
+            self.best_compression_ratio = self.best_compression_ratio.max(ratio);
+            self.worst_compression_ratio = self.worst_compression_ratio.min(ratio);
+        }
+
+        self.total_compression_time_ms += elapsed_ms;
+        self.average_compression_time_ms = self.total_compression_time_ms / self.total_compressions;
+    }
+
+    /// Resets ratios that are NaN, infinite or negative to 1.0, puts best
+    /// and worst back in order and recomputes the average time. Returns
+    /// true if anything changed.
+    fn repair(&mut self) -> bool {
+        let mut repaired = false;
+        for ratio in [
+            &mut self.average_compression_ratio,
+            &mut self.recent_compression_ratio,
+            &mut self.best_compression_ratio,
+            &mut self.worst_compression_ratio,
+        ] {
+            if !ratio.is_finite() || *ratio < 0.0 {
+                *ratio = 1.0;
+                repaired = true;
+            }
+        }
+        if self.best_compression_ratio < self.worst_compression_ratio {
+            std::mem::swap(&mut self.best_compression_ratio, &mut self.worst_compression_ratio);
+            repaired = true;
+        }
+
+        let average_time_ms = self.total_compression_time_ms / self.total_compressions.max(1);
+        if self.average_compression_time_ms != average_time_ms {
+            self.average_compression_time_ms = average_time_ms;
+            repaired = true;
+        }
+        repaired
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct GlobalCompressionConfig {
     pub default_algorithm: CompressionAlgorithm,
     pub min_chunk_size: u32,
@@ -40,6 +294,179 @@ pub struct GlobalCompressionConfig {
     pub concurrent_compressions_limit: u32,
     pub verify_all_compressions: bool,
     pub auto_decompress_on_access: bool,
+    /// When set, CompressAccount sends the rent the shrunken account no
+    /// longer needs to a destination chosen by its signer
+    pub refund_excess_rent: bool,
+    /// Percent of compressions, 0 to 100, that CompressAccount decompresses
+    /// again and checks against the input. `verify_all_compressions` is
+    /// the same as 100.
+    pub verify_sample_rate: u8,
+}
+
+impl Default for GlobalCompressionConfig {
+    fn default() -> Self {
+        Self {
+            default_algorithm: CompressionAlgorithm::Lz4,
+            min_chunk_size: 512,
+            max_chunk_size: MAX_CHUNK_SIZE,
+            concurrent_compressions_limit: 1,
+            verify_all_compressions: false,
+            auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
+        }
+    }
+}
+
+impl GlobalCompressionConfig {
+    /// Starts from the default configuration a new `CompressionState` gets.
+    pub fn builder() -> GlobalCompressionConfigBuilder {
+        GlobalCompressionConfigBuilder { config: Self::default() }
+    }
+
+    /// LZ4 over the largest chunks, for throughput.
+    pub fn fast() -> GlobalCompressionConfigBuilder {
+        Self::builder()
+            .default_algorithm(CompressionAlgorithm::Lz4)
+            .min_chunk_size(4096)
+            .max_chunk_size(MAX_CHUNK_SIZE)
+            .concurrent_compressions_limit(4)
+    }
+
+    /// Zstd for the best ratio. The level is per compression, in
+    /// `CompressionConfig`; dictionaries await `Capabilities::supports_dictionaries`.
+    pub fn dense() -> GlobalCompressionConfigBuilder {
+        Self::builder()
+            .default_algorithm(CompressionAlgorithm::Zstd)
+            .min_chunk_size(4096)
+            .max_chunk_size(MAX_CHUNK_SIZE)
+            .concurrent_compressions_limit(2)
+    }
+
+    /// Zstd with every compression verified, for accounts that are rarely
+    /// read back.
+    pub fn archival() -> GlobalCompressionConfigBuilder {
+        Self::dense()
+            .concurrent_compressions_limit(1)
+            .verify_all_compressions(true)
+            .auto_decompress_on_access(false)
+    }
+
+    /// Checks the configuration is usable at all: a non-empty chunk range
+    /// within `MAX_CHUNK_SIZE` and at least one concurrent compression.
+    pub fn validate(&self) -> Result<(), CompressionError> {
+        if self.min_chunk_size == 0
+            || self.max_chunk_size < self.min_chunk_size
+            || self.max_chunk_size > MAX_CHUNK_SIZE
+        {
+            return Err(CompressionError::InvalidChunkSize);
+        }
+        if self.concurrent_compressions_limit == 0 {
+            return Err(CompressionError::InvalidConcurrencyLimit);
+        }
+        if self.verify_sample_rate > 100 {
+            return Err(CompressionError::InvalidSampleRate);
+        }
+        Ok(())
+    }
+
+    /// Whether the compression of `account` at `slot` is one to verify. The
+    /// draw hashes both, so it is the same for every validator replaying
+    /// the slot but can't be steered by picking an account alone.
+    pub fn should_verify(&self, slot: u64, account: &Pubkey) -> bool {
+        if self.verify_all_compressions || self.verify_sample_rate >= 100 {
+            return true;
+        }
+        if self.verify_sample_rate == 0 {
+            return false;
+        }
+        let draw = hashv(&[account.as_ref(), &slot.to_le_bytes()]).to_bytes();
+        let draw = u64::from_le_bytes(draw[..8].try_into().unwrap());
+        draw % 100 < self.verify_sample_rate as u64
+    }
+
+    /// `validate`, plus the checks a deployment with `capabilities` applies:
+    /// the default algorithm must be enabled and chunks must fit its limit.
+    pub fn validate_for(&self, capabilities: &Capabilities) -> Result<(), CompressionError> {
+        self.validate()?;
+        if !capabilities.supports(&self.default_algorithm) {
+            return Err(CompressionError::InvalidAlgorithm);
+        }
+        if self.max_chunk_size > capabilities.max_chunk_size {
+            return Err(CompressionError::InvalidChunkSize);
+        }
+        Ok(())
+    }
+}
+
+/// Chainable construction of a `GlobalCompressionConfig`, so invalid
+/// combinations are caught before a transaction is sent.
+#[derive(Debug, Clone)]
+pub struct GlobalCompressionConfigBuilder {
+    config: GlobalCompressionConfig,
+}
+
+impl GlobalCompressionConfigBuilder {
+    pub fn default_algorithm(mut self, algorithm: CompressionAlgorithm) -> Self {
+        self.config.default_algorithm = algorithm;
+        self
+    }
+
+    pub fn min_chunk_size(mut self, size: u32) -> Self {
+        self.config.min_chunk_size = size;
+        self
+    }
+
+    pub fn max_chunk_size(mut self, size: u32) -> Self {
+        self.config.max_chunk_size = size;
+        self
+    }
+
+    pub fn concurrent_compressions_limit(mut self, limit: u32) -> Self {
+        self.config.concurrent_compressions_limit = limit;
+        self
+    }
+
+    pub fn verify_all_compressions(mut self, verify: bool) -> Self {
+        self.config.verify_all_compressions = verify;
+        self
+    }
+
+    pub fn auto_decompress_on_access(mut self, auto_decompress: bool) -> Self {
+        self.config.auto_decompress_on_access = auto_decompress;
+        self
+    }
+
+    pub fn refund_excess_rent(mut self, refund: bool) -> Self {
+        self.config.refund_excess_rent = refund;
+        self
+    }
+
+    pub fn verify_sample_rate(mut self, percent: u8) -> Self {
+        self.config.verify_sample_rate = percent;
+        self
+    }
+
+    pub fn build(self) -> Result<GlobalCompressionConfig, CompressionError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+
+    /// Builds for a deployment, as reported by `GetCapabilities`.
+    pub fn build_for(self, capabilities: &Capabilities) -> Result<GlobalCompressionConfig, CompressionError> {
+        self.config.validate_for(capabilities)?;
+        Ok(self.config)
+    }
+}
+
+/// Lets anything taking a config accept `impl TryInto<GlobalCompressionConfig>`
+/// and so either a config or a builder.
+impl TryFrom<GlobalCompressionConfigBuilder> for GlobalCompressionConfig {
+    type Error = CompressionError;
+
+    fn try_from(builder: GlobalCompressionConfigBuilder) -> Result<Self, Self::Error> {
+        builder.build()
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
@@ -49,6 +476,27 @@ pub enum CompressionAlgorithm {
     Zstd,
 }
 
+impl CompressionAlgorithm {
+    pub const ALL: [CompressionAlgorithm; 3] = [Self::Lz4, Self::Snappy, Self::Zstd];
+
+    fn index(&self) -> usize {
+        match self {
+            Self::Lz4 => 0,
+            Self::Snappy => 1,
+            Self::Zstd => 2,
+        }
+    }
+
+    /// This algorithm's bit in `Capabilities::supported_algorithms`
+    pub fn mask(&self) -> u8 {
+        match self {
+            Self::Lz4 => ALGORITHM_LZ4,
+            Self::Snappy => ALGORITHM_SNAPPY,
+            Self::Zstd => ALGORITHM_ZSTD,
+        }
+    }
+}
+
 impl Sealed for CompressionState {}
 
 impl IsInitialized for CompressionState {
@@ -60,13 +508,19 @@ impl IsInitialized for CompressionState {
 impl Pack for CompressionState {
     const LEN: usize = 1024; // Fixed size for the state account
 
-    fn pack_into_slice(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+    fn pack_into_slice(&self, dst: &mut [u8]) {
         let mut slice = dst;
-        self.serialize(&mut slice).map_err(|_| CompressionError::BufferOverflow.into())
+        self.serialize(&mut slice)
+            .expect("CompressionState exceeds CompressionState::LEN");
     }
 
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        Self::try_from_slice(src).map_err(|_| CompressionError::InvalidAccountState.into())
+        // The account is LEN bytes but the encoding is shorter
+        let mut state = Self::deserialize(&mut &src[..]).map_err(|_| CompressionError::InvalidAccountState)?;
+        if state.repair() {
+            log_info!("CompressionState: repaired inconsistent stats on load");
+        }
+        Ok(state)
     }
 }
 
@@ -92,6 +546,12 @@ pub enum AccountType {
 }
 
 impl CompressedAccountMetadata {
+    /// Bytes to allocate for a metadata account. Every field is fixed-size,
+    /// so this is also the exact encoded length.
+    pub const fn account_size() -> usize {
+        crate::layout::compressed_account_metadata::LEN
+    }
+
     pub fn get_compression_ratio(&self) -> f64 {
         if self.compressed_size == 0 {
             return 1.0;
@@ -104,46 +564,176 @@ impl CompressedAccountMetadata {
     }
 }
 
+/// Order in which a `CompressionQueue` hands out accounts, fixed when the
+/// queue is created
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueMode {
+    /// Strictly in enqueue order
+    #[default]
+    Fifo,
+    /// One account per group in turn, so a group with a long backlog cannot
+    /// starve the others
+    RoundRobin,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct QueuedAccount {
+    pub account: Pubkey,
+    /// Who enqueued the account; round-robin queues take turns between groups
+    pub group: Pubkey,
+    /// Slot at which a crank claimed the account for processing. Claimed
+    /// accounts stay queued, skipped by other cranks, until completed or
+    /// until the claim is `CLAIM_EXPIRY_SLOTS` old
+    pub claimed_at: Option<u64>,
+}
+
+impl QueuedAccount {
+    /// Whether a crank may take this account: `slot` is `None` for
+    /// `dequeue`, which never takes over a claim.
+    fn is_claimable(&self, slot: Option<u64>) -> bool {
+        match (self.claimed_at, slot) {
+            (None, _) => true,
+            (Some(claimed_at), Some(slot)) => slot >= claimed_at.saturating_add(CLAIM_EXPIRY_SLOTS),
+            (Some(_), None) => false,
+        }
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct CompressionQueue {
     pub head: u32,
     pub tail: u32,
     pub size: u32,
     pub max_size: u32,
-    pub accounts: Vec<Pubkey>,
+    pub mode: QueueMode,
+    /// Queued accounts, oldest first
+    pub accounts: Vec<QueuedAccount>,
+    /// Round-robin only: groups with queued accounts, in serving order
+    pub groups: Vec<Pubkey>,
+    /// Round-robin only: index into `groups` of the next group to serve.
+    /// Stored with the queue so turns carry over between cranks
+    pub cursor: u32,
 }
 
 impl CompressionQueue {
     pub fn new(max_size: u32) -> Self {
+        Self::with_mode(max_size, QueueMode::default())
+    }
+
+    pub fn with_mode(max_size: u32, mode: QueueMode) -> Self {
         Self {
             head: 0,
             tail: 0,
             size: 0,
             max_size,
+            mode,
             accounts: Vec::with_capacity(max_size as usize),
+            // There are never more groups than queued accounts
+            groups: match mode {
+                QueueMode::RoundRobin => Vec::with_capacity(max_size as usize),
+                QueueMode::Fifo => Vec::new(),
+            },
+            cursor: 0,
         }
     }
 
-    pub fn enqueue(&mut self, account: Pubkey) -> Result<(), CompressionError> {
+    pub fn enqueue(&mut self, account: Pubkey, group: Pubkey) -> Result<(), CompressionError> {
         if self.size >= self.max_size {
             return Err(CompressionError::BufferOverflow);
         }
 
-        self.accounts.push(account);
+        self.accounts.push(QueuedAccount { account, group, claimed_at: None });
+        if self.mode == QueueMode::RoundRobin && !self.groups.contains(&group) {
+            self.groups.push(group);
+        }
         self.size += 1;
         self.tail = (self.tail + 1) % self.max_size;
         Ok(())
     }
 
-    pub fn dequeue(&mut self) -> Option<Pubkey> {
-        if self.size == 0 {
-            return None;
+    /// Index in `accounts` of the next account to hand out in the queue's
+    /// order, skipping ones that are not claimable at `slot`. Round-robin
+    /// queues move their cursor past the group served.
+    fn next_index(&mut self, slot: Option<u64>) -> Option<usize> {
+        match self.mode {
+            QueueMode::Fifo => self.accounts.iter().position(|queued| queued.is_claimable(slot)),
+            QueueMode::RoundRobin => {
+                let groups = self.groups.len();
+                let (turn, index) = (0..groups)
+                    .map(|offset| (self.cursor as usize + offset) % groups)
+                    .find_map(|turn| {
+                        let group = self.groups[turn];
+                        self.accounts
+                            .iter()
+                            .position(|queued| queued.group == group && queued.is_claimable(slot))
+                            .map(|index| (turn, index))
+                    })?;
+                self.cursor = ((turn + 1) % groups) as u32;
+                Some(index)
+            }
         }
+    }
 
-        let account = self.accounts.remove(self.head as usize);
+    fn remove(&mut self, index: usize) -> QueuedAccount {
+        let queued = self.accounts.remove(index);
         self.size -= 1;
         self.head = (self.head + 1) % self.max_size;
-        Some(account)
+
+        if self.mode == QueueMode::RoundRobin
+            && !self.accounts.iter().any(|other| other.group == queued.group)
+        {
+            let drained = self.groups.iter().position(|group| *group == queued.group).unwrap();
+            self.groups.remove(drained);
+            // Later groups slide down a slot, the cursor with them
+            if drained < self.cursor as usize {
+                self.cursor -= 1;
+            }
+            if self.cursor as usize >= self.groups.len() {
+                self.cursor = 0;
+            }
+        }
+
+        queued
+    }
+
+    /// Removes the next unclaimed account in the queue's order.
+    pub fn dequeue(&mut self) -> Option<Pubkey> {
+        let index = self.next_index(None)?;
+        Some(self.remove(index).account)
+    }
+
+    /// Removes up to `max_items` accounts for one crank.
+    pub fn dequeue_batch(&mut self, max_items: usize) -> Vec<Pubkey> {
+        (0..max_items).map_while(|_| self.dequeue()).collect()
+    }
+
+    /// Claims the next account in the queue's order for processing at
+    /// `slot`, leaving it queued so a crank that dies mid-way loses nothing.
+    /// Other cranks skip it until `complete` removes it or the claim
+    /// expires.
+    pub fn claim(&mut self, slot: u64) -> Option<Pubkey> {
+        let index = self.next_index(Some(slot))?;
+        let queued = &mut self.accounts[index];
+        queued.claimed_at = Some(slot);
+        Some(queued.account)
+    }
+
+    /// Claims up to `max_items` accounts for one crank.
+    pub fn claim_batch(&mut self, max_items: usize, slot: u64) -> Vec<Pubkey> {
+        (0..max_items).map_while(|_| self.claim(slot)).collect()
+    }
+
+    /// Removes `account` once processed. `claimed_at` is the slot the crank
+    /// claimed it at; if the claim lapsed and another crank took the account
+    /// over, only that crank may complete it.
+    pub fn complete(&mut self, account: &Pubkey, claimed_at: u64) -> Result<(), CompressionError> {
+        let index = self
+            .accounts
+            .iter()
+            .position(|queued| queued.account == *account && queued.claimed_at == Some(claimed_at))
+            .ok_or(CompressionError::InvalidAccountState)?;
+        self.remove(index);
+        Ok(())
     }
 
     pub fn is_empty(&self) -> bool {
@@ -153,4 +743,446 @@ impl CompressionQueue {
     pub fn is_full(&self) -> bool {
         self.size == self.max_size
     }
-} 
\ No newline at end of file
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOperation {
+    Compress,
+    Decompress,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub operation: AuditOperation,
+    pub account: Pubkey,
+    pub slot: u64,
+    /// SHA-256 of the account data before the operation
+    pub original_hash: [u8; 32],
+    /// SHA-256 of the data the operation produced
+    pub result_hash: [u8; 32],
+}
+
+impl AuditEntry {
+    pub const LEN: usize = 1 + 32 + 8 + 32 + 32;
+}
+
+/// Every successful compress and decompress, in the `AUDIT_LOG_SEED` PDA.
+/// Keeps the last `AUDIT_LOG_CAPACITY` entries; older ones survive only in
+/// the transaction logs, as `AuditRecorded` events.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default)]
+pub struct AuditLog {
+    pub is_initialized: bool,
+    /// Entries ever recorded, evicted ones included
+    pub total_entries: u64,
+    /// Ring buffer: once full, slot `total_entries % AUDIT_LOG_CAPACITY`
+    /// holds the oldest entry. `oldest_first` reads it in order.
+    pub entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self {
+            is_initialized: true,
+            total_entries: 0,
+            entries: Vec::with_capacity(AUDIT_LOG_CAPACITY),
+        }
+    }
+
+    pub fn find_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[AUDIT_LOG_SEED], program_id)
+    }
+
+    /// Appends `entry`, overwriting the oldest once the log is full
+    pub fn record(&mut self, entry: AuditEntry) {
+        if self.entries.len() < AUDIT_LOG_CAPACITY {
+            self.entries.push(entry);
+        } else {
+            self.entries[(self.total_entries % AUDIT_LOG_CAPACITY as u64) as usize] = entry;
+        }
+        self.total_entries += 1;
+    }
+
+    pub fn oldest_first(&self) -> impl Iterator<Item = &AuditEntry> {
+        let oldest = if self.entries.len() < AUDIT_LOG_CAPACITY {
+            0
+        } else {
+            (self.total_entries % AUDIT_LOG_CAPACITY as u64) as usize
+        };
+        self.entries[oldest..].iter().chain(&self.entries[..oldest])
+    }
+}
+
+impl Sealed for AuditLog {}
+
+impl IsInitialized for AuditLog {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for AuditLog {
+    const LEN: usize = 1 + 8 + 4 + AUDIT_LOG_CAPACITY * AuditEntry::LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let mut slice = dst;
+        self.serialize(&mut slice)
+            .expect("AuditLog exceeds AuditLog::LEN");
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        // Short of capacity, the encoding doesn't fill the account
+        Self::deserialize(&mut &src[..]).map_err(|_| CompressionError::InvalidAccountState.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiled_capabilities() {
+        let capabilities = Capabilities::compiled();
+
+        assert_eq!(capabilities.supports(&CompressionAlgorithm::Lz4), cfg!(feature = "lz4"));
+        assert_eq!(capabilities.supports(&CompressionAlgorithm::Snappy), cfg!(feature = "snappy"));
+        assert_eq!(capabilities.supports(&CompressionAlgorithm::Zstd), cfg!(feature = "zstd"));
+        assert_eq!(capabilities.max_chunk_size, MAX_CHUNK_SIZE);
+
+        let state = CompressionState::new(Pubkey::new_unique(), 32, 1024);
+        assert_eq!(state.capabilities, capabilities);
+        assert!(state.try_to_vec().unwrap().len() <= CompressionState::LEN);
+    }
+
+    #[test]
+    fn test_metadata_account_size() {
+        let account_types = [AccountType::User, AccountType::Token, AccountType::NFT, AccountType::Program];
+        for account_type in account_types {
+            for compression_algorithm in [CompressionAlgorithm::Lz4, CompressionAlgorithm::Snappy, CompressionAlgorithm::Zstd] {
+                let metadata = CompressedAccountMetadata {
+                    account_type: account_type.clone(),
+                    original_size: u64::MAX,
+                    compressed_size: 1,
+                    compression_algorithm,
+                    compression_level: 22,
+                    last_accessed: i64::MIN,
+                    access_count: 7,
+                    compression_time_ms: 0,
+                    verification_hash: [0xAB; 32],
+                };
+                assert_eq!(metadata.try_to_vec().unwrap().len(), CompressedAccountMetadata::account_size());
+            }
+        }
+    }
+
+    #[test]
+    fn test_recent_ratio_tracks_step_change() {
+        let mut stats = GlobalCompressionStats::new();
+
+        // Long history of highly compressible data (4:1)
+        for _ in 0..1000 {
+            stats.record_compression(4096, 1024, 2);
+        }
+        assert!((stats.average_compression_ratio - 4.0).abs() < 1e-9);
+        assert!((stats.recent_compression_ratio - 4.0).abs() < 1e-9);
+
+        // Data suddenly becomes incompressible
+        for _ in 0..20 {
+            stats.record_compression(4096, 4096, 2);
+        }
+
+        assert!(stats.recent_compression_ratio < 1.5);
+        assert!(stats.average_compression_ratio > 3.9);
+        assert_eq!(stats.best_compression_ratio, 4.0);
+        assert_eq!(stats.worst_compression_ratio, 1.0);
+        assert_eq!(stats.total_compressions, 1020);
+        assert_eq!(stats.average_compression_time_ms, 2);
+    }
+
+    fn enqueue_groups(queue: &mut CompressionQueue, group_a: Pubkey, group_b: Pubkey) -> Vec<Pubkey> {
+        let accounts: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        for account in &accounts[..10] {
+            queue.enqueue(*account, group_a).unwrap();
+        }
+        for account in &accounts[10..] {
+            queue.enqueue(*account, group_b).unwrap();
+        }
+        accounts
+    }
+
+    #[test]
+    fn test_full_queue_keeps_its_allocation() {
+        for mode in [QueueMode::Fifo, QueueMode::RoundRobin] {
+            let mut queue = CompressionQueue::with_mode(8, mode);
+            let (accounts, groups) = (queue.accounts.capacity(), queue.groups.capacity());
+            for _ in 0..8 {
+                queue.enqueue(Pubkey::new_unique(), Pubkey::new_unique()).unwrap();
+            }
+            assert!(queue.is_full());
+            assert_eq!(queue.enqueue(Pubkey::new_unique(), Pubkey::new_unique()), Err(CompressionError::BufferOverflow));
+            assert_eq!((queue.accounts.capacity(), queue.groups.capacity()), (accounts, groups));
+        }
+    }
+
+    #[test]
+    fn test_queue_defaults_to_fifo() {
+        let mut queue = CompressionQueue::new(16);
+        assert_eq!(queue.mode, QueueMode::Fifo);
+
+        let accounts = enqueue_groups(&mut queue, Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(queue.dequeue_batch(12), accounts);
+        assert!(queue.is_empty());
+        assert!(queue.groups.is_empty());
+    }
+
+    #[test]
+    fn test_round_robin_serves_small_group_early() {
+        let mut queue = CompressionQueue::with_mode(16, QueueMode::RoundRobin);
+        let accounts = enqueue_groups(&mut queue, Pubkey::new_unique(), Pubkey::new_unique());
+        let (a, b) = accounts.split_at(10);
+
+        assert_eq!(queue.dequeue_batch(4), vec![a[0], b[0], a[1], b[1]]);
+
+        // Once B is drained, A gets every slot
+        assert_eq!(queue.groups.len(), 1);
+        assert_eq!(queue.dequeue_batch(16), a[2..].to_vec());
+        assert!(queue.is_empty());
+        assert!(queue.groups.is_empty());
+    }
+
+    #[test]
+    fn test_round_robin_cursor_persists_across_cranks() {
+        let mut queue = CompressionQueue::with_mode(16, QueueMode::RoundRobin);
+        let accounts = enqueue_groups(&mut queue, Pubkey::new_unique(), Pubkey::new_unique());
+
+        // One item per crank, with the queue round-tripped through its
+        // account data in between
+        let mut served = Vec::new();
+        for _ in 0..4 {
+            served.extend(queue.dequeue_batch(1));
+            queue = CompressionQueue::try_from_slice(&queue.try_to_vec().unwrap()).unwrap();
+        }
+        assert_eq!(served, vec![accounts[0], accounts[10], accounts[1], accounts[11]]);
+    }
+
+    #[test]
+    fn test_overlapping_cranks_process_each_account_once() {
+        for mode in [QueueMode::Fifo, QueueMode::RoundRobin] {
+            let mut queue = CompressionQueue::with_mode(16, mode);
+            let accounts = enqueue_groups(&mut queue, Pubkey::new_unique(), Pubkey::new_unique());
+
+            // Crank B starts while crank A's claims are still in flight
+            let claimed_a = queue.claim_batch(5, 100);
+            let claimed_b = queue.claim_batch(5, 101);
+            assert!(claimed_b.iter().all(|account| !claimed_a.contains(account)));
+            assert!(queue.dequeue().is_some_and(|account| !claimed_a.contains(&account)
+                && !claimed_b.contains(&account)));
+
+            let mut compressed = std::collections::HashMap::new();
+            for (claims, slot) in [(&claimed_a, 100), (&claimed_b, 101)] {
+                for account in claims.iter() {
+                    *compressed.entry(*account).or_insert(0) += 1;
+                    queue.complete(account, slot).unwrap();
+                }
+            }
+
+            // The remaining accounts go to whichever crank asks next
+            for account in queue.claim_batch(16, 102) {
+                *compressed.entry(account).or_insert(0) += 1;
+                queue.complete(&account, 102).unwrap();
+            }
+            assert!(queue.is_empty());
+            assert_eq!(compressed.len(), accounts.len() - 1);
+            assert!(compressed.values().all(|count| *count == 1));
+        }
+    }
+
+    #[test]
+    fn test_stale_claim_is_reclaimable() {
+        let mut queue = CompressionQueue::new(4);
+        let account = Pubkey::new_unique();
+        queue.enqueue(account, Pubkey::new_unique()).unwrap();
+
+        // The first crank claims the account and never completes it
+        assert_eq!(queue.claim(100), Some(account));
+        assert_eq!(queue.claim(100 + CLAIM_EXPIRY_SLOTS - 1), None);
+        assert_eq!(queue.dequeue(), None);
+
+        assert_eq!(queue.claim(100 + CLAIM_EXPIRY_SLOTS), Some(account));
+        // The lapsed claim can no longer complete it
+        assert_eq!(queue.complete(&account, 100), Err(CompressionError::InvalidAccountState));
+        queue.complete(&account, 100 + CLAIM_EXPIRY_SLOTS).unwrap();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_config_presets() {
+        let fast = GlobalCompressionConfig::fast().build().unwrap();
+        assert_eq!(fast.default_algorithm, CompressionAlgorithm::Lz4);
+        assert_eq!(fast.max_chunk_size, MAX_CHUNK_SIZE);
+
+        let dense = GlobalCompressionConfig::dense().build().unwrap();
+        assert_eq!(dense.default_algorithm, CompressionAlgorithm::Zstd);
+        assert!(!dense.verify_all_compressions);
+
+        let archival = GlobalCompressionConfig::archival().build().unwrap();
+        assert_eq!(archival.default_algorithm, CompressionAlgorithm::Zstd);
+        assert!(archival.verify_all_compressions);
+        assert!(!archival.auto_decompress_on_access);
+
+        assert_eq!(GlobalCompressionConfig::builder().build().unwrap(), GlobalCompressionConfig::default());
+        assert_eq!(CompressionState::new(Pubkey::new_unique(), 32, 1024).config, GlobalCompressionConfig::default());
+        assert_eq!(GlobalCompressionConfig::try_from(GlobalCompressionConfig::fast()), Ok(fast));
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let invalid = [
+            (GlobalCompressionConfig::builder().min_chunk_size(0), CompressionError::InvalidChunkSize),
+            (
+                GlobalCompressionConfig::builder().min_chunk_size(4096).max_chunk_size(1024),
+                CompressionError::InvalidChunkSize,
+            ),
+            (
+                GlobalCompressionConfig::builder().max_chunk_size(MAX_CHUNK_SIZE + 1),
+                CompressionError::InvalidChunkSize,
+            ),
+            (
+                GlobalCompressionConfig::fast().concurrent_compressions_limit(0),
+                CompressionError::InvalidConcurrencyLimit,
+            ),
+            (GlobalCompressionConfig::builder().verify_sample_rate(101), CompressionError::InvalidSampleRate),
+        ];
+        for (builder, err) in invalid {
+            assert_eq!(builder.build(), Err(err));
+        }
+
+        // A deployment can mask algorithms and lower the chunk limit
+        let mut capabilities = Capabilities::compiled();
+        capabilities.supported_algorithms = ALGORITHM_LZ4;
+        assert_eq!(
+            GlobalCompressionConfig::dense().build_for(&capabilities),
+            Err(CompressionError::InvalidAlgorithm)
+        );
+        assert!(GlobalCompressionConfig::fast().build_for(&capabilities).is_ok());
+        capabilities.max_chunk_size = 8192;
+        assert_eq!(
+            GlobalCompressionConfig::fast().build_for(&capabilities),
+            Err(CompressionError::InvalidChunkSize)
+        );
+    }
+
+    #[test]
+    fn test_verify_sampling() {
+        let accounts: Vec<Pubkey> = (0..1000).map(|_| Pubkey::new_unique()).collect();
+        let sampled = |config: &GlobalCompressionConfig, slot| {
+            accounts.iter().filter(|account| config.should_verify(slot, account)).count()
+        };
+
+        let none = GlobalCompressionConfig::default();
+        assert_eq!(sampled(&none, 7), 0);
+        let all = GlobalCompressionConfig::builder().verify_sample_rate(100).build().unwrap();
+        assert_eq!(sampled(&all, 7), accounts.len());
+        assert_eq!(sampled(&GlobalCompressionConfig::archival().build().unwrap(), 7), accounts.len());
+
+        let half = GlobalCompressionConfig::builder().verify_sample_rate(50).build().unwrap();
+        let count = sampled(&half, 7);
+        assert!((400..=600).contains(&count), "{} of 1000 sampled at 50%", count);
+
+        // Deterministic for a slot, and a different slot draws differently
+        assert_eq!(sampled(&half, 7), count);
+        let moved = accounts
+            .iter()
+            .filter(|account| half.should_verify(7, account) != half.should_verify(8, account))
+            .count();
+        assert!(moved > 0);
+    }
+
+    #[test]
+    fn test_repeated_zstd_failures_disable_zstd() {
+        let mut state = CompressionState::new(Pubkey::new_unique(), 32, 1024);
+        state.capabilities.supported_algorithms = ALGORITHM_LZ4 | ALGORITHM_ZSTD;
+        state.config.default_algorithm = CompressionAlgorithm::Zstd;
+        let zstd = CompressionAlgorithm::Zstd;
+
+        // One failure short of the threshold in a full window is tolerated,
+        // and the next window starts from zero
+        for attempt in 0..ALGORITHM_HEALTH_WINDOW {
+            let succeeded = attempt >= ALGORITHM_FAILURE_THRESHOLD - 1;
+            assert!(!state.record_algorithm_result(&zstd, succeeded));
+        }
+        assert!(state.capabilities.supports(&zstd));
+
+        for _ in 1..ALGORITHM_FAILURE_THRESHOLD {
+            assert!(!state.record_algorithm_result(&zstd, false));
+        }
+        assert!(state.record_algorithm_result(&zstd, false));
+
+        assert!(!state.capabilities.supports(&zstd));
+        assert!(state.capabilities.supports(&CompressionAlgorithm::Lz4));
+        assert_eq!(state.config.default_algorithm, CompressionAlgorithm::Lz4);
+        assert_eq!(state.algorithm_health[2].total_failures, 2 * ALGORITHM_FAILURE_THRESHOLD as u64 - 1);
+
+        // Already disabled, so later failures don't report it again
+        assert!(!state.record_algorithm_result(&zstd, false));
+        // Other algorithms are tracked separately
+        assert_eq!(state.algorithm_health[0], AlgorithmHealth::default());
+        assert!(state.try_to_vec().unwrap().len() <= CompressionState::LEN);
+    }
+
+    #[test]
+    fn test_audit_log_evicts_oldest() {
+        let entry = |slot: u64| AuditEntry {
+            operation: if slot % 2 == 0 { AuditOperation::Compress } else { AuditOperation::Decompress },
+            account: Pubkey::new_unique(),
+            slot,
+            original_hash: [slot as u8; 32],
+            result_hash: [!(slot as u8); 32],
+        };
+
+        let mut log = AuditLog::new();
+        for slot in 0..AUDIT_LOG_CAPACITY as u64 {
+            log.record(entry(slot));
+        }
+        let slots: Vec<u64> = log.oldest_first().map(|entry| entry.slot).collect();
+        assert_eq!(slots, (0..AUDIT_LOG_CAPACITY as u64).collect::<Vec<_>>());
+
+        // A full log packs into exactly LEN bytes
+        let mut data = vec![0u8; AuditLog::LEN];
+        AuditLog::pack(log, &mut data).unwrap();
+        let mut log = AuditLog::unpack(&data).unwrap();
+
+        for slot in AUDIT_LOG_CAPACITY as u64..AUDIT_LOG_CAPACITY as u64 + 10 {
+            log.record(entry(slot));
+        }
+        assert_eq!(log.total_entries, AUDIT_LOG_CAPACITY as u64 + 10);
+        let slots: Vec<u64> = log.oldest_first().map(|entry| entry.slot).collect();
+        assert_eq!(slots, (10..AUDIT_LOG_CAPACITY as u64 + 10).collect::<Vec<_>>());
+        assert_eq!(log.try_to_vec().unwrap().len(), AuditLog::LEN);
+    }
+
+    #[test]
+    fn test_unpack_repairs_corrupted_stats() {
+        let mut state = CompressionState::new(Pubkey::new_unique(), 32, 1024);
+        state.compression_stats.record_compression(400, 100, 10);
+        state.compression_stats.average_compression_ratio = -3.0;
+        state.compression_stats.best_compression_ratio = f64::INFINITY;
+        state.compression_stats.worst_compression_ratio = 2.0;
+        state.compression_stats.average_compression_time_ms = 999;
+        state.algorithm_health[1].failures = 3;
+        state.config.verify_sample_rate = 250;
+
+        let mut data = vec![0u8; CompressionState::LEN];
+        CompressionState::pack(state, &mut data).unwrap();
+        let mut state = CompressionState::unpack(&data).unwrap();
+
+        let stats = &state.compression_stats;
+        assert_eq!(stats.average_compression_ratio, 1.0);
+        assert_eq!((stats.best_compression_ratio, stats.worst_compression_ratio), (2.0, 1.0));
+        assert_eq!(stats.average_compression_time_ms, 10);
+        assert_eq!(state.algorithm_health[1], AlgorithmHealth::default());
+        assert_eq!(state.config.verify_sample_rate, 100);
+
+        // Consistent state loads unchanged
+        assert!(!state.repair());
+    }
+}
\ No newline at end of file
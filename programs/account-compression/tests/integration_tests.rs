@@ -1,20 +1,30 @@
 use {
+    borsh::{BorshDeserialize, BorshSerialize},
     solana_program::{
         account_info::AccountInfo,
         program_error::ProgramError,
+        program_pack::Pack,
         pubkey::Pubkey,
+        system_program,
         clock::Clock,
         sysvar::Sysvar,
     },
     solana_program_test::*,
     solana_sdk::{
+        account::Account,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        rent::Rent,
         signature::Signer,
-        transaction::Transaction,
+        transaction::{Transaction, TransactionError},
         signer::keypair::Keypair,
     },
     account_compression::{
-        state::{CompressionState, GlobalCompressionConfig, CompressionAlgorithm, AccountType},
+        state::{
+            AuditLog, AuditOperation, Capabilities, CompressionState, GlobalCompressionConfig, CompressionAlgorithm,
+            AccountType, MAX_UNCOMPRESSED_SIZE,
+        },
         error::CompressionError,
+        AccountCompressionInstruction, CompressedAccountState, CompressionConfig, CompressionStats,
     },
 };
 
@@ -34,6 +44,119 @@ mod common {
         (banks_client, payer, recent_blockhash)
     }
 
+    /// Adds an empty audit log at `program_id`'s audit log PDA
+    pub fn add_audit_log(program_test: &mut ProgramTest, program_id: &Pubkey) -> Pubkey {
+        let (audit_log, _) = AuditLog::find_pda(program_id);
+        let mut data = vec![0u8; AuditLog::LEN];
+        AuditLog::pack(AuditLog::new(), &mut data).unwrap();
+        add_program_account(program_test, program_id, audit_log, data);
+        audit_log
+    }
+
+    /// Borsh-encoded header of an account that has never been compressed
+    pub fn uncompressed_state() -> Vec<u8> {
+        CompressedAccountState {
+            is_compressed: false,
+            original_size: 0,
+            compressed_size: 0,
+            compression_algorithm: CompressionAlgorithm::Lz4,
+            last_modified: 0,
+            compression_stats: CompressionStats {
+                total_compressions: 0,
+                total_decompressions: 0,
+                average_compression_ratio: 1.0,
+                best_compression_ratio: 1.0,
+                total_bytes_saved: 0,
+            },
+        }
+        .try_to_vec()
+        .unwrap()
+    }
+
+    /// Adds a rent-exempt account holding `data`, owned by `program_id`
+    pub fn add_program_account(program_test: &mut ProgramTest, program_id: &Pubkey, key: Pubkey, data: Vec<u8>) {
+        program_test.add_account(
+            key,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: *program_id,
+                ..Account::default()
+            },
+        );
+    }
+
+    /// Adds an uncompressed account holding `data`, its header and
+    /// `global_state` at the global state PDA, returning their keys in that
+    /// order
+    pub fn add_uncompressed_account(
+        program_test: &mut ProgramTest,
+        program_id: &Pubkey,
+        data: Vec<u8>,
+        global_state: CompressionState,
+    ) -> (Pubkey, Pubkey, Pubkey) {
+        let mut global_data = vec![0u8; CompressionState::LEN];
+        CompressionState::pack(global_state, &mut global_data).unwrap();
+
+        let (account, state) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (global, _) = CompressionState::find_pda(program_id);
+        add_program_account(program_test, program_id, account, data);
+        add_program_account(program_test, program_id, state, uncompressed_state());
+        add_program_account(program_test, program_id, global, global_data);
+        (account, state, global)
+    }
+
+    /// CompressAccount of `account` with Lz4 at `level`, signed by
+    /// `authority`, against the program's global state and audit log PDAs
+    pub fn compress_ix(
+        program_id: &Pubkey,
+        authority: &Pubkey,
+        account: Pubkey,
+        state: Pubkey,
+        level: u8,
+        refund_to: Option<Pubkey>,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(account, false),
+            AccountMeta::new(state, false),
+            AccountMeta::new(CompressionState::find_pda(program_id).0, false),
+            AccountMeta::new(AuditLog::find_pda(program_id).0, false),
+        ];
+        accounts.extend(refund_to.map(|refund_to| AccountMeta::new(refund_to, false)));
+        Instruction::new_with_borsh(
+            *program_id,
+            &AccountCompressionInstruction::CompressAccount {
+                account_type: account_compression::AccountType::User,
+                compression_config: CompressionConfig {
+                    algorithm: CompressionAlgorithm::Lz4,
+                    level,
+                    chunk_size: 4096,
+                    concurrent_compression: false,
+                    verify_compression: false,
+                },
+            },
+            accounts,
+        )
+    }
+
+    /// DecompressAccount of `account`, with `payer` covering the regrown
+    /// account's rent
+    pub fn decompress_ix(program_id: &Pubkey, payer: &Pubkey, account: Pubkey, state: Pubkey) -> Instruction {
+        Instruction::new_with_borsh(
+            *program_id,
+            &AccountCompressionInstruction::DecompressAccount { account_id: account },
+            vec![
+                AccountMeta::new(*payer, true),
+                AccountMeta::new(account, false),
+                AccountMeta::new(state, false),
+                AccountMeta::new(CompressionState::find_pda(program_id).0, false),
+                AccountMeta::new(AuditLog::find_pda(program_id).0, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    }
+
     pub fn create_test_account(size: usize) -> (Keypair, Vec<u8>) {
         let account = Keypair::new();
         let data = vec![42u8; size]; // Fill with test data
@@ -104,6 +227,8 @@ async fn test_compress_and_decompress_account() {
         concurrent_compressions_limit: 4,
         verify_all_compressions: true,
         auto_decompress_on_access: false,
+        refund_excess_rent: false,
+        verify_sample_rate: 0,
     };
     
     // Compress account
@@ -218,6 +343,8 @@ async fn test_error_conditions() {
         concurrent_compressions_limit: 4,
         verify_all_compressions: true,
         auto_decompress_on_access: false,
+        refund_excess_rent: false,
+        verify_sample_rate: 0,
     };
     
     let transaction = Transaction::new_signed_with_payer(
@@ -278,6 +405,8 @@ async fn test_concurrent_compression() {
                         concurrent_compressions_limit: 4,
                         verify_all_compressions: true,
                         auto_decompress_on_access: false,
+                        refund_excess_rent: false,
+                        verify_sample_rate: 0,
                     },
                 )],
                 Some(&payer.pubkey()),
@@ -295,4 +424,375 @@ async fn test_concurrent_compression() {
     for handle in handles {
         handle.await.unwrap().unwrap();
     }
-} 
\ No newline at end of file
+} 
+
+#[tokio::test]
+async fn test_compression_refunds_excess_rent() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let mut program_test = ProgramTest::new(
+        "account_compression",
+        program_id,
+        processor!(account_compression::process_instruction),
+    );
+
+    let mut global_state = CompressionState::new(authority.pubkey(), 32, 1024);
+    global_state.config = GlobalCompressionConfig::builder().refund_excess_rent(true).build().unwrap();
+
+    let original = vec![42u8; 4096];
+    let funded = Rent::default().minimum_balance(original.len());
+    let (account, state, _) =
+        common::add_uncompressed_account(&mut program_test, &program_id, original.clone(), global_state);
+    let refund_to = Pubkey::new_unique();
+    program_test.add_account(refund_to, Account { lamports: 1_000_000, ..Account::default() });
+    common::add_audit_log(&mut program_test, &program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let compress = |signer: &Keypair| {
+        Transaction::new_signed_with_payer(
+            &[common::compress_ix(&program_id, &signer.pubkey(), account, state, 1, Some(refund_to))],
+            Some(&payer.pubkey()),
+            &[&payer, signer],
+            recent_blockhash,
+        )
+    };
+
+    // Someone other than the authority can't compress, and so can't
+    // redirect the refund
+    let result = banks_client.process_transaction(compress(&payer)).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CompressionError::Unauthorized as u32))
+    );
+
+    banks_client.process_transaction(compress(&authority)).await.unwrap();
+
+    let rent = banks_client.get_rent().await.unwrap();
+    let compressed = banks_client.get_account(account).await.unwrap().unwrap();
+    assert!(compressed.data.len() < original.len());
+    assert_eq!(compressed.lamports, rent.minimum_balance(compressed.data.len()));
+
+    let refunded = banks_client.get_account(refund_to).await.unwrap().unwrap();
+    assert_eq!(refunded.lamports, 1_000_000 + funded - compressed.lamports);
+
+    // Decompressing grows the account back, with the payer covering the
+    // rent that was refunded
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::decompress_ix(&program_id, &payer.pubkey(), account, state)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let restored = banks_client.get_account(account).await.unwrap().unwrap();
+    assert_eq!(restored.data, original);
+    assert_eq!(restored.lamports, rent.minimum_balance(original.len()));
+}
+
+#[tokio::test]
+async fn test_pause_halts_compression() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let mut program_test = ProgramTest::new(
+        "account_compression",
+        program_id,
+        processor!(account_compression::process_instruction),
+    );
+
+    let (account, state, global) = common::add_uncompressed_account(
+        &mut program_test,
+        &program_id,
+        vec![42u8; 4096],
+        CompressionState::new(authority.pubkey(), 32, 1024),
+    );
+
+    common::add_audit_log(&mut program_test, &program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let set_paused = |signer: &Keypair, paused: bool| {
+        Transaction::new_signed_with_payer(
+            &[Instruction::new_with_borsh(
+                program_id,
+                &AccountCompressionInstruction::SetPaused { paused },
+                vec![AccountMeta::new_readonly(signer.pubkey(), true), AccountMeta::new(global, false)],
+            )],
+            Some(&payer.pubkey()),
+            &[&payer, signer],
+            recent_blockhash,
+        )
+    };
+    // `level` varies so repeated attempts aren't deduplicated as the same
+    // transaction
+    let compress = |level: u8| {
+        Transaction::new_signed_with_payer(
+            &[common::compress_ix(&program_id, &authority.pubkey(), account, state, level, None)],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        )
+    };
+    let custom_error = |error: CompressionError| {
+        TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+    };
+
+    // Only the global authority may pause
+    let result = banks_client.process_transaction(set_paused(&payer, true)).await;
+    assert_eq!(result.unwrap_err().unwrap(), custom_error(CompressionError::Unauthorized));
+
+    banks_client.process_transaction(set_paused(&authority, true)).await.unwrap();
+    let result = banks_client.process_transaction(compress(1)).await;
+    assert_eq!(result.unwrap_err().unwrap(), custom_error(CompressionError::ProgramPaused));
+
+    // Stats stay readable while paused
+    let global_account = banks_client.get_account(global).await.unwrap().unwrap();
+    let paused = CompressionState::unpack(&global_account.data).unwrap();
+    assert!(paused.program_paused);
+
+    banks_client.process_transaction(set_paused(&authority, false)).await.unwrap();
+    banks_client.process_transaction(compress(2)).await.unwrap();
+
+    let header = banks_client.get_account(state).await.unwrap().unwrap();
+    assert!(CompressedAccountState::try_from_slice(&header.data).unwrap().is_compressed);
+}
+
+#[tokio::test]
+async fn test_compress_and_decompress_are_audited() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new(
+        "account_compression",
+        program_id,
+        processor!(account_compression::process_instruction),
+    );
+
+    let authority = Keypair::new();
+    let original = vec![42u8; 4096];
+    let (account, state, _) = common::add_uncompressed_account(
+        &mut program_test,
+        &program_id,
+        original.clone(),
+        CompressionState::new(authority.pubkey(), 32, 1024),
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let (audit_log, _) = AuditLog::find_pda(&program_id);
+    let send = |instruction: Instruction, signers: &[&Keypair]| {
+        Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), signers, recent_blockhash)
+    };
+
+    banks_client
+        .process_transaction(send(
+            Instruction::new_with_borsh(
+                program_id,
+                &AccountCompressionInstruction::InitializeAuditLog,
+                vec![
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new(audit_log, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+            ),
+            &[&payer],
+        ))
+        .await
+        .unwrap();
+
+    banks_client
+        .process_transaction(send(
+            common::compress_ix(&program_id, &authority.pubkey(), account, state, 1, None),
+            &[&payer, &authority],
+        ))
+        .await
+        .unwrap();
+    let compressed = banks_client.get_account(account).await.unwrap().unwrap().data;
+    assert!(compressed.len() < original.len());
+
+    banks_client
+        .process_transaction(send(common::decompress_ix(&program_id, &payer.pubkey(), account, state), &[&payer]))
+        .await
+        .unwrap();
+    assert_eq!(banks_client.get_account(account).await.unwrap().unwrap().data, original);
+
+    let audit_account = banks_client.get_account(audit_log).await.unwrap().unwrap();
+    let log = AuditLog::unpack(&audit_account.data).unwrap();
+    let entries: Vec<_> = log.oldest_first().collect();
+    assert_eq!(entries.len(), 2);
+
+    let original_hash = solana_program::hash::hash(&original).to_bytes();
+    let compressed_hash = solana_program::hash::hash(&compressed).to_bytes();
+    assert_eq!(entries[0].operation, AuditOperation::Compress);
+    assert_eq!(entries[0].account, account);
+    assert_eq!((entries[0].original_hash, entries[0].result_hash), (original_hash, compressed_hash));
+    assert_eq!(entries[1].operation, AuditOperation::Decompress);
+    assert_eq!(entries[1].account, account);
+    assert_eq!((entries[1].original_hash, entries[1].result_hash), (compressed_hash, original_hash));
+    assert!(entries[0].slot <= entries[1].slot);
+}
+
+#[tokio::test]
+async fn test_compression_updates_global_stats() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new(
+        "account_compression",
+        program_id,
+        processor!(account_compression::process_instruction),
+    );
+
+    // A second, less compressible account shares the first one's global
+    // state
+    let authority = Keypair::new();
+    let (repetitive, repetitive_state, global) = common::add_uncompressed_account(
+        &mut program_test,
+        &program_id,
+        vec![42u8; 4096],
+        CompressionState::new(authority.pubkey(), 32, 1024),
+    );
+    let (varied, varied_state) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let varied_data: Vec<u8> = (0..4096u32).map(|i| (i * 31 % 251) as u8).collect();
+    common::add_program_account(&mut program_test, &program_id, varied, varied_data);
+    common::add_program_account(&mut program_test, &program_id, varied_state, common::uncompressed_state());
+    common::add_audit_log(&mut program_test, &program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let compress = |account: Pubkey, state: Pubkey| {
+        Transaction::new_signed_with_payer(
+            &[common::compress_ix(&program_id, &authority.pubkey(), account, state, 1, None)],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        )
+    };
+    let ratio = |compressed: &Account| 4096.0 / compressed.data.len() as f64;
+
+    banks_client.process_transaction(compress(repetitive, repetitive_state)).await.unwrap();
+    let first = ratio(&banks_client.get_account(repetitive).await.unwrap().unwrap());
+    let stats = CompressionState::unpack(&banks_client.get_account(global).await.unwrap().unwrap().data)
+        .unwrap()
+        .compression_stats;
+    assert_eq!(stats.total_compressions, 1);
+    assert_eq!(stats.recent_compression_ratio, first);
+
+    banks_client.process_transaction(compress(varied, varied_state)).await.unwrap();
+    let second = ratio(&banks_client.get_account(varied).await.unwrap().unwrap());
+    let stats = CompressionState::unpack(&banks_client.get_account(global).await.unwrap().unwrap().data)
+        .unwrap()
+        .compression_stats;
+    assert_eq!(stats.total_compressions, 2);
+    assert!((stats.average_compression_ratio - (first + second) / 2.0).abs() < 1e-9);
+    assert_eq!(
+        stats.recent_compression_ratio,
+        (1.0 - account_compression::state::RATIO_EWMA_WEIGHT) * first
+            + account_compression::state::RATIO_EWMA_WEIGHT * second
+    );
+    assert_eq!(stats.worst_compression_ratio, first.min(second));
+}
+
+#[tokio::test]
+async fn test_initialize_global_state() {
+    let program_id = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+        "account_compression",
+        program_id,
+        processor!(account_compression::process_instruction),
+    )
+    .start()
+    .await;
+
+    let (global, _) = CompressionState::find_pda(&program_id);
+    let initialize = |max_decompress_size: u64| {
+        Transaction::new_signed_with_payer(
+            &[Instruction::new_with_borsh(
+                program_id,
+                &AccountCompressionInstruction::InitializeGlobalState {
+                    max_depth: 32,
+                    max_buffer_size: 1024,
+                    max_decompress_size,
+                },
+                vec![
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new(global, false),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        )
+    };
+
+    let result = banks_client.process_transaction(initialize(MAX_UNCOMPRESSED_SIZE + 1)).await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+    );
+
+    banks_client.process_transaction(initialize(1 << 20)).await.unwrap();
+
+    let account = banks_client.get_account(global).await.unwrap().unwrap();
+    assert_eq!(account.owner, program_id);
+    let global_state = CompressionState::unpack(&account.data).unwrap();
+    assert_eq!(global_state.authority, payer.pubkey());
+    assert_eq!(global_state.capabilities, Capabilities::compiled());
+    assert_eq!((global_state.max_depth, global_state.max_buffer_size), (32, 1024));
+    assert_eq!(global_state.max_decompress_size, 1 << 20);
+    assert!(!global_state.program_paused);
+
+    // The PDA exists now, so a second initialization can't replace the
+    // authority
+    assert!(banks_client.process_transaction(initialize(1 << 21)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_compress_requires_authority() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new(
+        "account_compression",
+        program_id,
+        processor!(account_compression::process_instruction),
+    );
+
+    // The account's own keypair may compress it, besides the global
+    // authority
+    let authority = Keypair::new();
+    let (_, state, global) = common::add_uncompressed_account(
+        &mut program_test,
+        &program_id,
+        vec![42u8; 4096],
+        CompressionState::new(authority.pubkey(), 32, 1024),
+    );
+    let owned = Keypair::new();
+    let owned_state = Pubkey::new_unique();
+    common::add_program_account(&mut program_test, &program_id, owned.pubkey(), vec![7u8; 4096]);
+    common::add_program_account(&mut program_test, &program_id, owned_state, common::uncompressed_state());
+    let audit_log = common::add_audit_log(&mut program_test, &program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let compress = |signer: &Keypair, account: Pubkey, state: Pubkey| {
+        Transaction::new_signed_with_payer(
+            &[common::compress_ix(&program_id, &signer.pubkey(), account, state, 1, None)],
+            Some(&payer.pubkey()),
+            &[&payer, signer],
+            recent_blockhash,
+        )
+    };
+    let error = |result: Result<(), BanksClientError>| result.unwrap_err().unwrap();
+
+    let result = banks_client.process_transaction(compress(&payer, owned.pubkey(), owned_state)).await;
+    assert_eq!(
+        error(result),
+        TransactionError::InstructionError(0, InstructionError::Custom(CompressionError::Unauthorized as u32))
+    );
+
+    // Not even the authority may compress the program's own state
+    for program_account in [global, audit_log] {
+        let result = banks_client.process_transaction(compress(&authority, program_account, state)).await;
+        assert_eq!(error(result), TransactionError::InstructionError(0, InstructionError::InvalidArgument));
+    }
+
+    banks_client.process_transaction(compress(&owned, owned.pubkey(), owned_state)).await.unwrap();
+    let header = banks_client.get_account(owned_state).await.unwrap().unwrap();
+    assert!(CompressedAccountState::try_from_slice(&header.data).unwrap().is_compressed);
+}
\ No newline at end of file
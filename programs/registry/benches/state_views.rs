@@ -0,0 +1,81 @@
+#![feature(test)]
+extern crate test;
+
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::pubkey::Pubkey,
+    test::{black_box, Bencher},
+    wave_verifier::state::{Nullifier, NullifierRef, ProofLog, ProofLogRef},
+};
+
+const ACCOUNTS: usize = 100_000;
+
+// Synthetic account data, padded to the allocated size like real accounts
+fn proof_logs() -> Vec<Vec<u8>> {
+    (0..ACCOUNTS as u64)
+        .map(|i| {
+            let mut data = ProofLog::new([i as u8; 32], i as i64, i % 64, [7u8; 32])
+                .try_to_vec()
+                .unwrap();
+            data.resize(ProofLog::account_size(), 0);
+            data
+        })
+        .collect()
+}
+
+fn nullifiers() -> Vec<Vec<u8>> {
+    (0..ACCOUNTS as u64)
+        .map(|i| {
+            let mut nullifier = Nullifier::new([i as u8; 32], i as i64, i % 64, Pubkey::new_unique());
+            nullifier.triggered_at = (i % 2 == 0).then_some(i as i64);
+            let mut data = nullifier.try_to_vec().unwrap();
+            data.resize(Nullifier::account_size(), 0);
+            data
+        })
+        .collect()
+}
+
+// Each bench sums one field per account, the shape of a typical scan
+#[bench]
+fn bench_proof_log_owned(b: &mut Bencher) {
+    let accounts = proof_logs();
+    b.iter(|| {
+        accounts
+            .iter()
+            .map(|data| ProofLog::deserialize(&mut &black_box(data)[..]).unwrap().flow_id)
+            .sum::<u64>()
+    });
+}
+
+#[bench]
+fn bench_proof_log_view(b: &mut Bencher) {
+    let accounts = proof_logs();
+    b.iter(|| {
+        accounts
+            .iter()
+            .map(|data| ProofLogRef::parse(black_box(data)).unwrap().flow_id())
+            .sum::<u64>()
+    });
+}
+
+#[bench]
+fn bench_nullifier_owned(b: &mut Bencher) {
+    let accounts = nullifiers();
+    b.iter(|| {
+        accounts
+            .iter()
+            .filter_map(|data| Nullifier::deserialize(&mut &black_box(data)[..]).unwrap().triggered_at)
+            .count()
+    });
+}
+
+#[bench]
+fn bench_nullifier_view(b: &mut Bencher) {
+    let accounts = nullifiers();
+    b.iter(|| {
+        accounts
+            .iter()
+            .filter_map(|data| NullifierRef::parse(black_box(data)).unwrap().triggered_at())
+            .count()
+    });
+}
\ No newline at end of file
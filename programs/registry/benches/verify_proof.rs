@@ -0,0 +1,34 @@
+#![feature(test)]
+#![cfg(feature = "simulation")]
+extern crate test;
+
+use {
+    test::Bencher,
+    wave_verifier::compute::{measure, validate_proof_fixture},
+};
+
+// Each iteration runs on fresh accounts, since the first accepted proof
+// records its nullifier. The metering stubs supply the clock sysvar.
+macro_rules! validate_proof_benches {
+    ($($name:ident => $inputs:expr),* $(,)?) => {
+        $(
+            #[bench]
+            fn $name(b: &mut Bencher) {
+                let (fixture, data) = validate_proof_fixture($inputs).unwrap();
+                measure(|| {
+                    b.iter(|| {
+                        let mut fixture = fixture.clone();
+                        fixture.process(&data).unwrap();
+                    })
+                });
+            }
+        )*
+    };
+}
+
+validate_proof_benches! {
+    bench_validate_proof_1_input => 1,
+    bench_validate_proof_4_inputs => 4,
+    bench_validate_proof_16_inputs => 16,
+    bench_validate_proof_64_inputs => 64,
+}
\ No newline at end of file
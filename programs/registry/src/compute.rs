@@ -0,0 +1,311 @@
+//! Estimates the compute units an instruction would consume on-chain by
+//! running the processor natively with metering syscall stubs. Each syscall
+//! is charged what the runtime's default compute budget charges for it.
+//!
+//! Only work that goes through the syscall stubs is charged: logging,
+//! sysvars, CPI and return data. PDA derivation and hashing run natively
+//! off-chain and cost nothing here, so estimates are a lower bound meant
+//! for comparing builds, not for sizing compute budget requests.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use borsh::BorshSerialize;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+    sysvar::clock::Clock,
+};
+
+use crate::{
+    constants::REGISTRY_SEED,
+    instructions::WaveInstruction,
+    processor::process_instruction,
+    state::{FlowRegistry, Nullifier, ProofLog},
+};
+
+/// Compute units a transaction may use by default
+pub const DEFAULT_COMPUTE_LIMIT: u64 = 1_400_000;
+
+/// Budget for ValidateProof without a receipt, across the public-input
+/// counts in `BENCH_PUBLIC_INPUTS`. Measured at 649 CU with `verbose-logs`:
+/// logging, the clock read and the remaining-compute check. The alt_bn128
+/// syscalls run natively and go uncharged, so pairing verification is not
+/// included; `verifier::verification_cost` estimates it. Doubling the cost
+/// exceeds the budget.
+pub const VALIDATE_PROOF_CU_BUDGET: u64 = 800;
+
+/// ValidateProof's cost without `verbose-logs`: the clock read, the
+/// remaining-compute check and the NullifierUsed event header. The
+/// instruction name and event fields are debug lines, which saves 309 CU
+/// over the 649 CU verbose build.
+pub const VALIDATE_PROOF_QUIET_CU: u64 = 340;
+
+/// Public-input counts the ValidateProof benchmark and budget test cover
+pub const BENCH_PUBLIC_INPUTS: [usize; 4] = [1, 4, 16, 64];
+
+// Default compute budget costs, as of solana-program 1.17
+const SYSCALL_BASE_COST: u64 = 100;
+const SYSVAR_BASE_COST: u64 = 100;
+const INVOKE_UNITS: u64 = 1_000;
+const MEM_OP_BASE_COST: u64 = 10;
+const CPI_BYTES_PER_UNIT: u64 = 250;
+
+// The stubs are process-wide, so only one measurement may run at a time
+static METER_LOCK: Mutex<()> = Mutex::new(());
+
+struct MeteringStubs {
+    consumed: Arc<AtomicU64>,
+}
+
+impl MeteringStubs {
+    fn charge(&self, units: u64) {
+        self.consumed.fetch_add(units, Ordering::Relaxed);
+    }
+
+    fn charge_mem_op(&self, n: usize) {
+        self.charge(MEM_OP_BASE_COST.max(n as u64 / CPI_BYTES_PER_UNIT));
+    }
+}
+
+impl SyscallStubs for MeteringStubs {
+    fn sol_log(&self, message: &str) {
+        self.charge(SYSCALL_BASE_COST.max(message.len() as u64));
+    }
+
+    fn sol_log_compute_units(&self) {
+        self.charge(SYSCALL_BASE_COST);
+    }
+
+    fn sol_remaining_compute_units(&self) -> u64 {
+        self.charge(SYSCALL_BASE_COST);
+        DEFAULT_COMPUTE_LIMIT.saturating_sub(self.consumed.load(Ordering::Relaxed))
+    }
+
+    // The callee is not run; only the invocation is charged
+    fn sol_invoke_signed(
+        &self,
+        _instruction: &Instruction,
+        _account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        self.charge(INVOKE_UNITS);
+        Ok(())
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.charge(SYSVAR_BASE_COST + std::mem::size_of::<Clock>() as u64);
+        unsafe { *(var_addr as *mut Clock) = Clock::default() };
+        solana_program::entrypoint::SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.charge(SYSVAR_BASE_COST + std::mem::size_of::<Rent>() as u64);
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        solana_program::entrypoint::SUCCESS
+    }
+
+    unsafe fn sol_memcpy(&self, dst: *mut u8, src: *const u8, n: usize) {
+        self.charge_mem_op(n);
+        std::ptr::copy_nonoverlapping(src, dst, n);
+    }
+
+    unsafe fn sol_memmove(&self, dst: *mut u8, src: *const u8, n: usize) {
+        self.charge_mem_op(n);
+        std::ptr::copy(src, dst, n);
+    }
+
+    unsafe fn sol_memcmp(&self, s1: *const u8, s2: *const u8, n: usize, result: *mut i32) {
+        self.charge_mem_op(n);
+        let a = std::slice::from_raw_parts(s1, n);
+        let b = std::slice::from_raw_parts(s2, n);
+        *result = a
+            .iter()
+            .zip(b)
+            .find(|(x, y)| x != y)
+            .map_or(0, |(x, y)| *x as i32 - *y as i32);
+    }
+
+    unsafe fn sol_memset(&self, s: *mut u8, c: u8, n: usize) {
+        self.charge_mem_op(n);
+        std::ptr::write_bytes(s, c, n);
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.charge(SYSCALL_BASE_COST + data.len() as u64 / CPI_BYTES_PER_UNIT);
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        let bytes: u64 = fields.iter().map(|field| field.len() as u64).sum();
+        self.charge(SYSCALL_BASE_COST * (1 + fields.len() as u64) + bytes);
+    }
+}
+
+/// Runs `f` with metering stubs installed and returns its result with the
+/// estimated compute units it consumed.
+pub fn measure<R>(f: impl FnOnce() -> R) -> (R, u64) {
+    let _guard = METER_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let consumed = Arc::new(AtomicU64::new(0));
+    let previous = set_syscall_stubs(Box::new(MeteringStubs {
+        consumed: consumed.clone(),
+    }));
+    let result = f();
+    set_syscall_stubs(previous);
+    (result, consumed.load(Ordering::Relaxed))
+}
+
+/// Owned accounts for driving `process_instruction` directly.
+#[derive(Clone)]
+pub struct AccountFixture {
+    pub program_id: Pubkey,
+    pub keys: Vec<Pubkey>,
+    pub owners: Vec<Pubkey>,
+    pub lamports: Vec<u64>,
+    pub data: Vec<Vec<u8>>,
+    pub signers: Vec<bool>,
+}
+
+impl AccountFixture {
+    pub fn new(program_id: Pubkey) -> Self {
+        Self {
+            program_id,
+            keys: Vec::new(),
+            owners: Vec::new(),
+            lamports: Vec::new(),
+            data: Vec::new(),
+            signers: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: Pubkey, owner: Pubkey, data: Vec<u8>, is_signer: bool) {
+        self.keys.push(key);
+        self.owners.push(owner);
+        self.lamports.push(1_000_000_000);
+        self.data.push(data);
+        self.signers.push(is_signer);
+    }
+
+    /// Runs `instruction_data` against the accounts, in push order.
+    pub fn process(&mut self, instruction_data: &[u8]) -> ProgramResult {
+        let accounts: Vec<AccountInfo> = self
+            .keys
+            .iter()
+            .zip(&self.owners)
+            .zip(self.lamports.iter_mut())
+            .zip(self.data.iter_mut())
+            .zip(&self.signers)
+            .map(|((((key, owner), lamports), data), is_signer)| {
+                AccountInfo::new(key, *is_signer, true, lamports, data, owner, false, 0)
+            })
+            .collect();
+        process_instruction(&self.program_id, &accounts, instruction_data)
+    }
+}
+
+/// Builds a receipt-less ValidateProof for a fresh flow, with `inputs`
+/// 32-byte public inputs. Returns the accounts and the instruction data.
+pub fn validate_proof_fixture(inputs: usize) -> Result<(AccountFixture, Vec<u8>), ProgramError> {
+    let program_id = Pubkey::new_unique();
+    let registry = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let nullifier = [9u8; 32];
+    let (registry_key, _) = Pubkey::find_program_address(
+        &[REGISTRY_SEED, &registry.flow_id.to_le_bytes()],
+        &program_id,
+    );
+    let mut registry_data = registry.try_to_vec()?;
+    registry_data.resize(FlowRegistry::account_size(), 0);
+    let (nullifier_key, _) = registry.find_nullifier_pda(&program_id, &nullifier);
+
+    let mut fixture = AccountFixture::new(program_id);
+    fixture.push(Pubkey::new_unique(), system_program::id(), Vec::new(), true);
+    fixture.push(registry_key, program_id, registry_data, false);
+    fixture.push(nullifier_key, program_id, vec![0u8; Nullifier::account_size()], false);
+    fixture.push(ProofLog::find_pda(&program_id, registry.flow_id, &nullifier).0, program_id, vec![0u8; ProofLog::SIZE], false);
+    fixture.push(system_program::id(), Pubkey::default(), Vec::new(), false);
+    fixture.push(Pubkey::new_unique(), program_id, Vec::new(), false);
+
+    let data = WaveInstruction::ValidateProof {
+        proof: vec![1u8; 256],
+        public_inputs: vec![1u8; 32 * inputs],
+        nullifier,
+        mint_receipt: false,
+        nullifier_ttl_seconds: None,
+    }
+    .try_to_vec()?;
+    Ok((fixture, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::WaveError;
+    use solana_program::msg;
+
+    #[test]
+    fn test_measure_charges_logs() {
+        let ((), units) = measure(|| msg!("short"));
+        assert_eq!(units, SYSCALL_BASE_COST);
+
+        let long = "x".repeat(300);
+        let ((), units) = measure(|| msg!("{}", long));
+        assert_eq!(units, 300);
+    }
+
+    #[test]
+    fn test_validate_proof_within_budget() {
+        for inputs in BENCH_PUBLIC_INPUTS {
+            let (mut fixture, data) = validate_proof_fixture(inputs).unwrap();
+            let (result, units) = measure(|| fixture.process(&data));
+            result.unwrap();
+            assert!(
+                units <= VALIDATE_PROOF_CU_BUDGET,
+                "ValidateProof with {} public inputs used {} CU, over the {} CU budget",
+                inputs,
+                units,
+                VALIDATE_PROOF_CU_BUDGET,
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_proof_checks_public_inputs_length() {
+        for (len, accepted) in [(0, false), (16, false), (64, true)] {
+            let (mut fixture, _) = validate_proof_fixture(1).unwrap();
+            let data = WaveInstruction::ValidateProof {
+                proof: vec![1u8; 256],
+                public_inputs: vec![1u8; len],
+                nullifier: [9u8; 32],
+                mint_receipt: false,
+                nullifier_ttl_seconds: None,
+            }
+            .try_to_vec()
+            .unwrap();
+
+            let (result, _) = measure(|| fixture.process(&data));
+            if accepted {
+                result.unwrap();
+            } else {
+                assert_eq!(result, Err(WaveError::InvalidPublicInputs.into()));
+            }
+        }
+    }
+
+    #[cfg(not(feature = "verbose-logs"))]
+    #[test]
+    fn test_quiet_logs_validate_proof_cost() {
+        for inputs in BENCH_PUBLIC_INPUTS {
+            let (mut fixture, data) = validate_proof_fixture(inputs).unwrap();
+            let (result, units) = measure(|| fixture.process(&data));
+            result.unwrap();
+            assert_eq!(units, VALIDATE_PROOF_QUIET_CU);
+        }
+    }
+}
\ No newline at end of file
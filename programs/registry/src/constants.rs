@@ -2,11 +2,88 @@
 pub const NULLIFIER_SEED: &[u8] = b"nullifier";
 pub const REGISTRY_SEED: &[u8] = b"registry";
 pub const PROOF_LOG_SEED: &[u8] = b"proof_log";
+pub const FLOW_TREE_SEED: &[u8] = b"flow_tree";
+pub const ATTESTATION_SEED: &[u8] = b"attestation";
+pub const NOTIFICATION_MANIFEST_SEED: &[u8] = b"notifications";
+/// Receives the rent of closed legacy nullifier accounts and flow
+/// registration fees
+pub const TREASURY_SEED: &[u8] = b"treasury";
+/// Program-wide settings such as the registration fee
+pub const PROGRAM_CONFIG_SEED: &[u8] = b"program_config";
+pub const PROOF_LOG_COMMITMENT_SEED: &[u8] = b"proof_log_commitment";
+/// Holds a circuit's verifying key, seeded by its `circuit_hash`
+pub const VERIFYING_KEY_SEED: &[u8] = b"verifying_key";
+/// Program-wide allowlist of circuits flows may register
+pub const APPROVED_CIRCUITS_SEED: &[u8] = b"approved_circuits";
+/// Records who may admit flows to a shared nullifier namespace
+pub const NULLIFIER_NAMESPACE_SEED: &[u8] = b"nullifier_namespace";
+/// Prefixes nullifier PDAs within a shared namespace in place of
+/// `NULLIFIER_SEED`, so a namespace never resolves to a flow's nullifiers
+pub const NAMESPACE_NULLIFIER_SEED: &[u8] = b"namespace_nullifier";
+
+/// Byte offset of the recipient wallet within ValidateProof public inputs,
+/// read when the proof mints an attestation. A key can exceed the BN254
+/// scalar field, so it takes two 32-byte big-endian signals, each holding
+/// 16 bytes of the key, high half first
+pub const ATTESTATION_RECIPIENT_OFFSET: usize = 32;
+
+/// Byte offset of the slot a proof commits to within ValidateProof public
+/// inputs, read when the flow has a freshness window. The field is 32 bytes
+/// big-endian, like every public signal, and must fit in a u64
+pub const PROOF_SLOT_OFFSET: usize = 96;
+
+/// Depth of a flow's on-chain tree, kept low enough that the AttachTreeState
+/// frontier (32 bytes per level) fits in one transaction
+pub const FLOW_TREE_DEPTH: usize = 26;
 
 /// Size limits
 pub const MAX_PROOF_SIZE: usize = 1024;
 pub const MAX_PUBLIC_INPUTS_SIZE: usize = 256;
 pub const MAX_FLOW_ID: u64 = 1000000;
+/// Most public inputs a stored verifying key may take, as many as fit in
+/// `MAX_PUBLIC_INPUTS_SIZE`
+pub const MAX_VERIFYING_KEY_INPUTS: usize = MAX_PUBLIC_INPUTS_SIZE / 32;
+
+/// Largest legacy transaction the runtime accepts, in bytes
+pub const MAX_TRANSACTION_SIZE: usize = 1232;
+
+/// SetRootBatch transaction bytes independent of the entry count: one
+/// signature, message header, authority and program keys, blockhash and the
+/// instruction framing
+pub const SET_ROOT_BATCH_BASE_SIZE: usize = 176;
+
+/// SetRootBatch bytes per entry: registry key (32), account index (1) and
+/// the `(u64, [u8; 32], u64)` entry itself (48)
+pub const SET_ROOT_BATCH_ENTRY_SIZE: usize = 81;
+
+/// Most entries a single-signer SetRootBatch transaction can carry
+pub const MAX_SET_ROOT_BATCH_ENTRIES: usize =
+    (MAX_TRANSACTION_SIZE - SET_ROOT_BATCH_BASE_SIZE) / SET_ROOT_BATCH_ENTRY_SIZE;
+
+/// ImportNullifiers transaction bytes independent of the nullifier count:
+/// one signature, message header, authority, registry, system program and
+/// program keys, blockhash and the instruction framing
+pub const IMPORT_NULLIFIERS_BASE_SIZE: usize = 242;
+
+/// ImportNullifiers bytes per nullifier: PDA key (32), account index (1) and
+/// the nullifier itself (32)
+pub const IMPORT_NULLIFIERS_ENTRY_SIZE: usize = 65;
+
+/// Most nullifiers a single ImportNullifiers transaction can carry
+pub const MAX_IMPORT_NULLIFIERS: usize =
+    (MAX_TRANSACTION_SIZE - IMPORT_NULLIFIERS_BASE_SIZE) / IMPORT_NULLIFIERS_ENTRY_SIZE;
+
+/// Most proofs one ValidateBatchProofs verifies, keeping the pairings
+/// within a transaction's compute budget
+pub const MAX_VALIDATE_BATCH_PROOFS: usize = 8;
+
+/// Most circuits the approved circuits allowlist holds
+pub const MAX_APPROVED_CIRCUITS: usize = 64;
+
+/// Most entries a flow's notification manifest holds
+pub const MAX_NOTIFICATION_ENTRIES: usize = 4;
+/// Longest notification entry label, in bytes
+pub const MAX_NOTIFICATION_LABEL_LEN: usize = 32;
 
 /// Flow tags
 pub const FLOW_TAG_MERKLE: u8 = 1;
@@ -55,8 +132,11 @@ pub mod test_data {
 }
 
 // Account sizes
+#[deprecated(note = "over-allocates; use FlowRegistry::account_size()")]
 pub const FLOW_REGISTRY_SIZE: usize = 1024;
+#[deprecated(note = "over-allocates; use Nullifier::account_size()")]
 pub const NULLIFIER_SIZE: usize = 128;
+#[deprecated(note = "over-allocates; use ProofLog::account_size()")]
 pub const PROOF_LOG_SIZE: usize = 256;
 
 // Program seeds
@@ -85,6 +165,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_account_sizes() {
         assert!(FLOW_REGISTRY_SIZE >= 1024);
         assert!(NULLIFIER_SIZE >= 128);
@@ -40,6 +40,63 @@ pub enum WaveError {
 
     #[error("Invalid account data")]
     InvalidAccountData,
+
+    #[error("Flow already registered")]
+    FlowAlreadyRegistered,
+
+    #[error("Insufficient compute for proof verification")]
+    InsufficientCompute,
+
+    #[error("Root sequence is stale")]
+    StaleRootSequence,
+
+    #[error("Attestation already exists")]
+    AttestationExists,
+
+    #[error("Flow circuit is frozen")]
+    CircuitFrozen,
+
+    #[error("Flow has reached its nullifier cap")]
+    FlowCapacityReached,
+
+    #[error("Nullifier migration is complete")]
+    MigrationComplete,
+
+    #[error("Invalid notification entry label")]
+    InvalidNotificationLabel,
+
+    #[error("Notification manifest is full")]
+    NotificationManifestFull,
+
+    #[error("Notification entry not found")]
+    NotificationEntryNotFound,
+
+    #[error("Proof slot is outside the flow's freshness window")]
+    ProofExpired,
+
+    #[error("Authority cannot pay the registration fee")]
+    InsufficientFunds,
+
+    #[error("Proof log is not in the commitment")]
+    ProofLogNotCommitted,
+
+    #[error("Invalid verifying key")]
+    InvalidVerifyingKey,
+
+    #[error("Approved circuit list is full")]
+    ApprovedCircuitsFull,
+
+    #[error("Malformed verification key")]
+    MalformedVerificationKey,
+
+    #[error("Account is not the expected PDA")]
+    InvalidPda,
+
+    #[error("Nullifier has expired")]
+    NullifierExpired,
+
+    #[error("Public inputs are shorter than 32 bytes")]
+    InvalidPublicInputs,
 }
 
 impl From<WaveError> for ProgramError {
@@ -113,6 +170,25 @@ mod tests {
             WaveError::FlowDisabled,
             WaveError::InvalidCallbackProgram,
             WaveError::InvalidAccountData,
+            WaveError::FlowAlreadyRegistered,
+            WaveError::InsufficientCompute,
+            WaveError::StaleRootSequence,
+            WaveError::AttestationExists,
+            WaveError::CircuitFrozen,
+            WaveError::FlowCapacityReached,
+            WaveError::MigrationComplete,
+            WaveError::InvalidNotificationLabel,
+            WaveError::NotificationManifestFull,
+            WaveError::NotificationEntryNotFound,
+            WaveError::ProofExpired,
+            WaveError::InsufficientFunds,
+            WaveError::ProofLogNotCommitted,
+            WaveError::InvalidVerifyingKey,
+            WaveError::ApprovedCircuitsFull,
+            WaveError::MalformedVerificationKey,
+            WaveError::InvalidPda,
+            WaveError::NullifierExpired,
+            WaveError::InvalidPublicInputs,
         ];
 
         for error in errors {
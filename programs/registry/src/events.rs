@@ -1,9 +1,10 @@
 use solana_program::{
-    msg,
     program_error::ProgramError,
     pubkey::Pubkey,
 };
 
+use crate::state::IncrementalMerkleTree;
+
 #[derive(Debug)]
 pub enum WaveEvent {
     FlowRegistered {
@@ -27,11 +28,99 @@ pub enum WaveEvent {
     RootUpdated {
         flow_id: u64,
         new_root: [u8; 32],
+        sequence: u64,
     },
     FlowTriggered {
         flow_id: u64,
         target_program: Pubkey,
     },
+    FlowCloned {
+        source_flow_id: u64,
+        new_flow_id: u64,
+    },
+    NullifiersImported {
+        flow_id: u64,
+        imported: u32,
+    },
+    AttestationRecorded {
+        flow_id: u64,
+        recipient: Pubkey,
+    },
+    AttestationRevoked {
+        flow_id: u64,
+        recipient: Pubkey,
+    },
+    RootAndCircuitUpdated {
+        flow_id: u64,
+        new_root: [u8; 32],
+        new_circuit_hash: [u8; 32],
+        sequence: u64,
+    },
+    CircuitFrozen {
+        flow_id: u64,
+        circuit_hash: [u8; 32],
+    },
+    NullifierMigrated {
+        flow_id: u64,
+        nullifier: [u8; 32],
+    },
+    NullifierMigrationFinalized {
+        flow_id: u64,
+    },
+    NotificationEntrySet {
+        flow_id: u64,
+        label: String,
+        enabled: bool,
+    },
+    NotificationEntryCleared {
+        flow_id: u64,
+        label: String,
+    },
+    ProgramConfigSet {
+        admin: Pubkey,
+        registration_fee: u64,
+    },
+    /// One event for a whole batch of accepted proofs in place of a
+    /// `NullifierUsed` per proof; `nullifiers_root` is `nullifiers_root`
+    /// over the batch's nullifiers in order.
+    BatchValidated {
+        flow_id: u64,
+        count: u32,
+        nullifiers_root: [u8; 32],
+    },
+    /// `count` proof logs were closed into the `ProofLogCommitment` with
+    /// `root`
+    ProofLogsCompacted {
+        flow_id: u64,
+        count: u32,
+        root: [u8; 32],
+    },
+    CircuitApprovalSet {
+        circuit_hash: [u8; 32],
+        approved: bool,
+    },
+    CircuitAllowlistEnforced {
+        enforced: bool,
+    },
+    NullifierNamespaceCreated {
+        namespace: u64,
+        authority: Pubkey,
+    },
+    FlowEnabledSet {
+        flow_id: u64,
+        enabled: bool,
+    },
+}
+
+/// Root of a flow-tree-shaped Merkle tree holding `nullifiers` as leaves in
+/// order, so an indexer can check a batch against its `BatchValidated`
+/// event with `IncrementalMerkleTree`.
+pub fn nullifiers_root(nullifiers: &[[u8; 32]]) -> Result<[u8; 32], ProgramError> {
+    let mut tree = IncrementalMerkleTree::new();
+    for nullifier in nullifiers {
+        tree.append(*nullifier)?;
+    }
+    Ok(tree.root)
 }
 
 #[cfg(test)]
@@ -58,36 +147,125 @@ impl WaveEvent {
     pub fn emit(&self) {
         match self {
             Self::FlowRegistered { flow_id, merkle_root, circuit_hash } => {
-                msg!("Event: FlowRegistered");
-                msg!("  flow_id: {}", flow_id);
-                msg!("  merkle_root: {:?}", merkle_root);
-                msg!("  circuit_hash: {:?}", circuit_hash);
+                log_info!("Event: FlowRegistered");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  merkle_root: {:?}", merkle_root);
+                log_debug!("  circuit_hash: {:?}", circuit_hash);
             }
             Self::FlowExecuted { flow_id, nullifier } => {
-                msg!("Event: FlowExecuted");
-                msg!("  flow_id: {}", flow_id);
-                msg!("  nullifier: {:?}", nullifier);
+                log_info!("Event: FlowExecuted");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  nullifier: {:?}", nullifier);
             }
             Self::ProofRejected { flow_id, reason } => {
-                msg!("Event: ProofRejected");
-                msg!("  flow_id: {}", flow_id);
-                msg!("  reason: {}", reason);
+                log_info!("Event: ProofRejected");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  reason: {}", reason);
             }
             Self::NullifierUsed { nullifier, flow_id, timestamp } => {
-                msg!("Event: NullifierUsed");
-                msg!("  nullifier: {:?}", nullifier);
-                msg!("  flow_id: {}", flow_id);
-                msg!("  timestamp: {}", timestamp);
+                log_info!("Event: NullifierUsed");
+                log_debug!("  nullifier: {:?}", nullifier);
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  timestamp: {}", timestamp);
             }
-            Self::RootUpdated { flow_id, new_root } => {
-                msg!("Event: RootUpdated");
-                msg!("  flow_id: {}", flow_id);
-                msg!("  new_root: {:?}", new_root);
+            Self::RootUpdated { flow_id, new_root, sequence } => {
+                log_info!("Event: RootUpdated");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  new_root: {:?}", new_root);
+                log_debug!("  sequence: {}", sequence);
             }
             Self::FlowTriggered { flow_id, target_program } => {
-                msg!("Event: FlowTriggered");
-                msg!("  flow_id: {}", flow_id);
-                msg!("  target_program: {}", target_program);
+                log_info!("Event: FlowTriggered");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  target_program: {}", target_program);
+            }
+            Self::FlowCloned { source_flow_id, new_flow_id } => {
+                log_info!("Event: FlowCloned");
+                log_debug!("  source_flow_id: {}", source_flow_id);
+                log_debug!("  new_flow_id: {}", new_flow_id);
+            }
+            Self::NullifiersImported { flow_id, imported } => {
+                log_info!("Event: NullifiersImported");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  imported: {}", imported);
+            }
+            Self::AttestationRecorded { flow_id, recipient } => {
+                log_info!("Event: AttestationRecorded");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  recipient: {}", recipient);
+            }
+            Self::AttestationRevoked { flow_id, recipient } => {
+                log_info!("Event: AttestationRevoked");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  recipient: {}", recipient);
+            }
+            Self::RootAndCircuitUpdated { flow_id, new_root, new_circuit_hash, sequence } => {
+                log_info!("Event: RootAndCircuitUpdated");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  new_root: {:?}", new_root);
+                log_debug!("  new_circuit_hash: {:?}", new_circuit_hash);
+                log_debug!("  sequence: {}", sequence);
+            }
+            Self::CircuitFrozen { flow_id, circuit_hash } => {
+                log_info!("Event: CircuitFrozen");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  circuit_hash: {:?}", circuit_hash);
+            }
+            Self::NullifierMigrated { flow_id, nullifier } => {
+                log_info!("Event: NullifierMigrated");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  nullifier: {:?}", nullifier);
+            }
+            Self::NullifierMigrationFinalized { flow_id } => {
+                log_info!("Event: NullifierMigrationFinalized");
+                log_debug!("  flow_id: {}", flow_id);
+            }
+            Self::NotificationEntrySet { flow_id, label, enabled } => {
+                log_info!("Event: NotificationEntrySet");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  label: {}", label);
+                log_debug!("  enabled: {}", enabled);
+            }
+            Self::NotificationEntryCleared { flow_id, label } => {
+                log_info!("Event: NotificationEntryCleared");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  label: {}", label);
+            }
+            Self::ProgramConfigSet { admin, registration_fee } => {
+                log_info!("Event: ProgramConfigSet");
+                log_debug!("  admin: {}", admin);
+                log_debug!("  registration_fee: {}", registration_fee);
+            }
+            Self::BatchValidated { flow_id, count, nullifiers_root } => {
+                log_info!("Event: BatchValidated");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  count: {}", count);
+                log_debug!("  nullifiers_root: {:?}", nullifiers_root);
+            }
+            Self::ProofLogsCompacted { flow_id, count, root } => {
+                log_info!("Event: ProofLogsCompacted");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  count: {}", count);
+                log_debug!("  root: {:?}", root);
+            }
+            Self::CircuitApprovalSet { circuit_hash, approved } => {
+                log_info!("Event: CircuitApprovalSet");
+                log_debug!("  circuit_hash: {:?}", circuit_hash);
+                log_debug!("  approved: {}", approved);
+            }
+            Self::CircuitAllowlistEnforced { enforced } => {
+                log_info!("Event: CircuitAllowlistEnforced");
+                log_debug!("  enforced: {}", enforced);
+            }
+            Self::NullifierNamespaceCreated { namespace, authority } => {
+                log_info!("Event: NullifierNamespaceCreated");
+                log_debug!("  namespace: {}", namespace);
+                log_debug!("  authority: {}", authority);
+            }
+            Self::FlowEnabledSet { flow_id, enabled } => {
+                log_info!("Event: FlowEnabledSet");
+                log_debug!("  flow_id: {}", flow_id);
+                log_debug!("  enabled: {}", enabled);
             }
         }
     }
@@ -125,4 +303,27 @@ mod tests {
         // This will print to program logs
         event.emit();
     }
+
+    #[test]
+    fn test_nullifiers_root() {
+        let batch = [NULLIFIER_1, NULLIFIER_2, NULLIFIER_3];
+        let root = nullifiers_root(&batch).unwrap();
+
+        let mut tree = IncrementalMerkleTree::new();
+        for nullifier in batch {
+            tree.append(nullifier).unwrap();
+        }
+        assert_eq!(root, tree.root);
+
+        // Order matters, and an empty batch is the empty tree
+        assert_ne!(root, nullifiers_root(&[NULLIFIER_3, NULLIFIER_2, NULLIFIER_1]).unwrap());
+        assert_eq!(nullifiers_root(&[]).unwrap(), IncrementalMerkleTree::new().root);
+
+        WaveEvent::BatchValidated {
+            flow_id: FLOW_ID_1,
+            count: batch.len() as u32,
+            nullifiers_root: root,
+        }
+        .emit();
+    }
 } 
\ No newline at end of file
@@ -5,6 +5,12 @@ use solana_program::{
     pubkey::Pubkey,
 };
 
+use crate::{
+    constants::FLOW_TREE_DEPTH,
+    state::{AttestationPolicy, FlowRegistry},
+    verifier::ProofSystem,
+};
+
 pub mod init_registry;
 pub mod set_root;
 pub mod trigger_flow;
@@ -15,37 +21,111 @@ pub enum WaveInstruction {
     /// Initialize a new flow registry
     /// 
     /// Accounts expected:
-    /// 0. `[signer]` The authority that will control this flow
-    /// 1. `[writable]` The flow registry account to initialize
+    /// 0. `[signer, writable]` The authority that will control this flow,
+    ///    paying for the registry and any registration fee
+    /// 1. `[writable]` The registry PDA of `flow_id`, which must hold no
+    ///    data yet; it is created here, keeping any lamports already sent
+    ///    to it, or the instruction fails with `FlowAlreadyRegistered`
     /// 2. `[]` System program
+    /// 3. `[]` The program config PDA, which need not exist yet
+    /// 4. `[writable]` The treasury PDA, credited the registration fee
+    /// 5. `[writable]` The verifying key PDA for `circuit_hash`, created
+    ///    holding `verifying_key` unless another flow already did
+    /// 6. `[]` The approved circuits PDA, which need not exist yet
+    ///
+    /// With `nullifier_namespace` set, followed by:
+    /// 7. `[]` The namespace's `NullifierNamespace` PDA
+    /// 8. `[signer]` The namespace authority, admitting the flow
+    ///
+    /// While the approved circuits allowlist is enforced, a `circuit_hash`
+    /// it doesn't list fails with `InvalidCircuitHash`.
+    ///
+    /// `verifying_key` is the Borsh-encoded `VerifyingKey` for
+    /// `proof_system`, failing with `MalformedVerificationKey` if it doesn't
+    /// decode or takes more than `MAX_VERIFYING_KEY_INPUTS` inputs, and with
+    /// `InvalidVerifyingKey` unless its SHA-256 is `circuit_hash`.
+    /// Flows registered with the same `nullifier_namespace` share one
+    /// nullifier space; without it nullifiers are scoped to `flow_id`. A
+    /// namespace must first be created with CreateNullifierNamespace, and
+    /// joining it without its authority's signature fails with `Unauthorized`.
+    /// A non-zero `max_verification_cu` makes ValidateProof refuse to start
+    /// verifying with fewer compute units remaining. `attestation_policy`
+    /// decides whether a recipient can refresh an existing attestation.
+    /// With `max_nullifiers` set, ValidateProof accepts at most that many
+    /// proofs for the flow. `nullifier_commitment` records each nullifier
+    /// as `sha256(nullifier || scope)`, which also seeds its PDA. With
+    /// `freshness_window_slots` set, proofs must commit to a recent slot at
+    /// `PROOF_SLOT_OFFSET` in their public inputs. A non-zero
+    /// `registration_fee` in the program config is charged to the authority,
+    /// failing with `InsufficientFunds` if it can't pay.
     InitRegistry {
         flow_id: u64,
         merkle_root: Option<[u8; 32]>,
         circuit_hash: [u8; 32],
         callback_program_id: Option<[u8; 32]>,
+        nullifier_namespace: Option<u64>,
+        max_verification_cu: u32,
+        attestation_policy: AttestationPolicy,
+        max_nullifiers: Option<u64>,
+        nullifier_commitment: bool,
+        freshness_window_slots: Option<u64>,
+        proof_system: ProofSystem,
+        verifying_key: Vec<u8>,
     },
 
     /// Update the Merkle root for a flow
     /// 
     /// Accounts expected:
     /// 0. `[signer]` The flow authority
-    /// 1. `[writable]` The flow registry account
+    /// 1. `[writable]` The flow's registry PDA
+    ///
+    /// Fails with `StaleRootSequence` unless `expected_sequence` equals the
+    /// registry's current `root_sequence`, which is then incremented.
     SetRoot {
         new_root: [u8; 32],
+        expected_sequence: u64,
     },
 
     /// Validate a zero-knowledge proof
     /// 
     /// Accounts expected:
-    /// 0. `[signer]` The fee payer
-    /// 1. `[]` The flow registry account
-    /// 2. `[writable]` The nullifier PDA
-    /// 3. `[writable]` The proof log PDA (optional)
+    /// 0. `[signer, writable]` The fee payer, paying for the PDAs below that
+    ///    don't exist yet
+    /// 1. `[writable]` The flow registry account, whose nullifier count grows
+    /// 2. `[writable]` The nullifier PDA, seeded by the flow's nullifier scope,
+    ///    created if missing
+    /// 3. `[writable]` The proof log PDA, seeded by the flow ID and nullifier,
+    ///    created if missing
     /// 4. `[]` System program
+    /// 5. `[]` The verifying key PDA for the flow's `circuit_hash`
+    ///
+    /// With `mint_receipt` set, followed by:
+    /// 6. `[writable]` The attestation PDA of the recipient at
+    ///    `ATTESTATION_RECIPIENT_OFFSET` in `public_inputs`, split as
+    ///    `Attestation::recipient_public_inputs` does
+    ///
+    /// Until the flow's nullifier migration is finalized, the nullifier's
+    /// legacy PDA `[]` follows last and must not be recorded.
+    ///
+    /// Fails with `FlowDisabled` for a disabled flow and with
+    /// `InvalidAccountData` for a registry account never initialized. A
+    /// proof log account other than the PDA fails with `InvalidPda`, and
+    /// public inputs shorter than 32 bytes with `InvalidPublicInputs`. The
+    /// all-zero nullifier, indistinguishable from an unrecorded account,
+    /// fails with `InvalidNullifier`.
+    /// Flows with a freshness window fail with `ProofExpired` unless the
+    /// slot at `PROOF_SLOT_OFFSET` in `public_inputs` is at most that many
+    /// slots behind the clock.
+    ///
+    /// With `nullifier_ttl_seconds` set, the nullifier expires that many
+    /// seconds after the clock and may then be proven again. The TTL must
+    /// be positive.
     ValidateProof {
         proof: Vec<u8>,
         public_inputs: Vec<u8>,
         nullifier: [u8; 32],
+        mint_receipt: bool,
+        nullifier_ttl_seconds: Option<i64>,
     },
 
     /// Trigger downstream program after proof validation
@@ -54,13 +134,387 @@ pub enum WaveInstruction {
     /// 0. `[signer]` The fee payer
     /// 1. `[]` The flow registry account
     /// 2. `[]` The target program to call
+    ///
+    /// With `nullifier` set, followed by:
+    /// 3. `[writable]` The nullifier PDA, stamped with the trigger time
+    /// 4. `[]` System program, to fund reallocating a legacy nullifier
+    ///
     /// Additional accounts based on target program
+    ///
+    /// A nullifier can be consumed by only one trigger, and not at all once
+    /// it has expired (`NullifierExpired`).
     TriggerFlow {
         flow_id: u64,
         instruction_data: Vec<u8>,
+        nullifier: Option<[u8; 32]>,
+    },
+
+    /// Update the Merkle roots of several flows atomically
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The authority of every listed flow
+    /// 1..=N. `[writable]` The flow registry accounts, in `entries` order
+    ///
+    /// Each entry is `(flow_id, new_root, expected_sequence)`, checked as in
+    /// `SetRoot`; at most `MAX_SET_ROOT_BATCH_ENTRIES` fit in one transaction.
+    SetRootBatch {
+        entries: Vec<(u64, [u8; 32], u64)>,
+    },
+
+    /// Create a new flow from an existing flow's configuration
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The source flow's authority, paying for the
+    ///    new registry and any registration fee
+    /// 1. `[]` The source flow registry account
+    /// 2. `[writable]` The new flow registry PDA, created as in InitRegistry
+    /// 3. `[]` System program
+    /// 4. `[]` The program config PDA, which need not exist yet
+    /// 5. `[writable]` The treasury PDA, credited the registration fee
+    /// 6. `[]` The approved circuits PDA, which need not exist yet
+    ///
+    /// If the new flow has a nullifier namespace, cloned or overridden,
+    /// followed by:
+    /// 7. `[]` The namespace's `NullifierNamespace` PDA
+    /// 8. `[signer]` The namespace authority, admitting the flow
+    ///
+    /// As with InitRegistry, the registration fee is charged and a source
+    /// circuit the enforced allowlist doesn't list fails with
+    /// `InvalidCircuitHash`. The new flow belongs to the caller and starts disabled until
+    /// SetFlowEnabled. The Merkle
+    /// root is not copied; fields set in `overrides` replace the source's.
+    CloneFlow {
+        source_flow_id: u64,
+        new_flow_id: u64,
+        overrides: FlowOverrides,
+    },
+
+    /// Continue an existing off-chain tree on-chain from its frontier
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The flow authority, paying for the tree account
+    /// 1. `[writable]` The flow registry account
+    /// 2. `[writable]` The flow tree PDA, which must not exist yet
+    /// 3. `[]` System program
+    ///
+    /// Fails unless `frontier` and `leaf_count` reproduce `root`, which
+    /// becomes the flow's Merkle root. The frontier is boxed so it doesn't
+    /// set the size of every other instruction.
+    AttachTreeState {
+        flow_id: u64,
+        frontier: Box<[[u8; 32]; FLOW_TREE_DEPTH]>,
+        leaf_count: u64,
+        root: [u8; 32],
+    },
+
+    /// Append a leaf to the flow's tree and adopt the new root
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The flow authority
+    /// 1. `[writable]` The flow registry account
+    /// 2. `[writable]` The flow tree PDA
+    AppendLeaf {
+        flow_id: u64,
+        leaf: [u8; 32],
+    },
+
+    /// Seed a flow's nullifier scope with nullifiers used elsewhere
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The flow authority, paying for nullifier PDAs
+    ///    that don't exist yet
+    /// 1. `[]` The flow registry account
+    /// 2. `[]` System program
+    ///
+    /// 3..=N+2. `[writable]` The nullifier PDAs, in `nullifiers` order,
+    ///    created if missing
+    ///
+    /// Already-recorded nullifiers are skipped; at most
+    /// `MAX_IMPORT_NULLIFIERS` fit in one transaction. The all-zero
+    /// nullifier fails with `InvalidNullifier`.
+    ImportNullifiers {
+        nullifiers: Vec<[u8; 32]>,
+    },
+
+    /// Revoke a recipient's attestation for a flow
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The flow authority
+    /// 1. `[]` The flow registry account
+    /// 2. `[writable]` The attestation PDA
+    ///
+    /// Revoked attestations fail `has_attestation` and cannot be refreshed.
+    RevokeAttestation {
+        flow_id: u64,
+        recipient: Pubkey,
+    },
+
+    /// Update a flow's Merkle root and circuit together
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The flow authority, paying for the verifying
+    ///    key account if it is created
+    /// 1. `[writable]` The flow registry account
+    /// 2. `[]` System program
+    /// 3. `[writable]` The verifying key PDA for `new_circuit_hash`, created
+    ///    holding `new_verifying_key` unless another flow already did
+    /// 4. `[]` The approved circuits PDA, which need not exist yet
+    ///
+    /// Sequenced like `SetRoot`. Fails with `CircuitFrozen` once the flow's
+    /// circuit has been frozen. `new_verifying_key` is checked as
+    /// InitRegistry checks `verifying_key`, and `new_circuit_hash` against
+    /// the approved circuits allowlist while it is enforced.
+    UpdateRootAndCircuit {
+        new_root: [u8; 32],
+        new_circuit_hash: [u8; 32],
+        expected_sequence: u64,
+        new_verifying_key: Vec<u8>,
+    },
+
+    /// Permanently fix a flow's circuit
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The flow authority
+    /// 1. `[writable]` The flow registry account
+    FreezeCircuit {
+        flow_id: u64,
+    },
+
+    /// Move a nullifier from its legacy, unscoped PDA to the flow-scoped one
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The fee payer, funding the scoped PDA
+    /// 1. `[]` The flow registry account of the flow that used the nullifier
+    /// 2. `[writable]` The legacy nullifier PDA, closed afterwards
+    /// 3. `[writable]` The scoped nullifier PDA, created if missing
+    /// 4. `[writable]` The treasury PDA, credited the legacy account's rent
+    /// 5. `[]` System program
+    ///
+    /// Anyone can crank this until the flow finalizes its migration. A
+    /// nullifier already recorded at the scoped PDA keeps that record. In
+    /// commitment mode the scoped PDA, and the record, are keyed by the
+    /// nullifier's commitment, as ValidateProof looks them up.
+    MigrateNullifier {
+        nullifier: [u8; 32],
+    },
+
+    /// End a flow's nullifier migration window
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The flow authority
+    /// 1. `[writable]` The flow registry account
+    ///
+    /// Afterwards MigrateNullifier fails with `MigrationComplete` and
+    /// ValidateProof checks only scoped PDAs. Legacy nullifiers not migrated
+    /// by then can be spent again, so crank every one first.
+    FinalizeNullifierMigration {
+        flow_id: u64,
+    },
+
+    /// Add or replace an entry in a flow's notification manifest
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The flow authority, paying for the manifest
+    ///    account the first time
+    /// 1. `[]` The flow registry account
+    /// 2. `[writable]` The flow's notification manifest PDA, created if missing
+    /// 3. `[]` System program
+    ///
+    /// Entries are keyed by `label`. A new label fails with
+    /// `NotificationManifestFull` once the manifest holds
+    /// `MAX_NOTIFICATION_ENTRIES`.
+    SetNotificationEntry {
+        flow_id: u64,
+        label: String,
+        uri_hash: [u8; 64],
+        enabled: bool,
+    },
+
+    /// Remove an entry from a flow's notification manifest
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The flow authority
+    /// 1. `[]` The flow registry account
+    /// 2. `[writable]` The flow's notification manifest PDA
+    ClearNotificationEntry {
+        flow_id: u64,
+        label: String,
+    },
+
+    /// Create or update the program-wide config
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The config admin, paying for the config
+    ///    account the first time
+    /// 1. `[writable]` The program config PDA, created if missing
+    /// 2. `[]` System program
+    ///
+    /// Whoever creates the config becomes its admin, so create it right
+    /// after deploying. Afterwards only the admin can change it.
+    SetProgramConfig {
+        registration_fee: u64,
+    },
+
+    /// Report whether a proof with `public_inputs_hash` was logged
+    ///
+    /// Accounts expected:
+    /// 0..N. `[]` Candidate proof log accounts
+    ///
+    /// Proof logs are keyed by nullifier and there is no index by public
+    /// inputs, so the caller finds candidates off-chain, e.g. by filtering
+    /// program accounts on the bytes at `layout::proof_log::PUBLIC_INPUTS_HASH`,
+    /// and the program scans them. Accounts it doesn't own and logs never
+    /// written are ignored. `public_inputs_hash` is the first 32 bytes of the
+    /// public inputs, as ValidateProof logs them. Sets one byte of return
+    /// data, 1 if a candidate matches and 0 if none does; nothing is written.
+    WasProofValidated {
+        public_inputs_hash: [u8; 32],
+    },
+
+    /// Replace a flow's proof logs with a single Merkle commitment
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The flow authority, paying for the
+    ///    commitment and receiving the closed logs' rent
+    /// 1. `[]` The flow registry account
+    /// 2. `[writable]` The `ProofLogCommitment` PDA for the resulting root
+    /// 3. `[]` System program
+    ///
+    /// 4..=N+3. `[writable]` The proof log PDAs, in `nullifiers` order
+    ///
+    /// Each log must be recorded for this flow. The commitment's leaves are
+    /// `ProofLogCommitment::leaf` of each log in `nullifiers` order, so the
+    /// caller computes the root, and the PDA, off-chain beforehand.
+    CompactProofLogs {
+        nullifiers: Vec<[u8; 32]>,
+    },
+
+    /// Check that a compacted proof log is in a commitment
+    ///
+    /// Accounts expected:
+    /// 0. `[]` The `ProofLogCommitment` PDA
+    ///
+    /// `index` is the log's position in the CompactProofLogs `nullifiers`
+    /// and `proof` its `IncrementalMerkleTree::proof` over the committed
+    /// leaves. Fails with `ProofLogNotCommitted` unless the log is there;
+    /// nothing is written.
+    VerifyProofLogInclusion {
+        nullifier: [u8; 32],
+        public_inputs_hash: [u8; 32],
+        timestamp: i64,
+        index: u64,
+        proof: Vec<[u8; 32]>,
+    },
+
+    /// Validate several proofs against one flow atomically
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The fee payer, paying for the PDAs below that
+    ///    don't exist yet
+    /// 1. `[writable]` The flow registry account, whose nullifier count grows
+    /// 2. `[]` The verifying key PDA for the flow's `circuit_hash`
+    /// 3. `[]` System program
+    ///
+    /// Followed, for each entry in `proofs` order, by:
+    /// - `[writable]` The nullifier PDA, seeded by the flow's nullifier scope,
+    ///   created if missing
+    /// - `[writable]` The proof log PDA, created if missing
+    /// - `[]` The nullifier's legacy PDA, until the flow's nullifier
+    ///   migration is finalized
+    ///
+    /// Each entry is checked as in `ValidateProof`; if any fails, nothing is
+    /// written. At most `MAX_VALIDATE_BATCH_PROOFS` entries, and receipts
+    /// are not minted. Emits one `BatchValidated` for the batch.
+    ValidateBatchProofs {
+        proofs: Vec<BatchProofEntry>,
+    },
+
+    /// Add a circuit to, or remove it from, the approved circuits allowlist
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The program config admin, paying for the
+    ///    allowlist account the first time
+    /// 1. `[]` The program config PDA
+    /// 2. `[writable]` The approved circuits PDA, created if missing
+    /// 3. `[]` System program
+    ///
+    /// Approving fails with `ApprovedCircuitsFull` once the allowlist holds
+    /// `MAX_APPROVED_CIRCUITS`. Flows already registered are unaffected.
+    SetCircuitApproval {
+        circuit_hash: [u8; 32],
+        approved: bool,
+    },
+
+    /// Turn enforcement of the approved circuits allowlist on or off
+    ///
+    /// Accounts expected as for `SetCircuitApproval`.
+    SetCircuitAllowlistEnforced {
+        enforced: bool,
+    },
+
+    /// Create a nullifier namespace that flows can share
+    ///
+    /// Accounts expected:
+    /// 0. `[signer, writable]` The namespace authority, paying for the
+    ///    namespace account
+    /// 1. `[writable]` The `NullifierNamespace` PDA, which must not exist yet
+    /// 2. `[]` System program
+    ///
+    /// Flows join with `nullifier_namespace` in InitRegistry or CloneFlow,
+    /// which the authority must co-sign.
+    CreateNullifierNamespace {
+        namespace: u64,
+    },
+
+    /// Enable or disable a flow
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The flow authority
+    /// 1. `[writable]` The flow registry account
+    ///
+    /// A disabled flow fails ValidateProof with `FlowDisabled`. Cloned flows
+    /// start disabled and are enabled with this.
+    SetFlowEnabled {
+        flow_id: u64,
+        enabled: bool,
     },
 }
 
+/// One proof in a ValidateBatchProofs, as `ValidateProof` takes it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BatchProofEntry {
+    pub proof: Vec<u8>,
+    pub public_inputs: Vec<u8>,
+    pub nullifier: [u8; 32],
+}
+
+/// Settings a cloned flow takes instead of the source flow's. Unset fields
+/// keep the cloned value.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct FlowOverrides {
+    pub merkle_root: Option<[u8; 32]>,
+    pub callback_program_id: Option<[u8; 32]>,
+    pub nullifier_namespace: Option<u64>,
+    pub max_verification_cu: Option<u32>,
+}
+
+impl FlowOverrides {
+    pub fn apply(&self, registry: &mut FlowRegistry) {
+        if let Some(root) = self.merkle_root {
+            registry.merkle_root = Some(root);
+        }
+        if let Some(callback) = self.callback_program_id {
+            registry.callback_program_id = Some(Pubkey::new_from_array(callback));
+        }
+        if let Some(namespace) = self.nullifier_namespace {
+            registry.nullifier_namespace = Some(namespace);
+        }
+        if let Some(max_verification_cu) = self.max_verification_cu {
+            registry.max_verification_cu = max_verification_cu;
+        }
+    }
+}
+
 #[cfg(test)]
 pub struct InstructionProcessor {
     pub last_instruction: Option<WaveInstruction>,
@@ -120,6 +574,14 @@ mod tests {
             merkle_root: Some(MERKLE_ROOT_1),
             circuit_hash: CIRCUIT_HASH_1,
             callback_program_id: None,
+            nullifier_namespace: None,
+            max_verification_cu: 0,
+            attestation_policy: AttestationPolicy::Reject,
+            max_nullifiers: None,
+            nullifier_commitment: false,
+            freshness_window_slots: None,
+            proof_system: ProofSystem::Groth16,
+            verifying_key: vec![3u8; 64],
         };
         
         let instruction_data = instruction.try_to_vec().unwrap();
@@ -145,18 +607,128 @@ mod tests {
                 merkle_root: Some(MERKLE_ROOT_1),
                 circuit_hash: CIRCUIT_HASH_1,
                 callback_program_id: None,
+                nullifier_namespace: Some(FLOW_ID_3),
+                max_verification_cu: 200_000,
+                attestation_policy: AttestationPolicy::Refresh,
+                max_nullifiers: Some(2),
+                nullifier_commitment: true,
+                freshness_window_slots: Some(150),
+                proof_system: ProofSystem::Groth16,
+                verifying_key: vec![3u8; 580],
             },
             WaveInstruction::SetRoot {
                 new_root: MERKLE_ROOT_2,
+                expected_sequence: 7,
             },
             WaveInstruction::ValidateProof {
                 proof: PROOF_1.to_vec(),
                 public_inputs: PUBLIC_INPUTS_1.to_vec(),
                 nullifier: NULLIFIER_1,
+                mint_receipt: true,
+                nullifier_ttl_seconds: Some(86_400),
             },
             WaveInstruction::TriggerFlow {
                 flow_id: FLOW_ID_2,
                 instruction_data: vec![1, 2, 3],
+                nullifier: Some(NULLIFIER_2),
+            },
+            WaveInstruction::SetRootBatch {
+                entries: vec![(FLOW_ID_1, MERKLE_ROOT_1, 0), (FLOW_ID_2, MERKLE_ROOT_2, 3)],
+            },
+            WaveInstruction::CloneFlow {
+                source_flow_id: FLOW_ID_1,
+                new_flow_id: FLOW_ID_2,
+                overrides: FlowOverrides {
+                    merkle_root: Some(MERKLE_ROOT_2),
+                    max_verification_cu: Some(200_000),
+                    ..FlowOverrides::default()
+                },
+            },
+            WaveInstruction::AttachTreeState {
+                flow_id: FLOW_ID_1,
+                frontier: Box::new([MERKLE_ROOT_2; FLOW_TREE_DEPTH]),
+                leaf_count: 3,
+                root: MERKLE_ROOT_1,
+            },
+            WaveInstruction::AppendLeaf {
+                flow_id: FLOW_ID_1,
+                leaf: NULLIFIER_1,
+            },
+            WaveInstruction::ImportNullifiers {
+                nullifiers: vec![NULLIFIER_1, NULLIFIER_2, NULLIFIER_3],
+            },
+            WaveInstruction::RevokeAttestation {
+                flow_id: FLOW_ID_1,
+                recipient: Pubkey::new_unique(),
+            },
+            WaveInstruction::UpdateRootAndCircuit {
+                new_root: MERKLE_ROOT_3,
+                new_circuit_hash: CIRCUIT_HASH_2,
+                expected_sequence: 4,
+                new_verifying_key: vec![3u8; 580],
+            },
+            WaveInstruction::FreezeCircuit {
+                flow_id: FLOW_ID_2,
+            },
+            WaveInstruction::MigrateNullifier {
+                nullifier: NULLIFIER_2,
+            },
+            WaveInstruction::FinalizeNullifierMigration {
+                flow_id: FLOW_ID_3,
+            },
+            WaveInstruction::SetNotificationEntry {
+                flow_id: FLOW_ID_1,
+                label: "ops".to_string(),
+                uri_hash: [7u8; 64],
+                enabled: true,
+            },
+            WaveInstruction::ClearNotificationEntry {
+                flow_id: FLOW_ID_1,
+                label: "ops".to_string(),
+            },
+            WaveInstruction::SetProgramConfig {
+                registration_fee: 5_000_000,
+            },
+            WaveInstruction::WasProofValidated {
+                public_inputs_hash: PUBLIC_INPUTS_1,
+            },
+            WaveInstruction::CompactProofLogs {
+                nullifiers: vec![NULLIFIER_1, NULLIFIER_2],
+            },
+            WaveInstruction::VerifyProofLogInclusion {
+                nullifier: NULLIFIER_1,
+                public_inputs_hash: PUBLIC_INPUTS_1,
+                timestamp: TIMESTAMP_1,
+                index: 1,
+                proof: vec![MERKLE_ROOT_1; FLOW_TREE_DEPTH],
+            },
+            WaveInstruction::ValidateBatchProofs {
+                proofs: vec![
+                    BatchProofEntry {
+                        proof: vec![1u8; 128],
+                        public_inputs: PUBLIC_INPUTS_1.to_vec(),
+                        nullifier: NULLIFIER_1,
+                    },
+                    BatchProofEntry {
+                        proof: vec![2u8; 128],
+                        public_inputs: PUBLIC_INPUTS_1.to_vec(),
+                        nullifier: NULLIFIER_2,
+                    },
+                ],
+            },
+            WaveInstruction::SetCircuitApproval {
+                circuit_hash: CIRCUIT_HASH_2,
+                approved: true,
+            },
+            WaveInstruction::SetCircuitAllowlistEnforced {
+                enforced: true,
+            },
+            WaveInstruction::CreateNullifierNamespace {
+                namespace: FLOW_ID_3,
+            },
+            WaveInstruction::SetFlowEnabled {
+                flow_id: FLOW_ID_2,
+                enabled: true,
             },
         ];
 
@@ -166,37 +738,221 @@ mod tests {
             
             match (instruction, deserialized) {
                 (
-                    WaveInstruction::InitRegistry { flow_id: f1, merkle_root: m1, circuit_hash: c1, callback_program_id: p1 },
-                    WaveInstruction::InitRegistry { flow_id: f2, merkle_root: m2, circuit_hash: c2, callback_program_id: p2 }
+                    WaveInstruction::InitRegistry { flow_id: f1, merkle_root: m1, circuit_hash: c1, callback_program_id: p1, nullifier_namespace: n1, max_verification_cu: v1, attestation_policy: a1, max_nullifiers: x1, nullifier_commitment: b1, freshness_window_slots: w1, proof_system: s1, verifying_key: k1 },
+                    WaveInstruction::InitRegistry { flow_id: f2, merkle_root: m2, circuit_hash: c2, callback_program_id: p2, nullifier_namespace: n2, max_verification_cu: v2, attestation_policy: a2, max_nullifiers: x2, nullifier_commitment: b2, freshness_window_slots: w2, proof_system: s2, verifying_key: k2 }
                 ) => {
                     assert_eq!(f1, f2);
                     assert_eq!(m1, m2);
                     assert_eq!(c1, c2);
                     assert_eq!(p1, p2);
+                    assert_eq!(n1, n2);
+                    assert_eq!(v1, v2);
+                    assert_eq!(a1, a2);
+                    assert_eq!(x1, x2);
+                    assert_eq!(b1, b2);
+                    assert_eq!(w1, w2);
+                    assert_eq!(s1, s2);
+                    assert_eq!(k1, k2);
                 }
                 (
-                    WaveInstruction::SetRoot { new_root: r1 },
-                    WaveInstruction::SetRoot { new_root: r2 }
+                    WaveInstruction::SetRoot { new_root: r1, expected_sequence: s1 },
+                    WaveInstruction::SetRoot { new_root: r2, expected_sequence: s2 }
                 ) => {
                     assert_eq!(r1, r2);
+                    assert_eq!(s1, s2);
                 }
                 (
-                    WaveInstruction::ValidateProof { proof: p1, public_inputs: i1, nullifier: n1 },
-                    WaveInstruction::ValidateProof { proof: p2, public_inputs: i2, nullifier: n2 }
+                    WaveInstruction::ValidateProof { proof: p1, public_inputs: i1, nullifier: n1, mint_receipt: r1, nullifier_ttl_seconds: t1 },
+                    WaveInstruction::ValidateProof { proof: p2, public_inputs: i2, nullifier: n2, mint_receipt: r2, nullifier_ttl_seconds: t2 }
                 ) => {
                     assert_eq!(p1, p2);
                     assert_eq!(i1, i2);
                     assert_eq!(n1, n2);
+                    assert_eq!(r1, r2);
+                    assert_eq!(t1, t2);
                 }
                 (
-                    WaveInstruction::TriggerFlow { flow_id: f1, instruction_data: d1 },
-                    WaveInstruction::TriggerFlow { flow_id: f2, instruction_data: d2 }
+                    WaveInstruction::TriggerFlow { flow_id: f1, instruction_data: d1, nullifier: n1 },
+                    WaveInstruction::TriggerFlow { flow_id: f2, instruction_data: d2, nullifier: n2 }
                 ) => {
                     assert_eq!(f1, f2);
                     assert_eq!(d1, d2);
+                    assert_eq!(n1, n2);
+                }
+                (
+                    WaveInstruction::SetRootBatch { entries: e1 },
+                    WaveInstruction::SetRootBatch { entries: e2 }
+                ) => {
+                    assert_eq!(e1, e2);
+                }
+                (
+                    WaveInstruction::CloneFlow { source_flow_id: s1, new_flow_id: n1, overrides: o1 },
+                    WaveInstruction::CloneFlow { source_flow_id: s2, new_flow_id: n2, overrides: o2 }
+                ) => {
+                    assert_eq!(s1, s2);
+                    assert_eq!(n1, n2);
+                    assert_eq!(o1, o2);
+                }
+                (
+                    WaveInstruction::AttachTreeState { flow_id: f1, frontier: fr1, leaf_count: c1, root: r1 },
+                    WaveInstruction::AttachTreeState { flow_id: f2, frontier: fr2, leaf_count: c2, root: r2 }
+                ) => {
+                    assert_eq!(f1, f2);
+                    assert_eq!(fr1, fr2);
+                    assert_eq!(c1, c2);
+                    assert_eq!(r1, r2);
+                }
+                (
+                    WaveInstruction::AppendLeaf { flow_id: f1, leaf: l1 },
+                    WaveInstruction::AppendLeaf { flow_id: f2, leaf: l2 }
+                ) => {
+                    assert_eq!(f1, f2);
+                    assert_eq!(l1, l2);
+                }
+                (
+                    WaveInstruction::ImportNullifiers { nullifiers: n1 },
+                    WaveInstruction::ImportNullifiers { nullifiers: n2 }
+                ) => {
+                    assert_eq!(n1, n2);
+                }
+                (
+                    WaveInstruction::RevokeAttestation { flow_id: f1, recipient: r1 },
+                    WaveInstruction::RevokeAttestation { flow_id: f2, recipient: r2 }
+                ) => {
+                    assert_eq!(f1, f2);
+                    assert_eq!(r1, r2);
+                }
+                (
+                    WaveInstruction::UpdateRootAndCircuit { new_root: r1, new_circuit_hash: c1, expected_sequence: s1, new_verifying_key: k1 },
+                    WaveInstruction::UpdateRootAndCircuit { new_root: r2, new_circuit_hash: c2, expected_sequence: s2, new_verifying_key: k2 }
+                ) => {
+                    assert_eq!(r1, r2);
+                    assert_eq!(c1, c2);
+                    assert_eq!(s1, s2);
+                    assert_eq!(k1, k2);
+                }
+                (
+                    WaveInstruction::FreezeCircuit { flow_id: f1 },
+                    WaveInstruction::FreezeCircuit { flow_id: f2 }
+                ) => {
+                    assert_eq!(f1, f2);
+                }
+                (
+                    WaveInstruction::MigrateNullifier { nullifier: n1 },
+                    WaveInstruction::MigrateNullifier { nullifier: n2 }
+                ) => {
+                    assert_eq!(n1, n2);
+                }
+                (
+                    WaveInstruction::FinalizeNullifierMigration { flow_id: f1 },
+                    WaveInstruction::FinalizeNullifierMigration { flow_id: f2 }
+                ) => {
+                    assert_eq!(f1, f2);
+                }
+                (
+                    WaveInstruction::SetNotificationEntry { flow_id: f1, label: l1, uri_hash: u1, enabled: e1 },
+                    WaveInstruction::SetNotificationEntry { flow_id: f2, label: l2, uri_hash: u2, enabled: e2 }
+                ) => {
+                    assert_eq!(f1, f2);
+                    assert_eq!(l1, l2);
+                    assert_eq!(u1, u2);
+                    assert_eq!(e1, e2);
+                }
+                (
+                    WaveInstruction::ClearNotificationEntry { flow_id: f1, label: l1 },
+                    WaveInstruction::ClearNotificationEntry { flow_id: f2, label: l2 }
+                ) => {
+                    assert_eq!(f1, f2);
+                    assert_eq!(l1, l2);
+                }
+                (
+                    WaveInstruction::SetProgramConfig { registration_fee: r1 },
+                    WaveInstruction::SetProgramConfig { registration_fee: r2 }
+                ) => {
+                    assert_eq!(r1, r2);
+                }
+                (
+                    WaveInstruction::WasProofValidated { public_inputs_hash: h1 },
+                    WaveInstruction::WasProofValidated { public_inputs_hash: h2 }
+                ) => {
+                    assert_eq!(h1, h2);
+                }
+                (
+                    WaveInstruction::CompactProofLogs { nullifiers: n1 },
+                    WaveInstruction::CompactProofLogs { nullifiers: n2 }
+                ) => {
+                    assert_eq!(n1, n2);
+                }
+                (
+                    WaveInstruction::VerifyProofLogInclusion { nullifier: n1, public_inputs_hash: h1, timestamp: t1, index: i1, proof: p1 },
+                    WaveInstruction::VerifyProofLogInclusion { nullifier: n2, public_inputs_hash: h2, timestamp: t2, index: i2, proof: p2 }
+                ) => {
+                    assert_eq!((n1, h1, t1, i1, p1), (n2, h2, t2, i2, p2));
+                }
+                (
+                    WaveInstruction::ValidateBatchProofs { proofs: p1 },
+                    WaveInstruction::ValidateBatchProofs { proofs: p2 }
+                ) => {
+                    assert_eq!(p1, p2);
+                }
+                (
+                    WaveInstruction::SetCircuitApproval { circuit_hash: c1, approved: a1 },
+                    WaveInstruction::SetCircuitApproval { circuit_hash: c2, approved: a2 }
+                ) => {
+                    assert_eq!(c1, c2);
+                    assert_eq!(a1, a2);
+                }
+                (
+                    WaveInstruction::SetCircuitAllowlistEnforced { enforced: e1 },
+                    WaveInstruction::SetCircuitAllowlistEnforced { enforced: e2 }
+                ) => {
+                    assert_eq!(e1, e2);
+                }
+                (
+                    WaveInstruction::CreateNullifierNamespace { namespace: n1 },
+                    WaveInstruction::CreateNullifierNamespace { namespace: n2 }
+                ) => {
+                    assert_eq!(n1, n2);
+                }
+                (
+                    WaveInstruction::SetFlowEnabled { flow_id: f1, enabled: e1 },
+                    WaveInstruction::SetFlowEnabled { flow_id: f2, enabled: e2 }
+                ) => {
+                    assert_eq!(f1, f2);
+                    assert_eq!(e1, e2);
                 }
                 _ => panic!("Instructions don't match after serialization/deserialization"),
             }
         }
     }
+
+    #[test]
+    fn test_flow_overrides_apply() {
+        let source = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_1,
+            Some(MERKLE_ROOT_1),
+            CIRCUIT_HASH_1,
+            None,
+        );
+
+        let mut unchanged = source.clone_as(FLOW_ID_2);
+        FlowOverrides::default().apply(&mut unchanged);
+        assert_eq!(unchanged, source.clone_as(FLOW_ID_2));
+
+        let callback = Pubkey::new_unique();
+        let mut clone = source.clone_as(FLOW_ID_2);
+        FlowOverrides {
+            merkle_root: Some(MERKLE_ROOT_2),
+            callback_program_id: Some(callback.to_bytes()),
+            nullifier_namespace: Some(FLOW_ID_1),
+            max_verification_cu: Some(150_000),
+        }
+        .apply(&mut clone);
+        assert_eq!(clone.merkle_root, Some(MERKLE_ROOT_2));
+        assert_eq!(clone.callback_program_id, Some(callback));
+        assert_eq!(clone.nullifier_namespace, Some(FLOW_ID_1));
+        assert_eq!(clone.max_verification_cu, 150_000);
+        assert_eq!(clone.circuit_hash, source.circuit_hash);
+    }
 } 
\ No newline at end of file
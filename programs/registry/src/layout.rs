@@ -0,0 +1,97 @@
+//! Byte offsets of account fields, for memcmp filters.
+//!
+//! Accounts are plain Borsh with no discriminator, so offsets only stay fixed
+//! up to the first `Option`. Fields after one move by 32 bytes depending on
+//! whether it is set and are deliberately not listed here.
+
+pub mod flow_registry {
+    pub const AUTHORITY: usize = 0;
+    pub const FLOW_ID: usize = 32;
+    /// Borsh tag of `merkle_root`: 0 for `None`, 1 for `Some`
+    pub const MERKLE_ROOT_TAG: usize = 40;
+}
+
+pub mod nullifier {
+    pub const HASH: usize = 0;
+    pub const TIMESTAMP: usize = 32;
+    pub const FLOW_ID: usize = 40;
+    pub const PROOF_LOG: usize = 48;
+    pub const VERSION: usize = 80;
+    /// Borsh tag of `triggered_at`: 0 for `None`, 1 for `Some`
+    pub const TRIGGERED_AT_TAG: usize = 81;
+}
+
+pub mod proof_log {
+    pub const NULLIFIER: usize = 0;
+    pub const TIMESTAMP: usize = 32;
+    pub const FLOW_ID: usize = 40;
+    pub const PUBLIC_INPUTS_HASH: usize = 48;
+    pub const CIRCUIT_HASH: usize = 80;
+    pub const PROOF_SYSTEM: usize = 112;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::{FlowRegistry, Nullifier, ProofLog},
+        verifier::ProofSystem,
+    };
+    use borsh::BorshSerialize;
+    use solana_program::pubkey::Pubkey;
+
+    fn field_at(data: &[u8], offset: usize, expected: &[u8]) -> bool {
+        data.get(offset..offset + expected.len()) == Some(expected)
+    }
+
+    #[test]
+    fn test_flow_registry_offsets() {
+        let authority = Pubkey::new_unique();
+        let flow_id = 0x0102_0304_0506_0708u64;
+
+        let mut registry = FlowRegistry::new(authority, flow_id, None, [3u8; 32], None);
+        let data = registry.try_to_vec().unwrap();
+        assert!(field_at(&data, flow_registry::AUTHORITY, authority.as_ref()));
+        assert!(field_at(&data, flow_registry::FLOW_ID, &flow_id.to_le_bytes()));
+        assert_eq!(data[flow_registry::MERKLE_ROOT_TAG], 0);
+
+        registry.merkle_root = Some([4u8; 32]);
+        let data = registry.try_to_vec().unwrap();
+        assert_eq!(data[flow_registry::MERKLE_ROOT_TAG], 1);
+        assert!(field_at(&data, flow_registry::MERKLE_ROOT_TAG + 1, &[4u8; 32]));
+    }
+
+    #[test]
+    fn test_nullifier_offsets() {
+        let proof_log = Pubkey::new_unique();
+        let mut record = Nullifier::new([5u8; 32], -7, 0x1122_3344_5566_7788, proof_log);
+        let data = record.try_to_vec().unwrap();
+        assert!(field_at(&data, nullifier::HASH, &[5u8; 32]));
+        assert!(field_at(&data, nullifier::TIMESTAMP, &(-7i64).to_le_bytes()));
+        assert!(field_at(&data, nullifier::FLOW_ID, &0x1122_3344_5566_7788u64.to_le_bytes()));
+        assert!(field_at(&data, nullifier::PROOF_LOG, proof_log.as_ref()));
+        assert_eq!(data[nullifier::VERSION], Nullifier::VERSION);
+        assert_eq!(data[nullifier::TRIGGERED_AT_TAG], 0);
+
+        record.triggered_at = Some(42);
+        let data = record.try_to_vec().unwrap();
+        assert_eq!(data[nullifier::TRIGGERED_AT_TAG], 1);
+        assert!(field_at(&data, nullifier::TRIGGERED_AT_TAG + 1, &42i64.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_proof_log_offsets() {
+        let log = ProofLog {
+            circuit_hash: [8u8; 32],
+            proof_system: ProofSystem::Groth16,
+            ..ProofLog::new([6u8; 32], 99, 0x99aa_bbcc_ddee_ff00, [7u8; 32])
+        };
+        let data = log.try_to_vec().unwrap();
+        assert!(field_at(&data, proof_log::NULLIFIER, &[6u8; 32]));
+        assert!(field_at(&data, proof_log::TIMESTAMP, &99i64.to_le_bytes()));
+        assert!(field_at(&data, proof_log::FLOW_ID, &0x99aa_bbcc_ddee_ff00u64.to_le_bytes()));
+        assert!(field_at(&data, proof_log::PUBLIC_INPUTS_HASH, &[7u8; 32]));
+        assert!(field_at(&data, proof_log::CIRCUIT_HASH, &[8u8; 32]));
+        assert_eq!(data[proof_log::PROOF_SYSTEM], ProofSystem::Groth16 as u8);
+    }
+}
\ No newline at end of file
@@ -5,29 +5,46 @@ use solana_program::{
     pubkey::Pubkey,
 };
 
+#[macro_use]
+mod log;
+
 pub mod constants;
 pub mod error;
 pub mod events;
 pub mod instructions;
+pub mod layout;
 pub mod processor;
 pub mod state;
+pub mod verifier;
 
 use processor::process_instruction;
 
 entrypoint!(process_instruction);
 
-#[cfg(test)]
+#[cfg(any(test, feature = "simulation"))]
+pub mod sim;
+
+#[cfg(any(test, feature = "simulation"))]
+pub mod compute;
+
+#[cfg(any(test, feature = "simulation"))]
 pub mod test_utils {
     use super::*;
+    use borsh::BorshDeserialize;
+    use solana_program::program_error::ProgramError;
     use crate::{
-        state::{
-            flow_registry::RegistryManager,
-            nullifier::NullifierSet,
-            proof_log::ProofHistory,
-        },
+        constants::{MAX_IMPORT_NULLIFIERS, MAX_SET_ROOT_BATCH_ENTRIES, MAX_VALIDATE_BATCH_PROOFS},
+        error::WaveError,
         instructions::WaveInstruction,
+        sim::{NullifierSet, ProofHistory, RegistryManager},
+        state::{FlowRegistry, IncrementalMerkleTree, NotificationManifest, Nullifier, ProofLog},
+        verifier::VerifyingKey,
     };
 
+    /// Runs instructions against the `sim` models instead of accounts.
+    /// Registries are found by their PDA, so callers pass the account keys
+    /// they would send to the program; account data is never read.
+    #[derive(Debug, Clone, Default, PartialEq)]
     pub struct TestEnvironment {
         pub registry_manager: RegistryManager,
         pub nullifier_set: NullifierSet,
@@ -43,89 +60,469 @@ pub mod test_utils {
             }
         }
 
+        fn registry(&self, program_id: &Pubkey, account: &AccountInfo) -> Result<FlowRegistry, ProgramError> {
+            self.registry_manager
+                .get_by_key(program_id, account.key)
+                .cloned()
+                .ok_or_else(|| WaveError::InvalidFlowId.into())
+        }
+
+        /// Checks `authority` may admit a flow to `namespace`; the namespace
+        /// account itself is not modelled.
+        fn check_namespace_admission(&self, namespace: u64, authority: &AccountInfo) -> ProgramResult {
+            if self.registry_manager.namespace_authority(namespace).is_none() {
+                return Err(ProgramError::IllegalOwner);
+            }
+            if !authority.is_signer
+                || self.registry_manager.namespace_authority(namespace) != Some(authority.key)
+            {
+                return Err(WaveError::Unauthorized.into());
+            }
+            Ok(())
+        }
+
+        /// Applies one instruction. Like the program, a failed instruction
+        /// may leave partial writes; callers modelling transactions should
+        /// `restore` a `snapshot` taken before it.
         pub fn process_instruction(
             &mut self,
             program_id: &Pubkey,
             accounts: &[AccountInfo],
             instruction_data: &[u8],
         ) -> ProgramResult {
-            let instruction = WaveInstruction::try_from_slice(instruction_data)?;
+            let instruction = WaveInstruction::try_from_slice(instruction_data)
+                .map_err(|_| WaveError::InvalidInstruction)?;
             
             match instruction {
                 WaveInstruction::InitRegistry { 
                     flow_id, 
                     merkle_root, 
                     circuit_hash, 
-                    callback_program_id 
+                    callback_program_id,
+                    nullifier_namespace,
+                    max_verification_cu,
+                    attestation_policy,
+                    max_nullifiers,
+                    nullifier_commitment,
+                    freshness_window_slots,
+                    proof_system,
+                    verifying_key,
                 } => {
-                    let registry = state::flow_registry::FlowRegistry::new(
+                    // Lamports aren't modelled, so any registration fee is
+                    // not charged; the key is checked but not stored, and no
+                    // circuit allowlist is enforced
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if self.registry_manager.get_by_id(flow_id).is_some() {
+                        return Err(WaveError::FlowAlreadyRegistered.into());
+                    }
+                    if circuit_hash == [0u8; 32] {
+                        return Err(WaveError::InvalidCircuitHash.into());
+                    }
+                    if VerifyingKey::from_bytes(&verifying_key)?.hash()? != circuit_hash {
+                        return Err(WaveError::InvalidVerifyingKey.into());
+                    }
+                    if callback_program_id == Some(program_id.to_bytes()) {
+                        return Err(WaveError::InvalidCallbackProgram.into());
+                    }
+                    if let Some(namespace) = nullifier_namespace {
+                        self.check_namespace_admission(namespace, &accounts[8])?;
+                    }
+                    let mut registry = FlowRegistry::new(
                         *accounts[0].key,
                         flow_id,
                         merkle_root,
                         circuit_hash,
                         callback_program_id.map(|id| Pubkey::new_from_array(id)),
                     );
+                    registry.nullifier_namespace = nullifier_namespace;
+                    registry.max_verification_cu = max_verification_cu;
+                    registry.attestation_policy = attestation_policy;
+                    registry.max_nullifiers = max_nullifiers;
+                    registry.nullifier_commitment = nullifier_commitment;
+                    registry.freshness_window_slots = freshness_window_slots;
+                    registry.proof_system = proof_system;
                     self.registry_manager.register(registry);
                     Ok(())
                 }
-                WaveInstruction::SetRoot { new_root } => {
-                    let registry = self.registry_manager.get_by_id(0).ok_or(error::WaveError::FlowNotRegistered)?;
+                WaveInstruction::SetRoot { new_root, expected_sequence } => {
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if registry.root_sequence != expected_sequence {
+                        return Err(WaveError::StaleRootSequence.into());
+                    }
+                    self.registry_manager.update_root(registry.flow_id, new_root)
+                }
+                WaveInstruction::UpdateRootAndCircuit {
+                    new_root,
+                    new_circuit_hash,
+                    expected_sequence,
+                    new_verifying_key,
+                } => {
+                    // As in InitRegistry, the key is checked but not stored
+                    // and no circuit allowlist is enforced
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if new_circuit_hash == [0u8; 32] {
+                        return Err(WaveError::InvalidCircuitHash.into());
+                    }
+                    if VerifyingKey::from_bytes(&new_verifying_key)?.hash()? != new_circuit_hash {
+                        return Err(WaveError::InvalidVerifyingKey.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if registry.circuit_frozen {
+                        return Err(WaveError::CircuitFrozen.into());
+                    }
+                    if registry.root_sequence != expected_sequence {
+                        return Err(WaveError::StaleRootSequence.into());
+                    }
+                    self.registry_manager.update_root(registry.flow_id, new_root)?;
+                    self.registry_manager.set_circuit(registry.flow_id, new_circuit_hash)
+                }
+                WaveInstruction::FreezeCircuit { flow_id } => {
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    if registry.flow_id != flow_id {
+                        return Err(WaveError::InvalidFlowId.into());
+                    }
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    self.registry_manager.freeze_circuit(flow_id)
+                }
+                WaveInstruction::SetRootBatch { entries } => {
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if entries.is_empty() || entries.len() > MAX_SET_ROOT_BATCH_ENTRIES {
+                        return Err(WaveError::InvalidInstruction.into());
+                    }
+                    for (i, (flow_id, _, expected_sequence)) in entries.iter().enumerate() {
+                        let registry = self.registry(program_id, &accounts[i + 1])?;
+                        if registry.flow_id != *flow_id {
+                            return Err(WaveError::InvalidFlowId.into());
+                        }
+                        if accounts[0].key != &registry.authority {
+                            return Err(WaveError::Unauthorized.into());
+                        }
+                        if registry.root_sequence != *expected_sequence {
+                            return Err(WaveError::StaleRootSequence.into());
+                        }
+                    }
+                    for (flow_id, new_root, _) in entries {
+                        self.registry_manager.update_root(flow_id, new_root)?;
+                    }
+                    Ok(())
+                }
+                WaveInstruction::CloneFlow { source_flow_id, new_flow_id, overrides } => {
+                    // As for InitRegistry, no fee is charged or allowlist enforced
+                    let source = self.registry_manager.get_by_id(source_flow_id)
+                        .ok_or(WaveError::InvalidFlowId)?;
+                    if accounts[0].key != &source.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if self.registry_manager.get_by_id(new_flow_id).is_some() {
+                        return Err(WaveError::FlowAlreadyRegistered.into());
+                    }
+                    let mut clone = source.clone_as(new_flow_id);
+                    clone.authority = *accounts[0].key;
+                    overrides.apply(&mut clone);
+                    if clone.callback_program_id == Some(*program_id) {
+                        return Err(WaveError::InvalidCallbackProgram.into());
+                    }
+                    if let Some(namespace) = clone.nullifier_namespace {
+                        self.check_namespace_admission(namespace, &accounts[8])?;
+                    }
+                    self.registry_manager.register(clone);
+                    Ok(())
+                }
+                WaveInstruction::AttachTreeState { flow_id, frontier, leaf_count, root } => {
+                    let registry = self.registry_manager.get_by_id(flow_id)
+                        .ok_or(WaveError::InvalidFlowId)?;
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    IncrementalMerkleTree::from_frontier(*frontier, leaf_count, root)?;
+                    self.registry_manager.update_root(flow_id, root)?;
+                    Ok(())
+                }
+                WaveInstruction::AppendLeaf { flow_id, .. } => {
+                    let registry = self.registry_manager.get_by_id(flow_id)
+                        .ok_or(WaveError::InvalidFlowId)?;
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    Ok(())
+                }
+                WaveInstruction::RevokeAttestation { flow_id, .. } => {
+                    let registry = self.registry_manager.get_by_id(flow_id)
+                        .ok_or(WaveError::InvalidFlowId)?;
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    Ok(())
+                }
+                WaveInstruction::ImportNullifiers { nullifiers } => {
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if nullifiers.is_empty() || nullifiers.len() > MAX_IMPORT_NULLIFIERS {
+                        return Err(WaveError::InvalidInstruction.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
                     if accounts[0].key != &registry.authority {
-                        return Err(error::WaveError::Unauthorized.into());
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if nullifiers.contains(&[0u8; 32]) {
+                        return Err(WaveError::InvalidNullifier.into());
+                    }
+                    for nullifier in nullifiers {
+                        self.nullifier_set.add(registry.nullifier_scope(), Nullifier::new(
+                            registry.nullifier_key(&nullifier),
+                            0,
+                            registry.flow_id,
+                            Pubkey::default(),
+                        ));
                     }
-                    self.registry_manager.update_root(0, new_root)?;
                     Ok(())
                 }
                 WaveInstruction::ValidateProof { 
-                    proof, 
                     public_inputs, 
-                    nullifier 
+                    nullifier,
+                    ..
                 } => {
-                    if self.nullifier_set.exists(&nullifier) {
-                        return Err(error::WaveError::NullifierAlreadyUsed.into());
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    registry.check_accepts_proofs()?;
+                    if nullifier == [0u8; 32] {
+                        return Err(WaveError::InvalidNullifier.into());
+                    }
+                    let public_inputs_hash: [u8; 32] = public_inputs
+                        .get(..32)
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .ok_or(WaveError::InvalidPublicInputs)?;
+                    if registry.at_capacity() {
+                        return Err(WaveError::FlowCapacityReached.into());
+                    }
+                    // The simulator has no clock, so it is always at slot 0
+                    registry.check_freshness(&public_inputs, 0)?;
+                    let nullifier_key = registry.nullifier_key(&nullifier);
+                    if self.nullifier_set.exists(registry.nullifier_scope(), &nullifier_key) {
+                        return Err(WaveError::NullifierAlreadyUsed.into());
                     }
                     
-                    let timestamp = 0i64; // For testing
-                    let flow_id = 0u64; // For testing
-                    
-                    let nullifier_entry = state::nullifier::Nullifier::new(
-                        nullifier,
-                        timestamp,
-                        flow_id,
-                    );
-                    self.nullifier_set.add(nullifier_entry);
-                    
-                    let mut public_inputs_hash = [0u8; 32];
-                    public_inputs_hash.copy_from_slice(&public_inputs[..32]);
+                    let timestamp = 0i64; // The simulator has no clock
                     
-                    let proof_log = state::proof_log::ProofLog::new(
+                    self.proof_history.record(ProofLog::validated(
+                        &registry,
                         nullifier,
                         timestamp,
-                        flow_id,
                         public_inputs_hash,
-                    );
-                    self.proof_history.record(proof_log);
+                    ));
+
+                    self.registry_manager.count_nullifier(registry.flow_id)?;
+                    self.nullifier_set.add(registry.nullifier_scope(), Nullifier::new(
+                        nullifier_key,
+                        timestamp,
+                        registry.flow_id,
+                        *accounts[3].key,
+                    ));
                     
                     Ok(())
                 }
                 WaveInstruction::TriggerFlow { 
                     flow_id, 
-                    instruction_data 
+                    ..
                 } => {
                     let registry = self.registry_manager.get_by_id(flow_id)
-                        .ok_or(error::WaveError::FlowNotRegistered)?;
+                        .ok_or(WaveError::InvalidFlowId)?;
                     
                     if !registry.is_enabled {
-                        return Err(error::WaveError::InvalidInstruction.into());
+                        return Err(WaveError::InvalidInstruction.into());
                     }
                     
                     // In test environment, just verify the accounts are present
                     if accounts.len() < 3 {
-                        return Err(error::WaveError::InvalidInstruction.into());
+                        return Err(WaveError::InvalidInstruction.into());
                     }
                     
                     Ok(())
                 }
+                WaveInstruction::MigrateNullifier { .. } => {
+                    // Legacy records are not modelled, so there is nothing
+                    // to move
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    if registry.migration_complete {
+                        return Err(WaveError::MigrationComplete.into());
+                    }
+                    Ok(())
+                }
+                WaveInstruction::FinalizeNullifierMigration { flow_id } => {
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    if registry.flow_id != flow_id {
+                        return Err(WaveError::InvalidFlowId.into());
+                    }
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    self.registry_manager.finalize_migration(flow_id)
+                }
+                WaveInstruction::SetFlowEnabled { flow_id, enabled } => {
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    if registry.flow_id != flow_id {
+                        return Err(WaveError::InvalidFlowId.into());
+                    }
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    self.registry_manager.set_enabled(flow_id, enabled)
+                }
+                WaveInstruction::SetNotificationEntry { flow_id, ref label, .. }
+                | WaveInstruction::ClearNotificationEntry { flow_id, ref label } => {
+                    // Manifests are not modelled; only the checks run
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    if registry.flow_id != flow_id {
+                        return Err(WaveError::InvalidFlowId.into());
+                    }
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    NotificationManifest::validate_label(label)?;
+                    Ok(())
+                }
+                WaveInstruction::CreateNullifierNamespace { namespace } => {
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if !self.registry_manager.create_namespace(namespace, *accounts[0].key) {
+                        return Err(ProgramError::AccountAlreadyInitialized);
+                    }
+                    Ok(())
+                }
+                WaveInstruction::SetProgramConfig { .. }
+                | WaveInstruction::SetCircuitApproval { .. }
+                | WaveInstruction::SetCircuitAllowlistEnforced { .. } => {
+                    // The program config and circuit allowlist are not
+                    // modelled; only the checks run
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    Ok(())
+                }
+                WaveInstruction::WasProofValidated { .. } => {
+                    // A read-only query; return data is not modelled, see
+                    // `ProofHistory::find_by_public_inputs_hash`
+                    Ok(())
+                }
+                WaveInstruction::CompactProofLogs { ref nullifiers } => {
+                    // The commitment is not modelled; the logs just leave
+                    // the history
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    let registry = self.registry(program_id, &accounts[1])?;
+                    if accounts[0].key != &registry.authority {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if nullifiers.is_empty() {
+                        return Err(WaveError::InvalidInstruction.into());
+                    }
+                    for (i, nullifier) in nullifiers.iter().enumerate() {
+                        if nullifiers[..i].contains(nullifier)
+                            || self.proof_history.get(registry.flow_id, nullifier).is_none()
+                        {
+                            return Err(WaveError::InvalidNullifier.into());
+                        }
+                    }
+                    for nullifier in nullifiers {
+                        self.proof_history.logs.remove(&(registry.flow_id, *nullifier));
+                    }
+                    Ok(())
+                }
+                WaveInstruction::VerifyProofLogInclusion { .. } => {
+                    // Commitments are not modelled, see
+                    // `ProofLogCommitment::includes`
+                    Ok(())
+                }
+                WaveInstruction::ValidateBatchProofs { proofs } => {
+                    if !accounts[0].is_signer {
+                        return Err(WaveError::Unauthorized.into());
+                    }
+                    if proofs.is_empty() || proofs.len() > MAX_VALIDATE_BATCH_PROOFS {
+                        return Err(WaveError::InvalidInstruction.into());
+                    }
+                    let mut registry = self.registry(program_id, &accounts[1])?;
+                    registry.check_accepts_proofs()?;
+                    // Past the payer, registry, verifying key and system
+                    // program, each entry brings a nullifier and a proof log
+                    // account, plus the legacy nullifier until migration is
+                    // finalized
+                    let stride = if registry.migration_complete { 2 } else { 3 };
+
+                    let mut nullifier_keys = Vec::with_capacity(proofs.len());
+                    for entry in &proofs {
+                        if registry.at_capacity() {
+                            return Err(WaveError::FlowCapacityReached.into());
+                        }
+                        registry.check_freshness(&entry.public_inputs, 0)?;
+                        if entry.nullifier == [0u8; 32] {
+                            return Err(WaveError::InvalidNullifier.into());
+                        }
+                        let nullifier_key = registry.nullifier_key(&entry.nullifier);
+                        if self.nullifier_set.exists(registry.nullifier_scope(), &nullifier_key)
+                            || nullifier_keys.contains(&nullifier_key)
+                        {
+                            return Err(WaveError::NullifierAlreadyUsed.into());
+                        }
+                        if entry.public_inputs.len() < 32 {
+                            return Err(WaveError::InvalidPublicInputs.into());
+                        }
+                        registry.nullifier_count += 1;
+                        nullifier_keys.push(nullifier_key);
+                    }
+
+                    for (i, (entry, nullifier_key)) in proofs.iter().zip(nullifier_keys).enumerate() {
+                        let mut public_inputs_hash = [0u8; 32];
+                        public_inputs_hash.copy_from_slice(&entry.public_inputs[..32]);
+                        self.proof_history.record(ProofLog::validated(
+                            &registry,
+                            entry.nullifier,
+                            0,
+                            public_inputs_hash,
+                        ));
+                        self.registry_manager.count_nullifier(registry.flow_id)?;
+                        self.nullifier_set.add(registry.nullifier_scope(), Nullifier::new(
+                            nullifier_key,
+                            0,
+                            registry.flow_id,
+                            *accounts[5 + i * stride].key,
+                        ));
+                    }
+                    Ok(())
+                }
             }
         }
 
@@ -134,5 +531,13 @@ pub mod test_utils {
             self.nullifier_set.reset();
             self.proof_history.reset();
         }
+
+        pub fn snapshot(&self) -> Self {
+            self.clone()
+        }
+
+        pub fn restore(&mut self, snapshot: Self) {
+            *self = snapshot;
+        }
     }
-} 
\ No newline at end of file
+}
\ No newline at end of file
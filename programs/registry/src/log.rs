@@ -0,0 +1,26 @@
+//! Leveled wrappers around `msg!`. Every log line costs at least 100 CU,
+//! so `log_debug!` compiles to nothing unless the `verbose-logs` feature is
+//! enabled; `log_info!` always logs.
+
+macro_rules! log_info {
+    ($($arg:tt)+) => {
+        solana_program::msg!($($arg)+)
+    };
+}
+
+#[cfg(feature = "verbose-logs")]
+macro_rules! log_debug {
+    ($($arg:tt)+) => {
+        solana_program::msg!($($arg)+)
+    };
+}
+
+// Still type-checks the arguments, so call sites behave the same either way
+#[cfg(not(feature = "verbose-logs"))]
+macro_rules! log_debug {
+    ($($arg:tt)+) => {
+        if false {
+            solana_program::msg!($($arg)+)
+        }
+    };
+}
\ No newline at end of file
@@ -1,45 +1,33 @@
 use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    compute_units::sol_remaining_compute_units,
     entrypoint::ProgramResult,
-    msg,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     pubkey::Pubkey,
+    system_instruction,
     system_program,
-    sysvar::{clock::Clock, Sysvar},
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
 use crate::{
+    constants::{
+        APPROVED_CIRCUITS_SEED, ATTESTATION_SEED, FLOW_TREE_SEED,
+        MAX_IMPORT_NULLIFIERS, MAX_SET_ROOT_BATCH_ENTRIES, MAX_VALIDATE_BATCH_PROOFS,
+        NOTIFICATION_MANIFEST_SEED, NULLIFIER_NAMESPACE_SEED, PROGRAM_CONFIG_SEED,
+        PROOF_LOG_COMMITMENT_SEED, PROOF_LOG_SEED, REGISTRY_SEED, TREASURY_SEED, VERIFYING_KEY_SEED,
+    },
     error::WaveError,
-    events::WaveEvent,
+    events::{nullifiers_root, WaveEvent},
     instructions::WaveInstruction,
-    state::{FlowRegistry, Nullifier, ProofLog},
+    state::{
+        ApprovedCircuits, Attestation, AttestationPolicy, FlowRegistry, IncrementalMerkleTree, NotificationManifest,
+        Nullifier, NullifierNamespace, ProgramConfig, ProofLog, ProofLogCommitment, ProofLogRef,
+    },
+    verifier::{check_proof, required_compute, VerifyingKey},
 };
 
-#[cfg(test)]
-pub struct Groth16Verifier {
-    accepted_proofs: Vec<[u8; 32]>,
-}
-
-#[cfg(test)]
-impl Groth16Verifier {
-    pub fn new() -> Self {
-        Self {
-            accepted_proofs: vec![
-                [1u8; 32], // Test proof 1
-                [2u8; 32], // Test proof 2
-                [3u8; 32], // Test proof 3
-            ],
-        }
-    }
-
-    pub fn verify(&self, proof: &[u8]) -> bool {
-        let mut proof_hash = [0u8; 32];
-        proof_hash.copy_from_slice(&proof[..32]);
-        self.accepted_proofs.contains(&proof_hash)
-    }
-}
-
 #[cfg(test)]
 pub struct MerkleTreeVerifier {
     valid_roots: Vec<[u8; 32]>,
@@ -70,8 +58,6 @@ pub fn process_instruction(
     let instruction = WaveInstruction::try_from_slice(instruction_data)
         .map_err(|_| WaveError::InvalidInstruction)?;
 
-    #[cfg(test)]
-    let proof_verifier = Groth16Verifier::new();
     #[cfg(test)]
     let merkle_verifier = MerkleTreeVerifier::new();
 
@@ -81,26 +67,84 @@ pub fn process_instruction(
             merkle_root,
             circuit_hash,
             callback_program_id,
+            nullifier_namespace,
+            max_verification_cu,
+            attestation_policy,
+            max_nullifiers,
+            nullifier_commitment,
+            freshness_window_slots,
+            proof_system,
+            verifying_key,
         } => {
-            msg!("Instruction: InitRegistry");
+            log_debug!("Instruction: InitRegistry");
             let accounts_iter = &mut accounts.iter();
             
             let authority = next_account_info(accounts_iter)?;
             let flow_registry = next_account_info(accounts_iter)?;
             let system_program = next_account_info(accounts_iter)?;
+            let program_config = next_account_info(accounts_iter)?;
+            let treasury = next_account_info(accounts_iter)?;
+            let verifying_key_account = next_account_info(accounts_iter)?;
+            let approved_circuits = next_account_info(accounts_iter)?;
 
             if !authority.is_signer {
                 return Err(WaveError::Unauthorized.into());
             }
 
+            let (expected_registry, registry_bump) = FlowRegistry::find_pda(program_id, flow_id);
+            if flow_registry.key != &expected_registry {
+                return Err(WaveError::InvalidPda.into());
+            }
+            if !flow_registry.data_is_empty() {
+                return Err(WaveError::FlowAlreadyRegistered.into());
+            }
+
             if system_program.key != &system_program::id() {
                 return Err(ProgramError::InvalidAccountData);
             }
 
+            // These are required even with no fee or allowlist set, so
+            // leaving them out can't dodge one
+            let (expected_config, _) = ProgramConfig::find_pda(program_id);
+            let (expected_treasury, _) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+            let (expected_allowlist, _) = ApprovedCircuits::find_pda(program_id);
+            if program_config.key != &expected_config
+                || treasury.key != &expected_treasury
+                || approved_circuits.key != &expected_allowlist
+            {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+            if (!program_config.data_is_empty() && program_config.owner != program_id)
+                || (!approved_circuits.data_is_empty() && approved_circuits.owner != program_id)
+            {
+                return Err(ProgramError::IllegalOwner);
+            }
+
             // Validate circuit hash
             if circuit_hash == [0u8; 32] {
                 return Err(WaveError::InvalidCircuitHash.into());
             }
+            ApprovedCircuits::load_or_default(approved_circuits)?.check(&circuit_hash)?;
+
+            // The key must be the circuit the flow names
+            let key = VerifyingKey::from_bytes(&verifying_key)?;
+            if key.hash()? != circuit_hash {
+                return Err(WaveError::InvalidVerifyingKey.into());
+            }
+            if verifying_key_account.key != &VerifyingKey::find_pda(program_id, &circuit_hash).0 {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+
+            // A callback into this program could re-enter it mid-update
+            if callback_program_id == Some(program_id.to_bytes()) {
+                return Err(WaveError::InvalidCallbackProgram.into());
+            }
+
+            if let Some(namespace) = nullifier_namespace {
+                let namespace_account = next_account_info(accounts_iter)?;
+                let namespace_authority = next_account_info(accounts_iter)?;
+                check_namespace_admission(program_id, namespace, namespace_account, namespace_authority)?;
+            }
 
             // Validate Merkle root if provided
             #[cfg(test)]
@@ -110,13 +154,49 @@ pub fn process_instruction(
                 }
             }
 
-            let registry = FlowRegistry::new(
+            let registration_fee = ProgramConfig::load_or_default(program_config)?.registration_fee;
+            if registration_fee > 0 {
+                if authority.lamports() < registration_fee {
+                    return Err(WaveError::InsufficientFunds.into());
+                }
+                invoke(
+                    &system_instruction::transfer(authority.key, treasury.key, registration_fee),
+                    &[authority.clone(), treasury.clone(), system_program.clone()],
+                )?;
+            }
+
+            store_verifying_key(
+                program_id,
+                authority,
+                verifying_key_account,
+                system_program,
+                &circuit_hash,
+                &verifying_key,
+            )?;
+
+            create_pda_account(
+                program_id,
+                authority,
+                flow_registry,
+                system_program,
+                FlowRegistry::account_size(),
+                &[REGISTRY_SEED, &flow_id.to_le_bytes(), &[registry_bump]],
+            )?;
+
+            let mut registry = FlowRegistry::new(
                 *authority.key,
                 flow_id,
                 merkle_root,
                 circuit_hash,
                 callback_program_id.map(|id| Pubkey::new_from_array(id)),
             );
+            registry.nullifier_namespace = nullifier_namespace;
+            registry.max_verification_cu = max_verification_cu;
+            registry.attestation_policy = attestation_policy;
+            registry.max_nullifiers = max_nullifiers;
+            registry.nullifier_commitment = nullifier_commitment;
+            registry.freshness_window_slots = freshness_window_slots;
+            registry.proof_system = proof_system;
 
             registry.save(flow_registry)?;
             WaveEvent::FlowRegistered { flow_id, merkle_root, circuit_hash }.emit();
@@ -127,8 +207,10 @@ pub fn process_instruction(
             proof,
             public_inputs,
             nullifier,
+            mint_receipt,
+            nullifier_ttl_seconds,
         } => {
-            msg!("Instruction: ValidateProof");
+            log_debug!("Instruction: ValidateProof");
             let accounts_iter = &mut accounts.iter();
             
             let payer = next_account_info(accounts_iter)?;
@@ -136,51 +218,211 @@ pub fn process_instruction(
             let nullifier_account = next_account_info(accounts_iter)?;
             let proof_log = next_account_info(accounts_iter)?;
             let system_program = next_account_info(accounts_iter)?;
+            let verifying_key = next_account_info(accounts_iter)?;
 
             if !payer.is_signer {
                 return Err(WaveError::Unauthorized.into());
             }
 
+            if system_program.key != &system_program::id() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            // Anyone can create an account that deserializes as a registry;
+            // only ones this program owns are trustworthy. The nullifier and
+            // proof log PDAs are created below if this is their first use
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut registry = FlowRegistry::load(flow_registry)?;
+            registry.check_accepts_proofs()?;
+
+            // A zeroed account reads as unrecorded, so the all-zero
+            // nullifier would never be seen as spent
+            if nullifier == [0u8; 32] {
+                return Err(WaveError::InvalidNullifier.into());
+            }
+
+            // The proof log records the first 32 bytes of the inputs
+            let public_inputs_hash: [u8; 32] = public_inputs
+                .get(..32)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or(WaveError::InvalidPublicInputs)?;
+
+            // Fail cleanly up front rather than exhausting compute mid-pairing
+            if sol_remaining_compute_units() < required_compute(&registry, public_inputs.len()) {
+                return Err(WaveError::InsufficientCompute.into());
+            }
+
+            if registry.at_capacity() {
+                return Err(WaveError::FlowCapacityReached.into());
+            }
+
+            // A proof hoarded past the window is refused before verifying it
+            if registry.freshness_window_slots.is_some() {
+                registry.check_freshness(&public_inputs, Clock::get()?.slot)?;
+            }
+
             // Verify proof
-            #[cfg(test)]
-            if !proof_verifier.verify(&proof) {
-                WaveEvent::ProofRejected {
-                    flow_id: 0,
-                    reason: "Invalid proof".to_string(),
-                }.emit();
-                return Err(WaveError::InvalidProof.into());
+            if let Err(err) = check_proof(program_id, &registry, verifying_key, &proof, &public_inputs) {
+                if err == WaveError::InvalidProof.into() {
+                    WaveEvent::ProofRejected {
+                        flow_id: registry.flow_id,
+                        reason: "Invalid proof".to_string(),
+                    }.emit();
+                }
+                return Err(err);
             }
 
-            // Record nullifier
+            // Nullifier PDAs are seeded by the flow's nullifier scope, so flows
+            // sharing a namespace resolve to the same account and share dedup.
+            // Any other account, even one this program owns, would give the
+            // nullifier a second record
+            let nullifier_key = registry.nullifier_key(&nullifier);
+            let (expected_nullifier, nullifier_bump) = registry.find_nullifier_pda(program_id, &nullifier_key);
+            if nullifier_account.key != &expected_nullifier {
+                return Err(WaveError::InvalidNullifier.into());
+            }
+            ensure_nullifier_account(
+                program_id,
+                &registry,
+                payer,
+                nullifier_account,
+                system_program,
+                &nullifier_key,
+                nullifier_bump,
+            )?;
+            // A nullifier past its TTL is stale and is recorded over as if new
             let clock = Clock::get()?;
-            let nullifier_data = Nullifier::new(
-                nullifier,
-                clock.unix_timestamp,
-                0, // Flow ID
-            );
-            nullifier_data.save(nullifier_account)?;
+            if Nullifier::is_spent(nullifier_account, clock.unix_timestamp)? {
+                return Err(WaveError::NullifierAlreadyUsed.into());
+            }
+            let expires_at = match nullifier_ttl_seconds {
+                Some(ttl) if ttl > 0 => Some(
+                    clock.unix_timestamp.checked_add(ttl).ok_or(WaveError::InvalidInstruction)?,
+                ),
+                Some(_) => return Err(WaveError::InvalidInstruction.into()),
+                None => None,
+            };
+            // Lookups find the log by its flow and nullifier, so one written
+            // elsewhere would be lost to them
+            let (expected_proof_log, proof_log_bump) = ProofLog::find_pda(program_id, registry.flow_id, &nullifier);
+            if proof_log.key != &expected_proof_log {
+                return Err(WaveError::InvalidPda.into());
+            }
+            ensure_proof_log_account(
+                program_id,
+                payer,
+                proof_log,
+                system_program,
+                registry.flow_id,
+                &nullifier,
+                proof_log_bump,
+            )?;
+
+            let attestation_account = if mint_receipt {
+                Some(next_account_info(accounts_iter)?)
+            } else {
+                None
+            };
+
+            // Until the flow finalizes its migration, a nullifier may still be
+            // recorded only at its legacy PDA
+            if !registry.migration_complete {
+                let legacy_nullifier = next_account_info(accounts_iter)?;
+                let (expected_legacy, _) = Nullifier::find_legacy_pda(program_id, &nullifier);
+                if legacy_nullifier.key != &expected_legacy {
+                    return Err(WaveError::InvalidNullifier.into());
+                }
+                if legacy_nullifier.owner == program_id && Nullifier::is_recorded(legacy_nullifier)? {
+                    return Err(WaveError::NullifierAlreadyUsed.into());
+                }
+            }
 
             // Record proof
-            let mut public_inputs_hash = [0u8; 32];
-            public_inputs_hash.copy_from_slice(&public_inputs[..32]);
-            
-            let proof_log_data = ProofLog::new(
+            let proof_log_data = ProofLog::validated(
+                &registry,
                 nullifier,
                 clock.unix_timestamp,
-                0, // Flow ID
                 public_inputs_hash,
             );
             proof_log_data.save(proof_log)?;
 
+            if let Some(attestation_account) = attestation_account {
+                let recipient = Attestation::recipient_from_public_inputs(&public_inputs)?;
+
+                let (expected_attestation, bump) =
+                    Attestation::find_pda(program_id, registry.flow_id, &recipient);
+                if attestation_account.key != &expected_attestation {
+                    return Err(WaveError::InvalidAccountData.into());
+                }
+
+                if attestation_account.data_is_empty() {
+                    let rent = Rent::get()?;
+                    invoke_signed(
+                        &system_instruction::create_account(
+                            payer.key,
+                            attestation_account.key,
+                            rent.minimum_balance(Attestation::account_size()),
+                            Attestation::account_size() as u64,
+                            program_id,
+                        ),
+                        &[payer.clone(), attestation_account.clone(), system_program.clone()],
+                        &[&[
+                            ATTESTATION_SEED,
+                            &registry.flow_id.to_le_bytes(),
+                            recipient.as_ref(),
+                            &[bump],
+                        ]],
+                    )?;
+                } else {
+                    if attestation_account.owner != program_id {
+                        return Err(ProgramError::IllegalOwner);
+                    }
+                    let existing = Attestation::load(attestation_account)?;
+                    if existing.revoked_at.is_some()
+                        || registry.attestation_policy == AttestationPolicy::Reject
+                    {
+                        return Err(WaveError::AttestationExists.into());
+                    }
+                }
+
+                Attestation::new(
+                    registry.flow_id,
+                    recipient,
+                    nullifier,
+                    *proof_log.key,
+                    clock.unix_timestamp,
+                )
+                .save(attestation_account)?;
+
+                WaveEvent::AttestationRecorded {
+                    flow_id: registry.flow_id,
+                    recipient,
+                }.emit();
+            }
+
+            registry.nullifier_count += 1;
+            registry.save(flow_registry)?;
+
+            // Record nullifier last: it is the dedup commit, so nothing may
+            // fail after it has been written
+            let nullifier_data = Nullifier {
+                expires_at,
+                ..Nullifier::new(nullifier_key, clock.unix_timestamp, registry.flow_id, *proof_log.key)
+            };
+            nullifier_data.save(nullifier_account)?;
+
             WaveEvent::FlowExecuted {
-                flow_id: 0,
+                flow_id: registry.flow_id,
                 nullifier,
             }.emit();
             Ok(())
         }
 
-        WaveInstruction::SetRoot { new_root } => {
-            msg!("Instruction: SetRoot");
+        WaveInstruction::SetRoot { new_root, expected_sequence } => {
+            log_debug!("Instruction: SetRoot");
             let accounts_iter = &mut accounts.iter();
             
             let authority = next_account_info(accounts_iter)?;
@@ -196,13 +438,28 @@ pub fn process_instruction(
                 return Err(WaveError::InvalidMerkleRoot.into());
             }
 
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
             let mut registry = FlowRegistry::load(flow_registry)?;
+            if flow_registry.key != &FlowRegistry::find_pda(program_id, registry.flow_id).0 {
+                return Err(WaveError::InvalidPda.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+            if registry.root_sequence != expected_sequence {
+                return Err(WaveError::StaleRootSequence.into());
+            }
             registry.merkle_root = Some(new_root);
+            registry.root_sequence += 1;
             registry.save(flow_registry)?;
 
             WaveEvent::RootUpdated {
                 flow_id: registry.flow_id,
                 new_root,
+                sequence: registry.root_sequence,
             }.emit();
             Ok(())
         }
@@ -210,8 +467,9 @@ pub fn process_instruction(
         WaveInstruction::TriggerFlow {
             flow_id,
             instruction_data,
+            nullifier,
         } => {
-            msg!("Instruction: TriggerFlow");
+            log_debug!("Instruction: TriggerFlow");
             let accounts_iter = &mut accounts.iter();
             
             let payer = next_account_info(accounts_iter)?;
@@ -222,8 +480,62 @@ pub fn process_instruction(
                 return Err(WaveError::Unauthorized.into());
             }
 
+            if let Some(nullifier) = nullifier {
+                let nullifier_account = next_account_info(accounts_iter)?;
+                let system_program = next_account_info(accounts_iter)?;
+
+                if flow_registry.owner != program_id || nullifier_account.owner != program_id {
+                    return Err(ProgramError::IllegalOwner);
+                }
+                let registry = FlowRegistry::load(flow_registry)?;
+                if registry.flow_id != flow_id {
+                    return Err(WaveError::InvalidFlowId.into());
+                }
+
+                let nullifier_key = registry.nullifier_key(&nullifier);
+                let (expected_nullifier, _) = registry.find_nullifier_pda(program_id, &nullifier_key);
+                if nullifier_account.key != &expected_nullifier
+                    || !Nullifier::is_recorded(nullifier_account)?
+                {
+                    return Err(WaveError::InvalidNullifier.into());
+                }
+
+                let mut record = Nullifier::load(nullifier_account)?;
+                // In a shared namespace the nullifier may belong to another flow
+                if record.flow_id != flow_id {
+                    return Err(WaveError::InvalidFlowId.into());
+                }
+                if record.triggered_at.is_some() {
+                    return Err(WaveError::NullifierAlreadyUsed.into());
+                }
+                let now = Clock::get()?.unix_timestamp;
+                if record.is_expired(now) {
+                    return Err(WaveError::NullifierExpired.into());
+                }
+
+                if nullifier_account.data_len() < Nullifier::account_size() {
+                    if system_program.key != &system_program::id() {
+                        return Err(ProgramError::InvalidAccountData);
+                    }
+                    let shortfall = Rent::get()?
+                        .minimum_balance(Nullifier::account_size())
+                        .saturating_sub(nullifier_account.lamports());
+                    if shortfall > 0 {
+                        invoke(
+                            &system_instruction::transfer(payer.key, nullifier_account.key, shortfall),
+                            &[payer.clone(), nullifier_account.clone(), system_program.clone()],
+                        )?;
+                    }
+                    nullifier_account.realloc(Nullifier::account_size(), false)?;
+                    record.version = Nullifier::VERSION;
+                }
+
+                record.triggered_at = Some(now);
+                record.save(nullifier_account)?;
+            }
+
             // Execute CPI call
-            msg!("Would trigger program {} with data {:?}", target_program.key, instruction_data);
+            log_debug!("Would trigger program {} with data {:?}", target_program.key, instruction_data);
             
             WaveEvent::FlowTriggered {
                 flow_id,
@@ -231,5 +543,1411 @@ pub fn process_instruction(
             }.emit();
             Ok(())
         }
+
+        WaveInstruction::SetRootBatch { entries } => {
+            log_debug!("Instruction: SetRootBatch");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if entries.is_empty() || entries.len() > MAX_SET_ROOT_BATCH_ENTRIES {
+                return Err(WaveError::InvalidInstruction.into());
+            }
+
+            // Validate every entry before writing anything, so one bad entry
+            // leaves all registries untouched
+            let mut updates = Vec::with_capacity(entries.len());
+            for (flow_id, new_root, expected_sequence) in entries {
+                let flow_registry = next_account_info(accounts_iter)?;
+
+                #[cfg(test)]
+                if !merkle_verifier.verify(&new_root) {
+                    return Err(WaveError::InvalidMerkleRoot.into());
+                }
+
+                if flow_registry.owner != program_id {
+                    return Err(ProgramError::IllegalOwner);
+                }
+
+                let mut registry = FlowRegistry::load(flow_registry)?;
+                if registry.flow_id != flow_id {
+                    return Err(WaveError::InvalidFlowId.into());
+                }
+                if registry.authority != *authority.key {
+                    return Err(WaveError::Unauthorized.into());
+                }
+                if registry.root_sequence != expected_sequence {
+                    return Err(WaveError::StaleRootSequence.into());
+                }
+
+                registry.merkle_root = Some(new_root);
+                registry.root_sequence += 1;
+                updates.push((flow_registry, registry, new_root));
+            }
+
+            for (flow_registry, registry, new_root) in updates {
+                registry.save(flow_registry)?;
+                WaveEvent::RootUpdated {
+                    flow_id: registry.flow_id,
+                    new_root,
+                    sequence: registry.root_sequence,
+                }.emit();
+            }
+            Ok(())
+        }
+
+        WaveInstruction::CloneFlow {
+            source_flow_id,
+            new_flow_id,
+            overrides,
+        } => {
+            log_debug!("Instruction: CloneFlow");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let source_registry = next_account_info(accounts_iter)?;
+            let new_registry = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            let program_config = next_account_info(accounts_iter)?;
+            let treasury = next_account_info(accounts_iter)?;
+            let approved_circuits = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if system_program.key != &system_program::id() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            // Cloning registers a flow too, so it can't skip the fee or
+            // the allowlist InitRegistry applies
+            let (expected_config, _) = ProgramConfig::find_pda(program_id);
+            let (expected_treasury, _) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+            let (expected_allowlist, _) = ApprovedCircuits::find_pda(program_id);
+            if program_config.key != &expected_config
+                || treasury.key != &expected_treasury
+                || approved_circuits.key != &expected_allowlist
+            {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+            if (!program_config.data_is_empty() && program_config.owner != program_id)
+                || (!approved_circuits.data_is_empty() && approved_circuits.owner != program_id)
+            {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            if source_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let source = FlowRegistry::load(source_registry)?;
+            if source.flow_id != source_flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if source.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+            ApprovedCircuits::load_or_default(approved_circuits)?.check(&source.circuit_hash)?;
+
+            let new_flow_seed = new_flow_id.to_le_bytes();
+            let (expected_registry, bump) =
+                Pubkey::find_program_address(&[REGISTRY_SEED, &new_flow_seed], program_id);
+            if new_registry.key != &expected_registry {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if !new_registry.data_is_empty() {
+                return Err(WaveError::FlowAlreadyRegistered.into());
+            }
+
+            let registration_fee = ProgramConfig::load_or_default(program_config)?.registration_fee;
+            if registration_fee > 0 {
+                if authority.lamports() < registration_fee {
+                    return Err(WaveError::InsufficientFunds.into());
+                }
+                invoke(
+                    &system_instruction::transfer(authority.key, treasury.key, registration_fee),
+                    &[authority.clone(), treasury.clone(), system_program.clone()],
+                )?;
+            }
+
+            create_pda_account(
+                program_id,
+                authority,
+                new_registry,
+                system_program,
+                FlowRegistry::account_size(),
+                &[REGISTRY_SEED, &new_flow_id.to_le_bytes(), &[bump]],
+            )?;
+
+            let mut registry = source.clone_as(new_flow_id);
+            registry.authority = *authority.key;
+            overrides.apply(&mut registry);
+            if registry.callback_program_id == Some(*program_id) {
+                return Err(WaveError::InvalidCallbackProgram.into());
+            }
+            // A clone is a new member of the namespace, cloned or overridden
+            if let Some(namespace) = registry.nullifier_namespace {
+                let namespace_account = next_account_info(accounts_iter)?;
+                let namespace_authority = next_account_info(accounts_iter)?;
+                check_namespace_admission(program_id, namespace, namespace_account, namespace_authority)?;
+            }
+            registry.save(new_registry)?;
+
+            WaveEvent::FlowCloned {
+                source_flow_id,
+                new_flow_id,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::AttachTreeState {
+            flow_id,
+            frontier,
+            leaf_count,
+            root,
+        } => {
+            log_debug!("Instruction: AttachTreeState");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let registry_account = next_account_info(accounts_iter)?;
+            let tree_account = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if system_program.key != &system_program::id() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            if registry_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut registry = FlowRegistry::load(registry_account)?;
+            if registry.flow_id != flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            let (expected_tree, bump) = IncrementalMerkleTree::find_pda(program_id, flow_id);
+            if tree_account.key != &expected_tree {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if tree_account.lamports() > 0 || !tree_account.data_is_empty() {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+
+            let tree = IncrementalMerkleTree::from_frontier(*frontier, leaf_count, root)?;
+
+            let rent = Rent::get()?;
+            invoke_signed(
+                &system_instruction::create_account(
+                    authority.key,
+                    tree_account.key,
+                    rent.minimum_balance(IncrementalMerkleTree::account_size()),
+                    IncrementalMerkleTree::account_size() as u64,
+                    program_id,
+                ),
+                &[authority.clone(), tree_account.clone(), system_program.clone()],
+                &[&[FLOW_TREE_SEED, &flow_id.to_le_bytes(), &[bump]]],
+            )?;
+            tree.save(tree_account)?;
+
+            registry.merkle_root = Some(root);
+            registry.root_sequence += 1;
+            registry.save(registry_account)?;
+
+            WaveEvent::RootUpdated {
+                flow_id,
+                new_root: root,
+                sequence: registry.root_sequence,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::AppendLeaf { flow_id, leaf } => {
+            log_debug!("Instruction: AppendLeaf");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let registry_account = next_account_info(accounts_iter)?;
+            let tree_account = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if registry_account.owner != program_id || tree_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut registry = FlowRegistry::load(registry_account)?;
+            if registry.flow_id != flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            let (expected_tree, _) = IncrementalMerkleTree::find_pda(program_id, flow_id);
+            if tree_account.key != &expected_tree {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+
+            let mut tree = IncrementalMerkleTree::load(tree_account)?;
+            tree.append(leaf)?;
+            tree.save(tree_account)?;
+
+            registry.merkle_root = Some(tree.root);
+            registry.root_sequence += 1;
+            registry.save(registry_account)?;
+
+            WaveEvent::RootUpdated {
+                flow_id,
+                new_root: tree.root,
+                sequence: registry.root_sequence,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::ImportNullifiers { nullifiers } => {
+            log_debug!("Instruction: ImportNullifiers");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let flow_registry = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if system_program.key != &system_program::id() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            if nullifiers.is_empty() || nullifiers.len() > MAX_IMPORT_NULLIFIERS {
+                return Err(WaveError::InvalidInstruction.into());
+            }
+
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let registry = FlowRegistry::load(flow_registry)?;
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            let clock = Clock::get()?;
+            let mut imported = 0u32;
+            for nullifier in nullifiers {
+                let nullifier_account = next_account_info(accounts_iter)?;
+
+                // Recorded raw, it would read back as unrecorded
+                if nullifier == [0u8; 32] {
+                    return Err(WaveError::InvalidNullifier.into());
+                }
+
+                let nullifier_key = registry.nullifier_key(&nullifier);
+                let (expected_nullifier, bump) = registry.find_nullifier_pda(program_id, &nullifier_key);
+                if nullifier_account.key != &expected_nullifier {
+                    return Err(WaveError::InvalidNullifier.into());
+                }
+                ensure_nullifier_account(
+                    program_id,
+                    &registry,
+                    authority,
+                    nullifier_account,
+                    system_program,
+                    &nullifier_key,
+                    bump,
+                )?;
+
+                // A repeat within the batch resolves to the same account, so
+                // it is seen as recorded here too
+                if Nullifier::is_recorded(nullifier_account)? {
+                    continue;
+                }
+
+                Nullifier::new(nullifier_key, clock.unix_timestamp, registry.flow_id, Pubkey::default())
+                    .save(nullifier_account)?;
+                imported += 1;
+            }
+
+            WaveEvent::NullifiersImported {
+                flow_id: registry.flow_id,
+                imported,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::RevokeAttestation { flow_id, recipient } => {
+            log_debug!("Instruction: RevokeAttestation");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let registry_account = next_account_info(accounts_iter)?;
+            let attestation_account = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if registry_account.owner != program_id || attestation_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let registry = FlowRegistry::load(registry_account)?;
+            if registry.flow_id != flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            let (expected_attestation, _) = Attestation::find_pda(program_id, flow_id, &recipient);
+            if attestation_account.key != &expected_attestation {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+
+            let mut attestation = Attestation::load(attestation_account)?;
+            if attestation.revoked_at.is_none() {
+                attestation.revoked_at = Some(Clock::get()?.unix_timestamp);
+                attestation.save(attestation_account)?;
+            }
+
+            WaveEvent::AttestationRevoked { flow_id, recipient }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::UpdateRootAndCircuit {
+            new_root,
+            new_circuit_hash,
+            expected_sequence,
+            new_verifying_key,
+        } => {
+            log_debug!("Instruction: UpdateRootAndCircuit");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let flow_registry = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            let verifying_key_account = next_account_info(accounts_iter)?;
+            let approved_circuits = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if system_program.key != &system_program::id() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            if new_circuit_hash == [0u8; 32] {
+                return Err(WaveError::InvalidCircuitHash.into());
+            }
+
+            // Rotating is held to the allowlist registering is, required
+            // even when it isn't enforced so leaving it out can't dodge it
+            if approved_circuits.key != &ApprovedCircuits::find_pda(program_id).0 {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+            if !approved_circuits.data_is_empty() && approved_circuits.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+            ApprovedCircuits::load_or_default(approved_circuits)?.check(&new_circuit_hash)?;
+
+            // Without the new circuit's key stored, no proof could verify
+            if VerifyingKey::from_bytes(&new_verifying_key)?.hash()? != new_circuit_hash {
+                return Err(WaveError::InvalidVerifyingKey.into());
+            }
+
+            #[cfg(test)]
+            if !merkle_verifier.verify(&new_root) {
+                return Err(WaveError::InvalidMerkleRoot.into());
+            }
+
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut registry = FlowRegistry::load(flow_registry)?;
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+            if registry.circuit_frozen {
+                return Err(WaveError::CircuitFrozen.into());
+            }
+            if registry.root_sequence != expected_sequence {
+                return Err(WaveError::StaleRootSequence.into());
+            }
+
+            store_verifying_key(
+                program_id,
+                authority,
+                verifying_key_account,
+                system_program,
+                &new_circuit_hash,
+                &new_verifying_key,
+            )?;
+
+            registry.merkle_root = Some(new_root);
+            registry.circuit_hash = new_circuit_hash;
+            registry.root_sequence += 1;
+            registry.save(flow_registry)?;
+
+            WaveEvent::RootAndCircuitUpdated {
+                flow_id: registry.flow_id,
+                new_root,
+                new_circuit_hash,
+                sequence: registry.root_sequence,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::FreezeCircuit { flow_id } => {
+            log_debug!("Instruction: FreezeCircuit");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let flow_registry = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut registry = FlowRegistry::load(flow_registry)?;
+            if registry.flow_id != flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+            registry.circuit_frozen = true;
+            registry.save(flow_registry)?;
+
+            WaveEvent::CircuitFrozen {
+                flow_id,
+                circuit_hash: registry.circuit_hash,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::MigrateNullifier { nullifier } => {
+            log_debug!("Instruction: MigrateNullifier");
+            let accounts_iter = &mut accounts.iter();
+
+            let payer = next_account_info(accounts_iter)?;
+            let flow_registry = next_account_info(accounts_iter)?;
+            let legacy_nullifier = next_account_info(accounts_iter)?;
+            let nullifier_account = next_account_info(accounts_iter)?;
+            let treasury = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            if !payer.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if flow_registry.owner != program_id || legacy_nullifier.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let registry = FlowRegistry::load(flow_registry)?;
+            if registry.migration_complete {
+                return Err(WaveError::MigrationComplete.into());
+            }
+
+            let (expected_legacy, _) = Nullifier::find_legacy_pda(program_id, &nullifier);
+            if legacy_nullifier.key != &expected_legacy || !Nullifier::is_recorded(legacy_nullifier)? {
+                return Err(WaveError::InvalidNullifier.into());
+            }
+            let mut record = Nullifier::load(legacy_nullifier)?;
+            if record.flow_id != registry.flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+
+            // Legacy records hold the raw nullifier; scoped ones are keyed as
+            // ValidateProof looks them up, by commitment in commitment mode
+            let nullifier_key = registry.nullifier_key(&nullifier);
+            record.hash = nullifier_key;
+            let scope = registry.nullifier_scope();
+            let (expected_nullifier, bump) = registry.find_nullifier_pda(program_id, &nullifier_key);
+            if nullifier_account.key != &expected_nullifier {
+                return Err(WaveError::InvalidNullifier.into());
+            }
+            let (expected_treasury, _) = Pubkey::find_program_address(&[TREASURY_SEED], program_id);
+            if treasury.key != &expected_treasury {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+
+            if nullifier_account.data_is_empty() {
+                if system_program.key != &system_program::id() {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                invoke_signed(
+                    &system_instruction::create_account(
+                        payer.key,
+                        nullifier_account.key,
+                        Rent::get()?.minimum_balance(Nullifier::account_size()),
+                        Nullifier::account_size() as u64,
+                        program_id,
+                    ),
+                    &[payer.clone(), nullifier_account.clone(), system_program.clone()],
+                    &[&[registry.nullifier_seed(), &scope.to_le_bytes(), &nullifier_key, &[bump]]],
+                )?;
+            } else if nullifier_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            // Either way the nullifier stays spent under the scoped seeds
+            if !Nullifier::is_recorded(nullifier_account)? {
+                record.save(nullifier_account)?;
+            }
+
+            // Close the legacy account into the treasury
+            let rent = legacy_nullifier.lamports();
+            **treasury.try_borrow_mut_lamports()? += rent;
+            **legacy_nullifier.try_borrow_mut_lamports()? = 0;
+            legacy_nullifier.realloc(0, false)?;
+            legacy_nullifier.assign(&system_program::id());
+
+            WaveEvent::NullifierMigrated {
+                flow_id: registry.flow_id,
+                nullifier,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::FinalizeNullifierMigration { flow_id } => {
+            log_debug!("Instruction: FinalizeNullifierMigration");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let flow_registry = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut registry = FlowRegistry::load(flow_registry)?;
+            if registry.flow_id != flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+            registry.migration_complete = true;
+            registry.save(flow_registry)?;
+
+            WaveEvent::NullifierMigrationFinalized { flow_id }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::SetNotificationEntry {
+            flow_id,
+            label,
+            uri_hash,
+            enabled,
+        } => {
+            log_debug!("Instruction: SetNotificationEntry");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let registry_account = next_account_info(accounts_iter)?;
+            let manifest_account = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if registry_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let registry = FlowRegistry::load(registry_account)?;
+            if registry.flow_id != flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            let (expected_manifest, bump) = NotificationManifest::find_pda(program_id, flow_id);
+            if manifest_account.key != &expected_manifest {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+
+            let mut manifest = if manifest_account.data_is_empty() {
+                if system_program.key != &system_program::id() {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                invoke_signed(
+                    &system_instruction::create_account(
+                        authority.key,
+                        manifest_account.key,
+                        Rent::get()?.minimum_balance(NotificationManifest::account_size()),
+                        NotificationManifest::account_size() as u64,
+                        program_id,
+                    ),
+                    &[authority.clone(), manifest_account.clone(), system_program.clone()],
+                    &[&[NOTIFICATION_MANIFEST_SEED, &flow_id.to_le_bytes(), &[bump]]],
+                )?;
+                NotificationManifest::new(registry.flow_id)
+            } else {
+                if manifest_account.owner != program_id {
+                    return Err(ProgramError::IllegalOwner);
+                }
+                NotificationManifest::load(manifest_account)?
+            };
+
+            manifest.set_entry(label.clone(), uri_hash, enabled)?;
+            manifest.save(manifest_account)?;
+
+            WaveEvent::NotificationEntrySet { flow_id, label, enabled }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::ClearNotificationEntry { flow_id, label } => {
+            log_debug!("Instruction: ClearNotificationEntry");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let registry_account = next_account_info(accounts_iter)?;
+            let manifest_account = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if registry_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let registry = FlowRegistry::load(registry_account)?;
+            if registry.flow_id != flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            let (expected_manifest, _) = NotificationManifest::find_pda(program_id, flow_id);
+            if manifest_account.key != &expected_manifest {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+            if manifest_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut manifest = NotificationManifest::load(manifest_account)?;
+            manifest.clear_entry(&label)?;
+            manifest.save(manifest_account)?;
+
+            WaveEvent::NotificationEntryCleared { flow_id, label }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::SetProgramConfig { registration_fee } => {
+            log_debug!("Instruction: SetProgramConfig");
+            let accounts_iter = &mut accounts.iter();
+
+            let admin = next_account_info(accounts_iter)?;
+            let config_account = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            if !admin.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            let (expected_config, bump) = ProgramConfig::find_pda(program_id);
+            if config_account.key != &expected_config {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+
+            let mut config = if config_account.data_is_empty() {
+                if system_program.key != &system_program::id() {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                invoke_signed(
+                    &system_instruction::create_account(
+                        admin.key,
+                        config_account.key,
+                        Rent::get()?.minimum_balance(ProgramConfig::SIZE),
+                        ProgramConfig::SIZE as u64,
+                        program_id,
+                    ),
+                    &[admin.clone(), config_account.clone(), system_program.clone()],
+                    &[&[PROGRAM_CONFIG_SEED, &[bump]]],
+                )?;
+                ProgramConfig::new(*admin.key)
+            } else {
+                if config_account.owner != program_id {
+                    return Err(ProgramError::IllegalOwner);
+                }
+                let config = ProgramConfig::load(config_account)?;
+                if config.admin != *admin.key {
+                    return Err(WaveError::Unauthorized.into());
+                }
+                config
+            };
+
+            config.registration_fee = registration_fee;
+            config.save(config_account)?;
+
+            WaveEvent::ProgramConfigSet {
+                admin: config.admin,
+                registration_fee,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::WasProofValidated { public_inputs_hash } => {
+            log_debug!("Instruction: WasProofValidated");
+
+            // No index by public inputs exists, so scan what the caller found
+            let mut validated = false;
+            for candidate in accounts {
+                if candidate.owner != program_id {
+                    continue;
+                }
+                let data = candidate.try_borrow_data()?;
+                if let Ok(log) = ProofLogRef::parse(&data) {
+                    if log.is_recorded() && log.public_inputs_hash() == &public_inputs_hash {
+                        validated = true;
+                        break;
+                    }
+                }
+            }
+
+            set_return_data(&[validated as u8]);
+            Ok(())
+        }
+
+        WaveInstruction::CompactProofLogs { nullifiers } => {
+            log_debug!("Instruction: CompactProofLogs");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let flow_registry = next_account_info(accounts_iter)?;
+            let commitment_account = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+            let registry = FlowRegistry::load(flow_registry)?;
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+            if nullifiers.is_empty() {
+                return Err(WaveError::InvalidInstruction.into());
+            }
+
+            // Each log must be this flow's, at its nullifier's PDA, and
+            // listed once, or the commitment would vouch for a log twice
+            let mut proof_logs = Vec::with_capacity(nullifiers.len());
+            let mut logs = Vec::with_capacity(nullifiers.len());
+            for (i, nullifier) in nullifiers.iter().enumerate() {
+                let proof_log = next_account_info(accounts_iter)?;
+                let (expected_log, _) = ProofLog::find_pda(program_id, registry.flow_id, nullifier);
+                if proof_log.key != &expected_log || nullifiers[..i].contains(nullifier) {
+                    return Err(WaveError::InvalidAccountData.into());
+                }
+                if proof_log.owner != program_id {
+                    return Err(ProgramError::IllegalOwner);
+                }
+                let log = ProofLog::load(proof_log)?;
+                if &log.nullifier != nullifier || log.flow_id != registry.flow_id {
+                    return Err(WaveError::InvalidNullifier.into());
+                }
+                proof_logs.push(proof_log);
+                logs.push(log);
+            }
+
+            let commitment = ProofLogCommitment::new(registry.flow_id, &logs, Clock::get()?.unix_timestamp)?;
+            let (expected_commitment, bump) =
+                ProofLogCommitment::find_pda(program_id, registry.flow_id, &commitment.root);
+            if commitment_account.key != &expected_commitment {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+            if !commitment_account.data_is_empty() {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            if system_program.key != &system_program::id() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            invoke_signed(
+                &system_instruction::create_account(
+                    authority.key,
+                    commitment_account.key,
+                    Rent::get()?.minimum_balance(ProofLogCommitment::account_size()),
+                    ProofLogCommitment::account_size() as u64,
+                    program_id,
+                ),
+                &[authority.clone(), commitment_account.clone(), system_program.clone()],
+                &[&[
+                    PROOF_LOG_COMMITMENT_SEED,
+                    &registry.flow_id.to_le_bytes(),
+                    &commitment.root,
+                    &[bump],
+                ]],
+            )?;
+            commitment.save(commitment_account)?;
+
+            // Close the logs into the authority. Their nullifiers stay
+            // recorded, so the proofs still can't be replayed
+            for proof_log in proof_logs {
+                let rent = proof_log.lamports();
+                **authority.try_borrow_mut_lamports()? += rent;
+                **proof_log.try_borrow_mut_lamports()? = 0;
+                proof_log.realloc(0, false)?;
+                proof_log.assign(&system_program::id());
+            }
+
+            WaveEvent::ProofLogsCompacted {
+                flow_id: registry.flow_id,
+                count: logs.len() as u32,
+                root: commitment.root,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::VerifyProofLogInclusion {
+            nullifier,
+            public_inputs_hash,
+            timestamp,
+            index,
+            proof,
+        } => {
+            log_debug!("Instruction: VerifyProofLogInclusion");
+            let accounts_iter = &mut accounts.iter();
+
+            let commitment_account = next_account_info(accounts_iter)?;
+            if commitment_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            // Only CompactProofLogs creates accounts at these addresses
+            let commitment = ProofLogCommitment::load(commitment_account)?;
+            let (expected_commitment, _) =
+                ProofLogCommitment::find_pda(program_id, commitment.flow_id, &commitment.root);
+            if commitment_account.key != &expected_commitment {
+                return Err(WaveError::InvalidAccountData.into());
+            }
+
+            let log = ProofLog::new(nullifier, timestamp, commitment.flow_id, public_inputs_hash);
+            if !commitment.includes(&log, index, &proof) {
+                return Err(WaveError::ProofLogNotCommitted.into());
+            }
+            Ok(())
+        }
+
+        WaveInstruction::ValidateBatchProofs { proofs } => {
+            log_debug!("Instruction: ValidateBatchProofs");
+            let accounts_iter = &mut accounts.iter();
+
+            let payer = next_account_info(accounts_iter)?;
+            let flow_registry = next_account_info(accounts_iter)?;
+            let verifying_key = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            if !payer.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if system_program.key != &system_program::id() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            if proofs.is_empty() || proofs.len() > MAX_VALIDATE_BATCH_PROOFS {
+                return Err(WaveError::InvalidInstruction.into());
+            }
+
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut registry = FlowRegistry::load(flow_registry)?;
+            registry.check_accepts_proofs()?;
+
+            // The budget covers every pairing in the batch
+            let required_cu: u64 = proofs
+                .iter()
+                .map(|entry| required_compute(&registry, entry.public_inputs.len()))
+                .sum();
+            if sol_remaining_compute_units() < required_cu {
+                return Err(WaveError::InsufficientCompute.into());
+            }
+
+            let clock = Clock::get()?;
+
+            // Check every proof before recording anything, so one bad proof
+            // leaves no nullifier or log behind. PDAs created on the way are
+            // rolled back with the transaction
+            let mut records = Vec::with_capacity(proofs.len());
+            for entry in &proofs {
+                let nullifier_account = next_account_info(accounts_iter)?;
+                let proof_log = next_account_info(accounts_iter)?;
+
+                if registry.at_capacity() {
+                    return Err(WaveError::FlowCapacityReached.into());
+                }
+
+                if registry.freshness_window_slots.is_some() {
+                    registry.check_freshness(&entry.public_inputs, clock.slot)?;
+                }
+
+                if let Err(err) = check_proof(program_id, &registry, verifying_key, &entry.proof, &entry.public_inputs) {
+                    if err == WaveError::InvalidProof.into() {
+                        WaveEvent::ProofRejected {
+                            flow_id: registry.flow_id,
+                            reason: "Invalid proof".to_string(),
+                        }.emit();
+                    }
+                    return Err(err);
+                }
+
+                if entry.nullifier == [0u8; 32] {
+                    return Err(WaveError::InvalidNullifier.into());
+                }
+                let nullifier_key = registry.nullifier_key(&entry.nullifier);
+                let (expected_nullifier, nullifier_bump) = registry.find_nullifier_pda(program_id, &nullifier_key);
+                if nullifier_account.key != &expected_nullifier {
+                    return Err(WaveError::InvalidNullifier.into());
+                }
+                ensure_nullifier_account(
+                    program_id,
+                    &registry,
+                    payer,
+                    nullifier_account,
+                    system_program,
+                    &nullifier_key,
+                    nullifier_bump,
+                )?;
+                // A nullifier listed twice would pass both checks against
+                // the still-empty PDA
+                if Nullifier::is_spent(nullifier_account, clock.unix_timestamp)?
+                    || records.iter().any(|(_, _, key, _)| key == &nullifier_key)
+                {
+                    return Err(WaveError::NullifierAlreadyUsed.into());
+                }
+                let (expected_proof_log, proof_log_bump) = ProofLog::find_pda(program_id, registry.flow_id, &entry.nullifier);
+                if proof_log.key != &expected_proof_log {
+                    return Err(WaveError::InvalidPda.into());
+                }
+                ensure_proof_log_account(
+                    program_id,
+                    payer,
+                    proof_log,
+                    system_program,
+                    registry.flow_id,
+                    &entry.nullifier,
+                    proof_log_bump,
+                )?;
+
+                if !registry.migration_complete {
+                    let legacy_nullifier = next_account_info(accounts_iter)?;
+                    let (expected_legacy, _) = Nullifier::find_legacy_pda(program_id, &entry.nullifier);
+                    if legacy_nullifier.key != &expected_legacy {
+                        return Err(WaveError::InvalidNullifier.into());
+                    }
+                    if legacy_nullifier.owner == program_id && Nullifier::is_recorded(legacy_nullifier)? {
+                        return Err(WaveError::NullifierAlreadyUsed.into());
+                    }
+                }
+
+                let public_inputs_hash: [u8; 32] = entry
+                    .public_inputs
+                    .get(..32)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or(WaveError::InvalidPublicInputs)?;
+
+                registry.nullifier_count += 1;
+                records.push((
+                    nullifier_account,
+                    proof_log,
+                    nullifier_key,
+                    ProofLog::validated(&registry, entry.nullifier, clock.unix_timestamp, public_inputs_hash),
+                ));
+            }
+
+            for (_, proof_log, _, log) in &records {
+                log.save(proof_log)?;
+            }
+            registry.save(flow_registry)?;
+
+            // Record nullifiers last: they are the dedup commit
+            for (nullifier_account, proof_log, nullifier_key, _) in &records {
+                Nullifier::new(*nullifier_key, clock.unix_timestamp, registry.flow_id, *proof_log.key)
+                    .save(nullifier_account)?;
+            }
+
+            let nullifiers: Vec<[u8; 32]> = proofs.iter().map(|entry| entry.nullifier).collect();
+            WaveEvent::BatchValidated {
+                flow_id: registry.flow_id,
+                count: proofs.len() as u32,
+                nullifiers_root: nullifiers_root(&nullifiers)?,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::SetCircuitApproval { circuit_hash, approved } => {
+            log_debug!("Instruction: SetCircuitApproval");
+            let accounts_iter = &mut accounts.iter();
+
+            let admin = next_account_info(accounts_iter)?;
+            let config_account = next_account_info(accounts_iter)?;
+            let allowlist_account = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            let mut allowlist = load_or_create_approved_circuits(
+                program_id,
+                admin,
+                config_account,
+                allowlist_account,
+                system_program,
+            )?;
+
+            if approved {
+                allowlist.approve(circuit_hash)?;
+            } else {
+                allowlist.revoke(&circuit_hash);
+            }
+            allowlist.save(allowlist_account)?;
+
+            WaveEvent::CircuitApprovalSet { circuit_hash, approved }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::SetCircuitAllowlistEnforced { enforced } => {
+            log_debug!("Instruction: SetCircuitAllowlistEnforced");
+            let accounts_iter = &mut accounts.iter();
+
+            let admin = next_account_info(accounts_iter)?;
+            let config_account = next_account_info(accounts_iter)?;
+            let allowlist_account = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            let mut allowlist = load_or_create_approved_circuits(
+                program_id,
+                admin,
+                config_account,
+                allowlist_account,
+                system_program,
+            )?;
+
+            allowlist.enforced = enforced;
+            allowlist.save(allowlist_account)?;
+
+            WaveEvent::CircuitAllowlistEnforced { enforced }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::CreateNullifierNamespace { namespace } => {
+            log_debug!("Instruction: CreateNullifierNamespace");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let namespace_account = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if system_program.key != &system_program::id() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            let (expected_namespace, bump) = NullifierNamespace::find_pda(program_id, namespace);
+            if namespace_account.key != &expected_namespace {
+                return Err(WaveError::InvalidPda.into());
+            }
+            if !namespace_account.data_is_empty() {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+
+            invoke_signed(
+                &system_instruction::create_account(
+                    authority.key,
+                    namespace_account.key,
+                    Rent::get()?.minimum_balance(NullifierNamespace::SIZE),
+                    NullifierNamespace::SIZE as u64,
+                    program_id,
+                ),
+                &[authority.clone(), namespace_account.clone(), system_program.clone()],
+                &[&[NULLIFIER_NAMESPACE_SEED, &namespace.to_le_bytes(), &[bump]]],
+            )?;
+            NullifierNamespace::new(*authority.key, namespace).save(namespace_account)?;
+
+            WaveEvent::NullifierNamespaceCreated {
+                namespace,
+                authority: *authority.key,
+            }.emit();
+            Ok(())
+        }
+
+        WaveInstruction::SetFlowEnabled { flow_id, enabled } => {
+            log_debug!("Instruction: SetFlowEnabled");
+            let accounts_iter = &mut accounts.iter();
+
+            let authority = next_account_info(accounts_iter)?;
+            let flow_registry = next_account_info(accounts_iter)?;
+
+            if !authority.is_signer {
+                return Err(WaveError::Unauthorized.into());
+            }
+
+            if flow_registry.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let mut registry = FlowRegistry::load(flow_registry)?;
+            if registry.flow_id != flow_id {
+                return Err(WaveError::InvalidFlowId.into());
+            }
+            if registry.authority != *authority.key {
+                return Err(WaveError::Unauthorized.into());
+            }
+            registry.is_enabled = enabled;
+            registry.save(flow_registry)?;
+
+            WaveEvent::FlowEnabledSet { flow_id, enabled }.emit();
+            Ok(())
+        }
+    }
+}
+
+/// Creates `account`, the PDA `signer_seeds` sign for, with `size` bytes
+/// owned by the program, paid for by `payer`. Anyone can send lamports to
+/// the address first, which would make create_account fail, so an account
+/// already holding some is topped up to rent exemption and allocated and
+/// assigned instead.
+fn create_pda_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    size: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let rent_exempt = Rent::get()?.minimum_balance(size);
+
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(payer.key, account.key, rent_exempt, size as u64, program_id),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[signer_seeds],
+        );
+    }
+
+    let shortfall = rent_exempt.saturating_sub(account.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, shortfall),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account.key, size as u64),
+        &[account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, program_id),
+        &[account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Creates the nullifier PDA of `nullifier_key` in `registry`'s scope, with
+/// bump `bump`, paid for by `payer`. An account already there must belong
+/// to the program and is left for the caller to check.
+fn ensure_nullifier_account<'a>(
+    program_id: &Pubkey,
+    registry: &FlowRegistry,
+    payer: &AccountInfo<'a>,
+    nullifier_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    nullifier_key: &[u8; 32],
+    bump: u8,
+) -> ProgramResult {
+    if !nullifier_account.data_is_empty() {
+        if nullifier_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+        return Ok(());
+    }
+    create_pda_account(
+        program_id,
+        payer,
+        nullifier_account,
+        system_program,
+        Nullifier::account_size(),
+        &[registry.nullifier_seed(), &registry.nullifier_scope().to_le_bytes(), nullifier_key, &[bump]],
+    )
+}
+
+/// Creates the proof log PDA of `nullifier` in flow `flow_id`, with bump
+/// `bump`, paid for by `payer`. A log already there, left by an expired
+/// nullifier, must belong to the program and is recorded over.
+fn ensure_proof_log_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    proof_log: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    flow_id: u64,
+    nullifier: &[u8; 32],
+    bump: u8,
+) -> ProgramResult {
+    if !proof_log.data_is_empty() {
+        if proof_log.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+        return Ok(());
+    }
+    create_pda_account(
+        program_id,
+        payer,
+        proof_log,
+        system_program,
+        ProofLog::account_size(),
+        &[PROOF_LOG_SEED, &flow_id.to_le_bytes(), nullifier, &[bump]],
+    )
+}
+
+/// Stores `verifying_key` at the verifying key PDA of `circuit_hash`, paid
+/// for by `payer`. Flows sharing a circuit share its key, stored by the
+/// first; an account already there is left as is.
+fn store_verifying_key<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    verifying_key_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    circuit_hash: &[u8; 32],
+    verifying_key: &[u8],
+) -> ProgramResult {
+    let (expected_key_account, bump) = VerifyingKey::find_pda(program_id, circuit_hash);
+    if verifying_key_account.key != &expected_key_account {
+        return Err(WaveError::InvalidAccountData.into());
+    }
+    if !verifying_key_account.data_is_empty() {
+        if verifying_key_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+        return Ok(());
+    }
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            verifying_key_account.key,
+            Rent::get()?.minimum_balance(verifying_key.len()),
+            verifying_key.len() as u64,
+            program_id,
+        ),
+        &[payer.clone(), verifying_key_account.clone(), system_program.clone()],
+        &[&[VERIFYING_KEY_SEED, circuit_hash, &[bump]]],
+    )?;
+    verifying_key_account.try_borrow_mut_data()?.copy_from_slice(verifying_key);
+    Ok(())
+}
+
+/// Loads the approved circuits allowlist for the program config admin
+/// `admin` to change, creating it at their expense the first time.
+fn load_or_create_approved_circuits<'a>(
+    program_id: &Pubkey,
+    admin: &AccountInfo<'a>,
+    config_account: &AccountInfo<'a>,
+    allowlist_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> Result<ApprovedCircuits, ProgramError> {
+    if !admin.is_signer {
+        return Err(WaveError::Unauthorized.into());
+    }
+
+    // Until the config exists there is no admin
+    if config_account.key != &ProgramConfig::find_pda(program_id).0 {
+        return Err(WaveError::InvalidAccountData.into());
+    }
+    if config_account.data_is_empty() {
+        return Err(WaveError::Unauthorized.into());
+    }
+    if config_account.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    if ProgramConfig::load(config_account)?.admin != *admin.key {
+        return Err(WaveError::Unauthorized.into());
+    }
+
+    let (expected_allowlist, bump) = ApprovedCircuits::find_pda(program_id);
+    if allowlist_account.key != &expected_allowlist {
+        return Err(WaveError::InvalidAccountData.into());
+    }
+
+    if !allowlist_account.data_is_empty() {
+        if allowlist_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+        return ApprovedCircuits::load(allowlist_account);
+    }
+    if system_program.key != &system_program::id() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    invoke_signed(
+        &system_instruction::create_account(
+            admin.key,
+            allowlist_account.key,
+            Rent::get()?.minimum_balance(ApprovedCircuits::SIZE),
+            ApprovedCircuits::SIZE as u64,
+            program_id,
+        ),
+        &[admin.clone(), allowlist_account.clone(), system_program.clone()],
+        &[&[APPROVED_CIRCUITS_SEED, &[bump]]],
+    )?;
+    Ok(ApprovedCircuits::default())
+}
+
+/// Checks a flow may join `namespace`: members can spend each other's
+/// nullifiers, so the namespace's authority has to sign for it.
+fn check_namespace_admission(
+    program_id: &Pubkey,
+    namespace: u64,
+    namespace_account: &AccountInfo,
+    namespace_authority: &AccountInfo,
+) -> ProgramResult {
+    if namespace_account.key != &NullifierNamespace::find_pda(program_id, namespace).0 {
+        return Err(WaveError::InvalidPda.into());
+    }
+    if namespace_account.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    if !namespace_authority.is_signer
+        || NullifierNamespace::load(namespace_account)?.authority != *namespace_authority.key
+    {
+        return Err(WaveError::Unauthorized.into());
     }
-} 
\ No newline at end of file
+    Ok(())
+}
\ No newline at end of file
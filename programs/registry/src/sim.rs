@@ -0,0 +1,416 @@
+//! Off-chain models of the registry program's state, for simulating
+//! instruction sequences without a validator. Every collection is
+//! BTree-backed, so iteration order is deterministic and two models that
+//! saw the same instructions compare equal.
+
+use std::collections::BTreeMap;
+
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{
+    constants::REGISTRY_SEED,
+    state::{FlowRegistry, Nullifier, ProofLog},
+};
+
+/// Registered flows, keyed by flow_id, and the authorities of nullifier
+/// namespaces, keyed by namespace
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegistryManager {
+    pub registries: BTreeMap<u64, FlowRegistry>,
+    pub namespaces: BTreeMap<u64, Pubkey>,
+}
+
+impl RegistryManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `registry`, replacing any flow with the same id.
+    pub fn register(&mut self, registry: FlowRegistry) {
+        self.registries.insert(registry.flow_id, registry);
+    }
+
+    pub fn get_by_id(&self, flow_id: u64) -> Option<&FlowRegistry> {
+        self.registries.get(&flow_id)
+    }
+
+    /// Finds the flow whose registry PDA is `key`.
+    pub fn get_by_key(&self, program_id: &Pubkey, key: &Pubkey) -> Option<&FlowRegistry> {
+        self.registries.values().find(|registry| {
+            let (expected, _) = Pubkey::find_program_address(
+                &[REGISTRY_SEED, &registry.flow_id.to_le_bytes()],
+                program_id,
+            );
+            &expected == key
+        })
+    }
+
+    /// Sets the flow's root and advances its root sequence, as SetRoot does.
+    pub fn update_root(&mut self, flow_id: u64, new_root: [u8; 32]) -> Result<(), ProgramError> {
+        let registry = self.registries.get_mut(&flow_id).ok_or(ProgramError::InvalidAccountData)?;
+        registry.merkle_root = Some(new_root);
+        registry.root_sequence += 1;
+        Ok(())
+    }
+
+    pub fn set_circuit(&mut self, flow_id: u64, circuit_hash: [u8; 32]) -> Result<(), ProgramError> {
+        let registry = self.registries.get_mut(&flow_id).ok_or(ProgramError::InvalidAccountData)?;
+        registry.circuit_hash = circuit_hash;
+        Ok(())
+    }
+
+    pub fn freeze_circuit(&mut self, flow_id: u64) -> Result<(), ProgramError> {
+        let registry = self.registries.get_mut(&flow_id).ok_or(ProgramError::InvalidAccountData)?;
+        registry.circuit_frozen = true;
+        Ok(())
+    }
+
+    /// Counts one accepted proof against the flow's nullifier cap
+    pub fn count_nullifier(&mut self, flow_id: u64) -> Result<(), ProgramError> {
+        let registry = self.registries.get_mut(&flow_id).ok_or(ProgramError::InvalidAccountData)?;
+        registry.nullifier_count += 1;
+        Ok(())
+    }
+
+    pub fn finalize_migration(&mut self, flow_id: u64) -> Result<(), ProgramError> {
+        let registry = self.registries.get_mut(&flow_id).ok_or(ProgramError::InvalidAccountData)?;
+        registry.migration_complete = true;
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, flow_id: u64, enabled: bool) -> Result<(), ProgramError> {
+        let registry = self.registries.get_mut(&flow_id).ok_or(ProgramError::InvalidAccountData)?;
+        registry.is_enabled = enabled;
+        Ok(())
+    }
+
+    /// Records `authority` as the owner of `namespace`. Returns false,
+    /// leaving the owner unchanged, if the namespace already exists.
+    pub fn create_namespace(&mut self, namespace: u64, authority: Pubkey) -> bool {
+        if self.namespaces.contains_key(&namespace) {
+            return false;
+        }
+        self.namespaces.insert(namespace, authority);
+        true
+    }
+
+    pub fn namespace_authority(&self, namespace: u64) -> Option<&Pubkey> {
+        self.namespaces.get(&namespace)
+    }
+
+    /// Flows in ascending flow_id order
+    pub fn iter(&self) -> impl Iterator<Item = &FlowRegistry> {
+        self.registries.values()
+    }
+
+    pub fn reset(&mut self) {
+        self.registries.clear();
+        self.namespaces.clear();
+    }
+
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn restore(&mut self, snapshot: Self) {
+        *self = snapshot;
+    }
+}
+
+/// Recorded nullifiers, keyed by scope and hash like the nullifier PDAs
+/// they model. A flow records in its `FlowRegistry::nullifier_scope`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NullifierSet {
+    pub nullifiers: BTreeMap<(u64, [u8; 32]), Nullifier>,
+}
+
+impl NullifierSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `nullifier` in `scope`. Returns false, leaving the set
+    /// unchanged, if its hash is already recorded there.
+    pub fn add(&mut self, scope: u64, nullifier: Nullifier) -> bool {
+        if self.exists(scope, &nullifier.hash) {
+            return false;
+        }
+        self.nullifiers.insert((scope, nullifier.hash), nullifier);
+        true
+    }
+
+    pub fn exists(&self, scope: u64, hash: &[u8; 32]) -> bool {
+        self.nullifiers.contains_key(&(scope, *hash))
+    }
+
+    pub fn get(&self, scope: u64, hash: &[u8; 32]) -> Option<&Nullifier> {
+        self.nullifiers.get(&(scope, *hash))
+    }
+
+    /// The flow's nullifiers in ascending scope and hash order
+    pub fn get_by_flow(&self, flow_id: u64) -> Vec<&Nullifier> {
+        self.nullifiers.values().filter(|n| n.flow_id == flow_id).collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.nullifiers.clear();
+    }
+
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn restore(&mut self, snapshot: Self) {
+        *self = snapshot;
+    }
+}
+
+/// Proof logs, keyed by flow and nullifier like the proof log PDAs they
+/// model
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProofHistory {
+    pub logs: BTreeMap<(u64, [u8; 32]), ProofLog>,
+}
+
+impl ProofHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `log`, replacing any log for the same flow and nullifier.
+    pub fn record(&mut self, log: ProofLog) {
+        self.logs.insert((log.flow_id, log.nullifier), log);
+    }
+
+    pub fn get(&self, flow_id: u64, nullifier: &[u8; 32]) -> Option<&ProofLog> {
+        self.logs.get(&(flow_id, *nullifier))
+    }
+
+    /// The flow's logs in ascending nullifier order
+    pub fn get_by_flow(&self, flow_id: u64) -> Vec<&ProofLog> {
+        self.logs.values().filter(|l| l.flow_id == flow_id).collect()
+    }
+
+    /// Logs of proofs whose public inputs began with `public_inputs_hash`,
+    /// what WasProofValidated looks for among its candidates
+    pub fn find_by_public_inputs_hash(&self, public_inputs_hash: &[u8; 32]) -> Vec<&ProofLog> {
+        self.logs.values()
+            .filter(|l| &l.public_inputs_hash == public_inputs_hash)
+            .collect()
+    }
+
+    pub fn get_by_timerange(&self, start: i64, end: i64) -> Vec<&ProofLog> {
+        self.logs.values()
+            .filter(|l| l.timestamp >= start && l.timestamp <= end)
+            .collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.logs.clear();
+    }
+
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn restore(&mut self, snapshot: Self) {
+        *self = snapshot;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::test_data::*;
+
+    #[test]
+    fn test_registry_manager() {
+        let mut manager = RegistryManager::new();
+        
+        let registry1 = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_1,
+            Some(MERKLE_ROOT_1),
+            CIRCUIT_HASH_1,
+            None,
+        );
+        manager.register(registry1);
+
+        let registry2 = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_2,
+            Some(MERKLE_ROOT_2),
+            CIRCUIT_HASH_2,
+            None,
+        );
+        manager.register(registry2);
+
+        let found = manager.get_by_id(FLOW_ID_1).unwrap();
+        assert_eq!(found.flow_id, FLOW_ID_1);
+
+        manager.update_root(FLOW_ID_1, MERKLE_ROOT_3).unwrap();
+        let updated = manager.get_by_id(FLOW_ID_1).unwrap();
+        assert_eq!(updated.merkle_root, Some(MERKLE_ROOT_3));
+        assert_eq!(updated.root_sequence, 1);
+
+        manager.set_enabled(FLOW_ID_1, false).unwrap();
+        let disabled = manager.get_by_id(FLOW_ID_1).unwrap();
+        assert!(!disabled.is_enabled);
+
+        let program_id = Pubkey::new_unique();
+        let (key, _) = Pubkey::find_program_address(
+            &[REGISTRY_SEED, &FLOW_ID_2.to_le_bytes()],
+            &program_id,
+        );
+        assert_eq!(manager.get_by_key(&program_id, &key).unwrap().flow_id, FLOW_ID_2);
+        assert!(manager.get_by_key(&program_id, &Pubkey::new_unique()).is_none());
+
+        let owner = Pubkey::new_unique();
+        assert!(manager.create_namespace(FLOW_ID_3, owner));
+        assert!(!manager.create_namespace(FLOW_ID_3, Pubkey::new_unique()));
+        assert_eq!(manager.namespace_authority(FLOW_ID_3), Some(&owner));
+        assert_eq!(manager.namespace_authority(FLOW_ID_1), None);
+    }
+
+    #[test]
+    fn test_nullifier_set() {
+        let mut set = NullifierSet::new();
+        
+        let nullifier1 = Nullifier::new(
+            NULLIFIER_1,
+            TIMESTAMP_1,
+            FLOW_ID_1,
+            Pubkey::default(),
+        );
+        assert!(set.add(FLOW_ID_1, nullifier1));
+
+        let nullifier2 = Nullifier::new(
+            NULLIFIER_2,
+            TIMESTAMP_2,
+            FLOW_ID_1,
+            Pubkey::default(),
+        );
+        assert!(set.add(FLOW_ID_1, nullifier2));
+
+        let nullifier3 = Nullifier::new(
+            NULLIFIER_3,
+            TIMESTAMP_3,
+            FLOW_ID_2,
+            Pubkey::default(),
+        );
+        assert!(set.add(FLOW_ID_2, nullifier3));
+
+        // A replay keeps the original entry; another scope records its own
+        assert!(!set.add(FLOW_ID_1, Nullifier::new(NULLIFIER_1, TIMESTAMP_3, FLOW_ID_1, Pubkey::default())));
+        assert!(set.add(FLOW_ID_2, Nullifier::new(NULLIFIER_1, TIMESTAMP_3, FLOW_ID_2, Pubkey::default())));
+
+        assert!(set.exists(FLOW_ID_1, &NULLIFIER_1));
+        assert!(!set.exists(FLOW_ID_2, &NULLIFIER_2));
+        assert!(!set.exists(FLOW_ID_1, &[0u8; 32]));
+
+        assert_eq!(set.get(FLOW_ID_1, &NULLIFIER_1).unwrap().timestamp, TIMESTAMP_1);
+        assert_eq!(set.get(FLOW_ID_2, &NULLIFIER_1).unwrap().timestamp, TIMESTAMP_3);
+
+        let flow1_nullifiers = set.get_by_flow(FLOW_ID_1);
+        assert_eq!(flow1_nullifiers.len(), 2);
+        assert_eq!(flow1_nullifiers[0].flow_id, FLOW_ID_1);
+        assert_eq!(flow1_nullifiers[1].flow_id, FLOW_ID_1);
+
+        let flow2_nullifiers = set.get_by_flow(FLOW_ID_2);
+        assert_eq!(flow2_nullifiers.len(), 2);
+        assert_eq!(flow2_nullifiers[0].flow_id, FLOW_ID_2);
+    }
+
+    #[test]
+    fn test_proof_history() {
+        let mut history = ProofHistory::new();
+        
+        let log1 = ProofLog::new(
+            NULLIFIER_1,
+            TIMESTAMP_1,
+            FLOW_ID_1,
+            PUBLIC_INPUTS_1,
+        );
+        history.record(log1);
+
+        let log2 = ProofLog::new(
+            NULLIFIER_2,
+            TIMESTAMP_2,
+            FLOW_ID_1,
+            PUBLIC_INPUTS_2,
+        );
+        history.record(log2);
+
+        let log3 = ProofLog::new(
+            NULLIFIER_3,
+            TIMESTAMP_3,
+            FLOW_ID_2,
+            PUBLIC_INPUTS_3,
+        );
+        history.record(log3);
+
+        let flow1_logs = history.get_by_flow(FLOW_ID_1);
+        assert_eq!(flow1_logs.len(), 2);
+        assert_eq!(flow1_logs[0].flow_id, FLOW_ID_1);
+        assert_eq!(flow1_logs[1].flow_id, FLOW_ID_1);
+
+        let nullifier1_log = history.get(FLOW_ID_1, &NULLIFIER_1).unwrap();
+        assert_eq!(nullifier1_log.nullifier, NULLIFIER_1);
+        assert!(history.get(FLOW_ID_2, &NULLIFIER_1).is_none());
+
+        let timerange_logs = history.get_by_timerange(
+            TIMESTAMP_1,
+            TIMESTAMP_2,
+        );
+        assert_eq!(timerange_logs.len(), 2);
+        assert!(timerange_logs.iter().all(|l| l.timestamp >= TIMESTAMP_1
+            && l.timestamp <= TIMESTAMP_2));
+
+        let by_inputs = history.find_by_public_inputs_hash(&PUBLIC_INPUTS_2);
+        assert_eq!(by_inputs.len(), 1);
+        assert_eq!(by_inputs[0].nullifier, NULLIFIER_2);
+        assert!(history.find_by_public_inputs_hash(&[0u8; 32]).is_empty());
+    }
+
+    #[test]
+    fn test_iteration_order_ignores_insertion_order() {
+        let flows = [FLOW_ID_3, FLOW_ID_1, FLOW_ID_2];
+        let nullifiers = [NULLIFIER_2, NULLIFIER_3, NULLIFIER_1];
+
+        let mut forward = (RegistryManager::new(), NullifierSet::new());
+        let mut backward = (RegistryManager::new(), NullifierSet::new());
+        for i in 0..flows.len() {
+            let j = flows.len() - 1 - i;
+            forward.0.register(FlowRegistry::new(Pubkey::default(), flows[i], None, CIRCUIT_HASH_1, None));
+            backward.0.register(FlowRegistry::new(Pubkey::default(), flows[j], None, CIRCUIT_HASH_1, None));
+            forward.1.add(FLOW_ID_1, Nullifier::new(nullifiers[i], 0, FLOW_ID_1, Pubkey::default()));
+            backward.1.add(FLOW_ID_1, Nullifier::new(nullifiers[j], 0, FLOW_ID_1, Pubkey::default()));
+        }
+
+        assert_eq!(forward, backward);
+        let ids: Vec<u64> = forward.0.iter().map(|r| r.flow_id).collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(ids, sorted);
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut manager = RegistryManager::new();
+        manager.register(FlowRegistry::new(Pubkey::default(), FLOW_ID_1, None, CIRCUIT_HASH_1, None));
+        let mut set = NullifierSet::new();
+        let mut history = ProofHistory::new();
+
+        let snapshots = (manager.snapshot(), set.snapshot(), history.snapshot());
+
+        manager.update_root(FLOW_ID_1, MERKLE_ROOT_1).unwrap();
+        set.add(FLOW_ID_1, Nullifier::new(NULLIFIER_1, TIMESTAMP_1, FLOW_ID_1, Pubkey::default()));
+        history.record(ProofLog::new(NULLIFIER_1, TIMESTAMP_1, FLOW_ID_1, PUBLIC_INPUTS_1));
+        assert_ne!(manager, snapshots.0);
+
+        manager.restore(snapshots.0.clone());
+        set.restore(snapshots.1.clone());
+        history.restore(snapshots.2.clone());
+        assert_eq!((manager, set, history), snapshots);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,131 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    constants::{APPROVED_CIRCUITS_SEED, MAX_APPROVED_CIRCUITS},
+    error::WaveError,
+};
+
+/// Program-wide allowlist of vetted circuits, at the `APPROVED_CIRCUITS_SEED`
+/// PDA and managed by the program config admin. While `enforced`,
+/// InitRegistry only registers flows whose `circuit_hash` is listed. Until
+/// the account exists nothing is enforced.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct ApprovedCircuits {
+    pub enforced: bool,
+    /// At most `MAX_APPROVED_CIRCUITS`, in approval order
+    pub circuits: Vec<[u8; 32]>,
+}
+
+impl ApprovedCircuits {
+    pub const SIZE: usize = 1 + 4 + MAX_APPROVED_CIRCUITS * 32;
+
+    pub fn is_approved(&self, circuit_hash: &[u8; 32]) -> bool {
+        self.circuits.contains(circuit_hash)
+    }
+
+    /// Adds `circuit_hash`, or does nothing if it is already listed.
+    pub fn approve(&mut self, circuit_hash: [u8; 32]) -> Result<(), WaveError> {
+        if self.is_approved(&circuit_hash) {
+            return Ok(());
+        }
+        if self.circuits.len() >= MAX_APPROVED_CIRCUITS {
+            return Err(WaveError::ApprovedCircuitsFull);
+        }
+        self.circuits.push(circuit_hash);
+        Ok(())
+    }
+
+    /// Removes `circuit_hash` if listed. Flows already registered for it
+    /// are unaffected.
+    pub fn revoke(&mut self, circuit_hash: &[u8; 32]) {
+        self.circuits.retain(|approved| approved != circuit_hash);
+    }
+
+    /// Fails with `InvalidCircuitHash` if the allowlist is enforced and
+    /// doesn't list `circuit_hash`.
+    pub fn check(&self, circuit_hash: &[u8; 32]) -> Result<(), WaveError> {
+        if self.enforced && !self.is_approved(circuit_hash) {
+            return Err(WaveError::InvalidCircuitHash);
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        account_data[..data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        // Revoking shortens the encoding, so ignore trailing bytes
+        let data = account.try_borrow_data()?;
+        let approved = Self::deserialize(&mut &data[..])?;
+        Ok(approved)
+    }
+
+    /// The allowlist `account` holds, or an empty, unenforced one if it
+    /// hasn't been created yet.
+    pub fn load_or_default(account: &AccountInfo) -> Result<Self, ProgramError> {
+        if account.data_is_empty() {
+            return Ok(Self::default());
+        }
+        Self::load(account)
+    }
+
+    /// Derives the approved circuits PDA.
+    pub fn find_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[APPROVED_CIRCUITS_SEED], program_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::test_data::*;
+
+    #[test]
+    fn test_check_only_when_enforced() {
+        let mut approved = ApprovedCircuits::default();
+        assert!(approved.check(&CIRCUIT_HASH_1).is_ok());
+
+        approved.enforced = true;
+        assert!(matches!(approved.check(&CIRCUIT_HASH_1), Err(WaveError::InvalidCircuitHash)));
+        approved.approve(CIRCUIT_HASH_1).unwrap();
+        assert!(approved.check(&CIRCUIT_HASH_1).is_ok());
+        assert!(approved.check(&CIRCUIT_HASH_2).is_err());
+
+        approved.revoke(&CIRCUIT_HASH_1);
+        assert!(approved.check(&CIRCUIT_HASH_1).is_err());
+    }
+
+    #[test]
+    fn test_approve_up_to_capacity() {
+        let mut approved = ApprovedCircuits::default();
+        for i in 0..MAX_APPROVED_CIRCUITS {
+            approved.approve([i as u8; 32]).unwrap();
+        }
+        // Re-approving is a no-op even when full
+        approved.approve([0u8; 32]).unwrap();
+        assert!(matches!(approved.approve([0xff; 32]), Err(WaveError::ApprovedCircuitsFull)));
+        assert_eq!(approved.try_to_vec().unwrap().len(), ApprovedCircuits::SIZE);
+
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0u8; ApprovedCircuits::SIZE];
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        approved.save(&account).unwrap();
+        approved.revoke(&[1u8; 32]);
+        approved.save(&account).unwrap();
+        assert_eq!(ApprovedCircuits::load_or_default(&account).unwrap(), approved);
+    }
+}
\ No newline at end of file
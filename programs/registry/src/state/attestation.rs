@@ -0,0 +1,236 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    constants::{ATTESTATION_RECIPIENT_OFFSET, ATTESTATION_SEED},
+    error::WaveError,
+};
+
+/// What ValidateProof does when a recipient who already holds an
+/// attestation for the flow proves again
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AttestationPolicy {
+    /// Fail with `AttestationExists`
+    #[default]
+    Reject,
+    /// Point the attestation at the new proof and timestamp
+    Refresh,
+}
+
+/// Record that a wallet completed a flow, kept so other programs can check
+/// it without replaying transaction history. It is a plain PDA, so there is
+/// nothing to transfer.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct Attestation {
+    /// The flow the proof was validated for
+    pub flow_id: u64,
+    /// The wallet named in the proof's public inputs
+    pub recipient: Pubkey,
+    /// Nullifier of the latest proof
+    pub nullifier: [u8; 32],
+    /// Proof log of the latest proof
+    pub proof_log: Pubkey,
+    /// When the latest proof was validated
+    pub attested_at: i64,
+    /// When the flow authority revoked the attestation, if it has
+    pub revoked_at: Option<i64>,
+}
+
+impl Attestation {
+    pub const SIZE: usize = 8 + 32 + 32 + 32 + 8 + 9;
+
+    /// Bytes to allocate for an attestation account, with room for
+    /// `revoked_at`.
+    pub const fn account_size() -> usize {
+        Self::SIZE
+    }
+
+    pub fn new(
+        flow_id: u64,
+        recipient: Pubkey,
+        nullifier: [u8; 32],
+        proof_log: Pubkey,
+        attested_at: i64,
+    ) -> Self {
+        Self {
+            flow_id,
+            recipient,
+            nullifier,
+            proof_log,
+            attested_at,
+            revoked_at: None,
+        }
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        account_data[..data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        let data = account.try_borrow_data()?;
+        let attestation = Self::deserialize(&mut &data[..])?;
+        Ok(attestation)
+    }
+
+    /// Derives the attestation PDA of `recipient` for `flow_id`.
+    pub fn find_pda(program_id: &Pubkey, flow_id: u64, recipient: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[ATTESTATION_SEED, &flow_id.to_le_bytes(), recipient.as_ref()],
+            program_id,
+        )
+    }
+
+    /// Reads the recipient `public_inputs` name at
+    /// `ATTESTATION_RECIPIENT_OFFSET`: two signals whose low 16 bytes are
+    /// the key's high and low halves. Any other byte set fails with
+    /// `InvalidInstruction`.
+    pub fn recipient_from_public_inputs(public_inputs: &[u8]) -> Result<Pubkey, ProgramError> {
+        let fields = public_inputs
+            .get(ATTESTATION_RECIPIENT_OFFSET..ATTESTATION_RECIPIENT_OFFSET + 64)
+            .ok_or(WaveError::InvalidInstruction)?;
+        let mut recipient = [0u8; 32];
+        for (half, field) in recipient.chunks_exact_mut(16).zip(fields.chunks_exact(32)) {
+            let (padding, limb) = field.split_at(16);
+            if padding.iter().any(|byte| *byte != 0) {
+                return Err(WaveError::InvalidInstruction.into());
+            }
+            half.copy_from_slice(limb);
+        }
+        Ok(Pubkey::new_from_array(recipient))
+    }
+
+    /// Encodes `recipient` as the two signals `recipient_from_public_inputs`
+    /// reads, each well below the field modulus.
+    pub fn recipient_public_inputs(recipient: &Pubkey) -> [u8; 64] {
+        let mut fields = [0u8; 64];
+        fields[16..32].copy_from_slice(&recipient.as_ref()[..16]);
+        fields[48..].copy_from_slice(&recipient.as_ref()[16..]);
+        fields
+    }
+}
+
+/// Returns whether `wallet` holds an unrevoked attestation for `flow_id`.
+/// Meant for programs that are handed the attestation account; it must be
+/// the PDA `Attestation::find_pda` derives, and one that was never created
+/// reads as no attestation.
+pub fn has_attestation(
+    program_id: &Pubkey,
+    attestation: &AccountInfo,
+    flow_id: u64,
+    wallet: &Pubkey,
+) -> Result<bool, ProgramError> {
+    let (expected, _) = Attestation::find_pda(program_id, flow_id, wallet);
+    if attestation.key != &expected {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if attestation.data_is_empty() {
+        return Ok(false);
+    }
+    if attestation.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    Ok(Attestation::load(attestation)?.revoked_at.is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::test_data::*;
+
+    #[test]
+    fn test_attestation_size() {
+        let attestation = Attestation::new(
+            FLOW_ID_1,
+            Pubkey::new_unique(),
+            NULLIFIER_1,
+            Pubkey::new_unique(),
+            TIMESTAMP_1,
+        );
+        assert!(attestation.try_to_vec().unwrap().len() <= Attestation::account_size());
+
+        let revoked = Attestation { revoked_at: Some(TIMESTAMP_2), ..attestation };
+        assert_eq!(revoked.try_to_vec().unwrap().len(), Attestation::account_size());
+    }
+
+    #[test]
+    fn test_recipient_public_inputs() {
+        // Read whole, this key would be past the BN254 modulus
+        let recipient = Pubkey::new_from_array([0xFF; 32]);
+        let mut public_inputs = vec![1u8; ATTESTATION_RECIPIENT_OFFSET];
+        public_inputs.extend_from_slice(&Attestation::recipient_public_inputs(&recipient));
+        for field in public_inputs[ATTESTATION_RECIPIENT_OFFSET..].chunks(32) {
+            assert_eq!(field[..16], [0u8; 16]);
+        }
+        assert_eq!(Attestation::recipient_from_public_inputs(&public_inputs), Ok(recipient));
+
+        // The key written as one signal
+        let mut whole = vec![1u8; ATTESTATION_RECIPIENT_OFFSET];
+        whole.extend_from_slice(recipient.as_ref());
+        whole.extend_from_slice(&[0u8; 32]);
+        assert_eq!(
+            Attestation::recipient_from_public_inputs(&whole),
+            Err(WaveError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            Attestation::recipient_from_public_inputs(&public_inputs[..ATTESTATION_RECIPIENT_OFFSET + 63]),
+            Err(WaveError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_has_attestation() {
+        let program_id = Pubkey::new_unique();
+        let wallet = Pubkey::new_unique();
+        let (key, _) = Attestation::find_pda(&program_id, FLOW_ID_1, &wallet);
+
+        let mut lamports = 0;
+        let mut empty = [];
+        let missing = AccountInfo::new(
+            &key, false, false, &mut lamports, &mut empty, &program_id, false, 0,
+        );
+        assert_eq!(has_attestation(&program_id, &missing, FLOW_ID_1, &wallet), Ok(false));
+
+        let mut attestation =
+            Attestation::new(FLOW_ID_1, wallet, NULLIFIER_1, Pubkey::new_unique(), TIMESTAMP_1);
+        let mut data = attestation.try_to_vec().unwrap();
+        let mut lamports = 1;
+        let account = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut data, &program_id, false, 0,
+        );
+        assert_eq!(has_attestation(&program_id, &account, FLOW_ID_1, &wallet), Ok(true));
+        // The same account does not vouch for another wallet or flow
+        assert!(has_attestation(&program_id, &account, FLOW_ID_2, &wallet).is_err());
+        assert!(has_attestation(&program_id, &account, FLOW_ID_1, &Pubkey::new_unique()).is_err());
+
+        attestation.revoked_at = Some(TIMESTAMP_2);
+        let mut data = attestation.try_to_vec().unwrap();
+        let mut lamports = 1;
+        let revoked = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut data, &program_id, false, 0,
+        );
+        assert_eq!(has_attestation(&program_id, &revoked, FLOW_ID_1, &wallet), Ok(false));
+
+        let mut data = Attestation::new(FLOW_ID_1, wallet, NULLIFIER_1, key, TIMESTAMP_1)
+            .try_to_vec()
+            .unwrap();
+        let mut lamports = 1;
+        let other_owner = Pubkey::new_unique();
+        let forged = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut data, &other_owner, false, 0,
+        );
+        assert_eq!(
+            has_attestation(&program_id, &forged, FLOW_ID_1, &wallet),
+            Err(ProgramError::IllegalOwner)
+        );
+    }
+}
\ No newline at end of file
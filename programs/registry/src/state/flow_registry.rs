@@ -5,7 +5,15 @@ use solana_program::{
     pubkey::Pubkey,
 };
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+use super::{AttestationPolicy, Nullifier};
+use crate::{
+    constants::{NAMESPACE_NULLIFIER_SEED, NULLIFIER_SEED, PROOF_SLOT_OFFSET, REGISTRY_SEED},
+    error::WaveError,
+    layout::flow_registry::{FLOW_ID, MERKLE_ROOT_TAG},
+    verifier::ProofSystem,
+};
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct FlowRegistry {
     /// The authority that can update this flow's settings
     pub authority: Pubkey,
@@ -19,10 +27,48 @@ pub struct FlowRegistry {
     pub is_enabled: bool,
     /// Optional program ID to call after successful verification
     pub callback_program_id: Option<Pubkey>,
+    /// Optional nullifier namespace shared with other flows, joined with
+    /// its `NullifierNamespace` authority's signature; when unset,
+    /// nullifiers are scoped to this flow's ID
+    pub nullifier_namespace: Option<u64>,
+    /// Compute units proof verification needs; ValidateProof fails fast when
+    /// less remains. Zero, or anything below `verifier::verification_cost`,
+    /// leaves that estimate as the floor
+    pub max_verification_cu: u32,
+    /// Number of root updates applied so far. SetRoot must quote the current
+    /// value, so a delayed or replayed update cannot overwrite a newer root
+    pub root_sequence: u64,
+    /// How ValidateProof treats a recipient proving this flow again
+    pub attestation_policy: AttestationPolicy,
+    /// Set once by FreezeCircuit; the circuit can no longer be rotated
+    pub circuit_frozen: bool,
+    /// Optional cap on `nullifier_count`; ValidateProof fails once reached
+    pub max_nullifiers: Option<u64>,
+    /// Number of proofs ValidateProof has accepted for this flow
+    pub nullifier_count: u64,
+    /// Set by FinalizeNullifierMigration. Until then ValidateProof also
+    /// rejects nullifiers still recorded at their legacy, unscoped PDA
+    pub migration_complete: bool,
+    /// When set, nullifier PDAs are seeded by, and records hold, the
+    /// commitment `sha256(nullifier || scope)` instead of the raw nullifier,
+    /// so the nullifier value is not exposed as a seed
+    pub nullifier_commitment: bool,
+    /// Optional number of slots a proof's committed slot may trail the
+    /// clock by; ValidateProof rejects proofs outside it with ProofExpired
+    pub freshness_window_slots: Option<u64>,
+    /// The proof system the flow's circuit is proven with, which decides
+    /// how ValidateProof verifies and what its verifying key account holds
+    pub proof_system: ProofSystem,
 }
 
 impl FlowRegistry {
-    pub const SIZE: usize = 32 + 8 + 33 + 32 + 1 + 33;
+    pub const SIZE: usize = 32 + 8 + 33 + 32 + 1 + 33 + 9 + 4 + 8 + 1 + 1 + 9 + 8 + 1 + 1 + 9 + 1;
+
+    /// Bytes to allocate for a registry account: the encoding with every
+    /// optional field set.
+    pub const fn account_size() -> usize {
+        Self::SIZE
+    }
 
     pub fn new(
         authority: Pubkey,
@@ -38,59 +84,164 @@ impl FlowRegistry {
             circuit_hash,
             is_enabled: true,
             callback_program_id,
+            nullifier_namespace: None,
+            max_verification_cu: 0,
+            root_sequence: 0,
+            attestation_policy: AttestationPolicy::default(),
+            circuit_frozen: false,
+            max_nullifiers: None,
+            nullifier_count: 0,
+            // Flows registered under scoped seeds have nothing to migrate
+            migration_complete: true,
+            nullifier_commitment: false,
+            freshness_window_slots: None,
+            proof_system: ProofSystem::Groth16,
         }
     }
 
-    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
-        let data = self.try_to_vec()?;
-        let mut account_data = account.try_borrow_mut_data()?;
-        account_data[..data.len()].copy_from_slice(&data);
+    /// Whether the flow has used up its nullifier cap
+    pub fn at_capacity(&self) -> bool {
+        self.max_nullifiers.is_some_and(|max| self.nullifier_count >= max)
+    }
+
+    /// Derives the registry PDA of `flow_id`.
+    pub fn find_pda(program_id: &Pubkey, flow_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[REGISTRY_SEED, &flow_id.to_le_bytes()], program_id)
+    }
+
+    /// Checks the flow can take proofs: it was registered, since a zeroed
+    /// account decodes with the default authority, and is enabled.
+    pub fn check_accepts_proofs(&self) -> Result<(), ProgramError> {
+        if self.authority == Pubkey::default() {
+            return Err(WaveError::InvalidAccountData.into());
+        }
+        if !self.is_enabled {
+            return Err(WaveError::FlowDisabled.into());
+        }
         Ok(())
     }
 
-    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
-        let data = account.try_borrow_data()?;
-        let registry = Self::try_from_slice(&data)?;
-        Ok(registry)
+    /// Returns the scope nullifier PDAs are derived from: the shared
+    /// namespace when one is set, otherwise the flow ID.
+    pub fn nullifier_scope(&self) -> u64 {
+        self.nullifier_namespace.unwrap_or(self.flow_id)
     }
-}
 
-#[cfg(test)]
-pub struct RegistryManager {
-    pub registries: Vec<FlowRegistry>,
-}
+    /// Returns the seed nullifier PDAs start with, which tells a namespace
+    /// scope from a flow ID scope of the same number.
+    pub fn nullifier_seed(&self) -> &'static [u8] {
+        if self.nullifier_namespace.is_some() {
+            NAMESPACE_NULLIFIER_SEED
+        } else {
+            NULLIFIER_SEED
+        }
+    }
 
-#[cfg(test)]
-impl RegistryManager {
-    pub fn new() -> Self {
-        Self {
-            registries: Vec::new(),
+    /// Derives the PDA the nullifier recorded under `nullifier_key` lives at.
+    pub fn find_nullifier_pda(&self, program_id: &Pubkey, nullifier_key: &[u8; 32]) -> (Pubkey, u8) {
+        match self.nullifier_namespace {
+            Some(namespace) => Nullifier::find_namespace_pda(program_id, namespace, nullifier_key),
+            None => Nullifier::find_pda(program_id, self.flow_id, nullifier_key),
         }
     }
 
-    pub fn add_registry(&mut self, registry: FlowRegistry) {
-        self.registries.push(registry);
+    /// Returns the key a nullifier is recorded under: its commitment in
+    /// commitment mode, otherwise the nullifier itself.
+    pub fn nullifier_key(&self, nullifier: &[u8; 32]) -> [u8; 32] {
+        if self.nullifier_commitment {
+            Nullifier::commitment(nullifier, self.nullifier_scope())
+        } else {
+            *nullifier
+        }
     }
 
-    pub fn get_by_id(&self, flow_id: u64) -> Option<&FlowRegistry> {
-        self.registries.iter().find(|r| r.flow_id == flow_id)
+    /// Checks the slot `public_inputs` commit to at `PROOF_SLOT_OFFSET` is
+    /// within the freshness window behind `current_slot`. A slot ahead of the
+    /// clock is as unusable as a stale one. Flows without a window accept
+    /// any proof.
+    pub fn check_freshness(&self, public_inputs: &[u8], current_slot: u64) -> Result<(), ProgramError> {
+        let Some(window) = self.freshness_window_slots else {
+            return Ok(());
+        };
+
+        let field = public_inputs
+            .get(PROOF_SLOT_OFFSET..PROOF_SLOT_OFFSET + 32)
+            .ok_or(WaveError::InvalidInstruction)?;
+        let (high, low) = field.split_at(24);
+        if high.iter().any(|byte| *byte != 0) {
+            return Err(WaveError::InvalidInstruction.into());
+        }
+        let proof_slot = u64::from_be_bytes(low.try_into().unwrap());
+
+        match current_slot.checked_sub(proof_slot) {
+            Some(age) if age <= window => Ok(()),
+            _ => Err(WaveError::ProofExpired.into()),
+        }
     }
 
-    pub fn update_root(&mut self, flow_id: u64, new_root: [u8; 32]) -> Result<(), ProgramError> {
-        if let Some(registry) = self.registries.iter_mut().find(|r| r.flow_id == flow_id) {
-            registry.merkle_root = Some(new_root);
-            Ok(())
-        } else {
-            Err(ProgramError::InvalidAccountData)
+    /// Returns a registry for `flow_id` carrying this flow's configuration
+    /// (circuit and proof system, callback, nullifier namespace and commitment mode,
+    /// attestation policy, nullifier cap, freshness window) but none of its runtime state: no Merkle root, an unfrozen
+    /// circuit, no nullifiers counted, and disabled until the authority
+    /// enables it.
+    pub fn clone_as(&self, flow_id: u64) -> Self {
+        Self {
+            authority: self.authority,
+            flow_id,
+            merkle_root: None,
+            circuit_hash: self.circuit_hash,
+            is_enabled: false,
+            callback_program_id: self.callback_program_id,
+            nullifier_namespace: self.nullifier_namespace,
+            max_verification_cu: self.max_verification_cu,
+            root_sequence: 0,
+            attestation_policy: self.attestation_policy,
+            circuit_frozen: false,
+            max_nullifiers: self.max_nullifiers,
+            nullifier_count: 0,
+            migration_complete: true,
+            nullifier_commitment: self.nullifier_commitment,
+            freshness_window_slots: self.freshness_window_slots,
+            proof_system: self.proof_system,
         }
     }
 
-    pub fn set_enabled(&mut self, flow_id: u64, enabled: bool) -> Result<(), ProgramError> {
-        if let Some(registry) = self.registries.iter_mut().find(|r| r.flow_id == flow_id) {
-            registry.is_enabled = enabled;
-            Ok(())
-        } else {
-            Err(ProgramError::InvalidAccountData)
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        let mut account_data = account.try_borrow_mut_data()?;
+        account_data[..data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        // Accounts are allocated at SIZE, which covers every Option being Some,
+        // so read the struct from the front and ignore any trailing bytes
+        let data = account.try_borrow_data()?;
+        let registry = Self::deserialize(&mut &data[..])?;
+        Ok(registry)
+    }
+
+    /// Reads `flow_id` from encoded registry data without decoding the rest.
+    pub fn read_flow_id(data: &[u8]) -> Result<u64, ProgramError> {
+        data.get(FLOW_ID..FLOW_ID + 8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+
+    /// Reads `is_enabled` from encoded registry data without decoding the
+    /// rest. It sits after `merkle_root`, so its offset depends on that
+    /// field's tag.
+    pub fn read_is_enabled(data: &[u8]) -> Result<bool, ProgramError> {
+        let root_len = match data.get(MERKLE_ROOT_TAG) {
+            Some(0) => 0,
+            Some(1) => 32,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        // Past the tag, the root if set, and circuit_hash
+        match data.get(MERKLE_ROOT_TAG + 1 + root_len + 32) {
+            Some(0) => Ok(false),
+            Some(1) => Ok(true),
+            _ => Err(ProgramError::InvalidAccountData),
         }
     }
 }
@@ -116,39 +267,266 @@ mod tests {
         assert_eq!(registry.merkle_root, Some(MERKLE_ROOT_1));
         assert_eq!(registry.circuit_hash, CIRCUIT_HASH_1);
         assert!(registry.is_enabled);
+        assert_eq!(registry.nullifier_namespace, None);
+        assert_eq!(registry.max_verification_cu, 0);
+        assert!(registry.migration_complete);
+        assert!(!registry.nullifier_commitment);
     }
 
     #[test]
-    fn test_registry_manager() {
-        let mut manager = RegistryManager::new();
-        
-        let registry1 = FlowRegistry::new(
+    fn test_nullifier_scope() {
+        let mut registry = FlowRegistry::new(
             Pubkey::new_unique(),
             FLOW_ID_1,
-            Some(MERKLE_ROOT_1),
+            None,
             CIRCUIT_HASH_1,
             None,
         );
-        manager.add_registry(registry1);
+        assert_eq!(registry.nullifier_scope(), FLOW_ID_1);
+
+        registry.nullifier_namespace = Some(42);
+        assert_eq!(registry.nullifier_scope(), 42);
+    }
 
-        let registry2 = FlowRegistry::new(
+    #[test]
+    fn test_namespace_nullifiers_do_not_alias_flow_ids() {
+        let program_id = Pubkey::new_unique();
+        let flow = FlowRegistry::new(Pubkey::new_unique(), FLOW_ID_1, None, CIRCUIT_HASH_1, None);
+        let mut member = FlowRegistry::new(Pubkey::new_unique(), FLOW_ID_2, None, CIRCUIT_HASH_1, None);
+        member.nullifier_namespace = Some(FLOW_ID_1);
+
+        assert_eq!(member.nullifier_scope(), flow.nullifier_scope());
+        assert_ne!(
+            member.find_nullifier_pda(&program_id, &NULLIFIER_1),
+            flow.find_nullifier_pda(&program_id, &NULLIFIER_1),
+        );
+        assert_eq!(
+            flow.find_nullifier_pda(&program_id, &NULLIFIER_1),
+            Nullifier::find_pda(&program_id, FLOW_ID_1, &NULLIFIER_1),
+        );
+    }
+
+    #[test]
+    fn test_nullifier_key() {
+        let mut registry = FlowRegistry::new(
             Pubkey::new_unique(),
-            FLOW_ID_2,
-            Some(MERKLE_ROOT_2),
-            CIRCUIT_HASH_2,
+            FLOW_ID_1,
+            None,
+            CIRCUIT_HASH_1,
             None,
         );
-        manager.add_registry(registry2);
+        assert_eq!(registry.nullifier_key(&NULLIFIER_1), NULLIFIER_1);
 
-        let found = manager.get_by_id(FLOW_ID_1).unwrap();
-        assert_eq!(found.flow_id, FLOW_ID_1);
+        registry.nullifier_commitment = true;
+        assert_eq!(
+            registry.nullifier_key(&NULLIFIER_1),
+            Nullifier::commitment(&NULLIFIER_1, FLOW_ID_1)
+        );
+
+        // Flows sharing a namespace must agree on the commitment
+        registry.nullifier_namespace = Some(FLOW_ID_3);
+        assert_eq!(
+            registry.nullifier_key(&NULLIFIER_1),
+            Nullifier::commitment(&NULLIFIER_1, FLOW_ID_3)
+        );
+    }
+
+    #[test]
+    fn test_check_freshness() {
+        let mut registry = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_1,
+            None,
+            CIRCUIT_HASH_1,
+            None,
+        );
+        let inputs = |slot: u64| {
+            let mut inputs = vec![1u8; PROOF_SLOT_OFFSET];
+            inputs.extend_from_slice(&[0u8; 24]);
+            inputs.extend_from_slice(&slot.to_be_bytes());
+            inputs
+        };
+
+        // No window: the slot is not even read
+        assert_eq!(registry.check_freshness(&[], 1_000), Ok(()));
+
+        registry.freshness_window_slots = Some(100);
+        assert_eq!(registry.check_freshness(&inputs(900), 1_000), Ok(()));
+        assert_eq!(registry.check_freshness(&inputs(1_000), 1_000), Ok(()));
+        assert_eq!(registry.check_freshness(&inputs(899), 1_000), Err(WaveError::ProofExpired.into()));
+        assert_eq!(registry.check_freshness(&inputs(1_001), 1_000), Err(WaveError::ProofExpired.into()));
+
+        let mut oversized = inputs(900);
+        oversized[PROOF_SLOT_OFFSET] = 1;
+        assert_eq!(registry.check_freshness(&oversized, 1_000), Err(WaveError::InvalidInstruction.into()));
+        assert_eq!(
+            registry.check_freshness(&inputs(900)[..PROOF_SLOT_OFFSET + 31], 1_000),
+            Err(WaveError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_at_capacity() {
+        let mut registry = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_1,
+            None,
+            CIRCUIT_HASH_1,
+            None,
+        );
+        registry.nullifier_count = 1_000;
+        assert!(!registry.at_capacity());
+
+        registry.max_nullifiers = Some(1_001);
+        assert!(!registry.at_capacity());
+        registry.nullifier_count += 1;
+        assert!(registry.at_capacity());
+    }
+
+    #[test]
+    fn test_check_accepts_proofs() {
+        let mut registry = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_1,
+            None,
+            CIRCUIT_HASH_1,
+            None,
+        );
+        assert!(registry.check_accepts_proofs().is_ok());
+
+        registry.is_enabled = false;
+        assert_eq!(registry.check_accepts_proofs(), Err(WaveError::FlowDisabled.into()));
+
+        let zeroed = FlowRegistry::deserialize(&mut &[0u8; FlowRegistry::SIZE][..]).unwrap();
+        assert_eq!(zeroed.check_accepts_proofs(), Err(WaveError::InvalidAccountData.into()));
+    }
+
+    #[test]
+    fn test_clone_as() {
+        let mut source = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_1,
+            Some(MERKLE_ROOT_1),
+            CIRCUIT_HASH_1,
+            Some(Pubkey::new_unique()),
+        );
+        source.nullifier_namespace = Some(FLOW_ID_3);
+        source.max_verification_cu = 200_000;
+        source.attestation_policy = AttestationPolicy::Refresh;
+        source.circuit_frozen = true;
+        source.max_nullifiers = Some(10);
+        source.nullifier_count = 4;
+        source.migration_complete = false;
+        source.nullifier_commitment = true;
+        source.freshness_window_slots = Some(150);
+
+        let clone = source.clone_as(FLOW_ID_2);
+        assert_eq!(clone.authority, source.authority);
+        assert_eq!(clone.flow_id, FLOW_ID_2);
+        assert_eq!(clone.merkle_root, None);
+        assert_eq!(clone.circuit_hash, source.circuit_hash);
+        assert!(!clone.is_enabled);
+        assert_eq!(clone.callback_program_id, source.callback_program_id);
+        assert_eq!(clone.nullifier_namespace, source.nullifier_namespace);
+        assert_eq!(clone.max_verification_cu, source.max_verification_cu);
+        assert_eq!(clone.root_sequence, 0);
+        assert_eq!(clone.attestation_policy, source.attestation_policy);
+        assert!(!clone.circuit_frozen);
+        assert_eq!(clone.max_nullifiers, source.max_nullifiers);
+        assert_eq!(clone.nullifier_count, 0);
+        assert!(clone.migration_complete);
+        assert!(clone.nullifier_commitment);
+        assert_eq!(clone.freshness_window_slots, source.freshness_window_slots);
+        assert_eq!(clone.proof_system, source.proof_system);
+    }
+
+    #[test]
+    fn test_account_size_bounds() {
+        // Every combination of the optional fields
+        for set in 0..32u8 {
+            let mut registry = FlowRegistry::new(
+                Pubkey::new_unique(),
+                FLOW_ID_1,
+                (set & 1 != 0).then_some(MERKLE_ROOT_1),
+                CIRCUIT_HASH_1,
+                (set & 2 != 0).then(Pubkey::new_unique),
+            );
+            registry.nullifier_namespace = (set & 4 != 0).then_some(FLOW_ID_3);
+            registry.max_nullifiers = (set & 8 != 0).then_some(100);
+            registry.freshness_window_slots = (set & 16 != 0).then_some(150);
+
+            let len = registry.try_to_vec().unwrap().len();
+            assert!(len <= FlowRegistry::account_size());
+            if set == 31 {
+                assert_eq!(len, FlowRegistry::account_size());
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_from_oversized_account() {
+        let mut registry = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_1,
+            Some(MERKLE_ROOT_1),
+            CIRCUIT_HASH_1,
+            Some(Pubkey::new_unique()),
+        );
+
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0u8; 1024];
+        let account = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut data, &owner, false, 0,
+        );
+
+        registry.save(&account).unwrap();
+        assert_eq!(FlowRegistry::load(&account).unwrap(), registry);
+
+        // Re-saving a shorter encoding leaves stale bytes behind it
+        registry.merkle_root = None;
+        registry.callback_program_id = None;
+        registry.save(&account).unwrap();
+        assert_eq!(FlowRegistry::load(&account).unwrap(), registry);
+    }
+
+    #[test]
+    fn test_field_readers_match_load() {
+        for set in 0..8u8 {
+            let mut registry = FlowRegistry::new(
+                Pubkey::new_unique(),
+                FLOW_ID_1 + set as u64,
+                (set & 1 != 0).then_some(MERKLE_ROOT_1),
+                CIRCUIT_HASH_1,
+                (set & 2 != 0).then(Pubkey::new_unique),
+            );
+            registry.is_enabled = set & 4 != 0;
+
+            let mut data = registry.try_to_vec().unwrap();
+            data.resize(FlowRegistry::account_size(), 0);
+            let loaded = FlowRegistry::deserialize(&mut &data[..]).unwrap();
+            assert_eq!(FlowRegistry::read_flow_id(&data).unwrap(), loaded.flow_id);
+            assert_eq!(FlowRegistry::read_is_enabled(&data).unwrap(), loaded.is_enabled);
+        }
+    }
+
+    #[test]
+    fn test_field_readers_reject_bad_data() {
+        let registry = FlowRegistry::new(
+            Pubkey::new_unique(),
+            FLOW_ID_1,
+            Some(MERKLE_ROOT_1),
+            CIRCUIT_HASH_1,
+            None,
+        );
+        let mut data = registry.try_to_vec().unwrap();
 
-        manager.update_root(FLOW_ID_1, MERKLE_ROOT_3).unwrap();
-        let updated = manager.get_by_id(FLOW_ID_1).unwrap();
-        assert_eq!(updated.merkle_root, Some(MERKLE_ROOT_3));
+        // Truncated just before the last byte each reader needs
+        assert!(FlowRegistry::read_flow_id(&data[..FLOW_ID + 7]).is_err());
+        assert!(FlowRegistry::read_is_enabled(&data[..MERKLE_ROOT_TAG + 65]).is_err());
 
-        manager.set_enabled(FLOW_ID_1, false).unwrap();
-        let disabled = manager.get_by_id(FLOW_ID_1).unwrap();
-        assert!(!disabled.is_enabled);
+        data[MERKLE_ROOT_TAG] = 2;
+        assert!(FlowRegistry::read_is_enabled(&data).is_err());
     }
 } 
\ No newline at end of file
@@ -0,0 +1,283 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    hash::hashv,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    constants::{FLOW_TREE_DEPTH, FLOW_TREE_SEED},
+    error::WaveError,
+};
+
+/// Append-only Merkle tree that keeps only the frontier, so a flow's tree
+/// can be continued on-chain without storing its leaves.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct IncrementalMerkleTree {
+    /// Number of leaves appended so far
+    pub leaf_count: u64,
+    /// Current root
+    pub root: [u8; 32],
+    /// Most recent left-hand node at each level; only the levels where
+    /// `leaf_count` has a set bit are part of the tree state
+    pub frontier: [[u8; 32]; FLOW_TREE_DEPTH],
+}
+
+impl IncrementalMerkleTree {
+    pub const SIZE: usize = 8 + 32 + 32 * FLOW_TREE_DEPTH;
+
+    /// Bytes to allocate for a flow tree account.
+    pub const fn account_size() -> usize {
+        Self::SIZE
+    }
+
+    /// Empty tree; every unset leaf is all zeros.
+    pub fn new() -> Self {
+        let frontier = [[0u8; 32]; FLOW_TREE_DEPTH];
+        let root = Self::compute_root(&frontier, 0);
+        Self {
+            leaf_count: 0,
+            root,
+            frontier,
+        }
+    }
+
+    /// Rebuilds a tree from a frontier exported elsewhere, rejecting it
+    /// unless the frontier and `leaf_count` reproduce `root`.
+    pub fn from_frontier(
+        frontier: [[u8; 32]; FLOW_TREE_DEPTH],
+        leaf_count: u64,
+        root: [u8; 32],
+    ) -> Result<Self, ProgramError> {
+        if leaf_count > Self::capacity() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if Self::compute_root(&frontier, leaf_count) != root {
+            return Err(WaveError::InvalidMerkleRoot.into());
+        }
+        Ok(Self {
+            leaf_count,
+            root,
+            frontier,
+        })
+    }
+
+    pub fn capacity() -> u64 {
+        1u64 << FLOW_TREE_DEPTH
+    }
+
+    /// Appends `leaf` and returns its index.
+    pub fn append(&mut self, leaf: [u8; 32]) -> Result<u64, ProgramError> {
+        if self.leaf_count >= Self::capacity() {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let index = self.leaf_count;
+        let zeros = zero_hashes();
+        let mut node = leaf;
+        for (level, (frontier, zero)) in self.frontier.iter_mut().zip(zeros.iter()).enumerate() {
+            if (index >> level) & 1 == 0 {
+                *frontier = node;
+                node = hashv(&[&node, zero]).to_bytes();
+            } else {
+                node = hashv(&[&*frontier, &node]).to_bytes();
+            }
+        }
+
+        self.leaf_count += 1;
+        self.root = node;
+        Ok(index)
+    }
+
+    /// Sibling path, leaf level first, of leaf `index` in the tree holding
+    /// `leaves` in order. Built off-chain from the full leaf list, since the
+    /// tree itself keeps only the frontier.
+    pub fn proof(leaves: &[[u8; 32]], index: u64) -> Option<Vec<[u8; 32]>> {
+        if index >= leaves.len() as u64 {
+            return None;
+        }
+
+        let zeros = zero_hashes();
+        let mut layer = leaves.to_vec();
+        let mut position = index as usize;
+        let mut proof = Vec::with_capacity(FLOW_TREE_DEPTH);
+        for zero in &zeros {
+            proof.push(layer.get(position ^ 1).copied().unwrap_or(*zero));
+            layer = layer
+                .chunks(2)
+                .map(|pair| hashv(&[&pair[0], pair.get(1).unwrap_or(zero)]).to_bytes())
+                .collect();
+            position /= 2;
+        }
+        Some(proof)
+    }
+
+    /// Whether `proof`, as built by `proof`, places `leaf` at `index` under
+    /// `root`
+    pub fn verify_proof(root: &[u8; 32], leaf: &[u8; 32], index: u64, proof: &[[u8; 32]]) -> bool {
+        if proof.len() != FLOW_TREE_DEPTH || index >= Self::capacity() {
+            return false;
+        }
+
+        let mut node = *leaf;
+        for (level, sibling) in proof.iter().enumerate() {
+            node = if (index >> level) & 1 == 0 {
+                hashv(&[&node, sibling]).to_bytes()
+            } else {
+                hashv(&[sibling, &node]).to_bytes()
+            };
+        }
+        &node == root
+    }
+
+    fn compute_root(frontier: &[[u8; 32]; FLOW_TREE_DEPTH], leaf_count: u64) -> [u8; 32] {
+        let zeros = zero_hashes();
+        let mut node = zeros[0];
+        for level in 0..FLOW_TREE_DEPTH {
+            node = if (leaf_count >> level) & 1 == 1 {
+                hashv(&[&frontier[level], &node]).to_bytes()
+            } else {
+                hashv(&[&node, &zeros[level]]).to_bytes()
+            };
+        }
+        node
+    }
+
+    /// Derives the tree PDA for `flow_id`.
+    pub fn find_pda(program_id: &Pubkey, flow_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[FLOW_TREE_SEED, &flow_id.to_le_bytes()], program_id)
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        account_data[..data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        let data = account.try_borrow_data()?;
+        let tree = Self::deserialize(&mut &data[..])?;
+        Ok(tree)
+    }
+}
+
+impl Default for IncrementalMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Root of an all-empty subtree at each level, starting from the leaves.
+fn zero_hashes() -> [[u8; 32]; FLOW_TREE_DEPTH] {
+    let mut zeros = [[0u8; 32]; FLOW_TREE_DEPTH];
+    for level in 1..FLOW_TREE_DEPTH {
+        zeros[level] = hashv(&[&zeros[level - 1], &zeros[level - 1]]).to_bytes();
+    }
+    zeros
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(i: u64) -> [u8; 32] {
+        hashv(&[b"leaf", &i.to_le_bytes()]).to_bytes()
+    }
+
+    /// Root computed level by level from the full leaf list.
+    fn reference_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+        let zeros = zero_hashes();
+        let mut layer = leaves.to_vec();
+        for zero in &zeros {
+            if layer.len() % 2 == 1 {
+                layer.push(*zero);
+            }
+            if layer.is_empty() {
+                layer.push(*zero);
+                layer.push(*zero);
+            }
+            layer = layer
+                .chunks(2)
+                .map(|pair| hashv(&[&pair[0], &pair[1]]).to_bytes())
+                .collect();
+        }
+        layer[0]
+    }
+
+    #[test]
+    fn test_append_matches_reference() {
+        let mut tree = IncrementalMerkleTree::new();
+        assert_eq!(tree.root, reference_root(&[]));
+
+        let leaves: Vec<_> = (0..13).map(leaf).collect();
+        for (i, l) in leaves.iter().enumerate() {
+            assert_eq!(tree.append(*l).unwrap(), i as u64);
+            assert_eq!(tree.root, reference_root(&leaves[..=i]));
+        }
+    }
+
+    #[test]
+    fn test_from_frontier_continues_tree() {
+        let leaves: Vec<_> = (0..11).map(leaf).collect();
+        let mut offchain = IncrementalMerkleTree::new();
+        for l in &leaves[..5] {
+            offchain.append(*l).unwrap();
+        }
+
+        let mut resumed =
+            IncrementalMerkleTree::from_frontier(offchain.frontier, offchain.leaf_count, offchain.root)
+                .unwrap();
+        for l in &leaves[5..] {
+            resumed.append(*l).unwrap();
+        }
+        assert_eq!(resumed.root, reference_root(&leaves));
+    }
+
+    #[test]
+    fn test_from_frontier_rejects_mismatch() {
+        let mut tree = IncrementalMerkleTree::new();
+        for i in 0..5 {
+            tree.append(leaf(i)).unwrap();
+        }
+
+        let wrong_root = IncrementalMerkleTree::from_frontier(tree.frontier, tree.leaf_count, [7u8; 32]);
+        assert_eq!(wrong_root.unwrap_err(), WaveError::InvalidMerkleRoot.into());
+
+        let wrong_count = IncrementalMerkleTree::from_frontier(tree.frontier, 4, tree.root);
+        assert_eq!(wrong_count.unwrap_err(), WaveError::InvalidMerkleRoot.into());
+
+        // 5 = 0b101, so level 0 is part of the state
+        let mut tampered = tree.frontier;
+        tampered[0] = [9u8; 32];
+        let tampered = IncrementalMerkleTree::from_frontier(tampered, tree.leaf_count, tree.root);
+        assert_eq!(tampered.unwrap_err(), WaveError::InvalidMerkleRoot.into());
+    }
+
+    #[test]
+    fn test_proofs_verify_against_root() {
+        let leaves: Vec<_> = (0..7).map(leaf).collect();
+        let mut tree = IncrementalMerkleTree::new();
+        for l in &leaves {
+            tree.append(*l).unwrap();
+        }
+
+        for (index, l) in leaves.iter().enumerate() {
+            let proof = IncrementalMerkleTree::proof(&leaves, index as u64).unwrap();
+            assert!(IncrementalMerkleTree::verify_proof(&tree.root, l, index as u64, &proof));
+            assert!(!IncrementalMerkleTree::verify_proof(&tree.root, &leaf(99), index as u64, &proof));
+            assert!(!IncrementalMerkleTree::verify_proof(&tree.root, l, index as u64 ^ 1, &proof));
+        }
+        assert_eq!(IncrementalMerkleTree::proof(&leaves, 7), None);
+    }
+
+    #[test]
+    fn test_size() {
+        let tree = IncrementalMerkleTree::new();
+        assert_eq!(tree.try_to_vec().unwrap().len(), IncrementalMerkleTree::account_size());
+    }
+}
\ No newline at end of file
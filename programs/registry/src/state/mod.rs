@@ -1,3 +1,21 @@
+pub mod approved_circuits;
+pub mod attestation;
 pub mod flow_registry;
+pub mod flow_tree;
+pub mod notification_manifest;
 pub mod nullifier;
-pub mod proof_log; 
\ No newline at end of file
+pub mod nullifier_namespace;
+pub mod program_config;
+pub mod proof_log_commitment;
+pub mod proof_log; 
+
+pub use approved_circuits::ApprovedCircuits;
+pub use attestation::{has_attestation, Attestation, AttestationPolicy};
+pub use flow_registry::FlowRegistry;
+pub use flow_tree::IncrementalMerkleTree;
+pub use notification_manifest::{NotificationEntry, NotificationManifest};
+pub use nullifier::{Nullifier, NullifierRef};
+pub use nullifier_namespace::NullifierNamespace;
+pub use program_config::ProgramConfig;
+pub use proof_log_commitment::ProofLogCommitment;
+pub use proof_log::{ProofLog, ProofLogRef};
\ No newline at end of file
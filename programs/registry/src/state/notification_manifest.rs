@@ -0,0 +1,175 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    constants::{MAX_NOTIFICATION_ENTRIES, MAX_NOTIFICATION_LABEL_LEN, NOTIFICATION_MANIFEST_SEED},
+    error::WaveError,
+};
+
+/// One off-chain endpoint interested in a flow's events. Only a hash of the
+/// URI is stored; the notification service maps it back.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NotificationEntry {
+    /// Unique within the manifest, at most `MAX_NOTIFICATION_LABEL_LEN` bytes
+    pub label: String,
+    pub uri_hash: [u8; 64],
+    pub enabled: bool,
+}
+
+/// Per-flow list of notification endpoints, read by indexers to route
+/// decoded events. The program only stores it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NotificationManifest {
+    pub flow_id: u64,
+    /// At most `MAX_NOTIFICATION_ENTRIES`, in insertion order
+    pub entries: Vec<NotificationEntry>,
+}
+
+impl NotificationManifest {
+    /// Largest encoding of one entry: a full-length label
+    pub const ENTRY_SIZE: usize = 4 + MAX_NOTIFICATION_LABEL_LEN + 64 + 1;
+    pub const SIZE: usize = 8 + 4 + MAX_NOTIFICATION_ENTRIES * Self::ENTRY_SIZE;
+
+    /// Bytes to allocate for a manifest account: the encoding with every
+    /// entry present and at full label length.
+    pub const fn account_size() -> usize {
+        Self::SIZE
+    }
+
+    pub fn new(flow_id: u64) -> Self {
+        Self {
+            flow_id,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn validate_label(label: &str) -> Result<(), WaveError> {
+        if label.is_empty() || label.len() > MAX_NOTIFICATION_LABEL_LEN {
+            return Err(WaveError::InvalidNotificationLabel);
+        }
+        Ok(())
+    }
+
+    /// Adds an entry under `label`, or replaces the one already there.
+    pub fn set_entry(&mut self, label: String, uri_hash: [u8; 64], enabled: bool) -> Result<(), WaveError> {
+        Self::validate_label(&label)?;
+        let entry = NotificationEntry { label, uri_hash, enabled };
+        match self.entries.iter().position(|existing| existing.label == entry.label) {
+            Some(index) => self.entries[index] = entry,
+            None if self.entries.len() >= MAX_NOTIFICATION_ENTRIES => {
+                return Err(WaveError::NotificationManifestFull)
+            }
+            None => self.entries.push(entry),
+        }
+        Ok(())
+    }
+
+    /// Removes the entry under `label`.
+    pub fn clear_entry(&mut self, label: &str) -> Result<NotificationEntry, WaveError> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.label == label)
+            .ok_or(WaveError::NotificationEntryNotFound)?;
+        Ok(self.entries.remove(index))
+    }
+
+    /// Entries that should currently receive the flow's events
+    pub fn enabled_entries(&self) -> impl Iterator<Item = &NotificationEntry> {
+        self.entries.iter().filter(|entry| entry.enabled)
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        account_data[..data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        // Clearing an entry shortens the encoding, so ignore trailing bytes
+        let data = account.try_borrow_data()?;
+        let manifest = Self::deserialize(&mut &data[..])?;
+        Ok(manifest)
+    }
+
+    /// Derives the notification manifest PDA of `flow_id`.
+    pub fn find_pda(program_id: &Pubkey, flow_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[NOTIFICATION_MANIFEST_SEED, &flow_id.to_le_bytes()], program_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::test_data::*;
+
+    fn uri_hash(byte: u8) -> [u8; 64] {
+        [byte; 64]
+    }
+
+    #[test]
+    fn test_entry_crud() {
+        let mut manifest = NotificationManifest::new(FLOW_ID_1);
+        manifest.set_entry("ops".to_string(), uri_hash(1), true).unwrap();
+        manifest.set_entry("audit".to_string(), uri_hash(2), false).unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+
+        // Setting an existing label updates it in place
+        manifest.set_entry("ops".to_string(), uri_hash(3), false).unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!(manifest.entries[0].uri_hash, uri_hash(3));
+        assert!(!manifest.entries[0].enabled);
+
+        let cleared = manifest.clear_entry("ops").unwrap();
+        assert_eq!(cleared.label, "ops");
+        assert_eq!(manifest.entries.len(), 1);
+        assert!(matches!(
+            manifest.clear_entry("ops"),
+            Err(WaveError::NotificationEntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_entry_bounds() {
+        let mut manifest = NotificationManifest::new(FLOW_ID_1);
+        assert!(matches!(
+            manifest.set_entry(String::new(), uri_hash(1), true),
+            Err(WaveError::InvalidNotificationLabel)
+        ));
+        assert!(matches!(
+            manifest.set_entry("x".repeat(MAX_NOTIFICATION_LABEL_LEN + 1), uri_hash(1), true),
+            Err(WaveError::InvalidNotificationLabel)
+        ));
+
+        for i in 0..MAX_NOTIFICATION_ENTRIES {
+            let label = format!("{:0>width$}", i, width = MAX_NOTIFICATION_LABEL_LEN);
+            manifest.set_entry(label, uri_hash(i as u8), true).unwrap();
+        }
+        assert!(matches!(
+            manifest.set_entry("one-more".to_string(), uri_hash(9), true),
+            Err(WaveError::NotificationManifestFull)
+        ));
+
+        // A full manifest with full-length labels is the largest encoding
+        assert_eq!(manifest.try_to_vec().unwrap().len(), NotificationManifest::account_size());
+    }
+
+    #[test]
+    fn test_enabled_entries_route_events() {
+        let mut manifest = NotificationManifest::new(FLOW_ID_1);
+        manifest.set_entry("ops".to_string(), uri_hash(1), true).unwrap();
+        manifest.set_entry("audit".to_string(), uri_hash(2), false).unwrap();
+        manifest.set_entry("alerts".to_string(), uri_hash(3), true).unwrap();
+
+        let routes: Vec<&str> = manifest.enabled_entries().map(|entry| entry.label.as_str()).collect();
+        assert_eq!(routes, vec!["ops", "alerts"]);
+    }
+}
\ No newline at end of file
@@ -1,10 +1,17 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::AccountInfo,
+    hash::hashv,
     program_error::ProgramError,
+    pubkey::Pubkey,
 };
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+use crate::{
+    constants::{NAMESPACE_NULLIFIER_SEED, NULLIFIER_SEED},
+    layout::nullifier::{FLOW_ID, HASH, PROOF_LOG, TIMESTAMP, TRIGGERED_AT_TAG, VERSION},
+};
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct Nullifier {
     /// The nullifier hash
     pub hash: [u8; 32],
@@ -12,60 +19,198 @@ pub struct Nullifier {
     pub timestamp: i64,
     /// The flow ID this nullifier was used with
     pub flow_id: u64,
+    /// The proof log written alongside this nullifier; the default key for
+    /// imported and legacy records
+    pub proof_log: Pubkey,
+    /// Layout version; 0 for accounts written before `proof_log` existed
+    pub version: u8,
+    /// When TriggerFlow consumed this nullifier, if it has
+    pub triggered_at: Option<i64>,
+    /// When the nullifier goes stale, for flows with rolling nullifier sets;
+    /// after this ValidateProof may record it again
+    pub expires_at: Option<i64>,
 }
 
 impl Nullifier {
-    pub const SIZE: usize = 32 + 8 + 8;
+    pub const VERSION: u8 = 1;
+    /// Size of accounts written before the version 1 fields were added
+    pub const LEGACY_SIZE: usize = 32 + 8 + 8;
+    pub const SIZE: usize = Self::LEGACY_SIZE + 32 + 1 + 9 + 9;
 
-    pub fn new(hash: [u8; 32], timestamp: i64, flow_id: u64) -> Self {
+    /// Bytes to allocate for a nullifier account, with room for
+    /// `triggered_at` and `expires_at`.
+    pub const fn account_size() -> usize {
+        Self::SIZE
+    }
+
+    pub fn new(hash: [u8; 32], timestamp: i64, flow_id: u64, proof_log: Pubkey) -> Self {
         Self {
             hash,
             timestamp,
             flow_id,
+            proof_log,
+            version: Self::VERSION,
+            triggered_at: None,
+            expires_at: None,
         }
     }
 
+    /// Returns true once `expires_at` has passed
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
     pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
         let data = self.try_to_vec()?;
         let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
         account_data[..data.len()].copy_from_slice(&data);
         Ok(())
     }
 
     pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
         let data = account.try_borrow_data()?;
-        let nullifier = Self::try_from_slice(&data)?;
+        if data.len() >= Self::LEGACY_SIZE && data.len() < Self::SIZE {
+            // Legacy accounts end after `flow_id`; reading them zero-filled
+            // yields version 0 with no proof log, no trigger and no expiry.
+            // Version 1 accounts sized before `expires_at` read as never
+            // expiring
+            let mut padded = data.to_vec();
+            padded.resize(Self::SIZE, 0);
+            return Ok(Self::deserialize(&mut &padded[..])?);
+        }
+        let nullifier = Self::deserialize(&mut &data[..])?;
         Ok(nullifier)
     }
+
+    /// Derives the nullifier PDA for `hash` within flow `flow_id`, for
+    /// flows without a shared nullifier namespace.
+    pub fn find_pda(program_id: &Pubkey, flow_id: u64, hash: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[NULLIFIER_SEED, &flow_id.to_le_bytes(), hash], program_id)
+    }
+
+    /// Derives the nullifier PDA for `hash` within shared `namespace`. Its
+    /// own seed prefix keeps namespace IDs from aliasing flow IDs.
+    pub fn find_namespace_pda(program_id: &Pubkey, namespace: u64, hash: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[NAMESPACE_NULLIFIER_SEED, &namespace.to_le_bytes(), hash], program_id)
+    }
+
+    /// Returns `sha256(hash || scope)`, which flows in commitment mode seed
+    /// the PDA with and record in place of the raw nullifier.
+    pub fn commitment(hash: &[u8; 32], scope: u64) -> [u8; 32] {
+        hashv(&[hash, &scope.to_le_bytes()]).to_bytes()
+    }
+
+    /// Derives the nullifier PDA used before nullifiers were scoped, seeded
+    /// by the hash alone. Only `MigrateNullifier` and ValidateProof's
+    /// migration-window check still look at these.
+    pub fn find_legacy_pda(program_id: &Pubkey, hash: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[NULLIFIER_SEED, hash], program_id)
+    }
+
+    /// Returns true if the account already holds a recorded nullifier. An
+    /// all-zero hash reads as unrecorded, which is why the all-zero
+    /// nullifier is refused wherever one is recorded.
+    pub fn is_recorded(account: &AccountInfo) -> Result<bool, ProgramError> {
+        let data = account.try_borrow_data()?;
+        Ok(data.len() >= 32 && data[..32] != [0u8; 32])
+    }
+
+    /// Returns true if the account holds a recorded nullifier that has not
+    /// expired by `now`. An expired one is stale and can be recorded over.
+    pub fn is_spent(account: &AccountInfo, now: i64) -> Result<bool, ProgramError> {
+        Ok(Self::is_recorded(account)? && !Self::load(account)?.is_expired(now))
+    }
 }
 
-#[cfg(test)]
-pub struct NullifierSet {
-    pub nullifiers: Vec<Nullifier>,
+/// Borrowed view of encoded `Nullifier` data; see `ProofLogRef`. Short
+/// (legacy) data reads the way `load` reads it, as if zero-filled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NullifierRef<'a> {
+    data: &'a [u8],
 }
 
-#[cfg(test)]
-impl NullifierSet {
-    pub fn new() -> Self {
-        Self {
-            nullifiers: Vec::new(),
+impl<'a> NullifierRef<'a> {
+    /// Checks that `data` holds at least a legacy nullifier and, for
+    /// version 1 data, valid `triggered_at` and `expires_at` tags, so the
+    /// accessors below never fail.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ProgramError> {
+        if data.len() < Nullifier::LEGACY_SIZE {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let view = Self { data };
+        if view.read::<1>(TRIGGERED_AT_TAG)[0] > 1 || view.read::<1>(view.expires_at_tag())[0] > 1 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(view)
+    }
+
+    /// Copies `N` bytes at `offset`, zero-filling past the end of the data
+    fn read<const N: usize>(&self, offset: usize) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        if let Some(available) = self.data.get(offset..) {
+            let len = available.len().min(N);
+            bytes[..len].copy_from_slice(&available[..len]);
         }
+        bytes
+    }
+
+    pub fn hash(&self) -> &'a [u8; 32] {
+        self.data[HASH..HASH + 32].try_into().unwrap()
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        i64::from_le_bytes(self.data[TIMESTAMP..TIMESTAMP + 8].try_into().unwrap())
+    }
+
+    pub fn flow_id(&self) -> u64 {
+        u64::from_le_bytes(self.data[FLOW_ID..FLOW_ID + 8].try_into().unwrap())
+    }
+
+    pub fn proof_log(&self) -> Pubkey {
+        Pubkey::new_from_array(self.read(PROOF_LOG))
+    }
+
+    pub fn version(&self) -> u8 {
+        self.read::<1>(VERSION)[0]
     }
 
-    pub fn add(&mut self, nullifier: Nullifier) {
-        self.nullifiers.push(nullifier);
+    pub fn triggered_at(&self) -> Option<i64> {
+        match self.read::<1>(TRIGGERED_AT_TAG)[0] {
+            0 => None,
+            _ => Some(i64::from_le_bytes(self.read(TRIGGERED_AT_TAG + 1))),
+        }
     }
 
-    pub fn exists(&self, hash: &[u8; 32]) -> bool {
-        self.nullifiers.iter().any(|n| n.hash == *hash)
+    /// `expires_at` follows `triggered_at`, so its offset depends on
+    /// whether the trigger is set
+    fn expires_at_tag(&self) -> usize {
+        match self.read::<1>(TRIGGERED_AT_TAG)[0] {
+            0 => TRIGGERED_AT_TAG + 1,
+            _ => TRIGGERED_AT_TAG + 9,
+        }
     }
 
-    pub fn get(&self, hash: &[u8; 32]) -> Option<&Nullifier> {
-        self.nullifiers.iter().find(|n| n.hash == *hash)
+    pub fn expires_at(&self) -> Option<i64> {
+        let tag = self.expires_at_tag();
+        match self.read::<1>(tag)[0] {
+            0 => None,
+            _ => Some(i64::from_le_bytes(self.read(tag + 1))),
+        }
     }
 
-    pub fn get_by_flow(&self, flow_id: u64) -> Vec<&Nullifier> {
-        self.nullifiers.iter().filter(|n| n.flow_id == flow_id).collect()
+    pub fn to_nullifier(&self) -> Nullifier {
+        Nullifier {
+            hash: *self.hash(),
+            timestamp: self.timestamp(),
+            flow_id: self.flow_id(),
+            proof_log: self.proof_log(),
+            version: self.version(),
+            triggered_at: self.triggered_at(),
+            expires_at: self.expires_at(),
+        }
     }
 }
 
@@ -76,55 +221,164 @@ mod tests {
 
     #[test]
     fn test_nullifier() {
+        let proof_log = Pubkey::new_unique();
         let nullifier = Nullifier::new(
             NULLIFIER_1,
             TIMESTAMP_1,
             FLOW_ID_1,
+            proof_log,
         );
 
         assert_eq!(nullifier.hash, NULLIFIER_1);
         assert_eq!(nullifier.timestamp, TIMESTAMP_1);
         assert_eq!(nullifier.flow_id, FLOW_ID_1);
+        assert_eq!(nullifier.proof_log, proof_log);
+        assert_eq!(nullifier.version, Nullifier::VERSION);
+        assert_eq!(nullifier.triggered_at, None);
+        assert_eq!(nullifier.expires_at, None);
+
+        assert!(nullifier.try_to_vec().unwrap().len() <= Nullifier::account_size());
+        let triggered = Nullifier {
+            triggered_at: Some(TIMESTAMP_2),
+            expires_at: Some(TIMESTAMP_3),
+            ..nullifier
+        };
+        assert_eq!(triggered.try_to_vec().unwrap().len(), Nullifier::account_size());
     }
 
     #[test]
-    fn test_nullifier_set() {
-        let mut set = NullifierSet::new();
-        
-        let nullifier1 = Nullifier::new(
-            NULLIFIER_1,
-            TIMESTAMP_1,
-            FLOW_ID_1,
-        );
-        set.add(nullifier1);
+    fn test_expiry() {
+        let mut nullifier = Nullifier::new(NULLIFIER_1, TIMESTAMP_1, FLOW_ID_1, Pubkey::new_unique());
+        assert!(!nullifier.is_expired(i64::MAX));
 
-        let nullifier2 = Nullifier::new(
-            NULLIFIER_2,
-            TIMESTAMP_2,
-            FLOW_ID_1,
-        );
-        set.add(nullifier2);
+        nullifier.expires_at = Some(TIMESTAMP_1 + 60);
+        assert!(!nullifier.is_expired(TIMESTAMP_1 + 59));
+        assert!(nullifier.is_expired(TIMESTAMP_1 + 60));
 
-        let nullifier3 = Nullifier::new(
-            NULLIFIER_3,
-            TIMESTAMP_3,
-            FLOW_ID_2,
+        let mut data = nullifier.try_to_vec().unwrap();
+        data.resize(Nullifier::account_size(), 0);
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        assert!(Nullifier::is_spent(&account, TIMESTAMP_1 + 59).unwrap());
+        assert!(!Nullifier::is_spent(&account, TIMESTAMP_1 + 60).unwrap());
+    }
+
+    #[test]
+    fn test_load_legacy_account() {
+        let mut data = NULLIFIER_1.to_vec();
+        data.extend_from_slice(&TIMESTAMP_1.to_le_bytes());
+        data.extend_from_slice(&FLOW_ID_1.to_le_bytes());
+        assert_eq!(data.len(), Nullifier::LEGACY_SIZE);
+
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut data, &owner, false, 0,
         );
-        set.add(nullifier3);
 
-        assert!(set.exists(&NULLIFIER_1));
-        assert!(!set.exists(&[0u8; 32]));
+        assert!(Nullifier::is_recorded(&account).unwrap());
+        let legacy = Nullifier::load(&account).unwrap();
+        assert_eq!(legacy.hash, NULLIFIER_1);
+        assert_eq!(legacy.timestamp, TIMESTAMP_1);
+        assert_eq!(legacy.flow_id, FLOW_ID_1);
+        assert_eq!(legacy.proof_log, Pubkey::default());
+        assert_eq!(legacy.version, 0);
+        assert_eq!(legacy.triggered_at, None);
+
+        // Too small to hold the new fields until reallocated
+        assert_eq!(legacy.save(&account), Err(ProgramError::AccountDataTooSmall));
+    }
 
-        let found = set.get(&NULLIFIER_1).unwrap();
-        assert_eq!(found.flow_id, FLOW_ID_1);
+    #[test]
+    fn test_nullifier_pda_scoping() {
+        let program_id = Pubkey::new_unique();
 
-        let flow1_nullifiers = set.get_by_flow(FLOW_ID_1);
-        assert_eq!(flow1_nullifiers.len(), 2);
-        assert_eq!(flow1_nullifiers[0].flow_id, FLOW_ID_1);
-        assert_eq!(flow1_nullifiers[1].flow_id, FLOW_ID_1);
+        let (flow1_pda, _) = Nullifier::find_pda(&program_id, FLOW_ID_1, &NULLIFIER_1);
+        let (flow2_pda, _) = Nullifier::find_pda(&program_id, FLOW_ID_2, &NULLIFIER_1);
+        assert_ne!(flow1_pda, flow2_pda);
+
+        let (again, _) = Nullifier::find_pda(&program_id, FLOW_ID_1, &NULLIFIER_1);
+        assert_eq!(flow1_pda, again);
+
+        let (legacy_pda, _) = Nullifier::find_legacy_pda(&program_id, &NULLIFIER_1);
+        assert_ne!(legacy_pda, flow1_pda);
+        assert_ne!(legacy_pda, flow2_pda);
+
+        // A namespace numbered like a flow still has its own nullifiers
+        let (namespace_pda, _) = Nullifier::find_namespace_pda(&program_id, FLOW_ID_1, &NULLIFIER_1);
+        assert_ne!(namespace_pda, flow1_pda);
+    }
+
+    #[test]
+    fn test_commitment_pda() {
+        let program_id = Pubkey::new_unique();
+
+        let commitment = Nullifier::commitment(&NULLIFIER_1, FLOW_ID_1);
+        assert_ne!(commitment, NULLIFIER_1);
+        assert_eq!(commitment, Nullifier::commitment(&NULLIFIER_1, FLOW_ID_1));
+        assert_ne!(commitment, Nullifier::commitment(&NULLIFIER_1, FLOW_ID_2));
+
+        let (raw_pda, _) = Nullifier::find_pda(&program_id, FLOW_ID_1, &NULLIFIER_1);
+        let (commitment_pda, _) = Nullifier::find_pda(&program_id, FLOW_ID_1, &commitment);
+        assert_ne!(raw_pda, commitment_pda);
+    }
+
+    #[test]
+    fn test_view_matches_borsh() {
+        let nullifier = Nullifier::new(NULLIFIER_1, TIMESTAMP_1, FLOW_ID_1, Pubkey::new_unique());
+        for (triggered_at, expires_at) in [
+            (None, None),
+            (Some(-TIMESTAMP_2), None),
+            (None, Some(TIMESTAMP_3)),
+            (Some(-TIMESTAMP_2), Some(TIMESTAMP_3)),
+        ] {
+            let record = Nullifier { triggered_at, expires_at, ..nullifier.clone() };
+            let mut data = record.try_to_vec().unwrap();
+            data.resize(Nullifier::account_size(), 0);
+
+            let view = NullifierRef::parse(&data).unwrap();
+            let decoded = Nullifier::deserialize(&mut &data[..]).unwrap();
+            assert_eq!(view.hash(), &decoded.hash);
+            assert_eq!(view.timestamp(), decoded.timestamp);
+            assert_eq!(view.flow_id(), decoded.flow_id);
+            assert_eq!(view.proof_log(), decoded.proof_log);
+            assert_eq!(view.version(), decoded.version);
+            assert_eq!(view.triggered_at(), decoded.triggered_at);
+            assert_eq!(view.expires_at(), decoded.expires_at);
+            assert_eq!(view.to_nullifier(), decoded);
+        }
+
+        // Short data reads as `load` pads it
+        let encoded = nullifier.try_to_vec().unwrap();
+        let legacy = &encoded[..Nullifier::LEGACY_SIZE];
+        let view = NullifierRef::parse(legacy).unwrap();
+        assert_eq!(view.version(), 0);
+        assert_eq!(view.proof_log(), Pubkey::default());
+        assert_eq!(view.triggered_at(), None);
+        assert_eq!(view.expires_at(), None);
+        for len in Nullifier::LEGACY_SIZE..encoded.len() {
+            let mut padded = encoded[..len].to_vec();
+            padded.resize(Nullifier::SIZE, 0);
+            let decoded = Nullifier::deserialize(&mut &padded[..]).unwrap();
+            assert_eq!(NullifierRef::parse(&encoded[..len]).unwrap().to_nullifier(), decoded);
+        }
+    }
+
+    #[test]
+    fn test_view_rejects_bad_data() {
+        let mut data = Nullifier::new(NULLIFIER_1, TIMESTAMP_1, FLOW_ID_1, Pubkey::new_unique())
+            .try_to_vec()
+            .unwrap();
+        data.resize(Nullifier::account_size(), 0);
 
-        let flow2_nullifiers = set.get_by_flow(FLOW_ID_2);
-        assert_eq!(flow2_nullifiers.len(), 1);
-        assert_eq!(flow2_nullifiers[0].flow_id, FLOW_ID_2);
+        assert!(NullifierRef::parse(&data[..Nullifier::LEGACY_SIZE - 1]).is_err());
+        data[TRIGGERED_AT_TAG + 1] = 2;
+        assert_eq!(NullifierRef::parse(&data), Err(ProgramError::InvalidAccountData));
+        data[TRIGGERED_AT_TAG + 1] = 0;
+        data[TRIGGERED_AT_TAG] = 2;
+        assert_eq!(NullifierRef::parse(&data), Err(ProgramError::InvalidAccountData));
     }
 } 
\ No newline at end of file
@@ -0,0 +1,66 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::constants::NULLIFIER_NAMESPACE_SEED;
+
+/// Owner of a shared nullifier namespace, at the `NULLIFIER_NAMESPACE_SEED`
+/// PDA of `namespace`. A flow can only join the namespace with `authority`
+/// co-signing, since members can spend each other's nullifiers.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NullifierNamespace {
+    /// The signer that admits flows: whoever created the namespace
+    pub authority: Pubkey,
+    pub namespace: u64,
+}
+
+impl NullifierNamespace {
+    pub const SIZE: usize = 32 + 8;
+
+    pub fn new(authority: Pubkey, namespace: u64) -> Self {
+        Self { authority, namespace }
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        account_data[..data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        let data = account.try_borrow_data()?;
+        let namespace = Self::deserialize(&mut &data[..])?;
+        Ok(namespace)
+    }
+
+    /// Derives the PDA recording who owns `namespace`.
+    pub fn find_pda(program_id: &Pubkey, namespace: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[NULLIFIER_NAMESPACE_SEED, &namespace.to_le_bytes()], program_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let namespace = NullifierNamespace::new(Pubkey::new_unique(), 7_000);
+
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0u8; NullifierNamespace::SIZE];
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        namespace.save(&account).unwrap();
+        assert_eq!(NullifierNamespace::load(&account).unwrap(), namespace);
+        assert_eq!(namespace.try_to_vec().unwrap().len(), NullifierNamespace::SIZE);
+    }
+}
\ No newline at end of file
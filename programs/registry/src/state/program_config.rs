@@ -0,0 +1,91 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::constants::PROGRAM_CONFIG_SEED;
+
+/// Program-wide settings, at the `PROGRAM_CONFIG_SEED` PDA. Until
+/// SetProgramConfig creates that account every setting is at its default.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct ProgramConfig {
+    /// The only signer that can change the config: whoever created it
+    pub admin: Pubkey,
+    /// Lamports InitRegistry moves from the flow authority to the treasury;
+    /// 0 charges nothing
+    pub registration_fee: u64,
+}
+
+impl ProgramConfig {
+    pub const SIZE: usize = 32 + 8;
+
+    pub fn new(admin: Pubkey) -> Self {
+        Self {
+            admin,
+            registration_fee: 0,
+        }
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        account_data[..data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        let data = account.try_borrow_data()?;
+        let config = Self::deserialize(&mut &data[..])?;
+        Ok(config)
+    }
+
+    /// The config `account` holds, or the defaults if it hasn't been
+    /// created yet.
+    pub fn load_or_default(account: &AccountInfo) -> Result<Self, ProgramError> {
+        if account.data_is_empty() {
+            return Ok(Self::default());
+        }
+        Self::load(account)
+    }
+
+    /// Derives the program config PDA.
+    pub fn find_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[PROGRAM_CONFIG_SEED], program_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_config_charges_nothing() {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![];
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        assert_eq!(ProgramConfig::load_or_default(&account).unwrap(), ProgramConfig::default());
+        assert_eq!(ProgramConfig::default().registration_fee, 0);
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let mut config = ProgramConfig::new(Pubkey::new_unique());
+        config.registration_fee = 5_000_000;
+
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0u8; ProgramConfig::SIZE];
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        config.save(&account).unwrap();
+        assert_eq!(ProgramConfig::load_or_default(&account).unwrap(), config);
+        assert_eq!(config.try_to_vec().unwrap().len(), ProgramConfig::SIZE);
+    }
+}
\ No newline at end of file
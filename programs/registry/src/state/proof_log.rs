@@ -2,9 +2,17 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::AccountInfo,
     program_error::ProgramError,
+    pubkey::Pubkey,
 };
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+use super::FlowRegistry;
+use crate::{
+    constants::PROOF_LOG_SEED,
+    layout::proof_log::{CIRCUIT_HASH, FLOW_ID, NULLIFIER, PROOF_SYSTEM, PUBLIC_INPUTS_HASH, TIMESTAMP},
+    verifier::ProofSystem,
+};
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct ProofLog {
     /// The nullifier hash
     pub nullifier: [u8; 32],
@@ -14,10 +22,22 @@ pub struct ProofLog {
     pub flow_id: u64,
     /// Public inputs hash
     pub public_inputs_hash: [u8; 32],
+    /// The flow's circuit when the proof was validated; zero for logs
+    /// written before it was recorded
+    pub circuit_hash: [u8; 32],
+    /// The flow's proof system when the proof was validated
+    pub proof_system: ProofSystem,
 }
 
 impl ProofLog {
-    pub const SIZE: usize = 32 + 8 + 8 + 32;
+    /// Size of logs written before `circuit_hash` and `proof_system`
+    pub const LEGACY_SIZE: usize = 32 + 8 + 8 + 32;
+    pub const SIZE: usize = Self::LEGACY_SIZE + 32 + 1;
+
+    /// Bytes to allocate for a proof log account.
+    pub const fn account_size() -> usize {
+        Self::SIZE
+    }
 
     pub fn new(
         nullifier: [u8; 32],
@@ -30,52 +50,128 @@ impl ProofLog {
             timestamp,
             flow_id,
             public_inputs_hash,
+            circuit_hash: [0u8; 32],
+            proof_system: ProofSystem::default(),
+        }
+    }
+
+    /// Logs a proof `registry` accepted, tagged with the circuit and proof
+    /// system that verified it.
+    pub fn validated(
+        registry: &FlowRegistry,
+        nullifier: [u8; 32],
+        timestamp: i64,
+        public_inputs_hash: [u8; 32],
+    ) -> Self {
+        Self {
+            circuit_hash: registry.circuit_hash,
+            proof_system: registry.proof_system,
+            ..Self::new(nullifier, timestamp, registry.flow_id, public_inputs_hash)
         }
     }
 
     pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
         let data = self.try_to_vec()?;
         let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
         account_data[..data.len()].copy_from_slice(&data);
         Ok(())
     }
 
     pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
         let data = account.try_borrow_data()?;
-        let log = Self::try_from_slice(&data)?;
+        if data.len() >= Self::LEGACY_SIZE && data.len() < Self::SIZE {
+            // Legacy logs end after `public_inputs_hash`; every flow was
+            // Groth16 then, which is what the zero fill reads as
+            let mut padded = data.to_vec();
+            padded.resize(Self::SIZE, 0);
+            return Ok(Self::deserialize(&mut &padded[..])?);
+        }
+        let log = Self::deserialize(&mut &data[..])?;
         Ok(log)
     }
+
+    /// Derives the proof log PDA for `nullifier` in flow `flow_id`. Flows
+    /// sharing a nullifier namespace, or seeing the same nullifier, each get
+    /// their own log.
+    pub fn find_pda(program_id: &Pubkey, flow_id: u64, nullifier: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[PROOF_LOG_SEED, &flow_id.to_le_bytes(), nullifier], program_id)
+    }
 }
 
-#[cfg(test)]
-pub struct ProofHistory {
-    pub logs: Vec<ProofLog>,
+/// Borrowed view of encoded `ProofLog` data, for scanners that read many
+/// accounts and only need a few fields. Fields are read in place from the
+/// fixed layout; nothing is allocated until `to_proof_log`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProofLogRef<'a> {
+    data: &'a [u8],
 }
 
-#[cfg(test)]
-impl ProofHistory {
-    pub fn new() -> Self {
-        Self {
-            logs: Vec::new(),
+impl<'a> ProofLogRef<'a> {
+    /// Accounts carry no discriminator, so this only checks that `data`
+    /// holds at least a legacy proof log with a valid proof system tag.
+    /// Trailing bytes are ignored and short data is zero-filled, as in
+    /// `load`.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ProgramError> {
+        if data.len() < ProofLog::LEGACY_SIZE {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let view = Self { data };
+        if ProofSystem::try_from_slice(&view.read::<1>(PROOF_SYSTEM)).is_err() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(view)
+    }
+
+    /// Copies `N` bytes at `offset`, zero-filling past the end of the data
+    fn read<const N: usize>(&self, offset: usize) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        if let Some(available) = self.data.get(offset..) {
+            let len = available.len().min(N);
+            bytes[..len].copy_from_slice(&available[..len]);
         }
+        bytes
     }
 
-    pub fn add_log(&mut self, log: ProofLog) {
-        self.logs.push(log);
+    pub fn nullifier(&self) -> &'a [u8; 32] {
+        self.data[NULLIFIER..NULLIFIER + 32].try_into().unwrap()
     }
 
-    pub fn get_by_flow(&self, flow_id: u64) -> Vec<&ProofLog> {
-        self.logs.iter().filter(|l| l.flow_id == flow_id).collect()
+    pub fn timestamp(&self) -> i64 {
+        i64::from_le_bytes(self.data[TIMESTAMP..TIMESTAMP + 8].try_into().unwrap())
     }
 
-    pub fn get_by_nullifier(&self, nullifier: &[u8; 32]) -> Vec<&ProofLog> {
-        self.logs.iter().filter(|l| l.nullifier == *nullifier).collect()
+    pub fn flow_id(&self) -> u64 {
+        u64::from_le_bytes(self.data[FLOW_ID..FLOW_ID + 8].try_into().unwrap())
     }
 
-    pub fn get_by_timerange(&self, start: i64, end: i64) -> Vec<&ProofLog> {
-        self.logs.iter()
-            .filter(|l| l.timestamp >= start && l.timestamp <= end)
-            .collect()
+    pub fn public_inputs_hash(&self) -> &'a [u8; 32] {
+        self.data[PUBLIC_INPUTS_HASH..PUBLIC_INPUTS_HASH + 32].try_into().unwrap()
+    }
+
+    pub fn circuit_hash(&self) -> [u8; 32] {
+        self.read(CIRCUIT_HASH)
+    }
+
+    pub fn proof_system(&self) -> ProofSystem {
+        // Checked by `parse`
+        ProofSystem::try_from_slice(&self.read::<1>(PROOF_SYSTEM)).unwrap()
+    }
+
+    /// Whether a proof was logged here; like `Nullifier::is_recorded`, an
+    /// all-zero nullifier means the account was only allocated
+    pub fn is_recorded(&self) -> bool {
+        self.nullifier() != &[0u8; 32]
+    }
+
+    pub fn to_proof_log(&self) -> ProofLog {
+        ProofLog {
+            circuit_hash: self.circuit_hash(),
+            proof_system: self.proof_system(),
+            ..ProofLog::new(*self.nullifier(), self.timestamp(), self.flow_id(), *self.public_inputs_hash())
+        }
     }
 }
 
@@ -97,51 +193,73 @@ mod tests {
         assert_eq!(log.timestamp, TIMESTAMP_1);
         assert_eq!(log.flow_id, FLOW_ID_1);
         assert_eq!(log.public_inputs_hash, PUBLIC_INPUTS_1);
+        assert_eq!(log.circuit_hash, [0u8; 32]);
+        assert_eq!(log.proof_system, ProofSystem::Groth16);
+        assert_eq!(log.try_to_vec().unwrap().len(), ProofLog::account_size());
     }
 
     #[test]
-    fn test_proof_history() {
-        let mut history = ProofHistory::new();
-        
-        let log1 = ProofLog::new(
-            NULLIFIER_1,
-            TIMESTAMP_1,
-            FLOW_ID_1,
-            PUBLIC_INPUTS_1,
-        );
-        history.add_log(log1);
+    fn test_load_legacy_account() {
+        let log = ProofLog::new(NULLIFIER_1, TIMESTAMP_1, FLOW_ID_1, PUBLIC_INPUTS_1);
+        let mut data = log.try_to_vec().unwrap();
+        data.truncate(ProofLog::LEGACY_SIZE);
 
-        let log2 = ProofLog::new(
-            NULLIFIER_2,
-            TIMESTAMP_2,
-            FLOW_ID_1,
-            PUBLIC_INPUTS_2,
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(
+            &key, false, true, &mut lamports, &mut data, &owner, false, 0,
         );
-        history.add_log(log2);
 
-        let log3 = ProofLog::new(
-            NULLIFIER_3,
-            TIMESTAMP_3,
-            FLOW_ID_2,
-            PUBLIC_INPUTS_3,
-        );
-        history.add_log(log3);
+        assert_eq!(ProofLog::load(&account).unwrap(), log);
+        let circuit = ProofLog { circuit_hash: CIRCUIT_HASH_1, ..log };
+        assert_eq!(circuit.save(&account), Err(ProgramError::AccountDataTooSmall));
+    }
 
-        let flow1_logs = history.get_by_flow(FLOW_ID_1);
-        assert_eq!(flow1_logs.len(), 2);
-        assert_eq!(flow1_logs[0].flow_id, FLOW_ID_1);
-        assert_eq!(flow1_logs[1].flow_id, FLOW_ID_1);
+    #[test]
+    fn test_view_matches_borsh() {
+        for (nullifier, timestamp, flow_id, circuit_hash, proof_system) in [
+            (NULLIFIER_1, TIMESTAMP_1, FLOW_ID_1, CIRCUIT_HASH_1, ProofSystem::Groth16),
+            (NULLIFIER_2, -TIMESTAMP_2, u64::MAX, CIRCUIT_HASH_2, ProofSystem::Groth16),
+        ] {
+            let log = ProofLog {
+                circuit_hash,
+                proof_system,
+                ..ProofLog::new(nullifier, timestamp, flow_id, PUBLIC_INPUTS_1)
+            };
+            let mut data = log.try_to_vec().unwrap();
+            // Account data may run past the encoding
+            data.extend_from_slice(&[0xff; 8]);
 
-        let nullifier1_logs = history.get_by_nullifier(&NULLIFIER_1);
-        assert_eq!(nullifier1_logs.len(), 1);
-        assert_eq!(nullifier1_logs[0].nullifier, NULLIFIER_1);
+            let view = ProofLogRef::parse(&data).unwrap();
+            let decoded = ProofLog::deserialize(&mut &data[..]).unwrap();
+            assert_eq!(view.nullifier(), &decoded.nullifier);
+            assert_eq!(view.timestamp(), decoded.timestamp);
+            assert_eq!(view.flow_id(), decoded.flow_id);
+            assert_eq!(view.public_inputs_hash(), &decoded.public_inputs_hash);
+            assert_eq!(view.circuit_hash(), decoded.circuit_hash);
+            assert_eq!(view.proof_system(), decoded.proof_system);
+            assert_eq!(view.to_proof_log(), decoded);
+            assert!(view.is_recorded());
+        }
 
-        let timerange_logs = history.get_by_timerange(
-            TIMESTAMP_1,
-            TIMESTAMP_2,
+        // An allocated but unwritten log
+        assert!(!ProofLogRef::parse(&[0u8; ProofLog::SIZE]).unwrap().is_recorded());
+
+        let mut data = ProofLog::new(NULLIFIER_1, TIMESTAMP_1, FLOW_ID_1, PUBLIC_INPUTS_1)
+            .try_to_vec()
+            .unwrap();
+        assert_eq!(
+            ProofLogRef::parse(&data[..ProofLog::LEGACY_SIZE - 1]),
+            Err(ProgramError::InvalidAccountData)
         );
-        assert_eq!(timerange_logs.len(), 2);
-        assert!(timerange_logs.iter().all(|l| l.timestamp >= TIMESTAMP_1
-            && l.timestamp <= TIMESTAMP_2));
+
+        // Legacy logs read as `load` pads them
+        let legacy = ProofLogRef::parse(&data[..ProofLog::LEGACY_SIZE]).unwrap();
+        assert_eq!(legacy.circuit_hash(), [0u8; 32]);
+        assert_eq!(legacy.proof_system(), ProofSystem::Groth16);
+
+        data[PROOF_SYSTEM] = 3;
+        assert_eq!(ProofLogRef::parse(&data), Err(ProgramError::InvalidAccountData));
     }
 } 
\ No newline at end of file
@@ -0,0 +1,115 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    hash::hashv,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    constants::PROOF_LOG_COMMITMENT_SEED,
+    state::{IncrementalMerkleTree, ProofLog},
+};
+
+/// Root of a flow-tree-shaped Merkle tree over proof logs that
+/// CompactProofLogs closed, in the order they were listed. Stands in for
+/// those logs: VerifyProofLogInclusion checks one against `root`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ProofLogCommitment {
+    pub flow_id: u64,
+    pub root: [u8; 32],
+    /// Number of logs committed to
+    pub leaf_count: u64,
+    pub compacted_at: i64,
+}
+
+impl ProofLogCommitment {
+    pub const SIZE: usize = 8 + 32 + 8 + 8;
+
+    /// Bytes to allocate for a proof log commitment account.
+    pub const fn account_size() -> usize {
+        Self::SIZE
+    }
+
+    /// Commits to `logs` in order.
+    pub fn new(flow_id: u64, logs: &[ProofLog], compacted_at: i64) -> Result<Self, ProgramError> {
+        let mut tree = IncrementalMerkleTree::new();
+        for log in logs {
+            tree.append(Self::leaf(log))?;
+        }
+        Ok(Self {
+            flow_id,
+            root: tree.root,
+            leaf_count: logs.len() as u64,
+            compacted_at,
+        })
+    }
+
+    /// A log's leaf: the hash of its nullifier, public inputs hash and
+    /// timestamp. The flow is implied by the commitment.
+    pub fn leaf(log: &ProofLog) -> [u8; 32] {
+        hashv(&[&log.nullifier, &log.public_inputs_hash, &log.timestamp.to_le_bytes()]).to_bytes()
+    }
+
+    /// Whether `log` was committed to at `index`; `proof` comes from
+    /// `IncrementalMerkleTree::proof` over the committed leaves.
+    pub fn includes(&self, log: &ProofLog, index: u64, proof: &[[u8; 32]]) -> bool {
+        index < self.leaf_count
+            && IncrementalMerkleTree::verify_proof(&self.root, &Self::leaf(log), index, proof)
+    }
+
+    /// Derives the commitment PDA. Seeding by root gives each compaction of
+    /// a flow its own account.
+    pub fn find_pda(program_id: &Pubkey, flow_id: u64, root: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[PROOF_LOG_COMMITMENT_SEED, &flow_id.to_le_bytes(), root],
+            program_id,
+        )
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        let data = self.try_to_vec()?;
+        let mut account_data = account.try_borrow_mut_data()?;
+        if account_data.len() < data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        account_data[..data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        let data = account.try_borrow_data()?;
+        let commitment = Self::deserialize(&mut &data[..])?;
+        Ok(commitment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::test_data::*;
+
+    #[test]
+    fn test_includes_committed_logs_only() {
+        let logs: Vec<_> = (0..5u8)
+            .map(|i| ProofLog::new([i + 1; 32], TIMESTAMP_1 + i as i64, FLOW_ID_1, PUBLIC_INPUTS_1))
+            .collect();
+        let commitment = ProofLogCommitment::new(FLOW_ID_1, &logs, TIMESTAMP_2).unwrap();
+        assert_eq!(commitment.try_to_vec().unwrap().len(), ProofLogCommitment::account_size());
+
+        let leaves: Vec<_> = logs.iter().map(ProofLogCommitment::leaf).collect();
+        for (index, log) in logs.iter().enumerate() {
+            let proof = IncrementalMerkleTree::proof(&leaves, index as u64).unwrap();
+            assert!(commitment.includes(log, index as u64, &proof));
+        }
+
+        // Any field of the log changes its leaf
+        let proof = IncrementalMerkleTree::proof(&leaves, 2).unwrap();
+        let mut altered = logs[2].clone();
+        altered.timestamp += 1;
+        assert!(!commitment.includes(&altered, 2, &proof));
+        altered = logs[2].clone();
+        altered.public_inputs_hash = PUBLIC_INPUTS_2;
+        assert!(!commitment.includes(&altered, 2, &proof));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,482 @@
+//! Groth16 proof verification over BN254 with the alt_bn128 syscalls.
+//! Flows name their proof system; Groth16 is the only one verifiable yet.
+//!
+//! Verifying keys use the syscalls' uncompressed big-endian layout: a G1
+//! point is `x | y` and a G2 point is `x.c1 | x.c0 | y.c1 | y.c0`, 32 bytes
+//! each. Proofs arrive as provers emit them, in arkworks' canonical
+//! serialization, and are converted.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    alt_bn128::{
+        compression::prelude::{alt_bn128_g1_decompress, alt_bn128_g2_decompress},
+        prelude::{alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing},
+    },
+    hash::hash,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    constants::{MAX_VERIFYING_KEY_INPUTS, VERIFYING_KEY_SEED},
+    error::WaveError,
+    state::FlowRegistry,
+};
+
+/// Size of an arkworks-serialized proof, A (G1), B (G2) and C (G1), with
+/// uncompressed points
+pub const PROOF_SIZE: usize = 64 + 128 + 64;
+/// Size of an arkworks-serialized proof with compressed points
+pub const COMPRESSED_PROOF_SIZE: usize = 32 + 64 + 32;
+
+// alt_bn128 syscall costs in the default compute budget, as of
+// solana-program 1.17
+const ALT_BN128_ADDITION_COST: u64 = 334;
+const ALT_BN128_MULTIPLICATION_COST: u64 = 3_840;
+const ALT_BN128_PAIRING_FIRST_PAIR_COST: u64 = 36_364;
+const ALT_BN128_PAIRING_OTHER_PAIR_COST: u64 = 12_121;
+const ALT_BN128_G1_DECOMPRESS_COST: u64 = 398;
+const ALT_BN128_G2_DECOMPRESS_COST: u64 = 13_610;
+
+/// BN254 base field modulus, big-endian
+const BASE_FIELD_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d, 0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47,
+];
+
+/// BN254 scalar field order, big-endian; public inputs must be below it
+const SCALAR_FIELD_ORDER: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91, 0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01,
+];
+
+/// How a flow's proofs are produced and checked. Groth16 is variant 0, so
+/// registries written before flows named a system read it from their
+/// zeroed slack. Other systems get a variant once they have a verifier.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProofSystem {
+    #[default]
+    Groth16,
+}
+
+/// A circuit's Groth16 verifying key. A flow's `circuit_hash` is the
+/// SHA-256 of its Borsh encoding, which InitRegistry stores in the PDA
+/// seeded by that hash, so flows sharing a circuit share the account.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct VerifyingKey {
+    pub alpha_g1: [u8; 64],
+    pub beta_g2: [u8; 128],
+    pub gamma_g2: [u8; 128],
+    pub delta_g2: [u8; 128],
+    /// The constant term's point, then one per public input
+    pub ic: Vec<[u8; 64]>,
+}
+
+impl VerifyingKey {
+    /// Encoded size of a key with no public inputs
+    pub const MIN_SIZE: usize = 64 + 3 * 128 + 4 + 64;
+    /// Encoded size of a key taking `MAX_VERIFYING_KEY_INPUTS` inputs
+    pub const MAX_SIZE: usize = Self::MIN_SIZE + MAX_VERIFYING_KEY_INPUTS * 64;
+
+    pub fn find_pda(program_id: &Pubkey, circuit_hash: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[VERIFYING_KEY_SEED, circuit_hash], program_id)
+    }
+
+    /// Decodes a key, failing with `MalformedVerificationKey` outside the
+    /// size bounds or on anything but an exact encoding
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WaveError> {
+        if !(Self::MIN_SIZE..=Self::MAX_SIZE).contains(&bytes.len()) {
+            return Err(WaveError::MalformedVerificationKey);
+        }
+        Self::try_from_slice(bytes).map_err(|_| WaveError::MalformedVerificationKey)
+    }
+
+    /// Number of 32-byte public inputs a proof for this key takes
+    pub fn input_count(&self) -> usize {
+        self.ic.len().saturating_sub(1)
+    }
+
+    /// SHA-256 of the key's Borsh encoding, as stored in `circuit_hash`
+    pub fn hash(&self) -> Result<[u8; 32], ProgramError> {
+        Ok(hash(&self.try_to_vec()?).to_bytes())
+    }
+
+    /// Loads the key from `account`, which must be the PDA InitRegistry
+    /// stored the circuit `circuit_hash` names in.
+    pub fn load(
+        program_id: &Pubkey,
+        account: &AccountInfo,
+        circuit_hash: &[u8; 32],
+    ) -> Result<Self, ProgramError> {
+        if account.key != &Self::find_pda(program_id, circuit_hash).0 || account.owner != program_id {
+            return Err(WaveError::InvalidVerifyingKey.into());
+        }
+        let key = Self::from_bytes(&account.try_borrow_data()?)?;
+        if &key.hash()? != circuit_hash {
+            return Err(WaveError::InvalidVerifyingKey.into());
+        }
+        Ok(key)
+    }
+}
+
+/// A proof's points in the syscalls' layout
+struct Proof {
+    a: [u8; 64],
+    b: [u8; 128],
+    c: [u8; 64],
+}
+
+impl Proof {
+    /// Decodes arkworks' serialization of a `Proof<Bn254>`, compressed or
+    /// not. Arkworks writes each base field element little-endian and G2
+    /// coordinates as `c0 | c1`, so reversing each element, or each G2
+    /// coordinate whole, gives the syscalls' layout.
+    fn from_arkworks(bytes: &[u8]) -> Result<Self, ProgramError> {
+        let invalid = |_| ProgramError::from(WaveError::InvalidProof);
+        match bytes.len() {
+            PROOF_SIZE => Ok(Self {
+                a: reverse_chunks(&bytes[..64], 32),
+                b: reverse_chunks(&bytes[64..192], 64),
+                c: reverse_chunks(&bytes[192..], 32),
+            }),
+            COMPRESSED_PROOF_SIZE => Ok(Self {
+                a: alt_bn128_g1_decompress(&reverse_chunks::<32>(&bytes[..32], 32)).map_err(invalid)?,
+                b: alt_bn128_g2_decompress(&reverse_chunks::<64>(&bytes[32..96], 64)).map_err(invalid)?,
+                c: alt_bn128_g1_decompress(&reverse_chunks::<32>(&bytes[96..], 32)).map_err(invalid)?,
+            }),
+            _ => Err(WaveError::InvalidProof.into()),
+        }
+    }
+}
+
+/// Copies `bytes` with each `chunk`-byte run reversed
+fn reverse_chunks<const N: usize>(bytes: &[u8], chunk: usize) -> [u8; N] {
+    let mut out = [0u8; N];
+    for (out, bytes) in out.chunks_mut(chunk).zip(bytes.chunks(chunk)) {
+        out.copy_from_slice(bytes);
+        out.reverse();
+    }
+    out
+}
+
+/// Checks `proof`, arkworks-serialized as `Proof::from_arkworks` takes it,
+/// against `key` for `public_inputs`, a run of 32-byte big-endian scalars,
+/// one per input the key takes. Fails with `InvalidProof` for a malformed
+/// proof as well as a false one.
+pub fn verify(key: &VerifyingKey, proof: &[u8], public_inputs: &[u8]) -> Result<(), ProgramError> {
+    if key.ic.is_empty() || public_inputs.len() != key.input_count() * 32 {
+        return Err(WaveError::InvalidProof.into());
+    }
+    let Proof { a, b, c } = Proof::from_arkworks(proof)?;
+
+    // vk_x = ic[0] + sum(input_i * ic[i + 1])
+    let mut vk_x = key.ic[0].to_vec();
+    for (input, point) in public_inputs.chunks(32).zip(&key.ic[1..]) {
+        if input >= &SCALAR_FIELD_ORDER[..] {
+            return Err(WaveError::InvalidProof.into());
+        }
+        let product = alt_bn128_multiplication(&[&point[..], input].concat())
+            .map_err(|_| WaveError::InvalidProof)?;
+        vk_x = alt_bn128_addition(&[&vk_x[..], &product[..]].concat())
+            .map_err(|_| WaveError::InvalidProof)?;
+    }
+
+    // e(-A, B) * e(alpha, beta) * e(vk_x, gamma) * e(C, delta) == 1
+    let pairing_input = [
+        &negate_g1(&a)?[..],
+        &b,
+        &key.alpha_g1,
+        &key.beta_g2,
+        &vk_x,
+        &key.gamma_g2,
+        &c,
+        &key.delta_g2,
+    ]
+    .concat();
+    let result = alt_bn128_pairing(&pairing_input).map_err(|_| WaveError::InvalidProof)?;
+    if result.len() != 32 || result[..31].iter().any(|&b| b != 0) || result[31] != 1 {
+        return Err(WaveError::InvalidProof.into());
+    }
+    Ok(())
+}
+
+/// Compute units `verify` spends in syscalls on `public_inputs_len` bytes
+/// of public inputs: decompressing the proof, priced as if compressed, a
+/// multiplication and an addition per input, then the four-pair pairing.
+pub fn verification_cost(public_inputs_len: usize) -> u64 {
+    let inputs = (public_inputs_len / 32) as u64;
+    2 * ALT_BN128_G1_DECOMPRESS_COST
+        + ALT_BN128_G2_DECOMPRESS_COST
+        + inputs * (ALT_BN128_MULTIPLICATION_COST + ALT_BN128_ADDITION_COST)
+        + ALT_BN128_PAIRING_FIRST_PAIR_COST
+        + 3 * ALT_BN128_PAIRING_OTHER_PAIR_COST
+}
+
+/// Compute units to require before verifying a proof for `registry`: its
+/// `max_verification_cu` when set higher than the syscall estimate.
+pub fn required_compute(registry: &FlowRegistry, public_inputs_len: usize) -> u64 {
+    (registry.max_verification_cu as u64).max(verification_cost(public_inputs_len))
+}
+
+/// Negates a G1 point by replacing `y` with `p - y`. The point at infinity
+/// is its own negation.
+fn negate_g1(point: &[u8]) -> Result<[u8; 64], WaveError> {
+    let mut negated = [0u8; 64];
+    negated.copy_from_slice(point);
+    if point.iter().all(|&b| b == 0) {
+        return Ok(negated);
+    }
+    if point[32..] >= BASE_FIELD_MODULUS[..] {
+        return Err(WaveError::InvalidProof);
+    }
+
+    let mut borrow = 0u16;
+    for i in (0..32).rev() {
+        let difference = BASE_FIELD_MODULUS[i] as u16 + 0x100 - point[32 + i] as u16 - borrow;
+        negated[32 + i] = difference as u8;
+        borrow = u16::from(difference < 0x100);
+    }
+    Ok(negated)
+}
+
+/// Test double accepting proofs whose first 32 bytes are on an allowlist,
+/// for tests that don't carry real proofs
+#[cfg(any(test, feature = "mock-verifier"))]
+pub struct Groth16Verifier {
+    accepted_proofs: Vec<[u8; 32]>,
+}
+
+#[cfg(any(test, feature = "mock-verifier"))]
+impl Groth16Verifier {
+    pub fn new() -> Self {
+        Self {
+            accepted_proofs: vec![
+                [1u8; 32], // Test proof 1
+                [2u8; 32], // Test proof 2
+                [3u8; 32], // Test proof 3
+            ],
+        }
+    }
+
+    pub fn verify(&self, proof: &[u8]) -> bool {
+        proof
+            .get(..32)
+            .is_some_and(|prefix| self.accepted_proofs.iter().any(|accepted| accepted[..] == *prefix))
+    }
+}
+
+/// Checks a ValidateProof proof for `registry`'s circuit, whose verifying
+/// key is in `verifying_key`, with the flow's proof system. With
+/// `mock-verifier` the key account is ignored and the allowlist decides.
+pub fn check_proof(
+    program_id: &Pubkey,
+    registry: &FlowRegistry,
+    verifying_key: &AccountInfo,
+    proof: &[u8],
+    public_inputs: &[u8],
+) -> Result<(), ProgramError> {
+    #[cfg(any(test, feature = "mock-verifier"))]
+    {
+        let _ = (program_id, registry, verifying_key, public_inputs);
+        if !Groth16Verifier::new().verify(proof) {
+            return Err(WaveError::InvalidProof.into());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(test, feature = "mock-verifier")))]
+    {
+        let key = VerifyingKey::load(program_id, verifying_key, &registry.circuit_hash)?;
+        verify(&key, proof, public_inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::alt_bn128::compression::prelude::{alt_bn128_g1_compress, alt_bn128_g2_compress};
+
+    const G1_GENERATOR: [u8; 64] = {
+        let mut point = [0u8; 64];
+        point[31] = 1;
+        point[63] = 2;
+        point
+    };
+
+    const G2_GENERATOR: [u8; 128] = [
+        0x19, 0x8e, 0x93, 0x93, 0x92, 0x0d, 0x48, 0x3a, 0x72, 0x60, 0xbf, 0xb7, 0x31, 0xfb, 0x5d, 0x25,
+        0xf1, 0xaa, 0x49, 0x33, 0x35, 0xa9, 0xe7, 0x12, 0x97, 0xe4, 0x85, 0xb7, 0xae, 0xf3, 0x12, 0xc2,
+        0x18, 0x00, 0xde, 0xef, 0x12, 0x1f, 0x1e, 0x76, 0x42, 0x6a, 0x00, 0x66, 0x5e, 0x5c, 0x44, 0x79,
+        0x67, 0x43, 0x22, 0xd4, 0xf7, 0x5e, 0xda, 0xdd, 0x46, 0xde, 0xbd, 0x5c, 0xd9, 0x92, 0xf6, 0xed,
+        0x09, 0x06, 0x89, 0xd0, 0x58, 0x5f, 0xf0, 0x75, 0xec, 0x9e, 0x99, 0xad, 0x69, 0x0c, 0x33, 0x95,
+        0xbc, 0x4b, 0x31, 0x33, 0x70, 0xb3, 0x8e, 0xf3, 0x55, 0xac, 0xda, 0xdc, 0xd1, 0x22, 0x97, 0x5b,
+        0x12, 0xc8, 0x5e, 0xa5, 0xdb, 0x8c, 0x6d, 0xeb, 0x4a, 0xab, 0x71, 0x80, 0x8d, 0xcb, 0x40, 0x8f,
+        0xe3, 0xd1, 0xe7, 0x69, 0x0c, 0x43, 0xd3, 0x7b, 0x4c, 0xe6, 0xcc, 0x01, 0x66, 0xfa, 0x7d, 0xaa,
+    ];
+
+    fn scalar(value: u64) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes[24..].copy_from_slice(&value.to_be_bytes());
+        bytes
+    }
+
+    fn g1(value: u64) -> [u8; 64] {
+        alt_bn128_multiplication(&[&G1_GENERATOR[..], &scalar(value)].concat())
+            .unwrap()
+            .try_into()
+            .unwrap()
+    }
+
+    /// A key with every G2 point at the generator, so the pairing check
+    /// reduces to `a == alpha + vk_x + c` over scalars of the G1 generator
+    fn test_key() -> VerifyingKey {
+        VerifyingKey {
+            alpha_g1: g1(5),
+            beta_g2: G2_GENERATOR,
+            gamma_g2: G2_GENERATOR,
+            delta_g2: G2_GENERATOR,
+            ic: vec![g1(7), g1(11), g1(13)],
+        }
+    }
+
+    /// Proof for `test_key` with inputs `x` and `y`, in the syscalls' layout
+    fn test_points(x: u64, y: u64) -> ([u8; 64], [u8; 128], [u8; 64]) {
+        let c = 3;
+        let a = 5 + (7 + 11 * x + 13 * y) + c;
+        (g1(a), G2_GENERATOR, g1(c))
+    }
+
+    /// `test_points` serialized as arkworks does, uncompressed
+    fn test_proof(x: u64, y: u64) -> Vec<u8> {
+        let (a, b, c) = test_points(x, y);
+        [
+            reverse_chunks::<64>(&a, 32).to_vec(),
+            reverse_chunks::<128>(&b, 64).to_vec(),
+            reverse_chunks::<64>(&c, 32).to_vec(),
+        ]
+        .concat()
+    }
+
+    /// `test_points` serialized as arkworks does, compressed
+    fn test_proof_compressed(x: u64, y: u64) -> Vec<u8> {
+        let (a, b, c) = test_points(x, y);
+        [
+            reverse_chunks::<32>(&alt_bn128_g1_compress(&a).unwrap(), 32).to_vec(),
+            reverse_chunks::<64>(&alt_bn128_g2_compress(&b).unwrap(), 64).to_vec(),
+            reverse_chunks::<32>(&alt_bn128_g1_compress(&c).unwrap(), 32).to_vec(),
+        ]
+        .concat()
+    }
+
+    fn inputs(values: &[u64]) -> Vec<u8> {
+        values.iter().flat_map(|&value| scalar(value)).collect()
+    }
+
+    #[test]
+    fn test_verify_accepts_valid_proof_only() {
+        let key = test_key();
+        let proof = test_proof(2, 4);
+        assert_eq!(verify(&key, &proof, &inputs(&[2, 4])), Ok(()));
+        assert_eq!(verify(&key, &test_proof_compressed(2, 4), &inputs(&[2, 4])), Ok(()));
+
+        // Other inputs, a tampered proof, a wrong input count and inputs
+        // outside the scalar field are all rejected
+        assert_eq!(verify(&key, &proof, &inputs(&[4, 2])), Err(WaveError::InvalidProof.into()));
+        assert_eq!(verify(&key, &test_proof(2, 5), &inputs(&[2, 4])), Err(WaveError::InvalidProof.into()));
+        assert_eq!(
+            verify(&key, &test_proof_compressed(2, 5), &inputs(&[2, 4])),
+            Err(WaveError::InvalidProof.into())
+        );
+        assert_eq!(verify(&key, &proof, &inputs(&[2])), Err(WaveError::InvalidProof.into()));
+        assert_eq!(verify(&key, &proof[..PROOF_SIZE - 1], &inputs(&[2, 4])), Err(WaveError::InvalidProof.into()));
+        let mut oversized = inputs(&[2, 4]);
+        oversized[..32].copy_from_slice(&SCALAR_FIELD_ORDER);
+        assert_eq!(verify(&key, &proof, &oversized), Err(WaveError::InvalidProof.into()));
+
+        // Bytes that aren't a curve point
+        let mut garbage = proof.clone();
+        garbage[..64].fill(0xff);
+        assert_eq!(verify(&key, &garbage, &inputs(&[2, 4])), Err(WaveError::InvalidProof.into()));
+    }
+
+    #[test]
+    fn test_required_compute_covers_pairing() {
+        // Decompression, two input terms and four pairs
+        assert_eq!(verification_cost(64), 14_406 + 2 * 4_174 + 72_727);
+
+        let mut registry = FlowRegistry::new(
+            solana_program::pubkey::Pubkey::new_unique(),
+            1,
+            None,
+            [1u8; 32],
+            None,
+        );
+        assert_eq!(required_compute(&registry, 64), verification_cost(64));
+        registry.max_verification_cu = 200_000;
+        assert_eq!(required_compute(&registry, 64), 200_000);
+    }
+
+    #[test]
+    fn test_negate_g1() {
+        let point = g1(9);
+        let negated = negate_g1(&point).unwrap();
+        assert_eq!(point[..32], negated[..32]);
+
+        // P + (-P) is the point at infinity
+        let sum = alt_bn128_addition(&[&point[..], &negated[..]].concat()).unwrap();
+        assert_eq!(sum, vec![0u8; 64]);
+        assert_eq!(negate_g1(&[0u8; 64]).unwrap(), [0u8; 64]);
+    }
+
+    #[test]
+    fn test_load_checks_circuit_hash() {
+        let key = test_key();
+        let circuit_hash = key.hash().unwrap();
+        let mut data = key.try_to_vec().unwrap();
+        let mut lamports = 0;
+        let program_id = Pubkey::new_unique();
+        let account_key = VerifyingKey::find_pda(&program_id, &circuit_hash).0;
+        let account = AccountInfo::new(&account_key, false, false, &mut lamports, &mut data, &program_id, false, 0);
+
+        assert_eq!(VerifyingKey::load(&program_id, &account, &circuit_hash).unwrap(), key);
+        assert_eq!(
+            VerifyingKey::load(&program_id, &account, &[0u8; 32]),
+            Err(WaveError::InvalidVerifyingKey.into())
+        );
+        // Only the program's PDA for the circuit is trusted
+        assert_eq!(
+            VerifyingKey::load(&Pubkey::new_unique(), &account, &circuit_hash),
+            Err(WaveError::InvalidVerifyingKey.into())
+        );
+    }
+
+    #[test]
+    fn test_verifying_key_size_bounds() {
+        let mut key = test_key();
+        let bytes = key.try_to_vec().unwrap();
+        assert_eq!(VerifyingKey::from_bytes(&bytes).unwrap(), key);
+
+        // Trailing bytes, truncation and an empty `ic` are all rejected
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(VerifyingKey::from_bytes(&trailing), Err(WaveError::MalformedVerificationKey)));
+        assert!(matches!(
+            VerifyingKey::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(WaveError::MalformedVerificationKey)
+        ));
+        key.ic.clear();
+        assert!(matches!(
+            VerifyingKey::from_bytes(&key.try_to_vec().unwrap()),
+            Err(WaveError::MalformedVerificationKey)
+        ));
+
+        key.ic = vec![g1(1); MAX_VERIFYING_KEY_INPUTS + 1];
+        assert_eq!(key.try_to_vec().unwrap().len(), VerifyingKey::MAX_SIZE);
+        key.ic.push(g1(1));
+        assert!(matches!(
+            VerifyingKey::from_bytes(&key.try_to_vec().unwrap()),
+            Err(WaveError::MalformedVerificationKey)
+        ));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,11 @@
+#!/usr/bin/env bash
+
+# Builds and tests the program libs without their default `solana` feature,
+# the configuration off-chain services use to leave out solana-program.
+
+set -eux
+
+for lib in merkle-tree hash-set; do
+    cargo check -p "$lib" --no-default-features --all-targets
+    cargo test -p "$lib" --no-default-features
+done
@@ -67,6 +67,8 @@ async fn test_large_account_compression() {
             concurrent_compressions_limit: 4,
             verify_all_compressions: true,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         };
         
         let start = std::time::Instant::now();
@@ -133,6 +135,8 @@ async fn test_concurrent_compression_performance() {
                 concurrent_compressions_limit: 8,
                 verify_all_compressions: true,
                 auto_decompress_on_access: false,
+                refund_excess_rent: false,
+                verify_sample_rate: 0,
             };
             
             let transaction = Transaction::new_signed_with_payer(
@@ -184,6 +188,8 @@ async fn test_compression_with_different_chunk_sizes() {
             concurrent_compressions_limit: 1,
             verify_all_compressions: true,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         };
         
         let start = std::time::Instant::now();
@@ -235,6 +241,8 @@ async fn test_compression_memory_usage() {
             concurrent_compressions_limit: 1,
             verify_all_compressions: true,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         };
         
         let before_memory = get_process_memory();
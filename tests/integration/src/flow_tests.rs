@@ -0,0 +1,1685 @@
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program_test::*,
+    solana_sdk::{
+        account::Account,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::{Keypair, Signer},
+        system_program,
+        transaction::{Transaction, TransactionError},
+    },
+    wave_verifier::{
+        constants::{FLOW_TREE_DEPTH, MAX_SET_ROOT_BATCH_ENTRIES},
+        error::WaveError,
+        instructions::{FlowOverrides, WaveInstruction},
+        state::{
+            ApprovedCircuits, AttestationPolicy, FlowRegistry, IncrementalMerkleTree, NotificationManifest,
+            Nullifier, NullifierNamespace, ProgramConfig, ProofLog,
+        },
+    },
+};
+
+use super::*;
+
+fn set_root_batch_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    registries: &[Pubkey],
+    entries: Vec<(u64, [u8; 32], u64)>,
+) -> Instruction {
+    let mut accounts = vec![AccountMeta::new_readonly(*authority, true)];
+    accounts.extend(registries.iter().map(|key| AccountMeta::new(*key, false)));
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::SetRootBatch { entries },
+        accounts,
+    )
+}
+
+fn set_root_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    registry: &Pubkey,
+    new_root: [u8; 32],
+    expected_sequence: u64,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::SetRoot { new_root, expected_sequence },
+        vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*registry, false),
+        ],
+    )
+}
+
+/// Rotates to the circuit `key` verifies
+fn update_root_and_circuit_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    registry: &Pubkey,
+    new_root: [u8; 32],
+    key: &VerifyingKey,
+    expected_sequence: u64,
+) -> Instruction {
+    let new_circuit_hash = key.hash().unwrap();
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::UpdateRootAndCircuit {
+            new_root,
+            new_circuit_hash,
+            expected_sequence,
+            new_verifying_key: key.try_to_vec().unwrap(),
+        },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*registry, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(VerifyingKey::find_pda(program_id, &new_circuit_hash).0, false),
+            AccountMeta::new_readonly(ApprovedCircuits::find_pda(program_id).0, false),
+        ],
+    )
+}
+
+/// A well-formed key for a circuit other than `common::test_verifying_key`'s
+fn rotated_verifying_key() -> VerifyingKey {
+    VerifyingKey {
+        alpha_g1: [8u8; 64],
+        ..common::test_verifying_key()
+    }
+}
+
+/// Registers flows 1..=5 under `authority`, except `rogue_index` (if any),
+/// which gets a different authority.
+fn add_flows(
+    program_test: &mut ProgramTest,
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    rogue_index: Option<usize>,
+) -> Vec<Pubkey> {
+    (0..5)
+        .map(|i| {
+            let owner = if Some(i) == rogue_index { Pubkey::new_unique() } else { *authority };
+            let registry = FlowRegistry::new(owner, i as u64 + 1, Some([0u8; 32]), [1u8; 32], None);
+            common::add_registry(program_test, program_id, &registry)
+        })
+        .collect()
+}
+
+async fn load_registry(banks_client: &mut BanksClient, registry: Pubkey) -> FlowRegistry {
+    let account = banks_client.get_account(registry).await.unwrap().unwrap();
+    FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap()
+}
+
+async fn merkle_root(banks_client: &mut BanksClient, registry: Pubkey) -> Option<[u8; 32]> {
+    load_registry(banks_client, registry).await.merkle_root
+}
+
+#[tokio::test]
+async fn test_set_root_batch_updates_every_flow() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let registries = add_flows(&mut program_test, &program_id, &authority.pubkey(), None);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let entries: Vec<_> = (0..5).map(|i| (i as u64 + 1, [i as u8 + 1; 32], 0)).collect();
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_batch_ix(&program_id, &authority.pubkey(), &registries, entries.clone())],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    for (registry, (_, root, _)) in registries.iter().zip(entries) {
+        let loaded = load_registry(&mut banks_client, *registry).await;
+        assert_eq!(loaded.merkle_root, Some(root));
+        assert_eq!(loaded.root_sequence, 1);
+    }
+}
+
+#[tokio::test]
+async fn test_set_root_batch_is_atomic_on_authority_mismatch() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let registries = add_flows(&mut program_test, &program_id, &authority.pubkey(), Some(2));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let entries: Vec<_> = (0..5).map(|i| (i as u64 + 1, [i as u8 + 1; 32], 0)).collect();
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_batch_ix(&program_id, &authority.pubkey(), &registries, entries)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::Unauthorized as u32),
+        )
+    );
+
+    // Entries before and after the bad one must be untouched
+    for registry in registries {
+        assert_eq!(merkle_root(&mut banks_client, registry).await, Some([0u8; 32]));
+    }
+}
+
+#[tokio::test]
+async fn test_set_root_batch_rejects_mismatched_flow_id() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let registries = add_flows(&mut program_test, &program_id, &authority.pubkey(), None);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // Registries passed in the wrong order for their entries
+    let entries = vec![(2, [7u8; 32], 0), (1, [8u8; 32], 0)];
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_batch_ix(&program_id, &authority.pubkey(), &registries[..2], entries)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::InvalidFlowId as u32),
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_set_root_batch_rejects_oversized_batch() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let registries = add_flows(&mut program_test, &program_id, &authority.pubkey(), None);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // Reuse the same registry so the transaction itself still fits
+    let entries = vec![(1, [7u8; 32], 0); MAX_SET_ROOT_BATCH_ENTRIES + 1];
+    let accounts = vec![registries[0]; MAX_SET_ROOT_BATCH_ENTRIES + 1];
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_batch_ix(&program_id, &authority.pubkey(), &accounts, entries)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::InvalidInstruction as u32),
+        )
+    );
+}
+
+/// The nullifier namespace of the `add_clone_source` flow, owned by its
+/// authority
+const CLONE_SOURCE_NAMESPACE: u64 = 7_000;
+
+fn clone_flow_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    source_flow_id: u64,
+    new_flow_id: u64,
+    overrides: FlowOverrides,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::CloneFlow { source_flow_id, new_flow_id, overrides },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(common::registry_pda(program_id, source_flow_id), false),
+            AccountMeta::new(common::registry_pda(program_id, new_flow_id), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(ProgramConfig::find_pda(program_id).0, false),
+            AccountMeta::new(common::treasury_pda(program_id), false),
+            AccountMeta::new_readonly(ApprovedCircuits::find_pda(program_id).0, false),
+            AccountMeta::new_readonly(NullifierNamespace::find_pda(program_id, CLONE_SOURCE_NAMESPACE).0, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+    )
+}
+
+/// Pre-loads `namespace`, owned by `authority`, as CreateNullifierNamespace
+/// would have left it.
+fn add_namespace(program_test: &mut ProgramTest, program_id: &Pubkey, namespace: u64, authority: &Pubkey) {
+    program_test.add_account(
+        NullifierNamespace::find_pda(program_id, namespace).0,
+        Account {
+            lamports: 1_000_000_000,
+            data: NullifierNamespace::new(*authority, namespace).try_to_vec().unwrap(),
+            owner: *program_id,
+            ..Account::default()
+        },
+    );
+}
+
+/// Registers a fully configured source flow 1 and funds its authority so it
+/// can pay for the cloned registry.
+fn add_clone_source(program_test: &mut ProgramTest, program_id: &Pubkey) -> (Keypair, FlowRegistry) {
+    add_clone_source_with_lamports(program_test, program_id, 10_000_000_000)
+}
+
+fn add_clone_source_with_lamports(
+    program_test: &mut ProgramTest,
+    program_id: &Pubkey,
+    lamports: u64,
+) -> (Keypair, FlowRegistry) {
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account {
+            lamports,
+            owner: system_program::id(),
+            ..Account::default()
+        },
+    );
+
+    let mut source = FlowRegistry::new(
+        authority.pubkey(),
+        1,
+        Some([10u8; 32]),
+        [1u8; 32],
+        Some(Pubkey::new_unique()),
+    );
+    source.nullifier_namespace = Some(CLONE_SOURCE_NAMESPACE);
+    source.max_verification_cu = 200_000;
+    common::add_registry(program_test, program_id, &source);
+    add_namespace(program_test, program_id, CLONE_SOURCE_NAMESPACE, &authority.pubkey());
+    (authority, source)
+}
+
+#[tokio::test]
+async fn test_clone_flow_copies_configuration_only() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let (authority, source) = add_clone_source(&mut program_test, &program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[clone_flow_ix(&program_id, &authority.pubkey(), 1, 2, FlowOverrides::default())],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = banks_client
+        .get_account(common::registry_pda(&program_id, 2))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data.len(), FlowRegistry::account_size());
+    let clone = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+
+    assert_eq!(clone.authority, source.authority);
+    assert_eq!(clone.flow_id, 2);
+    assert_eq!(clone.merkle_root, None);
+    assert_eq!(clone.circuit_hash, source.circuit_hash);
+    assert!(!clone.is_enabled);
+    assert_eq!(clone.callback_program_id, source.callback_program_id);
+    assert_eq!(clone.nullifier_namespace, source.nullifier_namespace);
+    assert_eq!(clone.max_verification_cu, source.max_verification_cu);
+}
+
+#[tokio::test]
+async fn test_clone_flow_applies_overrides() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let (authority, source) = add_clone_source(&mut program_test, &program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let callback = Pubkey::new_unique();
+    let overrides = FlowOverrides {
+        callback_program_id: Some(callback.to_bytes()),
+        ..FlowOverrides::default()
+    };
+    let transaction = Transaction::new_signed_with_payer(
+        &[clone_flow_ix(&program_id, &authority.pubkey(), 1, 2, overrides)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let clone = load_registry(&mut banks_client, common::registry_pda(&program_id, 2)).await;
+    assert_eq!(clone.callback_program_id, Some(callback));
+    assert_eq!(clone.authority, authority.pubkey());
+    assert_eq!(clone.flow_id, 2);
+    assert_eq!(clone.merkle_root, None);
+    assert_eq!(clone.circuit_hash, source.circuit_hash);
+    assert!(!clone.is_enabled);
+    assert_eq!(clone.nullifier_namespace, source.nullifier_namespace);
+    assert_eq!(clone.max_verification_cu, source.max_verification_cu);
+}
+
+#[tokio::test]
+async fn test_clone_flow_requires_source_authority() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    add_clone_source(&mut program_test, &program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[clone_flow_ix(&program_id, &payer.pubkey(), 1, 2, FlowOverrides::default())],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::Unauthorized as u32),
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_clone_flow_rejects_existing_flow_id() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let (authority, _) = add_clone_source(&mut program_test, &program_id);
+    let existing = FlowRegistry::new(authority.pubkey(), 2, None, [2u8; 32], None);
+    common::add_registry(&mut program_test, &program_id, &existing);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[clone_flow_ix(&program_id, &authority.pubkey(), 1, 2, FlowOverrides::default())],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::FlowAlreadyRegistered as u32),
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_clone_flow_rejects_self_callback() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let (authority, _) = add_clone_source(&mut program_test, &program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let overrides = FlowOverrides {
+        callback_program_id: Some(program_id.to_bytes()),
+        ..FlowOverrides::default()
+    };
+    let transaction = Transaction::new_signed_with_payer(
+        &[clone_flow_ix(&program_id, &authority.pubkey(), 1, 2, overrides)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::InvalidCallbackProgram as u32),
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_clone_flow_into_foreign_namespace_requires_its_authority() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let (authority, _) = add_clone_source(&mut program_test, &program_id);
+    let foreign_namespace = 8_000;
+    add_namespace(&mut program_test, &program_id, foreign_namespace, &Pubkey::new_unique());
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let overrides = FlowOverrides {
+        nullifier_namespace: Some(foreign_namespace),
+        ..FlowOverrides::default()
+    };
+    let mut ix = clone_flow_ix(&program_id, &authority.pubkey(), 1, 2, overrides);
+    let namespace_index = ix.accounts.len() - 2;
+    ix.accounts[namespace_index].pubkey = NullifierNamespace::find_pda(&program_id, foreign_namespace).0;
+    let transaction = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::Unauthorized as u32))
+    );
+    assert!(banks_client.get_account(common::registry_pda(&program_id, 2)).await.unwrap().is_none());
+}
+
+fn set_flow_enabled_ix(program_id: &Pubkey, authority: &Pubkey, flow_id: u64, enabled: bool) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::SetFlowEnabled { flow_id, enabled },
+        vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(common::registry_pda(program_id, flow_id), false),
+        ],
+    )
+}
+
+#[tokio::test]
+async fn test_cloned_flow_accepts_proofs_once_enabled() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let (authority, source) = add_clone_source(&mut program_test, &program_id);
+
+    let nullifier = [4u8; 32];
+    let clone = source.clone_as(2);
+    let (nullifier_key, _) = clone.find_nullifier_pda(&program_id, &clone.nullifier_key(&nullifier));
+    let proof_log = common::proof_log_pda(&program_id, clone.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let validate = || {
+        common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            common::registry_pda(&program_id, 2),
+            nullifier_key,
+            proof_log,
+            nullifier,
+        )
+    };
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[clone_flow_ix(&program_id, &authority.pubkey(), 1, 2, FlowOverrides::default())],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // The clone starts disabled
+    let transaction = Transaction::new_signed_with_payer(
+        &[validate()],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    assert_eq!(
+        banks_client.process_transaction(transaction).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::FlowDisabled as u32))
+    );
+
+    // Only its authority can enable it
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_flow_enabled_ix(&program_id, &payer.pubkey(), 2, true)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    assert_eq!(
+        banks_client.process_transaction(transaction).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::Unauthorized as u32))
+    );
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_flow_enabled_ix(&program_id, &authority.pubkey(), 2, true), validate()],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = banks_client.get_account(nullifier_key).await.unwrap().unwrap();
+    assert_eq!(Nullifier::deserialize(&mut account.data.as_slice()).unwrap().flow_id, 2);
+}
+
+fn attach_tree_state_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    flow_id: u64,
+    frontier: [[u8; 32]; FLOW_TREE_DEPTH],
+    leaf_count: u64,
+    root: [u8; 32],
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::AttachTreeState { flow_id, frontier: Box::new(frontier), leaf_count, root },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(common::registry_pda(program_id, flow_id), false),
+            AccountMeta::new(IncrementalMerkleTree::find_pda(program_id, flow_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn append_leaf_ix(program_id: &Pubkey, authority: &Pubkey, flow_id: u64, leaf: [u8; 32]) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::AppendLeaf { flow_id, leaf },
+        vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(common::registry_pda(program_id, flow_id), false),
+            AccountMeta::new(IncrementalMerkleTree::find_pda(program_id, flow_id).0, false),
+        ],
+    )
+}
+
+/// Registers flow 1 without a root under a funded authority that can pay for
+/// the tree account.
+fn add_tree_flow(program_test: &mut ProgramTest, program_id: &Pubkey) -> Keypair {
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account {
+            lamports: 10_000_000_000,
+            owner: system_program::id(),
+            ..Account::default()
+        },
+    );
+    let registry = FlowRegistry::new(authority.pubkey(), 1, None, [1u8; 32], None);
+    common::add_registry(program_test, program_id, &registry);
+    authority
+}
+
+#[tokio::test]
+async fn test_attach_tree_state_continues_offchain_tree() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = add_tree_flow(&mut program_test, &program_id);
+
+    let leaves: Vec<[u8; 32]> = (0..9u8).map(|i| [i + 1; 32]).collect();
+    let mut offchain = IncrementalMerkleTree::new();
+    for leaf in &leaves[..6] {
+        offchain.append(*leaf).unwrap();
+    }
+    let mut reference = IncrementalMerkleTree::new();
+    for leaf in &leaves {
+        reference.append(*leaf).unwrap();
+    }
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut instructions = vec![attach_tree_state_ix(
+        &program_id,
+        &authority.pubkey(),
+        1,
+        offchain.frontier,
+        offchain.leaf_count,
+        offchain.root,
+    )];
+    instructions.extend(
+        leaves[6..].iter().map(|leaf| append_leaf_ix(&program_id, &authority.pubkey(), 1, *leaf)),
+    );
+    let transaction = Transaction::new_signed_with_payer(
+        &instructions[..1],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+    let registry = common::registry_pda(&program_id, 1);
+    assert_eq!(merkle_root(&mut banks_client, registry).await, Some(offchain.root));
+
+    let transaction = Transaction::new_signed_with_payer(
+        &instructions[1..],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = banks_client
+        .get_account(IncrementalMerkleTree::find_pda(&program_id, 1).0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), IncrementalMerkleTree::account_size());
+    let tree = IncrementalMerkleTree::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(tree.leaf_count, leaves.len() as u64);
+    assert_eq!(tree.root, reference.root);
+    assert_eq!(merkle_root(&mut banks_client, registry).await, Some(reference.root));
+}
+
+#[tokio::test]
+async fn test_attach_tree_state_rejects_mismatched_root() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = add_tree_flow(&mut program_test, &program_id);
+
+    let mut offchain = IncrementalMerkleTree::new();
+    for i in 0..3u8 {
+        offchain.append([i + 1; 32]).unwrap();
+    }
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[attach_tree_state_ix(
+            &program_id,
+            &authority.pubkey(),
+            1,
+            offchain.frontier,
+            offchain.leaf_count,
+            [0xAB; 32],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::InvalidMerkleRoot as u32),
+        )
+    );
+
+    let tree = banks_client
+        .get_account(IncrementalMerkleTree::find_pda(&program_id, 1).0)
+        .await
+        .unwrap();
+    assert!(tree.is_none());
+    let registry = common::registry_pda(&program_id, 1);
+    assert_eq!(merkle_root(&mut banks_client, registry).await, None);
+}
+
+#[tokio::test]
+async fn test_set_root_rejects_foreign_owned_registry() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let attacker = Keypair::new();
+    let forged = FlowRegistry::new(attacker.pubkey(), 1, None, [1u8; 32], None);
+    let registry = Pubkey::new_unique();
+    program_test.add_account(
+        registry,
+        Account {
+            lamports: 1_000_000_000,
+            data: forged.try_to_vec().unwrap(),
+            owner: Pubkey::new_unique(),
+            ..Account::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[Instruction::new_with_borsh(
+            program_id,
+            &WaveInstruction::SetRoot { new_root: [0xEE; 32], expected_sequence: 0 },
+            vec![
+                AccountMeta::new_readonly(attacker.pubkey(), true),
+                AccountMeta::new(registry, false),
+            ],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &attacker],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::IllegalOwner)
+    );
+}
+
+#[tokio::test]
+async fn test_set_root_applies_out_of_order_updates_in_sequence() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let registry = add_flows(&mut program_test, &program_id, &authority.pubkey(), None)[0];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let stale = TransactionError::InstructionError(
+        0,
+        InstructionError::Custom(WaveError::StaleRootSequence as u32),
+    );
+
+    // The second update arrives before the first
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_ix(&program_id, &authority.pubkey(), &registry, [2u8; 32], 1)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(err, stale);
+    assert_eq!(merkle_root(&mut banks_client, registry).await, Some([0u8; 32]));
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_ix(&program_id, &authority.pubkey(), &registry, [1u8; 32], 0)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // Redelivered after the first one landed, the second update now applies
+    let recent_blockhash = banks_client
+        .get_new_latest_blockhash(&recent_blockhash)
+        .await
+        .unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_ix(&program_id, &authority.pubkey(), &registry, [2u8; 32], 1)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let loaded = load_registry(&mut banks_client, registry).await;
+    assert_eq!(loaded.merkle_root, Some([2u8; 32]));
+    assert_eq!(loaded.root_sequence, 2);
+
+    // Replaying the first update cannot roll the root back
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_ix(&program_id, &authority.pubkey(), &registry, [1u8; 32], 0)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(err, stale);
+    assert_eq!(merkle_root(&mut banks_client, registry).await, Some([2u8; 32]));
+}
+
+#[tokio::test]
+async fn test_set_root_rejects_concurrent_update_from_same_sequence() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let registry = add_flows(&mut program_test, &program_id, &authority.pubkey(), None)[0];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // Both updaters read sequence 0 and race to replace the root
+    let first = Transaction::new_signed_with_payer(
+        &[set_root_ix(&program_id, &authority.pubkey(), &registry, [0xAA; 32], 0)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let second = Transaction::new_signed_with_payer(
+        &[set_root_ix(&program_id, &authority.pubkey(), &registry, [0xBB; 32], 0)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(first).await.unwrap();
+    let err = banks_client
+        .process_transaction(second)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::StaleRootSequence as u32),
+        )
+    );
+
+    let loaded = load_registry(&mut banks_client, registry).await;
+    assert_eq!(loaded.merkle_root, Some([0xAA; 32]));
+    assert_eq!(loaded.root_sequence, 1);
+}
+
+#[tokio::test]
+async fn test_update_root_and_circuit_changes_both() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(authority.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    let registry = add_flows(&mut program_test, &program_id, &authority.pubkey(), None)[0];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let key = rotated_verifying_key();
+    let transaction = Transaction::new_signed_with_payer(
+        &[update_root_and_circuit_ix(&program_id, &authority.pubkey(), &registry, [7u8; 32], &key, 0)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let loaded = load_registry(&mut banks_client, registry).await;
+    assert_eq!(loaded.merkle_root, Some([7u8; 32]));
+    assert_eq!(loaded.circuit_hash, key.hash().unwrap());
+    assert_eq!(loaded.root_sequence, 1);
+}
+
+#[tokio::test]
+async fn test_rotated_circuit_validates_proofs() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(authority.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    let registry = add_flows(&mut program_test, &program_id, &authority.pubkey(), None)[0];
+
+    let nullifier = [4u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, 1, &nullifier);
+    let proof_log = common::proof_log_pda(&program_id, 1, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // A key that isn't the new circuit's is refused
+    let key = rotated_verifying_key();
+    let mut mismatched = update_root_and_circuit_ix(&program_id, &authority.pubkey(), &registry, [7u8; 32], &key, 0);
+    let mut instruction = WaveInstruction::try_from_slice(&mismatched.data).unwrap();
+    if let WaveInstruction::UpdateRootAndCircuit { new_verifying_key, .. } = &mut instruction {
+        *new_verifying_key = common::test_verifying_key().try_to_vec().unwrap();
+    }
+    mismatched.data = instruction.try_to_vec().unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[mismatched],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    assert_eq!(
+        banks_client.process_transaction(transaction).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InvalidVerifyingKey as u32))
+    );
+
+    // Rotating stores the new circuit's key, which proofs then check against
+    let key_account = VerifyingKey::find_pda(&program_id, &key.hash().unwrap()).0;
+    let mut validate = common::validate_proof_ix(
+        &program_id,
+        &payer.pubkey(),
+        registry,
+        nullifier_key,
+        proof_log,
+        nullifier,
+    );
+    validate.accounts[5].pubkey = key_account;
+    let transaction = Transaction::new_signed_with_payer(
+        &[
+            update_root_and_circuit_ix(&program_id, &authority.pubkey(), &registry, [7u8; 32], &key, 0),
+            validate,
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = banks_client.get_account(key_account).await.unwrap().unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(VerifyingKey::from_bytes(&account.data).unwrap(), key);
+    let account = banks_client.get_account(proof_log).await.unwrap().unwrap();
+    assert_eq!(
+        ProofLog::deserialize(&mut account.data.as_slice()).unwrap().circuit_hash,
+        key.hash().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_frozen_circuit_rejects_rotation() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let registry = add_flows(&mut program_test, &program_id, &authority.pubkey(), None)[0];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[Instruction::new_with_borsh(
+            program_id,
+            &WaveInstruction::FreezeCircuit { flow_id: 1 },
+            vec![
+                AccountMeta::new_readonly(authority.pubkey(), true),
+                AccountMeta::new(registry, false),
+            ],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+    assert!(load_registry(&mut banks_client, registry).await.circuit_frozen);
+
+    let rotation =
+        update_root_and_circuit_ix(&program_id, &authority.pubkey(), &registry, [7u8; 32], &rotated_verifying_key(), 0);
+    let transaction = Transaction::new_signed_with_payer(
+        &[rotation],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::CircuitFrozen as u32))
+    );
+
+    // Neither field moved
+    let loaded = load_registry(&mut banks_client, registry).await;
+    assert_eq!(loaded.merkle_root, Some([0u8; 32]));
+    assert_eq!(loaded.circuit_hash, [1u8; 32]);
+    assert_eq!(loaded.root_sequence, 0);
+
+    // Root-only updates are unaffected by the freeze
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_root_ix(&program_id, &authority.pubkey(), &registry, [7u8; 32], 0)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+    assert_eq!(merkle_root(&mut banks_client, registry).await, Some([7u8; 32]));
+}
+
+fn set_notification_entry_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    registry: &Pubkey,
+    flow_id: u64,
+    label: &str,
+    enabled: bool,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::SetNotificationEntry {
+            flow_id,
+            label: label.to_string(),
+            uri_hash: [9u8; 64],
+            enabled,
+        },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(*registry, false),
+            AccountMeta::new(NotificationManifest::find_pda(program_id, flow_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn clear_notification_entry_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    registry: &Pubkey,
+    flow_id: u64,
+    label: &str,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::ClearNotificationEntry { flow_id, label: label.to_string() },
+        vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new_readonly(*registry, false),
+            AccountMeta::new(NotificationManifest::find_pda(program_id, flow_id).0, false),
+        ],
+    )
+}
+
+async fn load_manifest(banks_client: &mut BanksClient, program_id: &Pubkey, flow_id: u64) -> NotificationManifest {
+    let manifest = NotificationManifest::find_pda(program_id, flow_id).0;
+    let account = banks_client.get_account(manifest).await.unwrap().unwrap();
+    NotificationManifest::deserialize(&mut account.data.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn test_notification_manifest_entry_lifecycle() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    // The authority funds the manifest account on first use
+    program_test.add_account(authority.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    let registry = add_flows(&mut program_test, &program_id, &authority.pubkey(), None)[0];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[
+            set_notification_entry_ix(&program_id, &authority.pubkey(), &registry, 1, "ops", true),
+            set_notification_entry_ix(&program_id, &authority.pubkey(), &registry, 1, "audit", true),
+            set_notification_entry_ix(&program_id, &authority.pubkey(), &registry, 1, "ops", false),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let manifest = load_manifest(&mut banks_client, &program_id, 1).await;
+    assert_eq!(manifest.flow_id, 1);
+    assert_eq!(manifest.entries.len(), 2);
+    let enabled: Vec<_> = manifest.enabled_entries().map(|entry| entry.label.as_str()).collect();
+    assert_eq!(enabled, vec!["audit"]);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[clear_notification_entry_ix(&program_id, &authority.pubkey(), &registry, 1, "audit")],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let manifest = load_manifest(&mut banks_client, &program_id, 1).await;
+    assert_eq!(manifest.entries.len(), 1);
+    assert_eq!(manifest.enabled_entries().count(), 0);
+}
+
+#[tokio::test]
+async fn test_notification_manifest_requires_flow_authority() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Pubkey::new_unique();
+    let intruder = Keypair::new();
+    program_test.add_account(intruder.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    let registry = add_flows(&mut program_test, &program_id, &authority, None)[0];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[set_notification_entry_ix(&program_id, &intruder.pubkey(), &registry, 1, "ops", true)],
+        Some(&payer.pubkey()),
+        &[&payer, &intruder],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::Unauthorized as u32))
+    );
+
+    // Nothing was created
+    let manifest = NotificationManifest::find_pda(&program_id, 1).0;
+    assert!(banks_client.get_account(manifest).await.unwrap().is_none());
+}
+
+fn set_program_config_ix(program_id: &Pubkey, admin: &Pubkey, registration_fee: u64) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::SetProgramConfig { registration_fee },
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(ProgramConfig::find_pda(program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+fn init_registry_ix(program_id: &Pubkey, authority: &Pubkey, flow_id: u64) -> Instruction {
+    init_registry_with_key_ix(program_id, authority, flow_id, &common::test_verifying_key())
+}
+
+fn init_registry_with_key_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    flow_id: u64,
+    key: &VerifyingKey,
+) -> Instruction {
+    let circuit_hash = key.hash().unwrap();
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::InitRegistry {
+            flow_id,
+            merkle_root: None,
+            circuit_hash,
+            callback_program_id: None,
+            nullifier_namespace: None,
+            max_verification_cu: 0,
+            attestation_policy: AttestationPolicy::Reject,
+            max_nullifiers: None,
+            nullifier_commitment: false,
+            freshness_window_slots: None,
+            proof_system: ProofSystem::Groth16,
+            verifying_key: key.try_to_vec().unwrap(),
+        },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(common::registry_pda(program_id, flow_id), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(ProgramConfig::find_pda(program_id).0, false),
+            AccountMeta::new(common::treasury_pda(program_id), false),
+            AccountMeta::new(VerifyingKey::find_pda(program_id, &circuit_hash).0, false),
+            AccountMeta::new_readonly(ApprovedCircuits::find_pda(program_id).0, false),
+        ],
+    )
+}
+
+fn create_namespace_ix(program_id: &Pubkey, authority: &Pubkey, namespace: u64) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::CreateNullifierNamespace { namespace },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(NullifierNamespace::find_pda(program_id, namespace).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// InitRegistry joining `namespace`, with `namespace_authority` admitting it
+fn init_registry_in_namespace_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    flow_id: u64,
+    namespace: u64,
+    namespace_authority: &Pubkey,
+) -> Instruction {
+    let mut ix = init_registry_ix(program_id, authority, flow_id);
+    let mut instruction = WaveInstruction::try_from_slice(&ix.data).unwrap();
+    if let WaveInstruction::InitRegistry { nullifier_namespace, .. } = &mut instruction {
+        *nullifier_namespace = Some(namespace);
+    }
+    ix.data = instruction.try_to_vec().unwrap();
+    ix.accounts.push(AccountMeta::new_readonly(NullifierNamespace::find_pda(program_id, namespace).0, false));
+    ix.accounts.push(AccountMeta::new_readonly(*namespace_authority, true));
+    ix
+}
+
+#[tokio::test]
+async fn test_joining_namespace_requires_its_authority() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let owner = Keypair::new();
+    let intruder = Keypair::new();
+    program_test.add_account(owner.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    program_test.add_account(intruder.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let send = |instruction: Instruction, signers: &[&Keypair]| {
+        let mut all_signers = vec![&payer];
+        all_signers.extend_from_slice(signers);
+        Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &all_signers, recent_blockhash)
+    };
+
+    let namespace = 7_000;
+    banks_client
+        .process_transaction(send(create_namespace_ix(&program_id, &owner.pubkey(), namespace), &[&owner]))
+        .await
+        .unwrap();
+
+    // A namespace has a single owner
+    let blockhash = banks_client.get_new_latest_blockhash(&recent_blockhash).await.unwrap();
+    let err = banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[create_namespace_ix(&program_id, &intruder.pubkey(), namespace)],
+            Some(&payer.pubkey()),
+            &[&payer, &intruder],
+            blockhash,
+        ))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(err, TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized));
+
+    // Naming the owner without its signature, or a namespace never
+    // created, admits nothing
+    let mut unsigned = init_registry_in_namespace_ix(&program_id, &intruder.pubkey(), 1, namespace, &owner.pubkey());
+    unsigned.accounts.last_mut().unwrap().is_signer = false;
+    let err = banks_client.process_transaction(send(unsigned, &[&intruder])).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::Unauthorized as u32))
+    );
+    let err = banks_client
+        .process_transaction(send(
+            init_registry_in_namespace_ix(&program_id, &intruder.pubkey(), 1, namespace, &intruder.pubkey()),
+            &[&intruder],
+        ))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::Unauthorized as u32))
+    );
+    let err = banks_client
+        .process_transaction(send(
+            init_registry_in_namespace_ix(&program_id, &intruder.pubkey(), 1, namespace + 1, &intruder.pubkey()),
+            &[&intruder],
+        ))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(err, TransactionError::InstructionError(0, InstructionError::IllegalOwner));
+
+    banks_client
+        .process_transaction(send(
+            init_registry_in_namespace_ix(&program_id, &intruder.pubkey(), 1, namespace, &owner.pubkey()),
+            &[&intruder, &owner],
+        ))
+        .await
+        .unwrap();
+    let account = banks_client.get_account(common::registry_pda(&program_id, 1)).await.unwrap().unwrap();
+    let registry = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(registry.nullifier_namespace, Some(namespace));
+    assert_eq!(registry.authority, intruder.pubkey());
+}
+
+fn approved_circuits_ix(admin: &Pubkey, program_id: &Pubkey, instruction: WaveInstruction) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &instruction,
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(ProgramConfig::find_pda(program_id).0, false),
+            AccountMeta::new(ApprovedCircuits::find_pda(program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+#[tokio::test]
+async fn test_init_registry_stores_verifying_key() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(authority.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let send = |instruction: Instruction| {
+        Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        )
+    };
+
+    // The key is the instruction's last field, so this breaks its hash
+    let mut tampered = init_registry_ix(&program_id, &authority.pubkey(), 1);
+    *tampered.data.last_mut().unwrap() ^= 1;
+    let err = banks_client.process_transaction(send(tampered)).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InvalidVerifyingKey as u32))
+    );
+
+    // The second flow on the circuit reuses the stored key
+    for flow_id in [1, 2] {
+        banks_client
+            .process_transaction(send(init_registry_ix(&program_id, &authority.pubkey(), flow_id)))
+            .await
+            .unwrap();
+    }
+    let key = common::test_verifying_key();
+    let account = banks_client
+        .get_account(VerifyingKey::find_pda(&program_id, &key.hash().unwrap()).0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(VerifyingKey::from_bytes(&account.data).unwrap(), key);
+}
+
+#[tokio::test]
+async fn test_init_registry_creates_registry_account() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(authority.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[init_registry_ix(&program_id, &authority.pubkey(), 1)],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    let account = banks_client
+        .get_account(common::registry_pda(&program_id, 1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data.len(), FlowRegistry::account_size());
+    assert_eq!(account.lamports, Rent::default().minimum_balance(FlowRegistry::account_size()));
+    let registry = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(registry.authority, authority.pubkey());
+    assert_eq!(registry.flow_id, 1);
+
+    // Registering the flow again would overwrite it
+    let blockhash = banks_client.get_new_latest_blockhash(&recent_blockhash).await.unwrap();
+    let err = banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[init_registry_ix(&program_id, &authority.pubkey(), 1)],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            blockhash,
+        ))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::FlowAlreadyRegistered as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_init_registry_takes_over_prefunded_pda() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    program_test.add_account(authority.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    // Anyone can send lamports to the address before the flow registers
+    program_test.add_account(common::registry_pda(&program_id, 1), Account::new(1_000, 0, &system_program::id()));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[init_registry_ix(&program_id, &authority.pubkey(), 1)],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    let account = banks_client
+        .get_account(common::registry_pda(&program_id, 1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data.len(), FlowRegistry::account_size());
+    // Topped up to rent exemption, not charged rent on top of the deposit
+    assert_eq!(account.lamports, Rent::default().minimum_balance(FlowRegistry::account_size()));
+    let registry = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(registry.authority, authority.pubkey());
+}
+
+#[tokio::test]
+async fn test_circuit_allowlist_enforcement() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let admin = Keypair::new();
+    let authority = Keypair::new();
+    program_test.add_account(admin.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    program_test.add_account(authority.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let send = |instruction: Instruction, signer: &Keypair| {
+        Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer, signer],
+            recent_blockhash,
+        )
+    };
+
+    let approved_key = common::test_verifying_key();
+    let mut unapproved_key = common::test_verifying_key();
+    unapproved_key.alpha_g1 = [9u8; 64];
+
+    banks_client
+        .process_transaction(send(set_program_config_ix(&program_id, &admin.pubkey(), 0), &admin))
+        .await
+        .unwrap();
+
+    // Only the config admin manages the allowlist
+    let err = banks_client
+        .process_transaction(send(
+            approved_circuits_ix(
+                &authority.pubkey(),
+                &program_id,
+                WaveInstruction::SetCircuitAllowlistEnforced { enforced: true },
+            ),
+            &authority,
+        ))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::Unauthorized as u32))
+    );
+
+    for instruction in [
+        WaveInstruction::SetCircuitApproval {
+            circuit_hash: approved_key.hash().unwrap(),
+            approved: true,
+        },
+        WaveInstruction::SetCircuitAllowlistEnforced { enforced: true },
+    ] {
+        banks_client
+            .process_transaction(send(approved_circuits_ix(&admin.pubkey(), &program_id, instruction), &admin))
+            .await
+            .unwrap();
+    }
+
+    let err = banks_client
+        .process_transaction(send(
+            init_registry_with_key_ix(&program_id, &authority.pubkey(), 1, &unapproved_key),
+            &authority,
+        ))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InvalidCircuitHash as u32))
+    );
+
+    banks_client
+        .process_transaction(send(
+            init_registry_with_key_ix(&program_id, &authority.pubkey(), 2, &approved_key),
+            &authority,
+        ))
+        .await
+        .unwrap();
+    let account = banks_client.get_account(common::registry_pda(&program_id, 2)).await.unwrap().unwrap();
+    let registry = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(registry.circuit_hash, approved_key.hash().unwrap());
+
+    // Nor can a registered flow rotate onto an unapproved circuit
+    let err = banks_client
+        .process_transaction(send(
+            update_root_and_circuit_ix(
+                &program_id,
+                &authority.pubkey(),
+                &common::registry_pda(&program_id, 2),
+                [7u8; 32],
+                &unapproved_key,
+                0,
+            ),
+            &authority,
+        ))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InvalidCircuitHash as u32))
+    );
+}
+
+async fn lamports(banks_client: &mut BanksClient, key: Pubkey) -> u64 {
+    banks_client.get_account(key).await.unwrap().map_or(0, |account| account.lamports)
+}
+
+#[tokio::test]
+async fn test_registration_fee_goes_to_treasury() {
+    // Above the treasury's rent-exempt minimum, so the first fee can create it
+    let fee = 10_000_000;
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let admin = Keypair::new();
+    let funded = Keypair::new();
+    let underfunded = Keypair::new();
+    program_test.add_account(admin.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    program_test.add_account(funded.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    program_test.add_account(underfunded.pubkey(), Account::new(fee - 1, 0, &system_program::id()));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let send = |instruction: Instruction, signer: &Keypair| {
+        Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer, signer],
+            recent_blockhash,
+        )
+    };
+
+    // Without a config only the registry's rent is paid
+    banks_client
+        .process_transaction(send(init_registry_ix(&program_id, &underfunded.pubkey(), 1), &underfunded))
+        .await
+        .unwrap();
+    assert_eq!(lamports(&mut banks_client, common::treasury_pda(&program_id)).await, 0);
+
+    banks_client
+        .process_transaction(send(set_program_config_ix(&program_id, &admin.pubkey(), fee), &admin))
+        .await
+        .unwrap();
+
+    banks_client
+        .process_transaction(send(init_registry_ix(&program_id, &funded.pubkey(), 2), &funded))
+        .await
+        .unwrap();
+    assert_eq!(lamports(&mut banks_client, common::treasury_pda(&program_id)).await, fee);
+    // The registry's rent is paid on top of the fee
+    let registry_rent = Rent::default().minimum_balance(FlowRegistry::account_size());
+    assert_eq!(lamports(&mut banks_client, funded.pubkey()).await, 1_000_000_000 - fee - registry_rent);
+
+    let err = banks_client
+        .process_transaction(send(init_registry_ix(&program_id, &underfunded.pubkey(), 3), &underfunded))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InsufficientFunds as u32))
+    );
+    assert_eq!(lamports(&mut banks_client, common::treasury_pda(&program_id)).await, fee);
+
+    // Only the admin that created the config can change it
+    let err = banks_client
+        .process_transaction(send(set_program_config_ix(&program_id, &funded.pubkey(), 0), &funded))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::Unauthorized as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_clone_flow_charges_registration_fee() {
+    let fee = 10_000_000;
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let admin = Keypair::new();
+    program_test.add_account(admin.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+    // Enough for the new registry's rent, but not the fee
+    let (authority, _) = add_clone_source_with_lamports(&mut program_test, &program_id, fee - 1);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[set_program_config_ix(&program_id, &admin.pubkey(), fee)],
+            Some(&payer.pubkey()),
+            &[&payer, &admin],
+            recent_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    let err = banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[clone_flow_ix(&program_id, &authority.pubkey(), 1, 2, FlowOverrides::default())],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        ))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InsufficientFunds as u32))
+    );
+    assert_eq!(lamports(&mut banks_client, common::treasury_pda(&program_id)).await, 0);
+    assert!(banks_client.get_account(common::registry_pda(&program_id, 2)).await.unwrap().is_none());
+}
\ No newline at end of file
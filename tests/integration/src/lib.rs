@@ -10,7 +10,7 @@ use {
     },
     wave_verifier::{
         instruction::CloudVerifierInstruction,
-        state::{FlowRegistry, Nullifier, ProofLog},
+        state::{ApprovedCircuits, AttestationPolicy, FlowRegistry, Nullifier, ProgramConfig, ProofLog},
     },
     wave_verifier_sdk::{WaveClient, types::{Flow, Proof}},
 };
@@ -26,6 +26,7 @@ use solana_program::{
 use wave_verifier::{
     constants::test_data::*,
     instructions::WaveInstruction,
+    verifier::{ProofSystem, VerifyingKey},
 };
 
 pub struct Proof {
@@ -34,6 +35,7 @@ pub struct Proof {
     pub nullifier: [u8; 32],
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Flow {
     pub id: u64,
     pub merkle_root: Option<[u8; 32]>,
@@ -41,8 +43,34 @@ pub struct Flow {
     pub callback_program_id: Option<[u8; 32]>,
 }
 
+impl Flow {
+    /// The registry InitRegistry would create for this flow under
+    /// `authority`, with every setting `Flow` doesn't carry at its default
+    pub fn into_registry(self, authority: Pubkey) -> FlowRegistry {
+        FlowRegistry::new(
+            authority,
+            self.id,
+            self.merkle_root,
+            self.circuit_hash,
+            self.callback_program_id.map(Pubkey::new_from_array),
+        )
+    }
+}
+
+impl From<FlowRegistry> for Flow {
+    fn from(registry: FlowRegistry) -> Self {
+        Self {
+            id: registry.flow_id,
+            merkle_root: registry.merkle_root,
+            circuit_hash: registry.circuit_hash,
+            callback_program_id: registry.callback_program_id.map(|id| id.to_bytes()),
+        }
+    }
+}
+
 mod common {
     use super::*;
+    use borsh::BorshSerialize;
     use solana_program_test::ProgramTest;
 
     pub async fn setup() -> (BanksClient, Keypair, Hash) {
@@ -73,6 +101,14 @@ mod common {
         (banks_client, payer, recent_blockhash)
     }
 
+    pub fn program_test(program_id: Pubkey) -> ProgramTest {
+        ProgramTest::new(
+            "wave_verifier",
+            program_id,
+            processor!(wave_verifier::processor::process_instruction),
+        )
+    }
+
     pub fn create_test_proof() -> Proof {
         Proof {
             proof_bytes: PROOF_1.to_vec(),
@@ -89,11 +125,117 @@ mod common {
             callback_program_id: None,
         }
     }
+
+    pub fn registry_pda(program_id: &Pubkey, flow_id: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[wave_verifier::constants::REGISTRY_SEED, &flow_id.to_le_bytes()],
+            program_id,
+        )
+        .0
+    }
+
+    pub fn proof_log_pda(program_id: &Pubkey, flow_id: u64, nullifier: &[u8; 32]) -> Pubkey {
+        Pubkey::find_program_address(
+            &[wave_verifier::constants::PROOF_LOG_SEED, &flow_id.to_le_bytes(), nullifier],
+            program_id,
+        )
+        .0
+    }
+
+    pub fn treasury_pda(program_id: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[wave_verifier::constants::TREASURY_SEED], program_id).0
+    }
+
+    /// Pre-loads a registry account holding `registry`, as InitRegistry would
+    /// have left it.
+    pub fn add_registry(
+        program_test: &mut ProgramTest,
+        program_id: &Pubkey,
+        registry: &FlowRegistry,
+    ) -> Pubkey {
+        let key = registry_pda(program_id, registry.flow_id);
+        program_test.add_account(
+            key,
+            Account {
+                lamports: 1_000_000_000,
+                data: registry.try_to_vec().unwrap(),
+                owner: *program_id,
+                ..Account::default()
+            },
+        );
+        key
+    }
+
+    /// Pre-loads a zeroed, program-owned account of `size` bytes.
+    pub fn add_empty_account(
+        program_test: &mut ProgramTest,
+        program_id: &Pubkey,
+        key: Pubkey,
+        size: usize,
+    ) {
+        program_test.add_account(
+            key,
+            Account {
+                lamports: 1_000_000_000,
+                data: vec![0u8; size],
+                owner: *program_id,
+                ..Account::default()
+            },
+        );
+    }
+
+    pub fn validate_proof_ix(
+        program_id: &Pubkey,
+        payer: &Pubkey,
+        flow_registry: Pubkey,
+        nullifier_account: Pubkey,
+        proof_log: Pubkey,
+        nullifier: [u8; 32],
+    ) -> Instruction {
+        Instruction::new_with_borsh(
+            *program_id,
+            &WaveInstruction::ValidateProof {
+                proof: vec![1u8; 128],
+                public_inputs: vec![1u8; 32],
+                nullifier,
+                mint_receipt: false,
+                nullifier_ttl_seconds: None,
+            },
+            vec![
+                AccountMeta::new(*payer, true),
+                AccountMeta::new(flow_registry, false),
+                AccountMeta::new(nullifier_account, false),
+                AccountMeta::new(proof_log, false),
+                AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+                AccountMeta::new_readonly(verifying_key(), false),
+            ],
+        )
+    }
+
+    /// Verifying key account for ValidateProof; the `mock-verifier` build
+    /// these tests use never reads it
+    pub fn verifying_key() -> Pubkey {
+        Pubkey::new_from_array([0xfe; 32])
+    }
+
+    /// A well-formed key for InitRegistry, which checks only its encoding
+    /// and hash; its points are placeholders no proof pairs with
+    pub fn test_verifying_key() -> VerifyingKey {
+        VerifyingKey {
+            alpha_g1: [1u8; 64],
+            beta_g2: [2u8; 128],
+            gamma_g2: [3u8; 128],
+            delta_g2: [4u8; 128],
+            ic: vec![[5u8; 64], [6u8; 64]],
+        }
+    }
 }
 
 mod flow_tests;
 mod proof_tests;
 mod nullifier_tests;
+mod sim_tests;
+mod snapshot;
 
 mod compression_tests {
     use super::*;
@@ -176,6 +318,8 @@ mod compression_tests {
             concurrent_compressions_limit: 4,
             verify_all_compressions: true,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         };
         
         // Compress account
@@ -286,6 +430,8 @@ mod compression_tests {
             concurrent_compressions_limit: 4,
             verify_all_compressions: true,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         };
         
         let transaction = Transaction::new_signed_with_payer(
@@ -361,9 +507,15 @@ async fn test_flow_registration() -> Result<()> {
 
     let ix = CloudVerifierInstruction::InitRegistry {
         flow_id,
-        merkle_root: Some(flow.merkle_root),
+        merkle_root: flow.merkle_root,
         circuit_hash: flow.circuit_hash,
         callback_program_id: None,
+        nullifier_namespace: None,
+        max_verification_cu: 0,
+        attestation_policy: AttestationPolicy::Reject,
+        max_nullifiers: None,
+        nullifier_commitment: false,
+        freshness_window_slots: None,
     };
 
     let transaction = Transaction::new_signed_with_payer(
@@ -374,6 +526,8 @@ async fn test_flow_registration() -> Result<()> {
                 AccountMeta::new(payer.pubkey(), true),
                 AccountMeta::new(flow_registry_key, false),
                 AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+                AccountMeta::new_readonly(ProgramConfig::find_pda(&wave_verifier::id()).0, false),
+                AccountMeta::new(common::treasury_pda(&wave_verifier::id()), false),
             ],
         )],
         Some(&payer.pubkey()),
@@ -385,9 +539,7 @@ async fn test_flow_registration() -> Result<()> {
 
     let flow_registry = banks_client.get_account(flow_registry_key).await?.unwrap();
     let flow_data = FlowRegistry::try_from_slice(&flow_registry.data)?;
-    assert_eq!(flow_data.flow_id, flow_id);
-    assert_eq!(flow_data.merkle_root, Some(flow.merkle_root));
-    assert_eq!(flow_data.circuit_hash, flow.circuit_hash);
+    assert_eq!(Flow::from(flow_data), flow);
 
     Ok(())
 }
@@ -402,7 +554,7 @@ async fn test_proof_verification() -> Result<()> {
     let nullifier = [3u8; 32];
     
     let nullifier_key = Pubkey::find_program_address(
-        &[b"nullifier", &nullifier],
+        &[b"nullifier", &flow_id.to_le_bytes(), &nullifier],
         &wave_verifier::id(),
     ).0;
 
@@ -415,6 +567,7 @@ async fn test_proof_verification() -> Result<()> {
         proof: proof.proof_bytes,
         public_inputs: proof.public_inputs,
         nullifier,
+        mint_receipt: false,
     };
 
     let transaction = Transaction::new_signed_with_payer(
@@ -423,7 +576,7 @@ async fn test_proof_verification() -> Result<()> {
             &ix,
             vec![
                 AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new_readonly(flow_registry_key, false),
+                AccountMeta::new(flow_registry_key, false),
                 AccountMeta::new(nullifier_key, false),
                 AccountMeta::new(proof_log_key, false),
                 AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
@@ -451,7 +604,7 @@ async fn test_nullifier_tracking() -> Result<()> {
     let flow_id = 1u64;
     
     let nullifier_key = Pubkey::find_program_address(
-        &[b"nullifier", &nullifier],
+        &[b"nullifier", &flow_id.to_le_bytes(), &nullifier],
         &wave_verifier::id(),
     ).0;
 
@@ -460,6 +613,7 @@ async fn test_nullifier_tracking() -> Result<()> {
         proof: common::create_test_proof().proof_bytes,
         public_inputs: vec![1, 2, 3],
         nullifier,
+        mint_receipt: false,
     };
 
     let transaction1 = Transaction::new_signed_with_payer(
@@ -468,7 +622,7 @@ async fn test_nullifier_tracking() -> Result<()> {
             &ix1,
             vec![
                 AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new_readonly(flow_registry_key, false),
+                AccountMeta::new(flow_registry_key, false),
                 AccountMeta::new(nullifier_key, false),
                 AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
             ],
@@ -485,6 +639,7 @@ async fn test_nullifier_tracking() -> Result<()> {
         proof: common::create_test_proof().proof_bytes,
         public_inputs: vec![1, 2, 3],
         nullifier,
+        mint_receipt: false,
     };
 
     let transaction2 = Transaction::new_signed_with_payer(
@@ -493,7 +648,7 @@ async fn test_nullifier_tracking() -> Result<()> {
             &ix2,
             vec![
                 AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new_readonly(flow_registry_key, false),
+                AccountMeta::new(flow_registry_key, false),
                 AccountMeta::new(nullifier_key, false),
                 AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
             ],
@@ -544,18 +699,31 @@ async fn test_flow_trigger() -> Result<()> {
 
 #[test]
 fn test_init_registry() {
-    let flow = common::create_test_flow();
+    let key = common::test_verifying_key();
+    let flow = Flow {
+        circuit_hash: key.hash().unwrap(),
+        ..common::create_test_flow()
+    };
     
     let instruction = WaveInstruction::InitRegistry {
         flow_id: flow.id,
         merkle_root: flow.merkle_root,
         circuit_hash: flow.circuit_hash,
         callback_program_id: flow.callback_program_id,
+        nullifier_namespace: None,
+        max_verification_cu: 0,
+        attestation_policy: AttestationPolicy::Reject,
+        max_nullifiers: None,
+        nullifier_commitment: false,
+        freshness_window_slots: None,
+        proof_system: ProofSystem::Groth16,
+        verifying_key: key.try_to_vec().unwrap(),
     };
 
+    let program_id = Pubkey::new_unique();
     let authority = Pubkey::new_unique();
     let registry_account = AccountInfo::new(
-        &Pubkey::new_unique(),
+        &common::registry_pda(&program_id, flow.id),
         true,
         true,
         &mut [0u8; 1000],
@@ -589,10 +757,54 @@ fn test_init_registry() {
         ),
         registry_account.clone(),
         system_program_account,
+        // No program config yet, so no fee is charged
+        AccountInfo::new(
+            &ProgramConfig::find_pda(&program_id).0,
+            false,
+            false,
+            &mut 0,
+            &mut [],
+            &system_program::id(),
+            false,
+            0,
+        ),
+        AccountInfo::new(
+            &common::treasury_pda(&program_id),
+            false,
+            true,
+            &mut 0,
+            &mut [],
+            &system_program::id(),
+            false,
+            0,
+        ),
+        // Already stored by another flow on the circuit, so no account is
+        // created
+        AccountInfo::new(
+            &VerifyingKey::find_pda(&program_id, &flow.circuit_hash).0,
+            false,
+            true,
+            &mut 0,
+            &mut key.try_to_vec().unwrap(),
+            &program_id,
+            false,
+            0,
+        ),
+        // No allowlist yet, so any circuit may register
+        AccountInfo::new(
+            &ApprovedCircuits::find_pda(&program_id).0,
+            false,
+            false,
+            &mut 0,
+            &mut [],
+            &system_program::id(),
+            false,
+            0,
+        ),
     ];
 
     let result = wave_verifier::processor::process_instruction(
-        &Pubkey::new_unique(),
+        &program_id,
         &accounts,
         &instruction.try_to_vec().unwrap(),
     );
@@ -600,9 +812,34 @@ fn test_init_registry() {
     assert!(result.is_ok());
 
     let loaded_registry = FlowRegistry::load(&registry_account).unwrap();
-    assert_eq!(loaded_registry.flow_id, flow.id);
-    assert_eq!(loaded_registry.merkle_root, flow.merkle_root);
-    assert_eq!(loaded_registry.circuit_hash, flow.circuit_hash);
+    assert_eq!(loaded_registry, flow.clone().into_registry(authority));
+    assert_eq!(Flow::from(loaded_registry), flow);
+}
+
+#[test]
+fn test_flow_registry_conversion_round_trip() {
+    let authority = Pubkey::new_unique();
+    let callback = Pubkey::new_unique();
+
+    for (merkle_root, callback_program_id) in [
+        (None, None),
+        (Some(MERKLE_ROOT_1), None),
+        (None, Some(callback.to_bytes())),
+        (Some(MERKLE_ROOT_2), Some(callback.to_bytes())),
+    ] {
+        let flow = Flow {
+            id: FLOW_ID_2,
+            merkle_root,
+            circuit_hash: CIRCUIT_HASH_2,
+            callback_program_id,
+        };
+
+        let registry = flow.clone().into_registry(authority);
+        assert_eq!(registry.authority, authority);
+        assert_eq!(registry.merkle_root, merkle_root);
+        assert_eq!(registry.callback_program_id, callback_program_id.map(Pubkey::new_from_array));
+        assert_eq!(Flow::from(registry), flow);
+    }
 }
 
 #[test]
@@ -614,13 +851,15 @@ fn test_validate_proof() {
         proof: proof.proof_bytes,
         public_inputs: proof.public_inputs,
         nullifier: proof.nullifier,
+        mint_receipt: false,
+        nullifier_ttl_seconds: None,
     };
 
     let payer = Pubkey::new_unique();
     let registry_account = AccountInfo::new(
         &Pubkey::new_unique(),
         false,
-        false,
+        true,
         &mut [0u8; 1000],
         &mut [],
         &Pubkey::new_unique(),
@@ -697,6 +936,7 @@ fn test_validate_proof() {
 fn test_set_root() {
     let instruction = WaveInstruction::SetRoot {
         new_root: MERKLE_ROOT_2,
+        expected_sequence: 0,
     };
 
     let authority = Pubkey::new_unique();
@@ -742,6 +982,7 @@ fn test_trigger_flow() {
     let instruction = WaveInstruction::TriggerFlow {
         flow_id: FLOW_ID_1,
         instruction_data: vec![1, 2, 3],
+        nullifier: None,
     };
 
     let payer = Pubkey::new_unique();
@@ -0,0 +1,1043 @@
+use {
+    borsh::BorshDeserialize,
+    solana_program_test::*,
+    solana_sdk::{
+        account::Account,
+        clock::Clock,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::{Keypair, Signer},
+        system_program,
+        transaction::{Transaction, TransactionError},
+    },
+    wave_verifier::{
+        error::WaveError,
+        instructions::WaveInstruction,
+        state::{FlowRegistry, Nullifier, ProofLog},
+    },
+};
+
+use super::*;
+
+#[tokio::test]
+async fn test_shared_namespace_blocks_reuse_across_flows() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let namespace = 7_000u64;
+    let mut flow_a = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    flow_a.nullifier_namespace = Some(namespace);
+    let mut flow_b = FlowRegistry::new(Pubkey::new_unique(), 2, None, [2u8; 32], None);
+    flow_b.nullifier_namespace = Some(namespace);
+
+    let registry_a = common::add_registry(&mut program_test, &program_id, &flow_a);
+    let registry_b = common::add_registry(&mut program_test, &program_id, &flow_b);
+
+    // Both flows derive the same nullifier PDA from the shared namespace
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_namespace_pda(&program_id, namespace, &nullifier);
+    assert_eq!(flow_b.find_nullifier_pda(&program_id, &nullifier).0, nullifier_key);
+    // but each keeps its own proof log
+    let proof_log_a = common::proof_log_pda(&program_id, flow_a.flow_id, &nullifier);
+    let proof_log_b = common::proof_log_pda(&program_id, flow_b.flow_id, &nullifier);
+    assert_ne!(proof_log_a, proof_log_b);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let spend = |registry, proof_log_key| {
+        Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                nullifier_key,
+                proof_log_key,
+                nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        )
+    };
+
+    banks_client.process_transaction(spend(registry_a, proof_log_a)).await.unwrap();
+
+    let err = banks_client
+        .process_transaction(spend(registry_b, proof_log_b))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32),
+        )
+    );
+
+    let account = banks_client.get_account(nullifier_key).await.unwrap().unwrap();
+    let recorded = Nullifier::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(recorded.flow_id, flow_a.flow_id);
+}
+
+#[tokio::test]
+async fn test_flows_without_namespace_keep_separate_nullifiers() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow_a = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let flow_b = FlowRegistry::new(Pubkey::new_unique(), 2, None, [2u8; 32], None);
+    let registry_a = common::add_registry(&mut program_test, &program_id, &flow_a);
+    let registry_b = common::add_registry(&mut program_test, &program_id, &flow_b);
+
+    let nullifier = [9u8; 32];
+    let (nullifier_a, _) = Nullifier::find_pda(&program_id, flow_a.flow_id, &nullifier);
+    let (nullifier_b, _) = Nullifier::find_pda(&program_id, flow_b.flow_id, &nullifier);
+    assert_ne!(nullifier_a, nullifier_b);
+
+    let proof_log_a = common::proof_log_pda(&program_id, flow_a.flow_id, &nullifier);
+    let proof_log_b = common::proof_log_pda(&program_id, flow_b.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let spends = [
+        (registry_a, nullifier_a, proof_log_a, flow_a.flow_id),
+        (registry_b, nullifier_b, proof_log_b, flow_b.flow_id),
+    ];
+    for (registry, nullifier_key, proof_log_key, _) in spends {
+        let transaction = Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                nullifier_key,
+                proof_log_key,
+                nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        banks_client.process_transaction(transaction).await.unwrap();
+    }
+
+    // Neither flow's log was overwritten by the other's proof
+    for (_, _, proof_log_key, flow_id) in spends {
+        let account = banks_client.get_account(proof_log_key).await.unwrap().unwrap();
+        let log = ProofLog::deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!((log.nullifier, log.flow_id), (nullifier, flow_id));
+    }
+}
+
+#[tokio::test]
+async fn test_commitment_mode_seeds_pda_with_commitment() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let mut flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    flow.nullifier_commitment = true;
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifier = [9u8; 32];
+    let commitment = Nullifier::commitment(&nullifier, flow.flow_id);
+    let (raw_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &commitment);
+    assert_ne!(raw_key, nullifier_key);
+
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let spend = |nullifier_account| {
+        Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                nullifier_account,
+                proof_log_key,
+                nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        )
+    };
+
+    // The raw-seeded PDA is not where this flow records nullifiers
+    let err = banks_client.process_transaction(spend(raw_key)).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::InvalidNullifier as u32),
+        )
+    );
+
+    banks_client.process_transaction(spend(nullifier_key)).await.unwrap();
+    let recorded = load_nullifier(&mut banks_client, nullifier_key).await;
+    assert_eq!(recorded.hash, commitment);
+
+    let err = banks_client
+        .process_transaction(spend(nullifier_key))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32),
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_validate_rejects_non_pda_nullifier_account() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    // A funded, program-owned account that is not the nullifier's PDA
+    let nullifier = [9u8; 32];
+    let impostor = Pubkey::new_unique();
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+    common::add_empty_account(&mut program_test, &program_id, impostor, Nullifier::SIZE);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let spend = |nullifier_account| {
+        Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                nullifier_account,
+                proof_log_key,
+                nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        )
+    };
+
+    let err = banks_client.process_transaction(spend(impostor)).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::InvalidNullifier as u32),
+        )
+    );
+    let account = banks_client.get_account(impostor).await.unwrap().unwrap();
+    assert!(account.data.iter().all(|byte| *byte == 0));
+
+    // The nullifier is still unspent at its PDA
+    banks_client.process_transaction(spend(nullifier_key)).await.unwrap();
+    assert_eq!(load_nullifier(&mut banks_client, nullifier_key).await.hash, nullifier);
+}
+
+#[tokio::test]
+async fn test_zero_nullifier_is_rejected() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let authority = Keypair::new();
+    let flow = FlowRegistry::new(authority.pubkey(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    // Recorded raw, it would be indistinguishable from the zeroed account
+    let nullifier = [0u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let validate = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            registry,
+            nullifier_key,
+            proof_log_key,
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let import = Transaction::new_signed_with_payer(
+        &[import_nullifiers_ix(&program_id, &authority.pubkey(), registry, flow.flow_id, vec![nullifier])],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+
+    for transaction in [validate, import] {
+        let err = banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(WaveError::InvalidNullifier as u32),
+            )
+        );
+    }
+    assert!(banks_client.get_account(nullifier_key).await.unwrap().is_none());
+}
+
+fn import_nullifiers_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    flow_registry: Pubkey,
+    scope: u64,
+    nullifiers: Vec<[u8; 32]>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new_readonly(flow_registry, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.extend(nullifiers.iter().map(|nullifier| {
+        AccountMeta::new(Nullifier::find_pda(program_id, scope, nullifier).0, false)
+    }));
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::ImportNullifiers { nullifiers },
+        accounts,
+    )
+}
+
+async fn process_with_logs(banks_client: &mut BanksClient, transaction: Transaction) -> Vec<String> {
+    let result = banks_client
+        .process_transaction_with_metadata(transaction)
+        .await
+        .unwrap();
+    result.result.unwrap();
+    result.metadata.unwrap().log_messages
+}
+
+#[tokio::test]
+async fn test_import_nullifiers_skips_present_ones() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    // The authority pays for the nullifier PDAs the import creates
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account {
+            lamports: 1_000_000_000,
+            owner: system_program::id(),
+            ..Account::default()
+        },
+    );
+    let flow = FlowRegistry::new(authority.pubkey(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifiers = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let import = |batch: Vec<[u8; 32]>| {
+        Transaction::new_signed_with_payer(
+            &[import_nullifiers_ix(&program_id, &authority.pubkey(), registry, flow.flow_id, batch)],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        )
+    };
+
+    let logs = process_with_logs(&mut banks_client, import(vec![nullifiers[0]])).await;
+    assert!(logs.iter().any(|log| log.ends_with("  imported: 1")));
+
+    // nullifiers[0] is already present and nullifiers[2] repeats in the batch
+    let batch = vec![nullifiers[0], nullifiers[1], nullifiers[2], nullifiers[2], nullifiers[3]];
+    let logs = process_with_logs(&mut banks_client, import(batch)).await;
+    assert!(logs.iter().any(|log| log.ends_with("  imported: 3")));
+
+    for nullifier in nullifiers {
+        let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+        let transaction = Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                nullifier_key,
+                common::proof_log_pda(&program_id, flow.flow_id, &nullifier),
+                nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let err = banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32),
+            )
+        );
+    }
+}
+
+fn trigger_flow_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    flow_registry: Pubkey,
+    flow_id: u64,
+    nullifier_account: Pubkey,
+    nullifier: [u8; 32],
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::TriggerFlow {
+            flow_id,
+            instruction_data: vec![],
+            nullifier: Some(nullifier),
+        },
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(flow_registry, false),
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            AccountMeta::new(nullifier_account, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+async fn load_nullifier(banks_client: &mut BanksClient, key: Pubkey) -> Nullifier {
+    let account = banks_client.get_account(key).await.unwrap().unwrap();
+    Nullifier::deserialize(&mut account.data.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn test_validate_records_proof_log_without_trigger() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            registry,
+            nullifier_key,
+            proof_log_key,
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let recorded = load_nullifier(&mut banks_client, nullifier_key).await;
+    assert_eq!(recorded.proof_log, proof_log_key);
+    assert_eq!(recorded.version, Nullifier::VERSION);
+    assert_eq!(recorded.triggered_at, None);
+}
+
+#[tokio::test]
+async fn test_trigger_consumes_validated_nullifier_once() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[
+            common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                nullifier_key,
+                proof_log_key,
+                nullifier,
+            ),
+            trigger_flow_ix(&program_id, &payer.pubkey(), registry, 1, nullifier_key, nullifier),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let recorded = load_nullifier(&mut banks_client, nullifier_key).await;
+    assert_eq!(recorded.proof_log, proof_log_key);
+    assert!(recorded.triggered_at.is_some());
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[trigger_flow_ix(&program_id, &payer.pubkey(), registry, 1, nullifier_key, nullifier)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32),
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_legacy_nullifier_is_migrated_on_trigger() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    // Written before the proof log reference existed: hash, timestamp, flow_id
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let mut legacy = nullifier.to_vec();
+    legacy.extend_from_slice(&1_000i64.to_le_bytes());
+    legacy.extend_from_slice(&flow.flow_id.to_le_bytes());
+    program_test.add_account(
+        nullifier_key,
+        Account {
+            lamports: Rent::default().minimum_balance(Nullifier::LEGACY_SIZE),
+            data: legacy,
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // Still counts as spent
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            registry,
+            nullifier_key,
+            proof_log_key,
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32),
+        )
+    );
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[trigger_flow_ix(&program_id, &payer.pubkey(), registry, 1, nullifier_key, nullifier)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = banks_client.get_account(nullifier_key).await.unwrap().unwrap();
+    assert_eq!(account.data.len(), Nullifier::account_size());
+    assert!(account.lamports >= Rent::default().minimum_balance(Nullifier::account_size()));
+
+    let migrated = load_nullifier(&mut banks_client, nullifier_key).await;
+    assert_eq!(migrated.hash, nullifier);
+    assert_eq!(migrated.timestamp, 1_000);
+    assert_eq!(migrated.flow_id, flow.flow_id);
+    assert_eq!(migrated.proof_log, Pubkey::default());
+    assert_eq!(migrated.version, Nullifier::VERSION);
+    assert!(migrated.triggered_at.is_some());
+}
+
+/// Pre-loads a nullifier recorded under the pre-scoping seeds, in the legacy
+/// layout: hash, timestamp, flow_id.
+fn add_legacy_nullifier(
+    program_test: &mut ProgramTest,
+    program_id: &Pubkey,
+    flow_id: u64,
+    nullifier: [u8; 32],
+) -> Pubkey {
+    let (key, _) = Nullifier::find_legacy_pda(program_id, &nullifier);
+    let mut legacy = nullifier.to_vec();
+    legacy.extend_from_slice(&1_000i64.to_le_bytes());
+    legacy.extend_from_slice(&flow_id.to_le_bytes());
+    program_test.add_account(
+        key,
+        Account {
+            lamports: Rent::default().minimum_balance(Nullifier::LEGACY_SIZE),
+            data: legacy,
+            owner: *program_id,
+            ..Account::default()
+        },
+    );
+    key
+}
+
+fn migrate_nullifier_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    registry: &FlowRegistry,
+    nullifier: [u8; 32],
+) -> Instruction {
+    let (legacy_key, _) = Nullifier::find_legacy_pda(program_id, &nullifier);
+    let (nullifier_key, _) = registry.find_nullifier_pda(program_id, &registry.nullifier_key(&nullifier));
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::MigrateNullifier { nullifier },
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(common::registry_pda(program_id, registry.flow_id), false),
+            AccountMeta::new(legacy_key, false),
+            AccountMeta::new(nullifier_key, false),
+            AccountMeta::new(common::treasury_pda(program_id), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// Cranks `MigrateNullifier` over `nullifiers`, a few per transaction.
+async fn migrate_nullifiers(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: solana_sdk::hash::Hash,
+    program_id: &Pubkey,
+    registry: &FlowRegistry,
+    nullifiers: &[[u8; 32]],
+) -> Result<(), BanksClientError> {
+    for chunk in nullifiers.chunks(4) {
+        let instructions: Vec<_> = chunk
+            .iter()
+            .map(|nullifier| migrate_nullifier_ix(program_id, &payer.pubkey(), registry, *nullifier))
+            .collect();
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            recent_blockhash,
+        );
+        banks_client.process_transaction(transaction).await?;
+    }
+    Ok(())
+}
+
+/// ValidateProof with the legacy PDA appended, as required until the flow
+/// finalizes its migration.
+fn validate_proof_in_window_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    registry: &FlowRegistry,
+    nullifier: [u8; 32],
+) -> Instruction {
+    let (nullifier_key, _) = registry.find_nullifier_pda(program_id, &registry.nullifier_key(&nullifier));
+    let mut ix = common::validate_proof_ix(
+        program_id,
+        payer,
+        common::registry_pda(program_id, registry.flow_id),
+        nullifier_key,
+        common::proof_log_pda(program_id, registry.flow_id, &nullifier),
+        nullifier,
+    );
+    let (legacy_key, _) = Nullifier::find_legacy_pda(program_id, &nullifier);
+    ix.accounts.push(AccountMeta::new_readonly(legacy_key, false));
+    ix
+}
+
+#[tokio::test]
+async fn test_legacy_pda_is_checked_until_migration_finalized() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let mut flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    flow.migration_complete = false;
+    common::add_registry(&mut program_test, &program_id, &flow);
+
+    // Spent only under the legacy seeds; the scoped PDA doesn't exist yet
+    let nullifier = [9u8; 32];
+    add_legacy_nullifier(&mut program_test, &program_id, flow.flow_id, nullifier);
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[validate_proof_in_window_ix(&program_id, &payer.pubkey(), &flow, nullifier)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32),
+        )
+    );
+
+    // Omitting the legacy PDA is not a way around the check
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            common::registry_pda(&program_id, flow.flow_id),
+            nullifier_key,
+            common::proof_log_pda(&program_id, flow.flow_id, &nullifier),
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+    );
+}
+
+#[tokio::test]
+async fn test_migrate_nullifier_moves_record_and_closes_legacy() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let mut flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    flow.migration_complete = false;
+    common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifiers: Vec<[u8; 32]> = (1..=6u8).map(|i| [i; 32]).collect();
+    for nullifier in &nullifiers {
+        add_legacy_nullifier(&mut program_test, &program_id, flow.flow_id, *nullifier);
+    }
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    migrate_nullifiers(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &program_id,
+        &flow,
+        &nullifiers,
+    )
+    .await
+    .unwrap();
+
+    for nullifier in &nullifiers {
+        let (legacy_key, _) = Nullifier::find_legacy_pda(&program_id, nullifier);
+        assert!(banks_client.get_account(legacy_key).await.unwrap().is_none());
+
+        let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, nullifier);
+        let migrated = load_nullifier(&mut banks_client, nullifier_key).await;
+        assert_eq!(migrated.hash, *nullifier);
+        assert_eq!(migrated.timestamp, 1_000);
+        assert_eq!(migrated.flow_id, flow.flow_id);
+    }
+
+    let treasury = banks_client.get_account(common::treasury_pda(&program_id)).await.unwrap().unwrap();
+    assert_eq!(
+        treasury.lamports,
+        Rent::default().minimum_balance(Nullifier::LEGACY_SIZE) * nullifiers.len() as u64
+    );
+
+    // Already moved, so a second crank has no legacy record to read
+    let transaction = Transaction::new_signed_with_payer(
+        &[migrate_nullifier_ix(&program_id, &payer.pubkey(), &flow, nullifiers[0])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::IllegalOwner)
+    );
+}
+
+#[tokio::test]
+async fn test_migrated_commitment_nullifier_stays_spent() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let mut flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    flow.migration_complete = false;
+    flow.nullifier_commitment = true;
+    common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifier = [9u8; 32];
+    add_legacy_nullifier(&mut program_test, &program_id, flow.flow_id, nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    migrate_nullifiers(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &program_id,
+        &flow,
+        &[nullifier],
+    )
+    .await
+    .unwrap();
+
+    // Moved to the commitment's PDA, where ValidateProof looks
+    let commitment = flow.nullifier_key(&nullifier);
+    assert_ne!(commitment, nullifier);
+    let (nullifier_key, _) = flow.find_nullifier_pda(&program_id, &commitment);
+    assert_eq!(load_nullifier(&mut banks_client, nullifier_key).await.hash, commitment);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[validate_proof_in_window_ix(&program_id, &payer.pubkey(), &flow, nullifier)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32),
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_finalized_migration_drops_legacy_check() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let authority = Keypair::new();
+    let mut flow = FlowRegistry::new(authority.pubkey(), 1, None, [1u8; 32], None);
+    flow.migration_complete = false;
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let stale = [8u8; 32];
+    add_legacy_nullifier(&mut program_test, &program_id, flow.flow_id, stale);
+
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[Instruction::new_with_borsh(
+            program_id,
+            &WaveInstruction::FinalizeNullifierMigration { flow_id: flow.flow_id },
+            vec![
+                AccountMeta::new_readonly(authority.pubkey(), true),
+                AccountMeta::new(registry, false),
+            ],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = banks_client.get_account(registry).await.unwrap().unwrap();
+    let finalized = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+    assert!(finalized.migration_complete);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[migrate_nullifier_ix(&program_id, &payer.pubkey(), &flow, stale)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::MigrationComplete as u32),
+        )
+    );
+
+    // No legacy PDA needed any more
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            registry,
+            nullifier_key,
+            proof_log_key,
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+}
+
+/// ValidateProof for `flow` whose nullifier expires `ttl` seconds later
+fn validate_with_ttl_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    flow: &FlowRegistry,
+    nullifier: [u8; 32],
+    ttl: i64,
+) -> Instruction {
+    let (nullifier_key, _) = flow.find_nullifier_pda(program_id, &nullifier);
+    let accounts = common::validate_proof_ix(
+        program_id,
+        payer,
+        common::registry_pda(program_id, flow.flow_id),
+        nullifier_key,
+        common::proof_log_pda(program_id, flow.flow_id, &nullifier),
+        nullifier,
+    )
+    .accounts;
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::ValidateProof {
+            proof: vec![1u8; 128],
+            public_inputs: vec![1u8; 32],
+            nullifier,
+            mint_receipt: false,
+            nullifier_ttl_seconds: Some(ttl),
+        },
+        accounts,
+    )
+}
+
+async fn process(context: &mut ProgramTestContext, ix: Instruction) -> Result<(), BanksClientError> {
+    let transaction = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await
+}
+
+#[tokio::test]
+async fn test_expired_nullifier_can_be_proven_again() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.pubkey();
+
+    let expect_error = |result: Result<(), BanksClientError>, error: WaveError| {
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::Custom(error as u32)),
+        );
+    };
+
+    // A TTL must be positive
+    expect_error(
+        process(&mut context, validate_with_ttl_ix(&program_id, &payer, &flow, nullifier, 0)).await,
+        WaveError::InvalidInstruction,
+    );
+
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    process(&mut context, validate_with_ttl_ix(&program_id, &payer, &flow, nullifier, 60))
+        .await
+        .unwrap();
+    let recorded = load_nullifier(&mut context.banks_client, nullifier_key).await;
+    let expires_at = recorded.expires_at.unwrap();
+    assert!(expires_at >= clock.unix_timestamp + 60);
+
+    // Until it expires the nullifier counts as used
+    context.last_blockhash = context.get_new_latest_blockhash().await.unwrap();
+    expect_error(
+        process(&mut context, validate_with_ttl_ix(&program_id, &payer, &flow, nullifier, 60)).await,
+        WaveError::NullifierAlreadyUsed,
+    );
+
+    context.last_blockhash = context.get_new_latest_blockhash().await.unwrap();
+    context.set_sysvar(&Clock { unix_timestamp: expires_at, ..clock });
+
+    // Past its TTL it can no longer be triggered...
+    expect_error(
+        process(
+            &mut context,
+            trigger_flow_ix(&program_id, &payer, registry, 1, nullifier_key, nullifier),
+        )
+        .await,
+        WaveError::NullifierExpired,
+    );
+
+    // ...but a new proof records it afresh, here without an expiry
+    process(
+        &mut context,
+        common::validate_proof_ix(&program_id, &payer, registry, nullifier_key, proof_log_key, nullifier),
+    )
+    .await
+    .unwrap();
+    let recorded = load_nullifier(&mut context.banks_client, nullifier_key).await;
+    assert!(recorded.timestamp >= expires_at);
+    assert_eq!(recorded.expires_at, None);
+    assert_eq!(recorded.triggered_at, None);
+}
\ No newline at end of file
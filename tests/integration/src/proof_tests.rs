@@ -0,0 +1,1186 @@
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program_test::*,
+    solana_sdk::{
+        account::Account,
+        account_info::AccountInfo,
+        compute_budget::ComputeBudgetInstruction,
+        hash::Hash,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+        system_program,
+        transaction::{Transaction, TransactionError},
+    },
+    wave_verifier::{
+        constants::{ATTESTATION_RECIPIENT_OFFSET, PROOF_SLOT_OFFSET},
+        error::WaveError,
+        instructions::{BatchProofEntry, WaveInstruction},
+        state::{
+            has_attestation, Attestation, AttestationPolicy, FlowRegistry, IncrementalMerkleTree,
+            Nullifier, ProofLog, ProofLogCommitment,
+        },
+        verifier::verification_cost,
+    },
+};
+
+use super::*;
+
+#[tokio::test]
+async fn test_proof_log_failure_leaves_nullifier_unspent() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+    // One byte short, so writing the proof log fails
+    common::add_empty_account(&mut program_test, &program_id, proof_log_key, ProofLog::SIZE - 1);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            registry,
+            nullifier_key,
+            proof_log_key,
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::AccountDataTooSmall)
+    );
+
+    assert!(banks_client.get_account(nullifier_key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_proof_log_records_validating_circuit() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flows = [
+        FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None),
+        FlowRegistry::new(Pubkey::new_unique(), 2, None, [2u8; 32], None),
+    ];
+    let mut spends = Vec::new();
+    for (flow, nullifier) in flows.iter().zip([[7u8; 32], [8u8; 32]]) {
+        common::add_registry(&mut program_test, &program_id, flow);
+        let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+        let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+        spends.push((flow, nullifier, nullifier_key, proof_log_key));
+    }
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    for (flow, nullifier, nullifier_key, proof_log_key) in spends {
+        let transaction = Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                common::registry_pda(&program_id, flow.flow_id),
+                nullifier_key,
+                proof_log_key,
+                nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        let account = banks_client.get_account(proof_log_key).await.unwrap().unwrap();
+        let log = ProofLog::deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!(log.flow_id, flow.flow_id);
+        assert_eq!(log.circuit_hash, flow.circuit_hash);
+        assert_eq!(log.proof_system, flow.proof_system);
+    }
+}
+
+#[tokio::test]
+async fn test_compute_cap_fails_before_verification() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let mut flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    flow.max_verification_cu = 500_000;
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // Leave far less compute than the flow says verification needs
+    let transaction = Transaction::new_signed_with_payer(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(50_000),
+            common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                nullifier_key,
+                proof_log_key,
+                nullifier,
+            ),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(WaveError::InsufficientCompute as u32),
+        )
+    );
+
+    assert!(banks_client.get_account(nullifier_key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_pairing_cost_is_required_without_compute_cap() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    // No max_verification_cu: the verifier's own estimate still applies
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    assert!(verification_cost(32) > 50_000);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(50_000),
+            common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                nullifier_key,
+                proof_log_key,
+                nullifier,
+            ),
+        ],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(WaveError::InsufficientCompute as u32),
+        )
+    );
+
+    // The default limit covers one proof
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            registry,
+            nullifier_key,
+            proof_log_key,
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_nullifier_cap_rejects_further_proofs() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let mut flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    flow.max_nullifiers = Some(2);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifiers = [[1u8; 32], [2u8; 32], [3u8; 32]];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut results = Vec::new();
+    for nullifier in nullifiers {
+        let transaction = Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                Nullifier::find_pda(&program_id, flow.flow_id, &nullifier).0,
+                common::proof_log_pda(&program_id, flow.flow_id, &nullifier),
+                nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        results.push(banks_client.process_transaction(transaction).await);
+    }
+
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+    assert_eq!(
+        results.pop().unwrap().unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::FlowCapacityReached as u32),
+        )
+    );
+
+    let account = banks_client.get_account(registry).await.unwrap().unwrap();
+    let stored = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(stored.nullifier_count, 2);
+
+    let (rejected_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifiers[2]);
+    assert!(banks_client.get_account(rejected_key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_only_enabled_registered_flows_accept_proofs() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let enabled = FlowRegistry::new(Pubkey::new_unique(), 7, None, [1u8; 32], None);
+    let mut disabled = FlowRegistry::new(Pubkey::new_unique(), 8, None, [1u8; 32], None);
+    disabled.is_enabled = false;
+    let enabled_registry = common::add_registry(&mut program_test, &program_id, &enabled);
+    let disabled_registry = common::add_registry(&mut program_test, &program_id, &disabled);
+    let uninitialized_registry = common::registry_pda(&program_id, 9);
+    common::add_empty_account(&mut program_test, &program_id, uninitialized_registry, FlowRegistry::SIZE);
+
+    let cases = [
+        (enabled_registry, 7, [1u8; 32]),
+        (disabled_registry, 8, [2u8; 32]),
+        (uninitialized_registry, 9, [3u8; 32]),
+    ];
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut results = Vec::new();
+    for (registry, flow_id, nullifier) in cases {
+        let transaction = Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                Nullifier::find_pda(&program_id, flow_id, &nullifier).0,
+                common::proof_log_pda(&program_id, flow_id, &nullifier),
+                nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        results.push(banks_client.process_transaction(transaction).await);
+    }
+
+    let mut results = results.into_iter();
+    assert!(results.next().unwrap().is_ok());
+    assert_eq!(
+        results.next().unwrap().unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::FlowDisabled as u32))
+    );
+    assert_eq!(
+        results.next().unwrap().unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InvalidAccountData as u32))
+    );
+
+    // The nullifier and log are attributed to the registered flow
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, 7, &[1u8; 32]);
+    let account = banks_client.get_account(nullifier_key).await.unwrap().unwrap();
+    assert_eq!(Nullifier::deserialize(&mut account.data.as_slice()).unwrap().flow_id, 7);
+    let account = banks_client
+        .get_account(common::proof_log_pda(&program_id, 7, &[1u8; 32]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(ProofLog::deserialize(&mut account.data.as_slice()).unwrap().flow_id, 7);
+}
+
+#[tokio::test]
+async fn test_accounts_off_their_pdas_are_rejected() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+
+    let flow = FlowRegistry::new(authority.pubkey(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+    // A program-owned registry copy and log account, each at a key no seed
+    // derives
+    let registry_copy = Pubkey::new_unique();
+    program_test.add_account(
+        registry_copy,
+        Account {
+            lamports: 1_000_000_000,
+            data: flow.try_to_vec().unwrap(),
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+    let stray_log = Pubkey::new_unique();
+    common::add_empty_account(&mut program_test, &program_id, stray_log, ProofLog::SIZE);
+
+    let nullifier = [1u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(&program_id, &payer.pubkey(), registry, nullifier_key, stray_log, nullifier)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client.process_transaction(transaction).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InvalidPda as u32))
+    );
+    assert!(banks_client.get_account(nullifier_key).await.unwrap().is_none());
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[Instruction::new_with_borsh(
+            program_id,
+            &WaveInstruction::SetRoot { new_root: [0xEE; 32], expected_sequence: 0 },
+            vec![
+                AccountMeta::new_readonly(authority.pubkey(), true),
+                AccountMeta::new(registry_copy, false),
+            ],
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let err = banks_client.process_transaction(transaction).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InvalidPda as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_foreign_owned_registry_is_rejected() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    // Deserializes as a valid registry but belongs to another program
+    let forged = FlowRegistry::new(Pubkey::new_unique(), 1, Some([0xEE; 32]), [1u8; 32], None);
+    let registry = Pubkey::new_unique();
+    program_test.add_account(
+        registry,
+        Account {
+            lamports: 1_000_000_000,
+            data: forged.try_to_vec().unwrap(),
+            owner: Pubkey::new_unique(),
+            ..Account::default()
+        },
+    );
+
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, forged.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, forged.flow_id, &nullifier);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            registry,
+            nullifier_key,
+            proof_log_key,
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::IllegalOwner)
+    );
+
+    assert!(banks_client.get_account(nullifier_key).await.unwrap().is_none());
+}
+
+/// Registers flow 1 under `authority` with `policy`
+fn add_attestation_flow(
+    program_test: &mut ProgramTest,
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    policy: AttestationPolicy,
+) {
+    let mut flow = FlowRegistry::new(*authority, 1, None, [1u8; 32], None);
+    flow.attestation_policy = policy;
+    common::add_registry(program_test, program_id, &flow);
+}
+
+/// ValidateProof for flow 1 that mints an attestation for `recipient`
+fn validate_with_receipt_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    nullifier: [u8; 32],
+    recipient: &Pubkey,
+) -> Instruction {
+    let mut public_inputs = vec![1u8; ATTESTATION_RECIPIENT_OFFSET];
+    public_inputs.extend_from_slice(&Attestation::recipient_public_inputs(recipient));
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::ValidateProof {
+            proof: vec![1u8; 128],
+            public_inputs,
+            nullifier,
+            mint_receipt: true,
+            nullifier_ttl_seconds: None,
+        },
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(common::registry_pda(program_id, 1), false),
+            AccountMeta::new(Nullifier::find_pda(program_id, 1, &nullifier).0, false),
+            AccountMeta::new(common::proof_log_pda(program_id, 1, &nullifier), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(common::verifying_key(), false),
+            AccountMeta::new(Attestation::find_pda(program_id, 1, recipient).0, false),
+        ],
+    )
+}
+
+fn revoke_attestation_ix(program_id: &Pubkey, authority: &Pubkey, recipient: &Pubkey) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::RevokeAttestation { flow_id: 1, recipient: *recipient },
+        vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new_readonly(common::registry_pda(program_id, 1), false),
+            AccountMeta::new(Attestation::find_pda(program_id, 1, recipient).0, false),
+        ],
+    )
+}
+
+/// Runs the CPI-side `has_attestation` check against the account as stored
+async fn attested(banks_client: &mut BanksClient, program_id: &Pubkey, wallet: &Pubkey) -> bool {
+    let key = Attestation::find_pda(program_id, 1, wallet).0;
+    let account = banks_client.get_account(key).await.unwrap().unwrap_or_default();
+    let (mut lamports, mut data) = (account.lamports, account.data);
+    let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &account.owner, false, 0);
+    has_attestation(program_id, &info, 1, wallet).unwrap()
+}
+
+async fn load_attestation(banks_client: &mut BanksClient, program_id: &Pubkey, wallet: &Pubkey) -> Attestation {
+    let key = Attestation::find_pda(program_id, 1, wallet).0;
+    let account = banks_client.get_account(key).await.unwrap().unwrap();
+    Attestation::deserialize(&mut account.data.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn test_validate_proof_mints_attestation() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let nullifier = [9u8; 32];
+    add_attestation_flow(
+        &mut program_test,
+        &program_id,
+        &Pubkey::new_unique(),
+        AttestationPolicy::Reject,
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let recipient = Pubkey::new_unique();
+    assert!(!attested(&mut banks_client, &program_id, &recipient).await);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[validate_with_receipt_ix(&program_id, &payer.pubkey(), nullifier, &recipient)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let attestation = load_attestation(&mut banks_client, &program_id, &recipient).await;
+    assert_eq!(attestation.flow_id, 1);
+    assert_eq!(attestation.recipient, recipient);
+    assert_eq!(attestation.nullifier, nullifier);
+    assert_eq!(attestation.proof_log, common::proof_log_pda(&program_id, 1, &nullifier));
+    assert_eq!(attestation.revoked_at, None);
+
+    assert!(attested(&mut banks_client, &program_id, &recipient).await);
+    assert!(!attested(&mut banks_client, &program_id, &payer.pubkey()).await);
+}
+
+#[tokio::test]
+async fn test_attestation_recipient_above_field_modulus() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    add_attestation_flow(&mut program_test, &program_id, &Pubkey::new_unique(), AttestationPolicy::Reject);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // A top byte this high puts the key past the BN254 modulus, so it can
+    // only reach the circuit split in two
+    let mut key = Pubkey::new_unique().to_bytes();
+    key[0] = 0xFF;
+    let recipient = Pubkey::new_from_array(key);
+
+    // Sent whole, its padding check fails
+    let mut whole = validate_with_receipt_ix(&program_id, &payer.pubkey(), [9u8; 32], &recipient);
+    let mut instruction = WaveInstruction::try_from_slice(&whole.data).unwrap();
+    if let WaveInstruction::ValidateProof { public_inputs, .. } = &mut instruction {
+        public_inputs.truncate(ATTESTATION_RECIPIENT_OFFSET);
+        public_inputs.extend_from_slice(recipient.as_ref());
+        public_inputs.extend_from_slice(&[0u8; 32]);
+    }
+    whole.data = instruction.try_to_vec().unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[whole],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client.process_transaction(transaction).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::InvalidInstruction as u32))
+    );
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[validate_with_receipt_ix(&program_id, &payer.pubkey(), [9u8; 32], &recipient)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    assert_eq!(load_attestation(&mut banks_client, &program_id, &recipient).await.recipient, recipient);
+    assert!(attested(&mut banks_client, &program_id, &recipient).await);
+}
+
+#[tokio::test]
+async fn test_attestation_duplicate_policy() {
+    for policy in [AttestationPolicy::Reject, AttestationPolicy::Refresh] {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = common::program_test(program_id);
+        let (first, second) = ([9u8; 32], [10u8; 32]);
+        add_attestation_flow(
+            &mut program_test,
+            &program_id,
+            &Pubkey::new_unique(),
+            policy,
+        );
+
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let recipient = Pubkey::new_unique();
+
+        let transaction = Transaction::new_signed_with_payer(
+            &[validate_with_receipt_ix(&program_id, &payer.pubkey(), first, &recipient)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        // The same recipient proves again with a fresh nullifier
+        let transaction = Transaction::new_signed_with_payer(
+            &[validate_with_receipt_ix(&program_id, &payer.pubkey(), second, &recipient)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let result = banks_client.process_transaction(transaction).await;
+
+        let attestation = load_attestation(&mut banks_client, &program_id, &recipient).await;
+        match policy {
+            AttestationPolicy::Reject => {
+                assert_eq!(
+                    result.unwrap_err().unwrap(),
+                    TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(WaveError::AttestationExists as u32),
+                    )
+                );
+                assert_eq!(attestation.nullifier, first);
+            }
+            AttestationPolicy::Refresh => {
+                result.unwrap();
+                assert_eq!(attestation.nullifier, second);
+                assert_eq!(attestation.proof_log, common::proof_log_pda(&program_id, 1, &second));
+            }
+        }
+        assert!(attested(&mut banks_client, &program_id, &recipient).await);
+    }
+}
+
+#[tokio::test]
+async fn test_revoked_attestation_fails_check_and_refresh() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+    let authority = Keypair::new();
+    let (first, second) = ([9u8; 32], [10u8; 32]);
+    add_attestation_flow(
+        &mut program_test,
+        &program_id,
+        &authority.pubkey(),
+        AttestationPolicy::Refresh,
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let recipient = Pubkey::new_unique();
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[validate_with_receipt_ix(&program_id, &payer.pubkey(), first, &recipient)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // Only the flow authority may revoke
+    let transaction = Transaction::new_signed_with_payer(
+        &[revoke_attestation_ix(&program_id, &payer.pubkey(), &recipient)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(WaveError::Unauthorized as u32))
+    );
+    assert!(attested(&mut banks_client, &program_id, &recipient).await);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[revoke_attestation_ix(&program_id, &authority.pubkey(), &recipient)],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    assert!(!attested(&mut banks_client, &program_id, &recipient).await);
+    assert!(load_attestation(&mut banks_client, &program_id, &recipient).await.revoked_at.is_some());
+
+    // Revocation sticks even on a flow that allows refreshing
+    let transaction = Transaction::new_signed_with_payer(
+        &[validate_with_receipt_ix(&program_id, &payer.pubkey(), second, &recipient)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(WaveError::AttestationExists as u32),
+        )
+    );
+    assert!(!attested(&mut banks_client, &program_id, &recipient).await);
+}
+
+/// ValidateProof for flow 1 whose public inputs commit to `slot`
+fn validate_at_slot_ix(program_id: &Pubkey, payer: &Pubkey, nullifier: [u8; 32], slot: u64) -> Instruction {
+    let mut public_inputs = vec![1u8; PROOF_SLOT_OFFSET + 24];
+    public_inputs[PROOF_SLOT_OFFSET..].fill(0);
+    public_inputs.extend_from_slice(&slot.to_be_bytes());
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::ValidateProof {
+            proof: vec![1u8; 128],
+            public_inputs,
+            nullifier,
+            mint_receipt: false,
+            nullifier_ttl_seconds: None,
+        },
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(common::registry_pda(program_id, 1), false),
+            AccountMeta::new(Nullifier::find_pda(program_id, 1, &nullifier).0, false),
+            AccountMeta::new(common::proof_log_pda(program_id, 1, &nullifier), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(common::verifying_key(), false),
+        ],
+    )
+}
+
+/// Warps to `slot` and submits a proof for flow 1 committing to `proof_slot`
+async fn validate_at_slot(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    nullifier: [u8; 32],
+    proof_slot: u64,
+    slot: u64,
+) -> Result<(), BanksClientError> {
+    context.warp_to_slot(slot).unwrap();
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[validate_at_slot_ix(program_id, &context.payer.pubkey(), nullifier, proof_slot)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await
+}
+
+#[tokio::test]
+async fn test_freshness_window_boundaries() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let mut flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    flow.freshness_window_slots = Some(100);
+    common::add_registry(&mut program_test, &program_id, &flow);
+
+    let (fresh, stale) = ([1u8; 32], [2u8; 32]);
+
+    let mut context = program_test.start_with_context().await;
+    let proof_slot = 1_000;
+    let expired = TransactionError::InstructionError(
+        0,
+        InstructionError::Custom(WaveError::ProofExpired as u32),
+    );
+
+    // A slot the clock has not reached yet
+    let err = validate_at_slot(&mut context, &program_id, fresh, proof_slot, proof_slot - 1)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(err, expired);
+
+    // The last slot inside the window
+    validate_at_slot(&mut context, &program_id, fresh, proof_slot, proof_slot + 100)
+        .await
+        .unwrap();
+
+    // One past it
+    let err = validate_at_slot(&mut context, &program_id, stale, proof_slot, proof_slot + 101)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(err, expired);
+
+    let stale_key = Nullifier::find_pda(&program_id, 1, &stale).0;
+    assert!(context.banks_client.get_account(stale_key).await.unwrap().is_none());
+}
+
+/// Simulates WasProofValidated over `candidates`; the query writes nothing
+/// so it needn't be committed
+async fn was_proof_validated(
+    banks_client: &mut BanksClient,
+    program_id: &Pubkey,
+    payer: &Keypair,
+    recent_blockhash: Hash,
+    candidates: &[Pubkey],
+    public_inputs_hash: [u8; 32],
+) -> bool {
+    let transaction = Transaction::new_signed_with_payer(
+        &[Instruction::new_with_borsh(
+            *program_id,
+            &WaveInstruction::WasProofValidated { public_inputs_hash },
+            candidates.iter().map(|key| AccountMeta::new_readonly(*key, false)).collect(),
+        )],
+        Some(&payer.pubkey()),
+        &[payer],
+        recent_blockhash,
+    );
+    let simulation = banks_client.simulate_transaction(transaction).await.unwrap();
+    simulation.result.unwrap().unwrap();
+    let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+    assert_eq!(return_data.program_id, *program_id);
+    match return_data.data.as_slice() {
+        [0] => false,
+        [1] => true,
+        other => panic!("unexpected return data {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_was_proof_validated_finds_logged_proof() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+    let nullifier = [9u8; 32];
+    let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+    let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+    // validate_proof_ix submits public inputs of all ones
+    let public_inputs_hash = [1u8; 32];
+
+    // A matching log in an account the program doesn't own proves nothing
+    let forged_key = Pubkey::new_unique();
+    program_test.add_account(
+        forged_key,
+        Account {
+            lamports: 1_000_000_000,
+            data: ProofLog::new([7u8; 32], 0, flow.flow_id, public_inputs_hash).try_to_vec().unwrap(),
+            owner: Pubkey::new_unique(),
+            ..Account::default()
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let candidates = [forged_key, proof_log_key];
+
+    assert!(!was_proof_validated(&mut banks_client, &program_id, &payer, recent_blockhash, &candidates, public_inputs_hash).await);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[common::validate_proof_ix(
+            &program_id,
+            &payer.pubkey(),
+            registry,
+            nullifier_key,
+            proof_log_key,
+            nullifier,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    assert!(was_proof_validated(&mut banks_client, &program_id, &payer, recent_blockhash, &candidates, public_inputs_hash).await);
+    assert!(!was_proof_validated(&mut banks_client, &program_id, &payer, recent_blockhash, &candidates, [2u8; 32]).await);
+    assert!(!was_proof_validated(&mut banks_client, &program_id, &payer, recent_blockhash, &[forged_key], public_inputs_hash).await);
+
+    // The query wrote nothing
+    let account = banks_client.get_account(proof_log_key).await.unwrap().unwrap();
+    let log = ProofLog::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(log.public_inputs_hash, public_inputs_hash);
+}
+
+#[tokio::test]
+async fn test_compacted_logs_prove_inclusion() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let authority = Keypair::new();
+    program_test.add_account(
+        authority.pubkey(),
+        Account {
+            lamports: 1_000_000_000,
+            ..Account::default()
+        },
+    );
+    let flow = FlowRegistry::new(authority.pubkey(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifiers: Vec<[u8; 32]> = (0..3).map(|i| [9 + i; 32]).collect();
+    let mut accounts = Vec::new();
+    for nullifier in &nullifiers {
+        let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, nullifier);
+        let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, nullifier);
+        accounts.push((nullifier_key, proof_log_key));
+    }
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    for (nullifier, (nullifier_key, proof_log_key)) in nullifiers.iter().zip(&accounts) {
+        let transaction = Transaction::new_signed_with_payer(
+            &[common::validate_proof_ix(
+                &program_id,
+                &payer.pubkey(),
+                registry,
+                *nullifier_key,
+                *proof_log_key,
+                *nullifier,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        banks_client.process_transaction(transaction).await.unwrap();
+    }
+
+    let mut logs = Vec::new();
+    for (_, proof_log_key) in &accounts {
+        let account = banks_client.get_account(*proof_log_key).await.unwrap().unwrap();
+        logs.push(ProofLog::deserialize(&mut account.data.as_slice()).unwrap());
+    }
+    let leaves: Vec<[u8; 32]> = logs.iter().map(ProofLogCommitment::leaf).collect();
+    let root = ProofLogCommitment::new(flow.flow_id, &logs, 0).unwrap().root;
+    let (commitment_key, _) = ProofLogCommitment::find_pda(&program_id, flow.flow_id, &root);
+
+    let mut metas = vec![
+        AccountMeta::new(authority.pubkey(), true),
+        AccountMeta::new_readonly(registry, false),
+        AccountMeta::new(commitment_key, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    metas.extend(accounts.iter().map(|(_, proof_log_key)| AccountMeta::new(*proof_log_key, false)));
+    let transaction = Transaction::new_signed_with_payer(
+        &[Instruction::new_with_borsh(
+            program_id,
+            &WaveInstruction::CompactProofLogs { nullifiers: nullifiers.clone() },
+            metas,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // The logs are closed; their nullifiers still guard against replay
+    for (nullifier_key, proof_log_key) in &accounts {
+        assert!(banks_client.get_account(*proof_log_key).await.unwrap().is_none());
+        assert!(banks_client.get_account(*nullifier_key).await.unwrap().is_some());
+    }
+    let account = banks_client.get_account(commitment_key).await.unwrap().unwrap();
+    let commitment = ProofLogCommitment::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(commitment.root, root);
+    assert_eq!(commitment.leaf_count, 3);
+
+    let verify_ix = |log: &ProofLog, index: u64| {
+        Instruction::new_with_borsh(
+            program_id,
+            &WaveInstruction::VerifyProofLogInclusion {
+                nullifier: log.nullifier,
+                public_inputs_hash: log.public_inputs_hash,
+                timestamp: log.timestamp,
+                index,
+                proof: IncrementalMerkleTree::proof(&leaves, 1).unwrap(),
+            },
+            vec![AccountMeta::new_readonly(commitment_key, false)],
+        )
+    };
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[verify_ix(&logs[1], 1)],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    // The same proof at another index, or for an altered log, fails
+    let mut altered = logs[1].clone();
+    altered.timestamp += 1;
+    for instruction in [verify_ix(&logs[1], 2), verify_ix(&altered, 1)] {
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let err = banks_client.process_transaction(transaction).await.unwrap_err().unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(0, InstructionError::Custom(WaveError::ProofLogNotCommitted as u32))
+        );
+    }
+}
+
+/// ValidateBatchProofs for flow 1 over `nullifiers`, paid for by `payer`
+fn validate_batch_ix(program_id: &Pubkey, payer: &Pubkey, nullifiers: &[[u8; 32]]) -> Instruction {
+    let mut metas = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(common::registry_pda(program_id, 1), false),
+        AccountMeta::new_readonly(common::verifying_key(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    for nullifier in nullifiers {
+        metas.push(AccountMeta::new(Nullifier::find_pda(program_id, 1, nullifier).0, false));
+        metas.push(AccountMeta::new(common::proof_log_pda(program_id, 1, nullifier), false));
+    }
+    Instruction::new_with_borsh(
+        *program_id,
+        &WaveInstruction::ValidateBatchProofs {
+            proofs: nullifiers
+                .iter()
+                .map(|nullifier| BatchProofEntry {
+                    proof: vec![1u8; 128],
+                    public_inputs: vec![1u8; 32],
+                    nullifier: *nullifier,
+                })
+                .collect(),
+        },
+        metas,
+    )
+}
+
+#[tokio::test]
+async fn test_validate_batch_proofs_is_atomic() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    let registry = common::add_registry(&mut program_test, &program_id, &flow);
+
+    let nullifiers: Vec<[u8; 32]> = (0..3).map(|i| [9 + i; 32]).collect();
+    let accounts: Vec<(Pubkey, Pubkey)> = nullifiers
+        .iter()
+        .map(|nullifier| {
+            (
+                Nullifier::find_pda(&program_id, flow.flow_id, nullifier).0,
+                common::proof_log_pda(&program_id, flow.flow_id, nullifier),
+            )
+        })
+        .collect();
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // Each proof needs its own pairing's worth of compute
+    let compute_limit = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+    let batch_ix = |indices: &[usize]| {
+        let batch: Vec<[u8; 32]> = indices.iter().map(|&i| nullifiers[i]).collect();
+        validate_batch_ix(&program_id, &payer.pubkey(), &batch)
+    };
+
+    // A nullifier listed twice fails the whole batch, including the entry
+    // that would have passed on its own
+    let transaction = Transaction::new_signed_with_payer(
+        &[compute_limit.clone(), batch_ix(&[0, 1, 0])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client.process_transaction(transaction).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(1, InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32))
+    );
+    for (nullifier_key, proof_log_key) in &accounts {
+        assert!(banks_client.get_account(*nullifier_key).await.unwrap().is_none());
+        assert!(banks_client.get_account(*proof_log_key).await.unwrap().is_none());
+    }
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[compute_limit.clone(), batch_ix(&[0, 1, 2])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    for (nullifier, (nullifier_key, proof_log_key)) in nullifiers.iter().zip(&accounts) {
+        let account = banks_client.get_account(*nullifier_key).await.unwrap().unwrap();
+        let recorded = Nullifier::deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!(recorded.proof_log, *proof_log_key);
+        let account = banks_client.get_account(*proof_log_key).await.unwrap().unwrap();
+        let log = ProofLog::deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!(log.nullifier, *nullifier);
+    }
+    let account = banks_client.get_account(registry).await.unwrap().unwrap();
+    let stored = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(stored.nullifier_count, 3);
+
+    // Resubmitting any of them is a replay
+    let transaction = Transaction::new_signed_with_payer(
+        &[compute_limit.clone(), batch_ix(&[2])],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    let err = banks_client.process_transaction(transaction).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(1, InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_validate_batch_proofs_creates_missing_pdas() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = common::program_test(program_id);
+
+    let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+    common::add_registry(&mut program_test, &program_id, &flow);
+    // Pays for the PDAs, apart from the fee payer so its balance only moves
+    // by their rent
+    let sponsor = Keypair::new();
+    program_test.add_account(sponsor.pubkey(), Account::new(1_000_000_000, 0, &system_program::id()));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let rent = banks_client.get_rent().await.unwrap();
+    let nullifiers = [[9u8; 32], [10u8; 32]];
+    let compute_limit = ComputeBudgetInstruction::set_compute_unit_limit(1_400_000);
+
+    // The PDAs can only be created through the system program
+    let mut wrong_program = validate_batch_ix(&program_id, &sponsor.pubkey(), &nullifiers);
+    wrong_program.accounts[3].pubkey = Pubkey::new_unique();
+    let transaction = Transaction::new_signed_with_payer(
+        &[compute_limit.clone(), wrong_program],
+        Some(&payer.pubkey()),
+        &[&payer, &sponsor],
+        recent_blockhash,
+    );
+    let err = banks_client.process_transaction(transaction).await.unwrap_err().unwrap();
+    assert_eq!(err, TransactionError::InstructionError(1, InstructionError::InvalidAccountData));
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[compute_limit, validate_batch_ix(&program_id, &sponsor.pubkey(), &nullifiers)],
+        Some(&payer.pubkey()),
+        &[&payer, &sponsor],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    for nullifier in &nullifiers {
+        let (nullifier_key, _) = Nullifier::find_pda(&program_id, 1, nullifier);
+        let proof_log_key = common::proof_log_pda(&program_id, 1, nullifier);
+        for (key, size) in [(nullifier_key, Nullifier::account_size()), (proof_log_key, ProofLog::account_size())] {
+            let account = banks_client.get_account(key).await.unwrap().unwrap();
+            assert_eq!(account.owner, program_id);
+            assert_eq!(account.data.len(), size);
+            assert_eq!(account.lamports, rent.minimum_balance(size));
+        }
+        let account = banks_client.get_account(nullifier_key).await.unwrap().unwrap();
+        let recorded = Nullifier::deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!(recorded.proof_log, proof_log_key);
+    }
+
+    let spent = nullifiers.len() as u64
+        * (rent.minimum_balance(Nullifier::account_size()) + rent.minimum_balance(ProofLog::account_size()));
+    let sponsor_account = banks_client.get_account(sponsor.pubkey()).await.unwrap().unwrap();
+    assert_eq!(sponsor_account.lamports, 1_000_000_000 - spent);
+}
\ No newline at end of file
@@ -31,6 +31,8 @@ async fn test_unauthorized_compression() {
         concurrent_compressions_limit: 1,
         verify_all_compressions: true,
         auto_decompress_on_access: false,
+        refund_excess_rent: false,
+        verify_sample_rate: 0,
     };
     
     let transaction = Transaction::new_signed_with_payer(
@@ -85,6 +87,8 @@ async fn test_malformed_data_compression() {
         concurrent_compressions_limit: 1,
         verify_all_compressions: true,
         auto_decompress_on_access: false,
+        refund_excess_rent: false,
+        verify_sample_rate: 0,
     };
     
     let transaction = Transaction::new_signed_with_payer(
@@ -140,6 +144,8 @@ async fn test_compression_buffer_overflow() {
         concurrent_compressions_limit: 1,
         verify_all_compressions: true,
         auto_decompress_on_access: false,
+        refund_excess_rent: false,
+        verify_sample_rate: 0,
     };
     
     let transaction = Transaction::new_signed_with_payer(
@@ -173,6 +179,8 @@ async fn test_double_compression() {
         concurrent_compressions_limit: 1,
         verify_all_compressions: true,
         auto_decompress_on_access: false,
+        refund_excess_rent: false,
+        verify_sample_rate: 0,
     };
     
     // First compression
@@ -231,6 +239,8 @@ async fn test_compression_data_integrity() {
         concurrent_compressions_limit: 1,
         verify_all_compressions: true,
         auto_decompress_on_access: false,
+        refund_excess_rent: false,
+        verify_sample_rate: 0,
     };
     
     let transaction = Transaction::new_signed_with_payer(
@@ -284,6 +294,8 @@ async fn test_compression_with_invalid_config() {
             concurrent_compressions_limit: 1,
             verify_all_compressions: true,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         },
         GlobalCompressionConfig {
             default_algorithm: CompressionAlgorithm::Lz4,
@@ -292,6 +304,8 @@ async fn test_compression_with_invalid_config() {
             concurrent_compressions_limit: 1,
             verify_all_compressions: true,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         },
         GlobalCompressionConfig {
             default_algorithm: CompressionAlgorithm::Lz4,
@@ -300,6 +314,8 @@ async fn test_compression_with_invalid_config() {
             concurrent_compressions_limit: 0, // Invalid: zero concurrent limit
             verify_all_compressions: true,
             auto_decompress_on_access: false,
+            refund_excess_rent: false,
+            verify_sample_rate: 0,
         },
     ];
     
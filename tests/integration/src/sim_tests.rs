@@ -0,0 +1,213 @@
+use {
+    borsh::BorshDeserialize,
+    rand::{rngs::StdRng, Rng, SeedableRng},
+    solana_program_test::*,
+    solana_sdk::{
+        account::Account,
+        account_info::AccountInfo,
+        compute_budget::ComputeBudgetInstruction,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+        system_program,
+        transaction::{Transaction, TransactionError},
+    },
+    wave_verifier::{
+        instructions::WaveInstruction,
+        state::{FlowRegistry, Nullifier, ProofLog},
+        test_utils::TestEnvironment,
+    },
+};
+
+use super::*;
+
+const FLOWS: [u64; 2] = [1, 2];
+const NULLIFIER_POOL_SIZE: u8 = 4;
+const STEPS: usize = 40;
+
+/// Nullifiers every flow's instructions draw from. Sharing one pool makes
+/// flows spend the same nullifier, each in its own scope.
+fn nullifier_pool() -> Vec<[u8; 32]> {
+    (1..=NULLIFIER_POOL_SIZE).map(|i| [i; 32]).collect()
+}
+
+/// A SetRoot, ValidateProof or ImportNullifiers for a random flow, signed by
+/// `signer`. Roots mostly carry the current sequence so they can land.
+fn random_instruction(
+    rng: &mut StdRng,
+    program_id: &Pubkey,
+    env: &TestEnvironment,
+    signer: &Pubkey,
+) -> Instruction {
+    let flow_id = FLOWS[rng.gen_range(0..FLOWS.len())];
+    let registry = common::registry_pda(program_id, flow_id);
+    let pool = nullifier_pool();
+
+    match rng.gen_range(0..3) {
+        0 => {
+            let sequence = env.registry_manager.get_by_id(flow_id).unwrap().root_sequence;
+            let expected_sequence = if rng.gen_bool(0.25) { sequence + 1 } else { sequence };
+            Instruction::new_with_borsh(
+                *program_id,
+                &WaveInstruction::SetRoot { new_root: rng.gen(), expected_sequence },
+                vec![
+                    AccountMeta::new_readonly(*signer, true),
+                    AccountMeta::new(registry, false),
+                ],
+            )
+        }
+        1 => {
+            let nullifier = pool[rng.gen_range(0..pool.len())];
+            common::validate_proof_ix(
+                program_id,
+                signer,
+                registry,
+                Nullifier::find_pda(program_id, flow_id, &nullifier).0,
+                common::proof_log_pda(program_id, flow_id, &nullifier),
+                nullifier,
+            )
+        }
+        _ => {
+            let nullifiers: Vec<[u8; 32]> = (0..rng.gen_range(1..=3))
+                .map(|_| pool[rng.gen_range(0..pool.len())])
+                .collect();
+            let mut accounts = vec![
+                AccountMeta::new(*signer, true),
+                AccountMeta::new_readonly(registry, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ];
+            accounts.extend(nullifiers.iter().map(|nullifier| {
+                AccountMeta::new(Nullifier::find_pda(program_id, flow_id, nullifier).0, false)
+            }));
+            Instruction::new_with_borsh(
+                *program_id,
+                &WaveInstruction::ImportNullifiers { nullifiers },
+                accounts,
+            )
+        }
+    }
+}
+
+/// Runs `instruction` through the simulator with a transaction's
+/// atomicity: on failure the model is restored to where it started.
+fn simulate(
+    env: &mut TestEnvironment,
+    program_id: &Pubkey,
+    instruction: &Instruction,
+) -> Result<(), ProgramError> {
+    let mut lamports = vec![0u64; instruction.accounts.len()];
+    let mut data = vec![Vec::new(); instruction.accounts.len()];
+    let accounts: Vec<AccountInfo> = instruction
+        .accounts
+        .iter()
+        .zip(lamports.iter_mut())
+        .zip(data.iter_mut())
+        .map(|((meta, lamports), data)| {
+            AccountInfo::new(&meta.pubkey, meta.is_signer, meta.is_writable, lamports, data, program_id, false, 0)
+        })
+        .collect();
+
+    let snapshot = env.snapshot();
+    let result = env.process_instruction(program_id, &accounts, &instruction.data);
+    if result.is_err() {
+        env.restore(snapshot);
+    }
+    result
+}
+
+async fn assert_state_matches(banks_client: &mut BanksClient, program_id: &Pubkey, env: &TestEnvironment) {
+    for flow_id in FLOWS {
+        let account = banks_client
+            .get_account(common::registry_pda(program_id, flow_id))
+            .await
+            .unwrap()
+            .unwrap();
+        let registry = FlowRegistry::deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!(Some(&registry), env.registry_manager.get_by_id(flow_id));
+
+        // The simulator has no clock, so timestamps are not compared
+        for nullifier in nullifier_pool() {
+            let key = Nullifier::find_pda(program_id, flow_id, &nullifier).0;
+            let account = banks_client.get_account(key).await.unwrap();
+            match env.nullifier_set.get(registry.nullifier_scope(), &nullifier) {
+                Some(simulated) => {
+                    let account = account.unwrap();
+                    let recorded = Nullifier::deserialize(&mut account.data.as_slice()).unwrap();
+                    assert_eq!(
+                        (recorded.hash, recorded.flow_id, recorded.proof_log),
+                        (simulated.hash, simulated.flow_id, simulated.proof_log),
+                    );
+                }
+                None => assert!(account.is_none()),
+            }
+
+            let key = common::proof_log_pda(program_id, flow_id, &nullifier);
+            let account = banks_client.get_account(key).await.unwrap();
+            match env.proof_history.get(flow_id, &nullifier) {
+                Some(simulated) => {
+                    let account = account.unwrap();
+                    let logged = ProofLog::deserialize(&mut account.data.as_slice()).unwrap();
+                    assert_eq!(
+                        (logged.nullifier, logged.flow_id, logged.public_inputs_hash),
+                        (simulated.nullifier, simulated.flow_id, simulated.public_inputs_hash),
+                    );
+                }
+                None => assert!(account.is_none()),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_simulator_matches_processor() {
+    for seed in 0..3u64 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let program_id = Pubkey::new_unique();
+        let mut program_test = common::program_test(program_id);
+        // Either signer pays for the nullifier and proof log PDAs it creates
+        let authority = Keypair::new();
+        let intruder = Keypair::new();
+        for signer in [&authority, &intruder] {
+            program_test.add_account(
+                signer.pubkey(),
+                Account::new(1_000_000_000, 0, &system_program::id()),
+            );
+        }
+
+        let mut env = TestEnvironment::new();
+        for flow_id in FLOWS {
+            let mut flow = FlowRegistry::new(authority.pubkey(), flow_id, None, [1u8; 32], None);
+            // Cap one flow below its pool so the cap gets exercised
+            flow.max_nullifiers = (flow_id == 2).then_some(2);
+            common::add_registry(&mut program_test, &program_id, &flow);
+            env.registry_manager.register(flow);
+        }
+
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        for step in 0..STEPS {
+            let signer = if rng.gen_bool(0.8) { &authority } else { &intruder };
+            let instruction = random_instruction(&mut rng, &program_id, &env, &signer.pubkey());
+            let simulated = simulate(&mut env, &program_id, &instruction).map_err(|err| {
+                TransactionError::InstructionError(0, InstructionError::from(u64::from(err)))
+            });
+
+            // The price instruction keeps a repeated instruction from
+            // producing an identical, already-processed transaction
+            let transaction = Transaction::new_signed_with_payer(
+                &[instruction, ComputeBudgetInstruction::set_compute_unit_price(step as u64)],
+                Some(&payer.pubkey()),
+                &[&payer, signer],
+                recent_blockhash,
+            );
+            let processed = banks_client
+                .process_transaction(transaction)
+                .await
+                .map_err(|err| err.unwrap());
+
+            assert_eq!(processed, simulated, "seed {seed}, step {step}");
+            assert_state_matches(&mut banks_client, &program_id, &env).await;
+        }
+    }
+}
\ No newline at end of file
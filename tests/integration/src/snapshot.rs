@@ -0,0 +1,143 @@
+//! Account snapshots for program-test fixtures.
+//!
+//! A test that needs registries, nullifiers and proof logs in a particular
+//! state can capture them once from a `BanksClient` and preload them into a
+//! fresh `ProgramTest`, instead of replaying every transaction that built
+//! them.
+
+use {
+    anyhow::{Context, Result},
+    serde::{Deserialize, Serialize},
+    solana_program_test::*,
+    solana_sdk::{account::Account, pubkey::Pubkey},
+    std::path::Path,
+};
+
+/// A set of accounts exactly as the bank held them: owner, lamports,
+/// executable flag, rent epoch and data.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub accounts: Vec<(Pubkey, Account)>,
+}
+
+impl Snapshot {
+    /// Reads `keys` from the bank. BanksClient can't enumerate accounts by
+    /// owner, so the caller names them; keys that don't exist are skipped.
+    pub async fn capture(banks_client: &mut BanksClient, keys: &[Pubkey]) -> Result<Self> {
+        let mut accounts = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(account) = banks_client.get_account(*key).await? {
+                accounts.push((*key, account));
+            }
+        }
+        Ok(Self { accounts })
+    }
+
+    /// Preloads every account into `program_test` via `add_account`.
+    pub fn restore(&self, program_test: &mut ProgramTest) {
+        for (key, account) in &self.accounts {
+            program_test.add_account(*key, account.clone());
+        }
+    }
+
+    pub fn get(&self, key: &Pubkey) -> Option<&Account> {
+        self.accounts
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, account)| account)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec(self)?;
+        std::fs::write(path, json).with_context(|| format!("writing {}", path.display()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::common,
+        solana_sdk::{
+            instruction::InstructionError,
+            signature::Signer,
+            transaction::{Transaction, TransactionError},
+        },
+        wave_verifier::{
+            error::WaveError,
+            state::{FlowRegistry, Nullifier},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_snapshot_round_trips_program_state() {
+        let program_id = Pubkey::new_unique();
+        let mut first = common::program_test(program_id);
+
+        let flow = FlowRegistry::new(Pubkey::new_unique(), 1, None, [1u8; 32], None);
+        let registry = common::add_registry(&mut first, &program_id, &flow);
+        let nullifier = [9u8; 32];
+        let (nullifier_key, _) = Nullifier::find_pda(&program_id, flow.flow_id, &nullifier);
+        let proof_log_key = common::proof_log_pda(&program_id, flow.flow_id, &nullifier);
+
+        let (mut banks_client, payer, recent_blockhash) = first.start().await;
+        let spend = |payer: &solana_sdk::signature::Keypair, blockhash| {
+            Transaction::new_signed_with_payer(
+                &[common::validate_proof_ix(
+                    &program_id,
+                    &payer.pubkey(),
+                    registry,
+                    nullifier_key,
+                    proof_log_key,
+                    nullifier,
+                )],
+                Some(&payer.pubkey()),
+                &[payer],
+                blockhash,
+            )
+        };
+        banks_client
+            .process_transaction(spend(&payer, recent_blockhash))
+            .await
+            .unwrap();
+
+        let keys = [registry, nullifier_key, proof_log_key];
+        let snapshot = Snapshot::capture(&mut banks_client, &keys).await.unwrap();
+        assert_eq!(snapshot.accounts.len(), keys.len());
+
+        // Byte-identical through the file format
+        let path = std::env::temp_dir().join(format!("wave-snapshot-{}.json", program_id));
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, snapshot);
+
+        // ...and through a fresh bank
+        let mut second = common::program_test(program_id);
+        loaded.restore(&mut second);
+        let (mut banks_client, payer, recent_blockhash) = second.start().await;
+        let restored = Snapshot::capture(&mut banks_client, &keys).await.unwrap();
+        assert_eq!(restored, snapshot);
+
+        // The restored nullifier is still spent
+        let err = banks_client
+            .process_transaction(spend(&payer, recent_blockhash))
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(WaveError::NullifierAlreadyUsed as u32),
+            )
+        );
+    }
+}
\ No newline at end of file